@@ -0,0 +1,105 @@
+//! Allocations made by a wide read, collected into rows versus folded over
+//! borrowed row views.
+//!
+//! Run with `cargo bench --bench wide_read`.
+
+use futures::executor::block_on;
+use rust_sqlite::sqlite::{Schema, SqlQuery, SqliteConfig, SqliteService, Value};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+const ROWS: usize = 1_000;
+const COLUMNS: usize = 20;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Run `f`, returning its result with the allocations it made
+fn measure<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let result = f();
+    (result, ALLOCATIONS.load(Ordering::Relaxed) - before)
+}
+
+fn main() {
+    let dir = std::env::temp_dir().join(format!("wide_read_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("bench.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, Schema::new()));
+
+    block_on(service.connect()).unwrap();
+    let columns: Vec<String> = (0..COLUMNS).map(|i| format!("c{}", i)).collect();
+    block_on(service.execute_sql(SqlQuery::new(&format!(
+        "CREATE TABLE wide ({})",
+        columns.join(", ")
+    ))))
+    .unwrap();
+    block_on(service.execute_sql(SqlQuery::new(&format!(
+        "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < {rows}) \
+         INSERT INTO wide SELECT {values} FROM n",
+        rows = ROWS,
+        values = vec!["i"; COLUMNS].join(", "),
+    ))))
+    .unwrap();
+
+    let query = || SqlQuery::new("SELECT * FROM wide");
+    // Warm the statement caches so neither run pays for preparing
+    block_on(service.fold_rows(query(), (), |_, _| ())).unwrap();
+
+    let start = Instant::now();
+    let (sum, rows_allocations) = measure(|| {
+        block_on(service.fold_rows(query(), 0i64, |sum, row| {
+            sum + row
+                .values()
+                .filter_map(|value| match value {
+                    Value::Integer(i) => Some(*i),
+                    _ => None,
+                })
+                .sum::<i64>()
+        }))
+        .unwrap()
+    });
+    let rows_time = start.elapsed();
+
+    let start = Instant::now();
+    let (view_sum, view_allocations) = measure(|| {
+        block_on(service.fold_row_views(query(), 0i64, |sum, view| {
+            sum + (0..COLUMNS)
+                .filter_map(|i| view.get_index(i)?.as_i64())
+                .sum::<i64>()
+        }))
+        .unwrap()
+    });
+    let view_time = start.elapsed();
+    assert_eq!(sum, view_sum);
+
+    println!("{} rows x {} integer columns", ROWS, COLUMNS);
+    println!(
+        "fold_rows:      {:>7} allocations  {:?}",
+        rows_allocations, rows_time
+    );
+    println!(
+        "fold_row_views: {:>7} allocations  {:?}",
+        view_allocations, view_time
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
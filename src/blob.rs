@@ -0,0 +1,106 @@
+//! Structs stored serialized in BLOB columns.
+//!
+//! A [`TypedBlob`] names a BLOB column and the [`BlobCodec`] its values are
+//! serialized with, so call sites write and read a `T` instead of encoding
+//! bytes by hand. JSON is always available; bincode and CBOR come with the
+//! `bincode` and `cbor` features. The codec is not recorded with the value,
+//! so every writer and reader of a column must agree on it.
+
+use crate::sqlite::{Row, SqliteError, Value};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::PhantomData;
+
+/// A serialization format for values stored in BLOB columns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlobCodec {
+    Json,
+    #[cfg(feature = "bincode")]
+    Bincode,
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl BlobCodec {
+    /// Serialize `value` to bytes
+    pub fn encode<T: Serialize>(self, value: &T) -> anyhow::Result<Vec<u8>> {
+        Ok(match self {
+            BlobCodec::Json => serde_json::to_vec(value)?,
+            #[cfg(feature = "bincode")]
+            BlobCodec::Bincode => bincode::serialize(value)?,
+            #[cfg(feature = "cbor")]
+            BlobCodec::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes)?;
+                bytes
+            }
+        })
+    }
+
+    /// Deserialize a value from bytes made by [`BlobCodec::encode`]
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> anyhow::Result<T> {
+        Ok(match self {
+            BlobCodec::Json => serde_json::from_slice(bytes)?,
+            #[cfg(feature = "bincode")]
+            BlobCodec::Bincode => bincode::deserialize(bytes)?,
+            #[cfg(feature = "cbor")]
+            BlobCodec::Cbor => ciborium::from_reader(bytes)?,
+        })
+    }
+}
+
+/// A BLOB column holding values of type `T` serialized with a codec
+#[derive(Debug, Clone)]
+pub struct TypedBlob<T> {
+    pub column: String,
+    pub codec: BlobCodec,
+    value_type: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> TypedBlob<T> {
+    pub fn new(column: &str, codec: BlobCodec) -> Self {
+        Self {
+            column: column.to_string(),
+            codec,
+            value_type: PhantomData,
+        }
+    }
+
+    /// `value` serialized into the BLOB to write to the column
+    pub fn to_value(&self, value: &T) -> anyhow::Result<Value> {
+        self.codec
+            .encode(value)
+            .map(Value::Blob)
+            .map_err(|e| self.invalid(e))
+    }
+
+    /// Deserialize the column of `row`. Fails with
+    /// [`SqliteError::InvalidBlob`] when the row lacks the column or holds
+    /// anything but a BLOB that decodes.
+    pub fn read(&self, row: &Row) -> anyhow::Result<T> {
+        self.read_optional(row)?
+            .ok_or_else(|| self.invalid("value is NULL"))
+    }
+
+    /// Like [`TypedBlob::read`], but NULL reads as `None`
+    pub fn read_optional(&self, row: &Row) -> anyhow::Result<Option<T>> {
+        match row.get(&self.column) {
+            None => Err(self.invalid("row has no such column")),
+            Some(Value::Null) => Ok(None),
+            Some(Value::Blob(bytes)) => self
+                .codec
+                .decode(bytes)
+                .map(Some)
+                .map_err(|e| self.invalid(e)),
+            Some(other) => Err(self.invalid(format!("value is not a BLOB: {:?}", other))),
+        }
+    }
+
+    fn invalid(&self, reason: impl std::fmt::Display) -> anyhow::Error {
+        SqliteError::InvalidBlob {
+            column: self.column.clone(),
+            reason: reason.to_string(),
+        }
+        .into()
+    }
+}
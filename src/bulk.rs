@@ -0,0 +1,303 @@
+//! Bulk write helpers built on top of the service connection.
+
+use crate::sqlite::{quote_ident, Row, SqliteError, SqliteService, Value};
+use rusqlite::params_from_iter;
+use std::collections::HashMap;
+
+/// Column carrying the source rowid in the batches read by
+/// [`SqliteService::copy_table_to`]
+const COPY_ROWID: &str = "_runar_copy_rowid";
+
+/// Key and value pairs bound by one statement of
+/// [`SqliteService::update_values`], keeping it within SQLite's default
+/// limit of 32,766 parameters
+const UPDATE_VALUES_BATCH: usize = 16_383;
+
+/// Row counts reported by [`SqliteService::merge`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeResult {
+    pub inserted: usize,
+    pub updated: usize,
+    pub deleted: usize,
+}
+
+impl SqliteService {
+    /// Make `table` contain exactly `rows`, matched on `key_columns`.
+    ///
+    /// Within one transaction, rows whose key already exists are updated,
+    /// new keys are inserted, and existing rows whose key is not among `rows`
+    /// are deleted. Every row must contain every key column. Keys are compared
+    /// with `IS`, so NULL keys match each other. Scoped tables are rejected
+    /// with [`SqliteError::ScopeRequired`], as the delete would reach the
+    /// rows of every scope, and tables with a checksum column with
+    /// [`SqliteError::ChecksumTable`]. Values of compressed columns are
+    /// compressed before they are written.
+    pub async fn merge(
+        &self,
+        table: &str,
+        key_columns: &[&str],
+        rows: Vec<Row>,
+    ) -> anyhow::Result<MergeResult> {
+        self.check_unscoped(table)?;
+        self.check_no_checksum(table)?;
+        for operation in ["create", "update", "delete"] {
+            self.check_permission(table, operation)?;
+        }
+        #[cfg(feature = "compression")]
+        let rows = rows
+            .into_iter()
+            .map(|row| self.compress_values(table, row))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let table_sql = quote_ident(table);
+        let key_match = key_columns
+            .iter()
+            .map(|k| format!("{} IS ?", quote_ident(k)))
+            .collect::<Vec<_>>()
+            .join(" AND ");
+
+        self.with_connection(|conn| {
+            let tx = conn.transaction()?;
+            let mut result = MergeResult::default();
+
+            tx.execute_batch(&format!(
+                "CREATE TEMP TABLE _runar_merge_keys ({})",
+                key_columns
+                    .iter()
+                    .map(|k| quote_ident(k))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))?;
+
+            for row in &rows {
+                let keys: Vec<&Value> = key_columns
+                    .iter()
+                    .map(|k| {
+                        row.get(*k).ok_or_else(|| SqliteError::MissingColumn {
+                            table: table.to_string(),
+                            column: k.to_string(),
+                        })
+                    })
+                    .collect::<Result<_, _>>()?;
+                let mut values: Vec<(&String, &Value)> = row
+                    .iter()
+                    .filter(|(column, _)| !key_columns.contains(&column.as_str()))
+                    .collect();
+                values.sort_by(|a, b| a.0.cmp(b.0));
+
+                let exists = tx
+                    .prepare_cached(&format!(
+                        "SELECT EXISTS (SELECT 1 FROM {} WHERE {})",
+                        table_sql, key_match
+                    ))?
+                    .query_row(params_from_iter(&keys), |r| r.get::<_, bool>(0))?;
+                if exists {
+                    if !values.is_empty() {
+                        let assignments = values
+                            .iter()
+                            .map(|(column, _)| format!("{} = ?", quote_ident(column)))
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        tx.execute(
+                            &format!(
+                                "UPDATE {} SET {} WHERE {}",
+                                table_sql, assignments, key_match
+                            ),
+                            params_from_iter(values.iter().map(|(_, v)| *v).chain(keys.iter().copied())),
+                        )?;
+                    }
+                    result.updated += 1;
+                } else {
+                    let mut columns: Vec<(&String, &Value)> = row.iter().collect();
+                    columns.sort_by(|a, b| a.0.cmp(b.0));
+                    tx.execute(
+                        &format!(
+                            "INSERT INTO {} ({}) VALUES ({})",
+                            table_sql,
+                            columns
+                                .iter()
+                                .map(|(column, _)| quote_ident(column))
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                            vec!["?"; columns.len()].join(", ")
+                        ),
+                        params_from_iter(columns.iter().map(|(_, v)| *v)),
+                    )?;
+                    result.inserted += 1;
+                }
+
+                tx.execute(
+                    &format!(
+                        "INSERT INTO _runar_merge_keys VALUES ({})",
+                        vec!["?"; keys.len()].join(", ")
+                    ),
+                    params_from_iter(&keys),
+                )?;
+            }
+
+            let kept = key_columns
+                .iter()
+                .map(|k| format!("k.{} IS {}.{}", quote_ident(k), table_sql, quote_ident(k)))
+                .collect::<Vec<_>>()
+                .join(" AND ");
+            result.deleted = tx.execute(
+                &format!(
+                    "DELETE FROM {} WHERE NOT EXISTS (SELECT 1 FROM temp._runar_merge_keys AS k WHERE {})",
+                    table_sql, kept
+                ),
+                [],
+            )?;
+            tx.execute_batch("DROP TABLE temp._runar_merge_keys")?;
+            tx.commit()?;
+            Ok(result)
+        })
+        .await
+    }
+
+    /// Copy every row of `table` into the table of the same name in `other`,
+    /// returning the number of rows copied.
+    ///
+    /// Rows are read in rowid order, `batch_size` at a time, and each batch
+    /// is inserted into `other` in its own transaction before the next one is
+    /// read, so only one batch is held in memory and neither connection is
+    /// locked while the other is in use. The destination table must already
+    /// exist with the same columns. The copy is not a snapshot: rows written
+    /// to the source meanwhile may or may not be copied. Tables declared
+    /// `WITHOUT ROWID` are not supported, nor tables scoped in either
+    /// service, which fail with [`SqliteError::ScopeRequired`]. Values are
+    /// copied as stored, except that columns compressed in only one of the
+    /// services are compressed or decompressed to match `other`.
+    pub async fn copy_table_to(
+        &self,
+        other: &SqliteService,
+        table: &str,
+        batch_size: usize,
+    ) -> anyhow::Result<u64> {
+        self.check_unscoped(table)?;
+        other.check_unscoped(table)?;
+        self.check_permission(table, "read")?;
+        other.check_permission(table, "create")?;
+        let table_sql = quote_ident(table);
+        let select = format!(
+            "SELECT rowid AS {}, * FROM {} WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+            COPY_ROWID, table_sql
+        );
+        let mut after = i64::MIN;
+        let mut copied = 0;
+        loop {
+            let (columns, batch) = self
+                .with_connection(|conn| {
+                    let mut stmt = conn.prepare_cached(&select)?;
+                    let columns: Vec<String> = stmt
+                        .column_names()
+                        .into_iter()
+                        .skip(1)
+                        .map(String::from)
+                        .collect();
+                    let batch = stmt
+                        .query_map((after, batch_size.max(1) as i64), |row| {
+                            (0..=columns.len())
+                                .map(|i| row.get_ref(i).map(Value::from))
+                                .collect::<Result<Vec<_>, _>>()
+                        })?
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Ok((columns, batch))
+                })
+                .await?;
+            #[cfg(feature = "compression")]
+            let batch = {
+                let mut batch = batch;
+                for row in &mut batch {
+                    self.recompress_for(other, table, &columns, &mut row[1..])?;
+                }
+                batch
+            };
+            let Some(last) = batch.last() else {
+                return Ok(copied);
+            };
+            let Value::Integer(rowid) = last[0] else {
+                unreachable!("rowids are integers");
+            };
+            after = rowid;
+            let insert = format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                table_sql,
+                columns
+                    .iter()
+                    .map(|column| quote_ident(column))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                vec!["?"; columns.len()].join(", ")
+            );
+            other
+                .with_connection(|conn| {
+                    let tx = conn.transaction()?;
+                    {
+                        let mut stmt = tx.prepare_cached(&insert)?;
+                        for row in &batch {
+                            stmt.execute(params_from_iter(&row[1..]))?;
+                        }
+                    }
+                    tx.commit()?;
+                    Ok(())
+                })
+                .await?;
+            copied += batch.len() as u64;
+        }
+    }
+
+    /// Set `column` of the rows of `table` whose `key_column` equals a key
+    /// of `values` to the value paired with that key, returning the number
+    /// of rows updated.
+    ///
+    /// Runs `UPDATE ... FROM (VALUES (?, ?), ...)`, joining the table with
+    /// the bound pairs, so one statement updates many rows to distinct
+    /// values instead of one statement per row. Pairs beyond the parameter
+    /// limit go to further statements in the same transaction. Rows whose
+    /// key is not in `values` are left alone; when a key is repeated, which
+    /// of its values wins is unspecified. Values are checked against the
+    /// column's allowed values and compressed when the column is, as CRUD
+    /// updates do. Tables with a checksum column are rejected with
+    /// [`SqliteError::ChecksumTable`].
+    pub async fn update_values<K: Into<Value>, V: Into<Value>>(
+        &self,
+        table: &str,
+        key_column: &str,
+        column: &str,
+        values: impl IntoIterator<Item = (K, V)>,
+    ) -> anyhow::Result<usize> {
+        self.check_unscoped(table)?;
+        self.check_no_checksum(table)?;
+        self.check_permission(table, "update")?;
+        let mut pairs = Vec::new();
+        for (key, value) in values {
+            let set = HashMap::from([(column.to_string(), value.into())]);
+            self.check_allowed_values(table, &set)?;
+            #[cfg(feature = "compression")]
+            let set = self.compress_values(table, set)?;
+            pairs.push(key.into());
+            pairs.extend(set.into_values());
+        }
+        if pairs.is_empty() {
+            return Ok(0);
+        }
+        let table_sql = quote_ident(table);
+        self.with_connection(|conn| {
+            let tx = conn.transaction()?;
+            let mut updated = 0;
+            for batch in pairs.chunks(UPDATE_VALUES_BATCH * 2) {
+                let sql = format!(
+                    "UPDATE {table} SET {column} = v.column2 FROM (VALUES {values}) AS v \
+                     WHERE {table}.{key} = v.column1",
+                    table = table_sql,
+                    column = quote_ident(column),
+                    values = vec!["(?, ?)"; batch.len() / 2].join(", "),
+                    key = quote_ident(key_column),
+                );
+                updated += tx.prepare_cached(&sql)?.execute(params_from_iter(batch))?;
+            }
+            tx.commit()?;
+            Ok(updated)
+        })
+        .await
+    }
+}
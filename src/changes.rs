@@ -0,0 +1,34 @@
+//! Change polling on an `updated_at` column.
+
+use crate::sqlite::{
+    CrudOperation, Query, QueryOperator, ReadOperation, Row, SqliteService, Value,
+};
+
+/// Column [`SqliteService::changed_since`] compares against
+pub const UPDATED_AT_COLUMN: &str = "updated_at";
+
+impl SqliteService {
+    /// Rows of `table` whose `updated_at` is later than `since`, oldest
+    /// change first.
+    ///
+    /// The table must have an `updated_at` column that writers keep current,
+    /// for example with `DEFAULT CURRENT_TIMESTAMP` plus an explicit value on
+    /// every update. `since` is compared with SQLite's ordinary comparison
+    /// rules, so it should use the same representation as the column, such
+    /// as `"YYYY-MM-DD HH:MM:SS"` text or Unix seconds. Clients poll
+    /// incrementally by passing the largest `updated_at` they have seen;
+    /// rows changed within that same timestamp tick are only seen if they
+    /// were already visible at the previous poll.
+    pub async fn changed_since(
+        &self,
+        table: &str,
+        since: impl Into<Value>,
+    ) -> anyhow::Result<Vec<Row>> {
+        let query = Query::new()
+            .with_condition(UPDATED_AT_COLUMN, QueryOperator::GreaterThan(since.into()));
+        let read = ReadOperation::new(table)
+            .with_query(query)
+            .with_order_by(UPDATED_AT_COLUMN, true);
+        self.execute_crud(CrudOperation::Read(read)).await
+    }
+}
@@ -0,0 +1,174 @@
+//! Row checksums maintained by the service, to detect out-of-band changes.
+//!
+//! A table declared with [`TableDefinition::with_checksum_column`] gets a
+//! TEXT column holding a checksum of the rest of its row. Creates and
+//! updates made through the CRUD API stamp it in a savepoint with the write
+//! itself, locating each written row by its `rowid`. The bulk writers
+//! [`SqliteService::merge`] and [`SqliteService::update_values`] reject such
+//! tables. Anything else writing the table, raw SQL included, leaves it
+//! stale, and [`SqliteService::verify_row_checksums`] reports those rows.
+//! The checksum is FNV-1a over the column names and stored values: it
+//! catches stray edits, not someone who recomputes it after tampering.
+//!
+//! [`TableDefinition::with_checksum_column`]: crate::sqlite::TableDefinition::with_checksum_column
+
+use crate::sqlite::{quote_ident, CrudOperation, Row, SqlQuery, SqliteError, SqliteService, Value};
+use rusqlite::Connection;
+
+/// Name the `rowid` of written rows is returned under, removed before the
+/// rows reach the caller
+const ROWID_COLUMN: &str = "_runar_rowid";
+
+/// The checksum of `row`, over every column but `checksum_column`, as 16
+/// hexadecimal digits
+pub fn row_checksum(row: &Row, checksum_column: &str) -> String {
+    let mut columns: Vec<_> = row
+        .iter()
+        .filter(|(column, _)| column.as_str() != checksum_column)
+        .collect();
+    columns.sort_by(|a, b| a.0.cmp(b.0));
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    for (column, value) in columns {
+        feed(column.as_bytes());
+        match value {
+            Value::Null => feed(&[0]),
+            Value::Integer(i) => {
+                feed(&[1]);
+                feed(&i.to_be_bytes());
+            }
+            Value::Boolean(b) => {
+                feed(&[1]);
+                feed(&i64::from(*b).to_be_bytes());
+            }
+            Value::Real(r) => {
+                feed(&[2]);
+                feed(&r.to_bits().to_be_bytes());
+            }
+            Value::Text(text) => {
+                feed(&[3]);
+                feed(&(text.len() as u64).to_be_bytes());
+                feed(text.as_bytes());
+            }
+            Value::Blob(blob) => {
+                feed(&[4]);
+                feed(&(blob.len() as u64).to_be_bytes());
+                feed(blob);
+            }
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+impl SqliteService {
+    /// The checksum column of `table`, if the configured schema declares one
+    fn checksum_column(&self, table: &str) -> Option<&str> {
+        self.config
+            .schema
+            .tables
+            .iter()
+            .find(|t| t.name == table)?
+            .checksum_column
+            .as_deref()
+    }
+
+    /// Fail with [`SqliteError::ChecksumTable`] if `table` has a checksum
+    /// column, for writers that cannot stamp it
+    pub(crate) fn check_no_checksum(&self, table: &str) -> Result<(), SqliteError> {
+        match self.checksum_column(table) {
+            Some(_) => Err(SqliteError::ChecksumTable(table.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Run `write` with `sql`, the rendering of the create or update `op`
+    /// ending in `RETURNING *`, and stamp the checksums of the rows it
+    /// returns. The statement is made to return each row's `rowid` too, so
+    /// the stamp finds the row directly in the schema `op` writes to. Both happen in one savepoint, so a row is
+    /// never left written without its checksum.
+    pub(crate) fn write_with_checksums(
+        &self,
+        conn: &Connection,
+        op: &CrudOperation,
+        sql: &str,
+        write: impl FnOnce(&str) -> anyhow::Result<Vec<Row>>,
+    ) -> anyhow::Result<Vec<Row>> {
+        let (Some(checksum_column), Some(statement)) = (
+            self.checksum_column(op.table()),
+            sql.strip_suffix(" RETURNING *"),
+        ) else {
+            return write(sql);
+        };
+        let sql = format!(
+            "{} RETURNING rowid AS {}, *",
+            statement,
+            quote_ident(ROWID_COLUMN)
+        );
+        let table = op.qualified_table();
+        conn.execute_batch("SAVEPOINT row_checksum")?;
+        let result = write(&sql).and_then(|mut rows| {
+            for row in &mut rows {
+                let rowid = row.remove(ROWID_COLUMN).unwrap_or(Value::Null);
+                stamp(conn, &table, checksum_column, &rowid, row)?;
+            }
+            Ok(rows)
+        });
+        match result {
+            Ok(_) => conn.execute_batch("RELEASE row_checksum")?,
+            Err(_) => conn.execute_batch("ROLLBACK TO row_checksum; RELEASE row_checksum")?,
+        }
+        result
+    }
+
+    /// The rows of `table` whose checksum column does not match the rest of
+    /// the row, including rows without a checksum. Fails with
+    /// [`SqliteError::NoChecksumColumn`] unless the configured schema
+    /// declares a checksum column for the table.
+    pub async fn verify_row_checksums(&self, table: &str) -> anyhow::Result<Vec<Row>> {
+        let checksum_column = self
+            .checksum_column(table)
+            .ok_or_else(|| SqliteError::NoChecksumColumn(table.to_string()))?;
+        self.check_permission(table, "read")?;
+        let rows = self
+            .run_query(SqlQuery::new(&format!(
+                "SELECT * FROM {}",
+                quote_ident(table)
+            )))
+            .await?;
+        Ok(rows
+            .into_iter()
+            .filter(|row| {
+                let expected = Value::Text(row_checksum(row, checksum_column));
+                row.get(checksum_column) != Some(&expected)
+            })
+            .collect())
+    }
+}
+
+/// Write the checksum of `row` to the row with `rowid` of `table`, an
+/// already quoted and possibly schema-qualified name
+fn stamp(
+    conn: &Connection,
+    table: &str,
+    checksum_column: &str,
+    rowid: &Value,
+    row: &mut Row,
+) -> anyhow::Result<()> {
+    let checksum = row_checksum(row, checksum_column);
+    let sql = format!(
+        "UPDATE {} SET {} = ? WHERE rowid = ?",
+        table,
+        quote_ident(checksum_column)
+    );
+    let mut stmt = conn.prepare_cached(&sql)?;
+    stmt.raw_bind_parameter(1, &checksum)?;
+    stmt.raw_bind_parameter(2, rowid)?;
+    stmt.raw_execute()?;
+    row.insert(checksum_column.to_string(), Value::Text(checksum));
+    Ok(())
+}
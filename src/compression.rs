@@ -0,0 +1,154 @@
+//! Transparent zstd compression of selected columns.
+//!
+//! A column marked with [`ColumnDefinition::with_compressed`] stores its text
+//! and blob values as a BLOB holding a one-byte tag (the original type)
+//! followed by a zstd frame. Values are compressed when written and
+//! decompressed when read through [`SqliteService::execute_crud`] and
+//! [`SqliteService::load`], and compressed by the bulk writers
+//! [`SqliteService::merge`], [`SqliteService::update_values`] and
+//! [`SqliteService::copy_table_to`]; raw SQL sees the stored BLOBs.
+//!
+//! Queries on compressed columns are limited: comparisons, `LIKE`, ordering
+//! and indexes all operate on the compressed bytes, so filter and sort on
+//! other columns instead.
+//!
+//! [`ColumnDefinition::with_compressed`]: crate::sqlite::ColumnDefinition::with_compressed
+
+use crate::sqlite::{CrudOperation, Row, SqliteService, Value};
+use std::collections::HashMap;
+
+/// zstd's default level, a good balance for row-sized values
+const LEVEL: i32 = 3;
+
+const TEXT_TAG: u8 = b't';
+const BLOB_TAG: u8 = b'b';
+
+/// Compress a text or blob value; other values are stored unchanged
+pub(crate) fn compress(value: Value) -> anyhow::Result<Value> {
+    let (tag, bytes) = match &value {
+        Value::Text(text) => (TEXT_TAG, text.as_bytes()),
+        Value::Blob(blob) => (BLOB_TAG, blob.as_slice()),
+        _ => return Ok(value),
+    };
+    let mut stored = vec![tag];
+    stored.extend(zstd::bulk::compress(bytes, LEVEL)?);
+    Ok(Value::Blob(stored))
+}
+
+/// Reverse [`compress`]. Values that are not BLOBs were never compressed and
+/// are returned unchanged.
+pub(crate) fn decompress(value: Value) -> anyhow::Result<Value> {
+    let Value::Blob(stored) = value else {
+        return Ok(value);
+    };
+    let (tag, frame) = match stored.split_first() {
+        Some((&tag, frame)) if tag == TEXT_TAG || tag == BLOB_TAG => (tag, frame),
+        _ => anyhow::bail!("value in a compressed column is not a compressed value"),
+    };
+    let bytes = zstd::stream::decode_all(frame)?;
+    Ok(if tag == TEXT_TAG {
+        Value::Text(String::from_utf8(bytes)?)
+    } else {
+        Value::Blob(bytes)
+    })
+}
+
+impl SqliteService {
+    /// Names of the compressed columns of `table` in the configured schema
+    fn compressed_columns(&self, table: &str) -> Vec<&str> {
+        self.config
+            .schema
+            .tables
+            .iter()
+            .filter(|t| t.name == table)
+            .flat_map(|t| &t.columns)
+            .filter(|c| c.compressed)
+            .map(|c| c.name.as_str())
+            .collect()
+    }
+
+    /// Compress the values written to compressed columns of `table`
+    pub(crate) fn compress_values(
+        &self,
+        table: &str,
+        values: HashMap<String, Value>,
+    ) -> anyhow::Result<HashMap<String, Value>> {
+        let columns = self.compressed_columns(table);
+        values
+            .into_iter()
+            .map(|(column, value)| {
+                let value = if columns.contains(&column.as_str()) {
+                    compress(value)?
+                } else {
+                    value
+                };
+                Ok((column, value))
+            })
+            .collect()
+    }
+
+    /// Compress the values an operation writes to compressed columns
+    pub(crate) fn compress_operation(&self, op: CrudOperation) -> anyhow::Result<CrudOperation> {
+        Ok(match op {
+            CrudOperation::Create(mut create) => {
+                create.data = self.compress_values(&create.table, create.data)?;
+                CrudOperation::Create(create)
+            }
+            CrudOperation::Update(mut update) => {
+                update.updates = self.compress_values(&update.table, update.updates)?;
+                CrudOperation::Update(update)
+            }
+            op => op,
+        })
+    }
+
+    /// Convert the stored `values` of `columns`, read from `table` of this
+    /// service, to the form `other` stores: columns only this service
+    /// compresses are decompressed and columns only `other` compresses are
+    /// compressed
+    pub(crate) fn recompress_for(
+        &self,
+        other: &SqliteService,
+        table: &str,
+        columns: &[String],
+        values: &mut [Value],
+    ) -> anyhow::Result<()> {
+        let source = self.compressed_columns(table);
+        let target = other.compressed_columns(table);
+        for (column, value) in columns.iter().zip(values) {
+            let (compressed, wanted) = (
+                source.contains(&column.as_str()),
+                target.contains(&column.as_str()),
+            );
+            if compressed != wanted {
+                let stored = std::mem::replace(value, Value::Null);
+                *value = if compressed {
+                    decompress(stored)?
+                } else {
+                    compress(stored)?
+                };
+            }
+        }
+        Ok(())
+    }
+
+    /// Decompress the compressed columns of rows read from `table`
+    pub(crate) fn decompress_rows(
+        &self,
+        table: &str,
+        mut rows: Vec<Row>,
+    ) -> anyhow::Result<Vec<Row>> {
+        let columns = self.compressed_columns(table);
+        if columns.is_empty() {
+            return Ok(rows);
+        }
+        for row in &mut rows {
+            for column in &columns {
+                if let Some(value) = row.remove(*column) {
+                    row.insert(column.to_string(), decompress(value)?);
+                }
+            }
+        }
+        Ok(rows)
+    }
+}
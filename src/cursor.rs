@@ -0,0 +1,273 @@
+//! Keyset pagination with opaque cursor tokens.
+//!
+//! [`SqliteService::read_cursor_page`] pages through a read ordered by key
+//! columns. Rather than an offset, each page continues after the key of the
+//! last row of the previous one, so pages stay cheap however deep a client
+//! reads and do not skip or repeat rows when earlier ones are inserted or
+//! deleted meanwhile. The key travels as a token: the key values, without
+//! column names, followed by a checksum, base64 encoded for URLs. Tokens
+//! that were altered or belong to another table or key are rejected with
+//! [`SqliteError::InvalidCursor`]. The checksum is not a signature, so a
+//! client able to craft tokens can start a page at any key, but only within
+//! the read it was given.
+//!
+//! The `sqlite/read_chunk` action serves the same pages to remote callers
+//! as JSON, see [`SqliteService::read_rows_chunk`], so a large table crosses
+//! the node bus a bounded chunk at a time.
+
+use crate::filter::Condition;
+use crate::json::rows_to_json_with;
+use crate::sqlite::{
+    OrderTerm, QueryOperator, ReadOperation, Row, SqliteError, SqliteService, Value,
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+/// Most rows returned by one chunk of the `sqlite/read_chunk` action
+pub const MAX_READ_CHUNK: u32 = 1000;
+
+/// A page of rows read with [`SqliteService::read_cursor_page`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CursorPage {
+    pub rows: Vec<Row>,
+    /// Token for the next page, `None` on the last one
+    pub next_cursor: Option<String>,
+}
+
+/// Request of the `sqlite/read_chunk` action
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadChunkRequest {
+    pub table: String,
+    /// Columns that together identify a row, which chunks are ordered by
+    pub key_columns: Vec<String>,
+    /// Columns to return, all when `None`; must include the key columns
+    pub fields: Option<Vec<String>>,
+    /// Rows per chunk, at most [`MAX_READ_CHUNK`]
+    pub chunk_size: u32,
+    /// `next_cursor` of the previous chunk, `None` for the first
+    pub cursor: Option<String>,
+}
+
+/// A chunk of rows returned by the `sqlite/read_chunk` action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReadChunk {
+    /// The rows as JSON objects, with blobs in the configured encoding
+    pub rows: Vec<serde_json::Value>,
+    /// Cursor of the next chunk, `None` on the last one
+    pub next_cursor: Option<String>,
+}
+
+impl SqliteService {
+    /// Read the chunk of a table that `request` asks for, as served by the
+    /// `sqlite/read_chunk` action. The chunk size is clamped to
+    /// [`MAX_READ_CHUNK`], so no request holds more rows than that at once.
+    /// Fails with [`SqliteError::NoKeyColumns`] when the request has no key
+    /// columns.
+    pub async fn read_rows_chunk(&self, request: &ReadChunkRequest) -> anyhow::Result<ReadChunk> {
+        let mut op = ReadOperation::new(&request.table)
+            .with_limit(request.chunk_size.clamp(1, MAX_READ_CHUNK));
+        if let Some(fields) = &request.fields {
+            let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+            op = op.with_fields(&fields);
+        }
+        let key_columns: Vec<&str> = request.key_columns.iter().map(String::as_str).collect();
+        let page = self
+            .read_cursor_page(op, &key_columns, request.cursor.as_deref())
+            .await?;
+        let serde_json::Value::Array(rows) =
+            rows_to_json_with(&page.rows, self.config.blob_encoding)
+        else {
+            unreachable!("rows_to_json_with returns an array");
+        };
+        Ok(ReadChunk {
+            rows,
+            next_cursor: page.next_cursor,
+        })
+    }
+
+    /// Read the page of `op` following `cursor`, or the first page when it
+    /// is `None`.
+    ///
+    /// Rows are ordered by `key_columns`, ascending; any order of `op` is
+    /// replaced and its offset ignored. The key columns must together be
+    /// unique and never NULL, and selected when `op` lists its fields. The
+    /// page size is the limit of `op`; without one, every remaining row is
+    /// returned on a single page. Fails with [`SqliteError::NoKeyColumns`]
+    /// when `key_columns` is empty.
+    pub async fn read_cursor_page(
+        &self,
+        mut op: ReadOperation,
+        key_columns: &[&str],
+        cursor: Option<&str>,
+    ) -> anyhow::Result<CursorPage> {
+        if key_columns.is_empty() {
+            return Err(SqliteError::NoKeyColumns(op.table).into());
+        }
+        if let Some(cursor) = cursor {
+            let after = decode_cursor(&op.table, key_columns, cursor)?;
+            op.query = op.query.with_filter(after_key(key_columns, after));
+        }
+        op.order_by = Some(
+            key_columns
+                .iter()
+                .map(|c| (OrderTerm::Column(c.to_string()), true))
+                .collect(),
+        );
+        op.offset = None;
+        let table = op.table.clone();
+        let page = self.read_page(op).await?;
+        let next_cursor = match page.rows.last() {
+            Some(last) if page.has_more => {
+                let key = key_columns
+                    .iter()
+                    .map(|column| {
+                        last.get(*column)
+                            .cloned()
+                            .ok_or_else(|| SqliteError::MissingColumn {
+                                table: table.clone(),
+                                column: column.to_string(),
+                            })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Some(encode_cursor(&table, key_columns, &key))
+            }
+            _ => None,
+        };
+        Ok(CursorPage {
+            rows: page.rows,
+            next_cursor,
+        })
+    }
+}
+
+/// Rows whose key sorts after `key`: `(a, b) > (x, y)` spelled as
+/// `a > x OR (a = x AND b > y)`
+fn after_key(key_columns: &[&str], key: Vec<Value>) -> Condition {
+    let mut alternatives = Vec::with_capacity(key.len());
+    for (i, value) in key.iter().enumerate() {
+        let mut terms: Vec<Condition> = key_columns[..i]
+            .iter()
+            .zip(&key)
+            .map(|(column, value)| Condition::field(column, QueryOperator::Equal(value.clone())))
+            .collect();
+        terms.push(Condition::field(
+            key_columns[i],
+            QueryOperator::GreaterThan(value.clone()),
+        ));
+        alternatives.push(Condition::and(terms));
+    }
+    Condition::or(alternatives)
+}
+
+const TAG_NULL: u8 = 0;
+const TAG_INTEGER: u8 = 1;
+const TAG_REAL: u8 = 2;
+const TAG_TEXT: u8 = 3;
+const TAG_BLOB: u8 = 4;
+
+/// Encode `key`, the values of `key_columns` in a row of `table`
+fn encode_cursor(table: &str, key_columns: &[&str], key: &[Value]) -> String {
+    let mut bytes = Vec::new();
+    for value in key {
+        match value {
+            Value::Null => bytes.push(TAG_NULL),
+            Value::Integer(i) => {
+                bytes.push(TAG_INTEGER);
+                bytes.extend_from_slice(&i.to_be_bytes());
+            }
+            Value::Boolean(b) => {
+                bytes.push(TAG_INTEGER);
+                bytes.extend_from_slice(&i64::from(*b).to_be_bytes());
+            }
+            Value::Real(r) => {
+                bytes.push(TAG_REAL);
+                bytes.extend_from_slice(&r.to_bits().to_be_bytes());
+            }
+            Value::Text(text) => {
+                bytes.push(TAG_TEXT);
+                push_sized(&mut bytes, text.as_bytes());
+            }
+            Value::Blob(blob) => {
+                bytes.push(TAG_BLOB);
+                push_sized(&mut bytes, blob);
+            }
+        }
+    }
+    let checksum = checksum(table, key_columns, &bytes);
+    bytes.extend_from_slice(&checksum.to_be_bytes());
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn push_sized(bytes: &mut Vec<u8>, data: &[u8]) {
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(data);
+}
+
+/// Decode a token made by [`encode_cursor`] for the same table and key
+fn decode_cursor(
+    table: &str,
+    key_columns: &[&str],
+    cursor: &str,
+) -> Result<Vec<Value>, SqliteError> {
+    let invalid = || SqliteError::InvalidCursor(cursor.to_string());
+    let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+    let split = bytes.len().checked_sub(8).ok_or_else(invalid)?;
+    let (mut payload, expected) = bytes.split_at(split);
+    if checksum(table, key_columns, payload).to_be_bytes() != expected {
+        return Err(invalid());
+    }
+    let mut take = |n: usize| {
+        if payload.len() < n {
+            return Err(invalid());
+        }
+        let (taken, rest) = payload.split_at(n);
+        payload = rest;
+        Ok(taken)
+    };
+    let mut key = Vec::with_capacity(key_columns.len());
+    for _ in key_columns {
+        let value = match take(1)?[0] {
+            TAG_NULL => Value::Null,
+            TAG_INTEGER => Value::Integer(i64::from_be_bytes(take(8)?.try_into().unwrap())),
+            TAG_REAL => Value::Real(f64::from_bits(u64::from_be_bytes(
+                take(8)?.try_into().unwrap(),
+            ))),
+            tag @ (TAG_TEXT | TAG_BLOB) => {
+                let len = u32::from_be_bytes(take(4)?.try_into().unwrap()) as usize;
+                let data = take(len)?.to_vec();
+                if tag == TAG_BLOB {
+                    Value::Blob(data)
+                } else {
+                    Value::Text(String::from_utf8(data).map_err(|_| invalid())?)
+                }
+            }
+            _ => return Err(invalid()),
+        };
+        key.push(value);
+    }
+    if !payload.is_empty() {
+        return Err(invalid());
+    }
+    Ok(key)
+}
+
+/// FNV-1a over the table, the key columns and the encoded key, so a token
+/// only decodes for the listing it was made for
+fn checksum(table: &str, key_columns: &[&str], payload: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    feed(table.as_bytes());
+    for column in key_columns {
+        feed(&[0]);
+        feed(column.as_bytes());
+    }
+    feed(&[0]);
+    feed(payload);
+    hash
+}
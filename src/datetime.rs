@@ -0,0 +1,88 @@
+//! Bound parameters for relative time windows.
+//!
+//! The crate has no dedicated datetime value: timestamps are TEXT in the
+//! `YYYY-MM-DD HH:MM:SS` UTC form `CURRENT_TIMESTAMP` and `datetime()`
+//! produce, which compares correctly as text. The helpers here compute such
+//! values from a [`Duration`], so "the last 7 days" is a bound parameter
+//! rather than SQL spliced together by hand. Either compare against a point
+//! computed in Rust:
+//!
+//! ```ignore
+//! Query::new().with_condition("created_at", QueryOperator::GreaterThanOrEqual(datetime_ago(week)))
+//! ```
+//!
+//! or let SQLite compute it from a bound modifier:
+//!
+//! ```ignore
+//! SqlQuery::new("SELECT * FROM events WHERE created_at >= datetime('now', :since)")
+//!     .with_params(Params::new().with_value("since", modifier_ago(week)))
+//! ```
+//!
+//! Both round to whole seconds, the precision of the stored text.
+
+use crate::sqlite::Value;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// `time` as `YYYY-MM-DD HH:MM:SS` in UTC
+pub fn format_datetime(time: SystemTime) -> String {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs_f64().ceil() as i64),
+    };
+    let (days, of_day) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        of_day / 3600,
+        of_day % 3600 / 60,
+        of_day % 60
+    )
+}
+
+/// The datetime `duration` before now, as TEXT
+pub fn datetime_ago(duration: Duration) -> Value {
+    let now = SystemTime::now();
+    Value::Text(format_datetime(
+        now.checked_sub(duration).unwrap_or(UNIX_EPOCH),
+    ))
+}
+
+/// The datetime `duration` after now, as TEXT
+pub fn datetime_from_now(duration: Duration) -> Value {
+    let now = SystemTime::now();
+    Value::Text(format_datetime(now.checked_add(duration).unwrap_or(now)))
+}
+
+/// A `datetime()` modifier moving back by `duration`, e.g. `-604800 seconds`
+/// for a week, to bind as the second argument of `datetime('now', ?)`
+pub fn modifier_ago(duration: Duration) -> Value {
+    Value::Text(format!("-{} seconds", duration.as_secs()))
+}
+
+/// A `datetime()` modifier moving forward by `duration`
+pub fn modifier_from_now(duration: Duration) -> Value {
+    Value::Text(format!("+{} seconds", duration.as_secs()))
+}
+
+/// Year, month and day of the date `days` after 1970-01-01, in the
+/// proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
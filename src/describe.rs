@@ -0,0 +1,325 @@
+//! Schema and result introspection for client tooling.
+
+use crate::sqlite::{quote_ident, run_sql, Row, SqlQuery, SqliteService};
+use crate::transaction_log::BoundParams;
+use rusqlite::{ffi, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+
+/// The tables of a database as reported by SQLite itself
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaDescription {
+    pub tables: Vec<TableDescription>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableDescription {
+    pub name: String,
+    pub columns: Vec<ColumnDescription>,
+    pub indexes: Vec<IndexDescription>,
+    pub foreign_keys: Vec<ForeignKeyDescription>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnDescription {
+    pub name: String,
+    /// Declared type, as written in the `CREATE TABLE` statement
+    pub data_type: String,
+    pub nullable: bool,
+    /// Default value as an SQL expression, e.g. `'untitled'` or `CURRENT_TIMESTAMP`
+    pub default: Option<String>,
+    pub primary_key: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexDescription {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub unique: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForeignKeyDescription {
+    pub columns: Vec<String>,
+    pub foreign_table: String,
+    pub foreign_columns: Vec<String>,
+    pub on_delete: String,
+    pub on_update: String,
+}
+
+/// One column of a query result
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResultColumn {
+    pub name: String,
+    /// Declared type of the table column the values come from, `None` for
+    /// expressions
+    pub declared_type: Option<String>,
+    /// Table and column the values come from, `None` for expressions
+    pub table: Option<String>,
+    pub column: Option<String>,
+    /// Whether the source column accepts NULL, `None` for expressions. An
+    /// outer join can still produce NULL from a `NOT NULL` column.
+    pub nullable: Option<bool>,
+}
+
+/// Rows of a query together with a description of its columns
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryResult {
+    pub columns: Vec<ResultColumn>,
+    pub rows: Vec<Row>,
+}
+
+impl SqliteService {
+    /// Like [`SqliteService::execute_sql`], also describing each result
+    /// column, so generic clients can render values by type even when no
+    /// row is returned. Always runs on the writer connection.
+    pub async fn execute_sql_with_metadata(&self, query: SqlQuery) -> anyhow::Result<QueryResult> {
+        self.config.check_statement_allowed(&query.statement)?;
+        self.with_connection(|conn| {
+            let columns = result_columns(conn, &query.statement)?;
+            let rows = self.observe_query(
+                conn,
+                &query.statement,
+                BoundParams::Named(&query.params),
+                || run_sql(conn, &query, self.config.max_result_bytes),
+            )?;
+            Ok(QueryResult { columns, rows })
+        })
+        .await
+    }
+
+    /// Describe every user table in the database.
+    ///
+    /// Reads the live database rather than the configured [`Schema`], so
+    /// tables created by migrations are included. SQLite's own tables and
+    /// the service's bookkeeping tables (`_runar_*`) are left out, as are
+    /// the automatic indexes SQLite creates for `UNIQUE` and `PRIMARY KEY`
+    /// constraints.
+    ///
+    /// [`Schema`]: crate::sqlite::Schema
+    pub async fn describe_schema(&self) -> anyhow::Result<SchemaDescription> {
+        self.with_connection(|conn| {
+            let tables = user_tables(conn)?
+                .into_iter()
+                .map(|name| describe_table(conn, name))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            Ok(SchemaDescription { tables })
+        })
+        .await
+    }
+
+    /// The number of rows in each table, keyed by table name, covering the
+    /// same tables as [`SqliteService::describe_schema`].
+    ///
+    /// With `exact` every table is counted with `COUNT(*)`, which reads the
+    /// whole table (or its smallest index). Otherwise the row estimates
+    /// recorded by the last `ANALYZE` (or `PRAGMA optimize`) are used where
+    /// available, which is instant but may be stale; tables without
+    /// statistics are still counted exactly.
+    pub async fn table_counts(&self, exact: bool) -> anyhow::Result<HashMap<String, u64>> {
+        self.with_connection(|conn| {
+            let mut counts = if exact {
+                HashMap::new()
+            } else {
+                estimated_counts(conn)?
+            };
+            for table in user_tables(conn)? {
+                if counts.contains_key(&table) {
+                    continue;
+                }
+                let count: u64 = conn.query_row(
+                    &format!("SELECT COUNT(*) FROM {}", quote_ident(&table)),
+                    [],
+                    |row| row.get(0),
+                )?;
+                counts.insert(table, count);
+            }
+            Ok(counts)
+        })
+        .await
+    }
+}
+
+/// Names of the tables defined by the application
+fn user_tables(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type = 'table' \
+         AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' \
+         AND name NOT LIKE '\\_runar\\_%' ESCAPE '\\' ORDER BY name",
+    )?;
+    let names = stmt.query_map([], |row| row.get::<_, String>(0))?.collect();
+    names
+}
+
+/// Row counts recorded in `sqlite_stat1`, by user table as listed by
+/// [`user_tables`]. The first number of a `stat` entry is the row count of
+/// the table or index it describes.
+fn estimated_counts(conn: &Connection) -> rusqlite::Result<HashMap<String, u64>> {
+    let analyzed: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'sqlite_stat1')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !analyzed {
+        return Ok(HashMap::new());
+    }
+    let mut stmt = conn.prepare(
+        "SELECT tbl, MAX(CAST(stat AS INTEGER)) FROM sqlite_stat1 \
+         WHERE tbl NOT LIKE 'sqlite\\_%' ESCAPE '\\' \
+         AND tbl NOT LIKE '\\_runar\\_%' ESCAPE '\\' GROUP BY tbl",
+    )?;
+    let counts = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect();
+    counts
+}
+
+fn describe_table(conn: &Connection, name: String) -> anyhow::Result<TableDescription> {
+    let table = quote_ident(&name);
+
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns = stmt
+        .query_map([], |row| {
+            Ok(ColumnDescription {
+                name: row.get("name")?,
+                data_type: row.get("type")?,
+                nullable: !row.get::<_, bool>("notnull")?,
+                default: row.get("dflt_value")?,
+                primary_key: row.get::<_, i64>("pk")? > 0,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut stmt = conn.prepare(&format!("PRAGMA index_list({})", table))?;
+    let index_list = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>("name")?,
+                row.get::<_, bool>("unique")?,
+                row.get::<_, String>("origin")?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut indexes = Vec::new();
+    for (index_name, unique, origin) in index_list {
+        if origin != "c" {
+            continue;
+        }
+        let mut stmt = conn.prepare(&format!("PRAGMA index_info({})", quote_ident(&index_name)))?;
+        let columns = stmt
+            .query_map([], |row| row.get::<_, Option<String>>("name"))?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            // Expression index terms have no column name
+            .map(|c| c.unwrap_or_default())
+            .collect();
+        indexes.push(IndexDescription {
+            name: index_name,
+            columns,
+            unique,
+        });
+    }
+    indexes.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // One row per column; multi-column keys share an id
+    let mut stmt = conn.prepare(&format!("PRAGMA foreign_key_list({})", table))?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>("id")?,
+                row.get::<_, String>("table")?,
+                row.get::<_, String>("from")?,
+                row.get::<_, Option<String>>("to")?,
+                row.get::<_, String>("on_delete")?,
+                row.get::<_, String>("on_update")?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut foreign_keys: Vec<(i64, ForeignKeyDescription)> = Vec::new();
+    for (id, foreign_table, from, to, on_delete, on_update) in rows {
+        match foreign_keys.last_mut() {
+            Some((last, fk)) if *last == id => {
+                fk.columns.push(from);
+                fk.foreign_columns.push(to.unwrap_or_default());
+            }
+            _ => foreign_keys.push((
+                id,
+                ForeignKeyDescription {
+                    columns: vec![from],
+                    foreign_table,
+                    foreign_columns: vec![to.unwrap_or_default()],
+                    on_delete,
+                    on_update,
+                },
+            )),
+        }
+    }
+
+    Ok(TableDescription {
+        name,
+        columns,
+        indexes,
+        foreign_keys: foreign_keys.into_iter().map(|(_, fk)| fk).collect(),
+    })
+}
+
+/// Describe the result columns of the first statement of `sql`.
+///
+/// rusqlite does not expose where a column comes from, so the statement is
+/// prepared again through the C API, which the bundled SQLite is built to
+/// support (`SQLITE_ENABLE_COLUMN_METADATA`).
+fn result_columns(conn: &Connection, sql: &str) -> anyhow::Result<Vec<ResultColumn>> {
+    // Reports errors in the SQL the same way as running it would
+    conn.prepare_cached(sql)?;
+    let sql = CString::new(sql)?;
+    // SAFETY: the connection is borrowed for the whole call, every string is
+    // copied out before the statement is finalized, and a null statement
+    // (for SQL without a statement) is accepted by every function used
+    unsafe {
+        let db = conn.handle();
+        let mut stmt = ptr::null_mut();
+        if ffi::sqlite3_prepare_v2(db, sql.as_ptr(), -1, &mut stmt, ptr::null_mut())
+            != ffi::SQLITE_OK
+        {
+            anyhow::bail!("failed to prepare {:?}", sql);
+        }
+        let columns = (0..ffi::sqlite3_column_count(stmt))
+            .map(|i| {
+                let database = ffi::sqlite3_column_database_name(stmt, i);
+                let table = ffi::sqlite3_column_table_name(stmt, i);
+                let column = ffi::sqlite3_column_origin_name(stmt, i);
+                let mut not_null = 0;
+                let nullable = (!table.is_null()
+                    && !column.is_null()
+                    && ffi::sqlite3_table_column_metadata(
+                        db,
+                        database,
+                        table,
+                        column,
+                        ptr::null_mut(),
+                        ptr::null_mut(),
+                        &mut not_null,
+                        ptr::null_mut(),
+                        ptr::null_mut(),
+                    ) == ffi::SQLITE_OK)
+                    .then_some(not_null == 0);
+                ResultColumn {
+                    name: text(ffi::sqlite3_column_name(stmt, i)).unwrap_or_default(),
+                    declared_type: text(ffi::sqlite3_column_decltype(stmt, i)),
+                    table: text(table),
+                    column: text(column),
+                    nullable,
+                }
+            })
+            .collect();
+        ffi::sqlite3_finalize(stmt);
+        Ok(columns)
+    }
+}
+
+/// Copy a string returned by SQLite, which may be null
+unsafe fn text(ptr: *const c_char) -> Option<String> {
+    (!ptr.is_null()).then(|| CStr::from_ptr(ptr).to_string_lossy().into_owned())
+}
@@ -0,0 +1,90 @@
+//! Append-only event streams for auditing and event sourcing.
+
+use crate::sqlite::{create_on_missing, SqliteService, Value};
+use rusqlite::{params, Connection};
+
+/// Table holding the events of every stream
+pub const EVENTS_TABLE: &str = "_runar_events";
+
+/// An event read back with [`SqliteService::read_events`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// Position of the event. Sequence numbers are shared by all streams, so
+    /// they increase within a stream but are not contiguous.
+    pub seq: i64,
+    pub stream: String,
+    pub payload: Value,
+}
+
+/// Create the events table on first use. Triggers reject every `UPDATE` and
+/// `DELETE`, including ones issued through raw SQL.
+fn ensure_events_table(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {table} (
+            seq INTEGER PRIMARY KEY AUTOINCREMENT,
+            stream TEXT NOT NULL,
+            payload
+        );
+        CREATE INDEX IF NOT EXISTS {table}_stream ON {table} (stream, seq);
+        CREATE TRIGGER IF NOT EXISTS {table}_no_update BEFORE UPDATE ON {table}
+        BEGIN SELECT RAISE(ABORT, 'events are append-only'); END;
+        CREATE TRIGGER IF NOT EXISTS {table}_no_delete BEFORE DELETE ON {table}
+        BEGIN SELECT RAISE(ABORT, 'events are append-only'); END;",
+        table = EVENTS_TABLE
+    ))
+}
+
+impl SqliteService {
+    /// Append an event to `stream` and return its sequence number
+    pub async fn append_event(
+        &self,
+        stream: &str,
+        payload: impl Into<Value>,
+    ) -> anyhow::Result<i64> {
+        let payload = payload.into();
+        self.with_connection(|conn| {
+            create_on_missing(conn, ensure_events_table, |conn| {
+                let seq = conn.query_row(
+                    &format!(
+                        "INSERT INTO {} (stream, payload) VALUES (?1, ?2) RETURNING seq",
+                        EVENTS_TABLE
+                    ),
+                    params![stream, payload],
+                    |row| row.get(0),
+                )?;
+                Ok(seq)
+            })
+        })
+        .await
+    }
+
+    /// Up to `limit` events of `stream` with a sequence number greater than
+    /// `after_seq`, oldest first. Pass `0` to read from the start, then the
+    /// `seq` of the last event received to continue.
+    pub async fn read_events(
+        &self,
+        stream: &str,
+        after_seq: i64,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Event>> {
+        self.with_connection(|conn| {
+            create_on_missing(conn, ensure_events_table, |conn| {
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT seq, stream, payload FROM {} WHERE stream = ?1 AND seq > ?2 ORDER BY seq LIMIT ?3",
+                    EVENTS_TABLE
+                ))?;
+                let events = stmt
+                    .query_map(params![stream, after_seq, limit as i64], |row| {
+                        Ok(Event {
+                            seq: row.get(0)?,
+                            stream: row.get(1)?,
+                            payload: Value::from(row.get_ref(2)?),
+                        })
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(events)
+            })
+        })
+        .await
+    }
+}
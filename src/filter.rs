@@ -0,0 +1,186 @@
+//! Composable filter conditions and the [`query!`](crate::query) macro.
+
+use crate::sqlite::{QueryOperator, Value};
+use std::ops::Not;
+
+/// A boolean filter over the columns of a table
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Field { field: String, op: QueryOperator },
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+    Not(Box<Condition>),
+}
+
+impl Condition {
+    pub fn field(field: &str, op: QueryOperator) -> Self {
+        Condition::Field {
+            field: field.to_string(),
+            op,
+        }
+    }
+
+    /// All of `conditions`; a single condition is returned unwrapped
+    pub fn and(mut conditions: Vec<Condition>) -> Self {
+        if conditions.len() == 1 {
+            return conditions.remove(0);
+        }
+        Condition::And(conditions)
+    }
+
+    /// Any of `conditions`; a single condition is returned unwrapped
+    pub fn or(mut conditions: Vec<Condition>) -> Self {
+        if conditions.len() == 1 {
+            return conditions.remove(0);
+        }
+        Condition::Or(conditions)
+    }
+
+    /// Render the condition with positional `?` parameters. An empty `And`
+    /// is always true and an empty `Or` always false.
+    pub fn to_sql(&self) -> (String, Vec<Value>) {
+        match self {
+            Condition::Field { field, op } => op.to_sql(field),
+            Condition::And(conditions) if conditions.is_empty() => ("1".to_string(), Vec::new()),
+            Condition::Or(conditions) if conditions.is_empty() => ("0".to_string(), Vec::new()),
+            Condition::And(conditions) => join(conditions, " AND "),
+            Condition::Or(conditions) => join(conditions, " OR "),
+            Condition::Not(condition) => {
+                let (sql, params) = condition.to_sql();
+                (format!("NOT ({})", sql), params)
+            }
+        }
+    }
+
+    /// Like [`Condition::to_sql`], parenthesized when combined with other
+    /// operands would change its meaning
+    pub(crate) fn operand_sql(&self) -> (String, Vec<Value>) {
+        let (sql, params) = self.to_sql();
+        match self {
+            Condition::And(c) | Condition::Or(c) if c.len() > 1 => (format!("({})", sql), params),
+            _ => (sql, params),
+        }
+    }
+}
+
+impl Not for Condition {
+    type Output = Condition;
+
+    fn not(self) -> Condition {
+        Condition::Not(Box::new(self))
+    }
+}
+
+fn join(conditions: &[Condition], separator: &str) -> (String, Vec<Value>) {
+    let mut clauses = Vec::with_capacity(conditions.len());
+    let mut params = Vec::new();
+    for condition in conditions {
+        let (sql, mut values) = condition.operand_sql();
+        clauses.push(sql);
+        params.append(&mut values);
+    }
+    (clauses.join(separator), params)
+}
+
+/// Build a [`Condition`] from near-SQL syntax.
+///
+/// ```ignore
+/// let min_age = 18;
+/// let filter = query!(age >= min_age && (country == "US" || country == "CA"));
+/// ```
+///
+/// Field names are bare identifiers. Supported comparisons are `==`, `!=`,
+/// `>`, `>=`, `<` and `<=`, plus `field like "pattern"` and
+/// `field in [a, b, c]`. Comparisons combine with `&&` and `||` (`&&` binds
+/// tighter), `!` negates a comparison or parenthesized group. Values are
+/// arbitrary Rust expressions converted with `Value::from` and always bound
+/// as parameters, never spliced into the SQL.
+#[macro_export]
+macro_rules! query {
+    ($($tokens:tt)+) => {
+        $crate::__query_or!([] [] $($tokens)+)
+    };
+}
+
+/// Split a `query!` expression on top-level `||`
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __query_or {
+    ([$($done:tt)*] [$($current:tt)+] || $($rest:tt)+) => {
+        $crate::__query_or!([$($done)* [$($current)+]] [] $($rest)+)
+    };
+    ([$($done:tt)*] [$($current:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__query_or!([$($done)*] [$($current)* $next] $($rest)*)
+    };
+    ([$([$($done:tt)+])*] [$($current:tt)+]) => {
+        $crate::filter::Condition::or(vec![
+            $($crate::__query_and!([] [] $($done)+),)*
+            $crate::__query_and!([] [] $($current)+)
+        ])
+    };
+}
+
+/// Split one `||` operand on top-level `&&`
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __query_and {
+    ([$($done:tt)*] [$($current:tt)+] && $($rest:tt)+) => {
+        $crate::__query_and!([$($done)* [$($current)+]] [] $($rest)+)
+    };
+    ([$($done:tt)*] [$($current:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__query_and!([$($done)*] [$($current)* $next] $($rest)*)
+    };
+    ([$([$($done:tt)+])*] [$($current:tt)+]) => {
+        $crate::filter::Condition::and(vec![
+            $($crate::__query_atom!($($done)+),)*
+            $crate::__query_atom!($($current)+)
+        ])
+    };
+}
+
+/// A single comparison, negation or parenthesized group
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __query_atom {
+    (($($inner:tt)+)) => {
+        $crate::query!($($inner)+)
+    };
+    (! $($rest:tt)+) => {
+        !$crate::__query_atom!($($rest)+)
+    };
+    ($field:ident in [$($value:expr),* $(,)?]) => {
+        $crate::__query_field!($field, In(vec![$($crate::sqlite::Value::from($value)),*]))
+    };
+    ($field:ident like $($value:tt)+) => {
+        $crate::__query_field!($field, Like(::std::string::String::from($($value)+)))
+    };
+    ($field:ident == $($value:tt)+) => {
+        $crate::__query_field!($field, Equal($crate::sqlite::Value::from($($value)+)))
+    };
+    ($field:ident != $($value:tt)+) => {
+        $crate::__query_field!($field, NotEqual($crate::sqlite::Value::from($($value)+)))
+    };
+    ($field:ident >= $($value:tt)+) => {
+        $crate::__query_field!($field, GreaterThanOrEqual($crate::sqlite::Value::from($($value)+)))
+    };
+    ($field:ident <= $($value:tt)+) => {
+        $crate::__query_field!($field, LessThanOrEqual($crate::sqlite::Value::from($($value)+)))
+    };
+    ($field:ident > $($value:tt)+) => {
+        $crate::__query_field!($field, GreaterThan($crate::sqlite::Value::from($($value)+)))
+    };
+    ($field:ident < $($value:tt)+) => {
+        $crate::__query_field!($field, LessThan($crate::sqlite::Value::from($($value)+)))
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __query_field {
+    ($field:ident, $op:ident($value:expr)) => {
+        $crate::filter::Condition::field(
+            stringify!($field),
+            $crate::sqlite::QueryOperator::$op($value),
+        )
+    };
+}
@@ -0,0 +1,67 @@
+//! Corruption and consistency checks.
+
+use crate::sqlite::SqliteService;
+use serde::{Deserialize, Serialize};
+
+/// A row reported by `PRAGMA foreign_key_check`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForeignKeyViolation {
+    /// Table containing the offending row
+    pub table: String,
+    /// Rowid of the offending row; `None` for `WITHOUT ROWID` tables
+    pub rowid: Option<i64>,
+    /// Table the foreign key refers to
+    pub parent: String,
+    /// Index of the violated constraint in `PRAGMA foreign_key_list(table)`
+    pub fk_index: i64,
+}
+
+/// Result of [`SqliteService::integrity_check`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    /// Messages from `PRAGMA integrity_check`; a healthy database reports `["ok"]`
+    pub integrity: Vec<String>,
+    pub foreign_key_violations: Vec<ForeignKeyViolation>,
+}
+
+impl IntegrityReport {
+    /// Whether neither check found a problem
+    pub fn is_ok(&self) -> bool {
+        self.integrity == ["ok"] && self.foreign_key_violations.is_empty()
+    }
+}
+
+impl SqliteService {
+    /// Run `PRAGMA integrity_check` and `PRAGMA foreign_key_check`.
+    ///
+    /// Problems are reported in the returned value rather than as an error;
+    /// an error means the checks themselves could not run. Runs on the
+    /// service connection, so violations still pending in an open
+    /// transaction with deferred foreign keys are included.
+    pub async fn integrity_check(&self) -> anyhow::Result<IntegrityReport> {
+        self.with_connection(|conn| {
+            let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+            let integrity = stmt
+                .query_map([], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+
+            let mut stmt = conn.prepare("PRAGMA foreign_key_check")?;
+            let foreign_key_violations = stmt
+                .query_map([], |row| {
+                    Ok(ForeignKeyViolation {
+                        table: row.get(0)?,
+                        rowid: row.get(1)?,
+                        parent: row.get(2)?,
+                        fk_index: row.get(3)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(IntegrityReport {
+                integrity,
+                foreign_key_violations,
+            })
+        })
+        .await
+    }
+}
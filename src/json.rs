@@ -0,0 +1,248 @@
+//! Query results as JSON, for services that answer HTTP-style requests, and
+//! as typed structs deserialized through that JSON form.
+//!
+//! JSON has no bytes type, so blobs are written as strings in a
+//! [`BlobEncoding`]: base64 unless [`SqliteConfig::with_blob_encoding`]
+//! picks another for [`SqliteService::query_json`]. [`row_from_json`] reads
+//! rows back, decoding the blob columns with the same encoding.
+//!
+//! [`SqliteConfig::with_blob_encoding`]: crate::sqlite::SqliteConfig::with_blob_encoding
+
+use crate::sqlite::{
+    CrudOperation, ReadOperation, Row, SqlQuery, SqliteError, SqliteService, Value,
+};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// How blobs are written as JSON strings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlobEncoding {
+    /// Standard base64, padded
+    #[default]
+    Base64,
+    /// URL-safe base64, unpadded
+    Base64Url,
+    /// Lowercase hexadecimal
+    Hex,
+}
+
+impl BlobEncoding {
+    pub fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            BlobEncoding::Base64 => STANDARD.encode(bytes),
+            BlobEncoding::Base64Url => URL_SAFE_NO_PAD.encode(bytes),
+            BlobEncoding::Hex => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+        }
+    }
+
+    /// Decode a string made by [`BlobEncoding::encode`]; hexadecimal is
+    /// accepted in either case
+    pub fn decode(self, text: &str) -> anyhow::Result<Vec<u8>> {
+        Ok(match self {
+            BlobEncoding::Base64 => STANDARD.decode(text)?,
+            BlobEncoding::Base64Url => URL_SAFE_NO_PAD.decode(text)?,
+            BlobEncoding::Hex => {
+                if !text.len().is_multiple_of(2) {
+                    anyhow::bail!("odd number of hexadecimal digits");
+                }
+                (0..text.len())
+                    .step_by(2)
+                    .map(|i| {
+                        text.get(i..i + 2)
+                            .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                            .ok_or_else(|| anyhow::anyhow!("invalid hexadecimal at {}", i))
+                    })
+                    .collect::<anyhow::Result<_>>()?
+            }
+        })
+    }
+}
+
+/// `value` as JSON: text, numbers and booleans map to the matching JSON
+/// type, blobs to a string in `encoding`. Non-finite reals have no JSON form
+/// and become null.
+pub fn value_to_json(value: &Value, encoding: BlobEncoding) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Integer(i) => (*i).into(),
+        Value::Real(f) => serde_json::Number::from_f64(*f)
+            .map_or(serde_json::Value::Null, serde_json::Value::Number),
+        Value::Text(s) => s.clone().into(),
+        Value::Blob(bytes) => encoding.encode(bytes).into(),
+        Value::Boolean(b) => (*b).into(),
+    }
+}
+
+impl From<&Value> for serde_json::Value {
+    /// See [`value_to_json`]; blobs become base64 strings
+    fn from(value: &Value) -> Self {
+        value_to_json(value, BlobEncoding::Base64)
+    }
+}
+
+/// `row` as a JSON object keyed by column
+pub fn row_to_json(row: &Row) -> serde_json::Value {
+    row_to_json_with(row, BlobEncoding::Base64)
+}
+
+/// `rows` as a JSON array with one object per row
+pub fn rows_to_json(rows: &[Row]) -> serde_json::Value {
+    rows_to_json_with(rows, BlobEncoding::Base64)
+}
+
+/// Like [`row_to_json`], with blobs in `encoding`
+pub fn row_to_json_with(row: &Row, encoding: BlobEncoding) -> serde_json::Value {
+    row.iter()
+        .map(|(column, value)| (column.clone(), value_to_json(value, encoding)))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Like [`rows_to_json`], with blobs in `encoding`
+pub fn rows_to_json_with(rows: &[Row], encoding: BlobEncoding) -> serde_json::Value {
+    rows.iter()
+        .map(|row| row_to_json_with(row, encoding))
+        .collect::<Vec<serde_json::Value>>()
+        .into()
+}
+
+/// Read a JSON object made by [`row_to_json_with`] back into a row. The
+/// strings of `blob_columns` are decoded with `encoding`, failing with
+/// [`SqliteError::InvalidBlob`]; arrays and objects are kept as JSON text.
+pub fn row_from_json(
+    json: &serde_json::Value,
+    blob_columns: &[&str],
+    encoding: BlobEncoding,
+) -> anyhow::Result<Row> {
+    let serde_json::Value::Object(object) = json else {
+        return Err(SqliteError::RowMapping {
+            type_name: "Row".to_string(),
+            reason: "expected a JSON object".to_string(),
+        }
+        .into());
+    };
+    object
+        .iter()
+        .map(|(column, json)| {
+            let invalid_blob = |reason: String| SqliteError::InvalidBlob {
+                column: column.clone(),
+                reason,
+            };
+            let value = match json {
+                serde_json::Value::Null => Value::Null,
+                serde_json::Value::String(text) if blob_columns.contains(&column.as_str()) => {
+                    Value::Blob(
+                        encoding
+                            .decode(text)
+                            .map_err(|e| invalid_blob(e.to_string()))?,
+                    )
+                }
+                _ if blob_columns.contains(&column.as_str()) => {
+                    return Err(invalid_blob(format!("value is not a string: {}", json)).into())
+                }
+                serde_json::Value::Bool(b) => Value::Boolean(*b),
+                serde_json::Value::Number(n) => match n.as_i64() {
+                    Some(i) => Value::Integer(i),
+                    None => Value::Real(n.as_f64().unwrap_or(f64::NAN)),
+                },
+                serde_json::Value::String(text) => Value::Text(text.clone()),
+                other => Value::Text(other.to_string()),
+            };
+            Ok((column.clone(), value))
+        })
+        .collect()
+}
+
+/// Deserialize each row into a `T` whose fields are named after columns.
+///
+/// Rows go through their JSON form, see [`row_to_json`]: booleans are read
+/// back as the integers SQLite stores, so declare such fields as integers,
+/// and blobs as base64 strings. Integer columns fill float fields, and NULL
+/// columns `Option` fields. Fails with [`SqliteError::RowMapping`].
+pub fn rows_as<T: DeserializeOwned>(rows: &[Row]) -> anyhow::Result<Vec<T>> {
+    rows.iter()
+        .map(|row| {
+            serde_json::from_value(row_to_json(row)).map_err(|e| {
+                SqliteError::RowMapping {
+                    type_name: std::any::type_name::<T>().to_string(),
+                    reason: e.to_string(),
+                }
+                .into()
+            })
+        })
+        .collect()
+}
+
+/// Like [`rows_as`], but fields of `T` without a column in the row take
+/// their value from `T::default()`, so a projection of some columns maps
+/// into the full struct. Columns without a field are ignored either way.
+pub fn rows_as_with_defaults<T: DeserializeOwned + Serialize + Default>(
+    rows: &[Row],
+) -> anyhow::Result<Vec<T>> {
+    let mapping_error = |e: serde_json::Error| SqliteError::RowMapping {
+        type_name: std::any::type_name::<T>().to_string(),
+        reason: e.to_string(),
+    };
+    let serde_json::Value::Object(defaults) =
+        serde_json::to_value(T::default()).map_err(mapping_error)?
+    else {
+        return Err(mapping_error(serde::ser::Error::custom(
+            "default value does not serialize to an object",
+        ))
+        .into());
+    };
+    rows.iter()
+        .map(|row| {
+            let mut object = defaults.clone();
+            for (column, value) in row {
+                object.insert(column.clone(), value.into());
+            }
+            serde_json::from_value(object.into()).map_err(|e| mapping_error(e).into())
+        })
+        .collect()
+}
+
+impl SqliteService {
+    /// Run `query` like [`SqliteService::execute_sql`] and return the rows as
+    /// a JSON array of objects, with blobs in the configured encoding, see
+    /// [`rows_to_json_with`]
+    pub async fn query_json(&self, query: SqlQuery) -> anyhow::Result<serde_json::Value> {
+        Ok(rows_to_json_with(
+            &self.execute_sql(query).await?,
+            self.config.blob_encoding,
+        ))
+    }
+
+    /// Run `query` like [`SqliteService::execute_sql`] and deserialize each
+    /// row into a `T`, see [`rows_as`]
+    pub async fn query_as<T: DeserializeOwned>(&self, query: SqlQuery) -> anyhow::Result<Vec<T>> {
+        rows_as(&self.execute_sql(query).await?)
+    }
+
+    /// Run a read like [`SqliteService::execute_crud`] and deserialize each
+    /// row into a `T`, see [`rows_as`]. With aggregates and no fields, the
+    /// single row of totals maps into a struct with a field per alias.
+    pub async fn read_as<T: DeserializeOwned>(&self, op: ReadOperation) -> anyhow::Result<Vec<T>> {
+        rows_as(&self.execute_crud(CrudOperation::Read(op)).await?)
+    }
+
+    /// Like [`SqliteService::query_as`], but fields the query does not
+    /// select are filled from `T::default()`, see [`rows_as_with_defaults`]
+    pub async fn query_as_with_defaults<T: DeserializeOwned + Serialize + Default>(
+        &self,
+        query: SqlQuery,
+    ) -> anyhow::Result<Vec<T>> {
+        rows_as_with_defaults(&self.execute_sql(query).await?)
+    }
+
+    /// Like [`SqliteService::read_as`], but fields the read does not select
+    /// are filled from `T::default()`, see [`rows_as_with_defaults`]
+    pub async fn read_as_with_defaults<T: DeserializeOwned + Serialize + Default>(
+        &self,
+        op: ReadOperation,
+    ) -> anyhow::Result<Vec<T>> {
+        rows_as_with_defaults(&self.execute_crud(CrudOperation::Read(op)).await?)
+    }
+}
@@ -0,0 +1,188 @@
+//! A key-value store over a table of the service's database.
+//!
+//! [`SqliteService::kv_store`] returns a [`KvStore`] keeping text keys and
+//! byte values in a table of its own, created on first use:
+//!
+//! ```sql
+//! CREATE TABLE kv (key TEXT PRIMARY KEY, value BLOB NOT NULL, expires_at INTEGER)
+//! ```
+//!
+//! Entries written with [`KvStore::set_with_ttl`] expire: `expires_at` holds
+//! the Unix time in milliseconds after which reads no longer see them. The
+//! rows stay until swept with [`KvStore::sweep_expired`], or periodically by
+//! a sweeper started with [`KvStore::spawn_sweeper`].
+
+use crate::sqlite::{create_on_missing, quote_ident, SqliteService};
+use crate::timer;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Key-value access to one table, see [`SqliteService::kv_store`]
+#[derive(Clone)]
+pub struct KvStore {
+    service: SqliteService,
+    table: String,
+}
+
+impl SqliteService {
+    /// A key-value store kept in `table`, created if missing
+    pub fn kv_store(&self, table: &str) -> KvStore {
+        KvStore {
+            service: self.clone(),
+            table: table.to_string(),
+        }
+    }
+}
+
+/// The current Unix time in milliseconds
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+impl KvStore {
+    /// The table holding the entries
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// Run `f` with the quoted table name, creating the table first if `f`
+    /// finds it missing
+    async fn with_table<T>(
+        &self,
+        mut f: impl FnMut(&Connection, &str) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let table = quote_ident(&self.table);
+        self.service
+            .with_connection(|conn| {
+                let create = |conn: &Connection| {
+                    conn.execute_batch(&format!(
+                        "CREATE TABLE IF NOT EXISTS {} (
+                            key TEXT PRIMARY KEY,
+                            value BLOB NOT NULL,
+                            expires_at INTEGER
+                        )",
+                        table
+                    ))
+                };
+                create_on_missing(conn, create, |conn| f(conn, &table))
+            })
+            .await
+    }
+
+    /// The value stored under `key`, unless it is missing or expired
+    pub async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        self.with_table(|conn, table| {
+            Ok(conn
+                .prepare_cached(&format!(
+                    "SELECT value FROM {} WHERE key = ?1 \
+                     AND (expires_at IS NULL OR expires_at > ?2)",
+                    table
+                ))?
+                .query_row(params![key, now_millis()], |row| row.get(0))
+                .optional()?)
+        })
+        .await
+    }
+
+    /// Store `value` under `key`, replacing any previous value and expiry
+    pub async fn set(&self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        self.put(key, value, None).await
+    }
+
+    /// Store `value` under `key` for `ttl`, after which reads no longer see it
+    pub async fn set_with_ttl(&self, key: &str, value: &[u8], ttl: Duration) -> anyhow::Result<()> {
+        let expires_at =
+            now_millis().saturating_add(i64::try_from(ttl.as_millis()).unwrap_or(i64::MAX));
+        self.put(key, value, Some(expires_at)).await
+    }
+
+    async fn put(&self, key: &str, value: &[u8], expires_at: Option<i64>) -> anyhow::Result<()> {
+        self.with_table(|conn, table| {
+            conn.prepare_cached(&format!(
+                "INSERT INTO {} (key, value, expires_at) VALUES (?1, ?2, ?3) \
+                 ON CONFLICT (key) DO UPDATE SET \
+                 value = excluded.value, expires_at = excluded.expires_at",
+                table
+            ))?
+            .execute(params![key, value, expires_at])?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Remove `key`, returning whether a live entry was stored under it
+    pub async fn delete(&self, key: &str) -> anyhow::Result<bool> {
+        self.with_table(|conn, table| {
+            let deleted = conn
+                .prepare_cached(&format!(
+                    "DELETE FROM {} WHERE key = ?1 \
+                     AND (expires_at IS NULL OR expires_at > ?2)",
+                    table
+                ))?
+                .execute(params![key, now_millis()])?;
+            Ok(deleted > 0)
+        })
+        .await
+    }
+
+    /// The live entries whose key starts with `prefix`, ordered by key. The
+    /// comparison is case-sensitive.
+    pub async fn scan_prefix(&self, prefix: &str) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+        self.with_table(|conn, table| {
+            let mut stmt = conn.prepare_cached(&format!(
+                "SELECT key, value FROM {} \
+                 WHERE key >= ?1 AND substr(key, 1, length(?1)) = ?1 \
+                 AND (expires_at IS NULL OR expires_at > ?2) ORDER BY key",
+                table
+            ))?;
+            let entries = stmt
+                .query_map(params![prefix, now_millis()], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+        .await
+    }
+
+    /// Delete the expired entries and return how many there were
+    pub async fn sweep_expired(&self) -> anyhow::Result<usize> {
+        self.with_table(|conn, table| {
+            Ok(conn
+                .prepare_cached(&format!("DELETE FROM {} WHERE expires_at <= ?1", table))?
+                .execute([now_millis()])?)
+        })
+        .await
+    }
+
+    /// Sweep expired entries every `interval` until the returned handle is
+    /// dropped. Sweeps run on the crate's timer thread so no particular
+    /// async runtime is required; a failed sweep is retried at the next
+    /// interval.
+    pub fn spawn_sweeper(&self, interval: Duration) -> KvSweeper {
+        let alive = Arc::new(());
+        schedule_sweep(self.clone(), interval, Arc::downgrade(&alive));
+        KvSweeper { _alive: alive }
+    }
+}
+
+/// Sweep `store` after `interval`, then again every `interval`, for as long
+/// as the handle `alive` points to exists
+fn schedule_sweep(store: KvStore, interval: Duration, alive: Weak<()>) {
+    timer::schedule(interval, move || {
+        if alive.strong_count() == 0 {
+            return;
+        }
+        let _ = futures::executor::block_on(store.sweep_expired());
+        schedule_sweep(store, interval, alive);
+    });
+}
+
+/// Handle of a sweeper started with [`KvStore::spawn_sweeper`]; dropping
+/// it stops the sweeper
+pub struct KvSweeper {
+    _alive: Arc<()>,
+}
@@ -12,4 +12,36 @@
 
 // TODO: Add core library code here.
 
+pub mod blob;
+pub mod bulk;
+pub mod changes;
+pub mod checksum;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod cursor;
+pub mod datetime;
+pub mod describe;
+pub mod events;
+pub mod filter;
+pub mod integrity;
+pub mod json;
+pub mod kv;
+pub mod loader;
+pub mod migration;
+pub mod outbox;
+pub mod patch;
+pub mod retry;
+#[cfg(feature = "rtree")]
+pub mod rtree;
+pub mod scope;
+#[cfg(feature = "session")]
+pub mod session;
+pub mod slow_query;
 pub mod sqlite;
+pub mod tenant;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod timer;
+pub mod transaction_log;
+pub mod view;
+pub mod write_batch;
@@ -0,0 +1,207 @@
+//! Batched single-row loads, coalescing concurrent lookups into one query.
+
+use crate::sqlite::{quote_ident, ColumnConstraint, Params, Row, SqlQuery, SqliteService, Value};
+use futures::channel::oneshot;
+use futures::future::{FutureExt, Shared};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+type BatchResult = Arc<Result<Vec<Row>, String>>;
+
+/// Keys waiting to be loaded from one table, and where their rows will arrive
+pub(crate) struct LoadBatch {
+    id: u64,
+    keys: Vec<Value>,
+    rows: Shared<oneshot::Receiver<BatchResult>>,
+}
+
+static NEXT_BATCH_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Column the rows of the loaded batch are keyed by
+const KEY_ALIAS: &str = "_runar_load_key";
+
+impl SqliteService {
+    /// Load the row of `table` whose key is `key`.
+    ///
+    /// The key is the table's single-column primary key when the configured
+    /// schema declares one, otherwise its `rowid`. Loads for the same table
+    /// issued concurrently, for example from `join_all` or from tasks polled
+    /// in the same tick, are coalesced: the first one waits for the others to
+    /// register, then a single `SELECT ... WHERE key IN (...)` serves them all.
+    /// Scoped tables cannot be loaded, see [`crate::scope`].
+    pub async fn load(&self, table: &str, key: impl Into<Value>) -> anyhow::Result<Option<Row>> {
+        self.check_unscoped(table)?;
+        self.check_permission(table, "read")?;
+        let key = key.into();
+        let joined = {
+            let mut batches = self.load_batches.lock().unwrap();
+            match batches.get_mut(table) {
+                Some(batch) => {
+                    if !batch.keys.iter().any(|k| same_key(k, &key)) {
+                        batch.keys.push(key.clone());
+                    }
+                    Some(batch.rows.clone())
+                }
+                None => None,
+            }
+        };
+        let rows = match joined {
+            Some(rows) => rows.await,
+            None => self.lead_batch(table, key.clone()).await,
+        };
+        let rows = rows.map_err(|_| anyhow::anyhow!("batched load for {} was cancelled", table))?;
+        match rows.as_ref() {
+            Ok(rows) => Ok(rows
+                .iter()
+                .find(|row| row.get(KEY_ALIAS).is_some_and(|k| same_key(k, &key)))
+                .map(|row| {
+                    let mut row = row.clone();
+                    row.remove(KEY_ALIAS);
+                    row
+                })),
+            Err(message) => Err(anyhow::anyhow!("{}", message)),
+        }
+    }
+
+    /// Open a batch for `table`, give concurrent loads a chance to join it,
+    /// then run the query and publish the rows to every waiter
+    async fn lead_batch(&self, table: &str, key: Value) -> Result<BatchResult, oneshot::Canceled> {
+        let (sender, receiver) = oneshot::channel();
+        let rows = receiver.shared();
+        let id = {
+            let mut batches = self.load_batches.lock().unwrap();
+            let id = NEXT_BATCH_ID.fetch_add(1, Ordering::Relaxed);
+            batches.insert(
+                table.to_string(),
+                LoadBatch {
+                    id,
+                    keys: vec![key],
+                    rows: rows.clone(),
+                },
+            );
+            id
+        };
+        // Unregister the batch if this future is dropped before dispatching,
+        // so later loads do not join a batch nobody will run
+        let guard = BatchGuard {
+            service: self,
+            table,
+            id,
+        };
+        YieldNow(false).await;
+        let keys = guard.take();
+
+        let key_column = self.load_key_column(table);
+        let mut params = Params::new();
+        let mut placeholders = Vec::with_capacity(keys.len());
+        for (i, key) in keys.into_iter().enumerate() {
+            let name = format!("k{}", i);
+            placeholders.push(format!(":{}", name));
+            params = params.with_value(&name, key);
+        }
+        let query = SqlQuery::new(&format!(
+            "SELECT {key} AS {alias}, * FROM {table} WHERE {key} IN ({placeholders})",
+            key = key_column,
+            alias = KEY_ALIAS,
+            table = quote_ident(table),
+            placeholders = placeholders.join(", "),
+        ))
+        .with_params(params);
+        let result = self.run_query(query).await;
+        #[cfg(feature = "compression")]
+        let result = result.and_then(|rows| self.decompress_rows(table, rows));
+        let result = result.and_then(|rows| {
+            for row in &rows {
+                self.check_allowed_values(table, row)?;
+            }
+            Ok(rows)
+        });
+        let result = result.map_err(|e| e.to_string());
+        let _ = sender.send(Arc::new(result));
+        rows.await
+    }
+
+    /// The quoted key column used by [`SqliteService::load`] for `table`
+    fn load_key_column(&self, table: &str) -> String {
+        let Some(definition) = self.config.schema.tables.iter().find(|t| t.name == table) else {
+            return "rowid".to_string();
+        };
+        let inline: Vec<&str> = definition
+            .columns
+            .iter()
+            .filter(|c| c.constraints.contains(&ColumnConstraint::PrimaryKey))
+            .map(|c| c.name.as_str())
+            .collect();
+        match (inline.as_slice(), definition.primary_key.as_slice()) {
+            ([column], _) => quote_ident(column),
+            ([], [column]) => quote_ident(column),
+            _ => "rowid".to_string(),
+        }
+    }
+}
+
+/// Whether SQLite considers two keys equal: numbers compare by value
+/// whatever their storage class, so `Integer(1)`, `Real(1.0)` and
+/// `Boolean(true)` are the same key
+fn same_key(a: &Value, b: &Value) -> bool {
+    let integer = |value: &Value| match value {
+        Value::Integer(i) => Some(*i),
+        Value::Boolean(b) => Some(i64::from(*b)),
+        _ => None,
+    };
+    match (a, b) {
+        (Value::Real(a), Value::Real(b)) => a == b,
+        (Value::Real(r), other) | (other, Value::Real(r)) => {
+            integer(other).is_some_and(|i| i as f64 == *r && *r as i64 == i)
+        }
+        _ => match (integer(a), integer(b)) {
+            (Some(a), Some(b)) => a == b,
+            _ => a == b,
+        },
+    }
+}
+
+struct BatchGuard<'a> {
+    service: &'a SqliteService,
+    table: &'a str,
+    id: u64,
+}
+
+impl BatchGuard<'_> {
+    /// Close the batch to new keys and return the keys collected so far
+    fn take(&self) -> Vec<Value> {
+        let mut batches = self.service.load_batches.lock().unwrap();
+        batches
+            .remove(self.table)
+            .expect("the batch stays registered until its leader takes it")
+            .keys
+    }
+}
+
+impl Drop for BatchGuard<'_> {
+    fn drop(&mut self) {
+        let mut batches = self.service.load_batches.lock().unwrap();
+        if batches.get(self.table).is_some_and(|b| b.id == self.id) {
+            batches.remove(self.table);
+        }
+    }
+}
+
+/// Returns `Pending` once, letting other futures polled in the same tick run
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
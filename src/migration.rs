@@ -0,0 +1,236 @@
+//! Versioned schema migrations.
+//!
+//! Migrations run after the declared schema has been created, in ascending
+//! version order. Each applied version is recorded, according to
+//! [`MigrationTracking`], in the same transaction as the migration itself, so
+//! a failed migration leaves neither its changes nor its version behind.
+//! Each step holds the database's write lock from reading the applied
+//! version to committing, so services opening the same file at once apply
+//! every migration exactly once.
+
+use crate::sqlite::{is_missing_table, quote_ident, SqliteConfig, SqliteError, SqliteService};
+use rusqlite::{Connection, Transaction, TransactionBehavior};
+
+/// Table recording the applied migration versions, suffixed with the
+/// service's [`SqliteConfig::schema_owner`] when it has one
+pub const MIGRATIONS_TABLE: &str = "_runar_migrations";
+
+/// Where applied migration versions are recorded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MigrationTracking {
+    /// One row per applied migration in `_runar_migrations`, with its
+    /// description and when it was applied
+    #[default]
+    Table,
+    /// Only the latest applied version, in `PRAGMA user_version`. Leaves no
+    /// extra table in the database; every migration up to that version is
+    /// considered applied.
+    UserVersion,
+}
+
+/// A versioned schema change, with an optional way back
+#[derive(Debug, Clone, PartialEq)]
+pub struct Migration {
+    pub version: u32,
+    pub description: String,
+    /// SQL applied when migrating up to this version
+    pub up: String,
+    /// SQL that reverts `up`, used by [`SqliteService::rollback`]
+    pub down: Option<String>,
+}
+
+impl Migration {
+    pub fn new(version: u32, description: &str, up: &str) -> Self {
+        Self {
+            version,
+            description: description.to_string(),
+            up: up.to_string(),
+            down: None,
+        }
+    }
+    /// Set the SQL that reverts this migration
+    pub fn with_down(mut self, down: &str) -> Self {
+        self.down = Some(down.to_string());
+        self
+    }
+}
+
+/// Migrations sorted by version, rejecting duplicates
+fn sorted(migrations: &[Migration]) -> Result<Vec<&Migration>, SqliteError> {
+    let mut sorted: Vec<&Migration> = migrations.iter().collect();
+    sorted.sort_by_key(|m| m.version);
+    if let Some(pair) = sorted.windows(2).find(|w| w[0].version == w[1].version) {
+        return Err(SqliteError::DuplicateMigration(pair[0].version));
+    }
+    Ok(sorted)
+}
+
+fn user_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+pub(crate) fn set_user_version(conn: &Connection, version: u32) -> rusqlite::Result<()> {
+    conn.execute_batch(&format!("PRAGMA user_version = {}", version))
+}
+
+fn ensure_migrations_table(conn: &Connection, table: &str) -> rusqlite::Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {} (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+        table
+    ))
+}
+
+/// Applied versions, newest first. With `UserVersion` tracking these are the
+/// defined versions below the recorded one, plus the recorded one itself
+/// even if it is not defined.
+fn applied_versions(
+    conn: &Connection,
+    tracking: MigrationTracking,
+    table: &str,
+    migrations: &[&Migration],
+) -> rusqlite::Result<Vec<u32>> {
+    match tracking {
+        MigrationTracking::Table => {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT version FROM {} ORDER BY version DESC",
+                table
+            ))?;
+            let versions = stmt.query_map([], |row| row.get(0))?;
+            versions.collect()
+        }
+        MigrationTracking::UserVersion => {
+            let current = user_version(conn)?;
+            if current == 0 {
+                return Ok(Vec::new());
+            }
+            let mut versions: Vec<u32> = migrations
+                .iter()
+                .map(|m| m.version)
+                .filter(|v| *v < current)
+                .collect();
+            versions.push(current);
+            versions.reverse();
+            Ok(versions)
+        }
+    }
+}
+
+/// Apply every configured migration newer than the latest applied version
+pub(crate) fn apply_migrations(conn: &Connection, config: &SqliteConfig) -> anyhow::Result<()> {
+    if config.migrations.is_empty() {
+        return Ok(());
+    }
+    let migrations = sorted(&config.migrations)?;
+    let tracking = config.migration_tracking;
+    let table = quote_ident(&config.migrations_table());
+    if tracking == MigrationTracking::Table {
+        ensure_migrations_table(conn, &table)?;
+    }
+    loop {
+        let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+        let current = applied_versions(&tx, tracking, &table, &migrations)?
+            .first()
+            .copied()
+            .unwrap_or(0);
+        let Some(migration) = migrations.iter().find(|m| m.version > current) else {
+            return Ok(());
+        };
+        tx.execute_batch(&migration.up)?;
+        match tracking {
+            MigrationTracking::Table => {
+                tx.execute(
+                    &format!(
+                        "INSERT INTO {} (version, description) VALUES (?1, ?2)",
+                        table
+                    ),
+                    (migration.version, &migration.description),
+                )?;
+            }
+            MigrationTracking::UserVersion => set_user_version(&tx, migration.version)?,
+        }
+        tx.commit()?;
+    }
+}
+
+impl SqliteService {
+    /// The latest applied migration version, or 0 if none has been applied
+    pub async fn migration_version(&self) -> anyhow::Result<u32> {
+        let tracking = self.config.migration_tracking;
+        let table = quote_ident(&self.config.migrations_table());
+        let migrations = sorted(&self.config.migrations)?;
+        self.with_connection(|conn| {
+            match applied_versions(conn, tracking, &table, &migrations).map_err(Into::into) {
+                // The table is only created once there are migrations to apply
+                Err(e) if is_missing_table(&e) => Ok(0),
+                versions => Ok(versions?.first().copied().unwrap_or(0)),
+            }
+        })
+        .await
+    }
+
+    /// The database's `PRAGMA user_version`.
+    ///
+    /// Not to be confused with SQLite's own `PRAGMA schema_version`, which
+    /// counts schema changes. With [`MigrationTracking::UserVersion`] this is
+    /// the latest applied migration.
+    pub async fn schema_version(&self) -> anyhow::Result<u32> {
+        self.with_connection(|conn| Ok(user_version(conn)?)).await
+    }
+
+    /// Set `PRAGMA user_version`. With [`MigrationTracking::UserVersion`]
+    /// this changes which migrations are considered applied.
+    pub async fn set_schema_version(&self, version: u32) -> anyhow::Result<()> {
+        self.with_connection(|conn| Ok(set_user_version(conn, version)?))
+            .await
+    }
+
+    /// Revert applied migrations newer than `to_version`, newest first.
+    ///
+    /// Every migration to revert must define a `down` script; this is checked
+    /// before anything is reverted. Each step runs in its own transaction
+    /// together with the removal of its recorded version.
+    pub async fn rollback(&self, to_version: u32) -> anyhow::Result<()> {
+        let tracking = self.config.migration_tracking;
+        let table = quote_ident(&self.config.migrations_table());
+        let migrations = sorted(&self.config.migrations)?;
+        self.with_connection(|conn| {
+            if tracking == MigrationTracking::Table {
+                ensure_migrations_table(conn, &table)?;
+            }
+            let applied = applied_versions(conn, tracking, &table, &migrations)?;
+            let mut steps = Vec::new();
+            for (i, &version) in applied.iter().enumerate().filter(|(_, v)| **v > to_version) {
+                let migration = migrations
+                    .iter()
+                    .find(|m| m.version == version)
+                    .ok_or(SqliteError::UnknownMigration(version))?;
+                let down = migration
+                    .down
+                    .as_ref()
+                    .ok_or(SqliteError::IrreversibleMigration(version))?;
+                let previous = applied.get(i + 1).copied().unwrap_or(0);
+                steps.push((version, previous, down));
+            }
+            for (version, previous, down) in steps {
+                let tx = conn.unchecked_transaction()?;
+                tx.execute_batch(down)?;
+                match tracking {
+                    MigrationTracking::Table => {
+                        tx.execute(
+                            &format!("DELETE FROM {} WHERE version = ?1", table),
+                            [version],
+                        )?;
+                    }
+                    MigrationTracking::UserVersion => set_user_version(&tx, previous)?,
+                }
+                tx.commit()?;
+            }
+            Ok(())
+        })
+        .await
+    }
+}
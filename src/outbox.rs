@@ -0,0 +1,131 @@
+//! Transactional outbox for reliable event publishing.
+//!
+//! [`enqueue_outbox`] records an event in the same transaction as the
+//! writes it describes, so the event exists exactly when the writes were
+//! committed. [`SqliteService::drain_outbox`] later hands pending events to
+//! a publisher, typically the node bus, and marks each one sent only after
+//! it was published. A crash or failed publish leaves the event pending for
+//! the next drain: delivery is at least once, and subscribers should
+//! tolerate duplicates.
+//!
+//! ```ignore
+//! service
+//!     .transaction_retry(|tx| {
+//!         tx.execute("INSERT INTO orders (id) VALUES (?1)", [order_id])?;
+//!         enqueue_outbox(tx, "orders/placed", &json!({ "id": order_id }))
+//!     })
+//!     .await?;
+//!
+//! // Periodically, e.g. from a spawned task:
+//! service
+//!     .drain_outbox(100, |message| {
+//!         ctx.publish(&message.topic, Some(ArcValueType::from_struct(message.payload)))
+//!     })
+//!     .await?;
+//! ```
+
+use crate::sqlite::{create_on_missing, SqliteService};
+use rusqlite::{params, Connection};
+use std::future::Future;
+
+/// Table holding outbox events until they are sent
+pub const OUTBOX_TABLE: &str = "_runar_outbox";
+
+/// An event waiting in the outbox
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutboxMessage {
+    /// Position in the outbox; events are drained in this order
+    pub id: i64,
+    /// Topic to publish the event on
+    pub topic: String,
+    pub payload: serde_json::Value,
+}
+
+/// Create the outbox table on first use
+fn ensure_outbox_table(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {table} (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            topic TEXT NOT NULL,
+            payload TEXT NOT NULL,
+            sent_at TEXT
+        );
+        CREATE INDEX IF NOT EXISTS {table}_pending ON {table} (id) WHERE sent_at IS NULL;",
+        table = OUTBOX_TABLE
+    ))
+}
+
+/// Add an event to the outbox on `conn`, normally inside the transaction
+/// making the writes the event announces, e.g. the `tx` of
+/// [`SqliteService::transaction_retry`]. Returns the event's id.
+pub fn enqueue_outbox(
+    conn: &Connection,
+    topic: &str,
+    payload: &serde_json::Value,
+) -> anyhow::Result<i64> {
+    let payload = payload.to_string();
+    create_on_missing(conn, ensure_outbox_table, |conn| {
+        conn.execute(
+            &format!(
+                "INSERT INTO {} (topic, payload) VALUES (?1, ?2)",
+                OUTBOX_TABLE
+            ),
+            params![topic, payload],
+        )?;
+        Ok(conn.last_insert_rowid())
+    })
+}
+
+impl SqliteService {
+    /// Publish up to `limit` pending outbox events in order, marking each
+    /// sent once `publish` succeeds for it, and return how many were sent.
+    ///
+    /// Stops at the first failed publish and returns its error; that event
+    /// and the ones after it stay pending. Call repeatedly, for example on
+    /// an interval, until it returns 0.
+    pub async fn drain_outbox<F, Fut>(&self, limit: usize, mut publish: F) -> anyhow::Result<usize>
+    where
+        F: FnMut(OutboxMessage) -> Fut,
+        Fut: Future<Output = anyhow::Result<()>>,
+    {
+        let pending = self
+            .with_connection(|conn| {
+                create_on_missing(conn, ensure_outbox_table, |conn| {
+                    let mut stmt = conn.prepare(&format!(
+                        "SELECT id, topic, payload FROM {} WHERE sent_at IS NULL ORDER BY id LIMIT ?1",
+                        OUTBOX_TABLE
+                    ))?;
+                    let rows = stmt
+                        .query_map([limit as i64], |row| {
+                            Ok((row.get(0)?, row.get(1)?, row.get::<_, String>(2)?))
+                        })?
+                        .collect::<Result<Vec<(i64, String, String)>, _>>()?;
+                    Ok(rows)
+                })
+            })
+            .await?;
+
+        let mut sent = 0;
+        for (id, topic, payload) in pending {
+            let message = OutboxMessage {
+                id,
+                topic,
+                payload: serde_json::from_str(&payload)?,
+            };
+            publish(message).await?;
+            self.with_connection(|conn| {
+                conn.execute(
+                    &format!(
+                        "UPDATE {} SET sent_at = CURRENT_TIMESTAMP WHERE id = ?1",
+                        OUTBOX_TABLE
+                    ),
+                    [id],
+                )?;
+                Ok(())
+            })
+            .await?;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+}
@@ -0,0 +1,97 @@
+//! Partial updates and upserts from serializable structs.
+
+use crate::sqlite::{
+    CreateOperation, CrudOperation, Query, Row, SqliteError, SqliteService, UpdateOperation, Value,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// The columns a patch sets: every field of `patch` that is present and not
+/// null. Strings, numbers and booleans map to the matching [`Value`];
+/// nested structs, maps and sequences are stored as JSON text. Unsigned
+/// integers above `i64::MAX` fail with [`SqliteError::IntegerOutOfRange`]
+/// rather than being stored as an inexact REAL.
+pub fn patch_values<T: Serialize>(patch: &T) -> anyhow::Result<HashMap<String, Value>> {
+    let fields = struct_values(patch)?
+        .ok_or_else(|| SqliteError::InvalidPatch(std::any::type_name::<T>().to_string()))?;
+    Ok(fields
+        .into_iter()
+        .filter(|(_, value)| *value != Value::Null)
+        .collect())
+}
+
+/// Every field of `value` as a column, with `None` fields as NULL, or
+/// `None` when it does not serialize to a struct or map. Values map as in
+/// [`patch_values`].
+pub(crate) fn struct_values<T: Serialize>(
+    value: &T,
+) -> anyhow::Result<Option<HashMap<String, Value>>> {
+    let serde_json::Value::Object(fields) = serde_json::to_value(value)? else {
+        return Ok(None);
+    };
+    let values = fields
+        .into_iter()
+        .map(|(field, value)| {
+            let value = match value {
+                serde_json::Value::Null => Value::Null,
+                serde_json::Value::Bool(b) => Value::Boolean(b),
+                serde_json::Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+                    (Some(i), _) => Value::Integer(i),
+                    (None, Some(value)) => {
+                        return Err(SqliteError::IntegerOutOfRange { field, value })
+                    }
+                    (None, None) => Value::Real(n.as_f64().unwrap_or(f64::NAN)),
+                },
+                serde_json::Value::String(s) => Value::Text(s),
+                nested => Value::Text(nested.to_string()),
+            };
+            Ok((field, value))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Some(values))
+}
+
+impl SqliteService {
+    /// Update the rows matching `query` with the fields present in `patch`.
+    ///
+    /// Follows HTTP PATCH semantics: fields that are `None` (or skipped by
+    /// serde) leave their column unchanged, so a patch cannot set a column to
+    /// NULL; use [`UpdateOperation::with_null`] for that. Returns the updated
+    /// rows.
+    pub async fn update_patch<T: Serialize>(
+        &self,
+        table: &str,
+        query: Query,
+        patch: &T,
+    ) -> anyhow::Result<Vec<Row>> {
+        let mut update = UpdateOperation::new(table, query);
+        update.updates = patch_values(patch)?;
+        self.execute_crud(CrudOperation::Update(update)).await
+    }
+
+    /// Insert `record` into `table`, or update the row with the same values
+    /// in `key_columns`, setting every other field.
+    ///
+    /// Suits load, modify, save flows: unlike [`SqliteService::update_patch`]
+    /// every field is written, `None` ones as NULL. `key_columns` must be
+    /// the table's primary key or carry a unique index, and `record` must
+    /// have a field for each; columns it has no field for are left alone.
+    /// Returns the saved row.
+    pub async fn save<T: Serialize>(
+        &self,
+        table: &str,
+        key_columns: &[&str],
+        record: &T,
+    ) -> anyhow::Result<Row> {
+        let create = CreateOperation::from_struct(table, record)?.with_upsert(key_columns);
+        if let Some(column) = key_columns.iter().find(|c| !create.data.contains_key(**c)) {
+            return Err(SqliteError::MissingColumn {
+                table: table.to_string(),
+                column: column.to_string(),
+            }
+            .into());
+        }
+        let mut rows = self.execute_crud(CrudOperation::Create(create)).await?;
+        Ok(rows.remove(0))
+    }
+}
@@ -0,0 +1,59 @@
+//! Whole-transaction retries on lock conflicts.
+
+use crate::sqlite::SqliteService;
+use crate::timer::sleep;
+use rusqlite::{ErrorCode, Transaction};
+
+impl SqliteService {
+    /// Run `f` in a transaction and commit it, re-running the whole
+    /// transaction from scratch when it hits a lock conflict.
+    ///
+    /// A conflict is `SQLITE_BUSY` or `SQLITE_LOCKED` from any statement or
+    /// from the commit, typically another connection to the same file
+    /// holding the write lock or a read transaction that cannot be upgraded
+    /// to a write in WAL mode. Each conflict rolls back the attempt; up to
+    /// [`SqliteConfig::transaction_max_attempts`] attempts are made, with an
+    /// exponential backoff starting at
+    /// [`SqliteConfig::transaction_retry_backoff`] between them. Other errors
+    /// roll back and are returned immediately.
+    ///
+    /// Because `f` may run several times, it must only act through `tx`:
+    /// side effects outside the database, such as requests to other services,
+    /// happen once per attempt.
+    ///
+    /// [`SqliteConfig::transaction_max_attempts`]: crate::sqlite::SqliteConfig::transaction_max_attempts
+    /// [`SqliteConfig::transaction_retry_backoff`]: crate::sqlite::SqliteConfig::transaction_retry_backoff
+    pub async fn transaction_retry<T>(
+        &self,
+        mut f: impl FnMut(&Transaction<'_>) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let mut backoff = self.config.transaction_retry_backoff;
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .with_connection(|conn| {
+                    let tx = conn.transaction()?;
+                    let value = f(&tx)?;
+                    tx.commit()?;
+                    Ok(value)
+                })
+                .await;
+            match result {
+                Err(e) if is_conflict(&e) && attempt < self.config.transaction_max_attempts => {
+                    sleep(backoff).await;
+                    backoff = backoff.saturating_mul(2);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+fn is_conflict(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<rusqlite::Error>(),
+        Some(rusqlite::Error::SqliteFailure(e, _))
+            if matches!(e.code, ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+    )
+}
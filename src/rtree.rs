@@ -0,0 +1,145 @@
+//! R*Tree virtual tables for spatial range queries.
+//!
+//! An [`RtreeTable`] declared with [`Schema::add_rtree_table`] is created as
+//! `CREATE VIRTUAL TABLE ... USING rtree(...)`: an integer `id` followed by
+//! a `min_` and a `max_` column for each dimension, so a two dimensional
+//! table over `x` and `y` has the columns `id, min_x, max_x, min_y, max_y`.
+//! Write entries like rows of any table, then find those overlapping,
+//! within or containing a region with [`SqliteService::rtree_query`]. The
+//! bounds of an entry usually link to a row elsewhere through its `id`.
+//!
+//! SQLite stores R*Tree coordinates as 32-bit floats, rounding minimums down
+//! and maximums up, so results can include entries that only touch the
+//! region once rounded.
+//!
+//! [`Schema::add_rtree_table`]: crate::sqlite::Schema::add_rtree_table
+
+use crate::sqlite::{
+    quote_ident, quote_literal, Params, Row, SqlQuery, SqliteError, SqliteService, Value,
+};
+
+/// An R*Tree virtual table with one to five dimensions
+#[derive(Debug, Clone, PartialEq)]
+pub struct RtreeTable {
+    pub name: String,
+    /// Names of the dimensions, each stored as a `min_` and `max_` column
+    pub dimensions: Vec<String>,
+}
+
+impl RtreeTable {
+    pub fn new(name: &str, dimensions: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            dimensions: dimensions.iter().map(|d| d.to_string()).collect(),
+        }
+    }
+
+    /// The `CREATE VIRTUAL TABLE` statement for this table
+    pub fn create_table_sql(&self) -> String {
+        let mut columns = vec![quote_ident("id")];
+        for dimension in &self.dimensions {
+            columns.push(quote_ident(&format!("min_{}", dimension)));
+            columns.push(quote_ident(&format!("max_{}", dimension)));
+        }
+        format!(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS {} USING rtree({})",
+            quote_ident(&self.name),
+            columns.join(", ")
+        )
+    }
+}
+
+/// A region with a `(min, max)` range per dimension, in the order the
+/// dimensions were declared
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundingBox {
+    pub ranges: Vec<(f64, f64)>,
+}
+
+impl BoundingBox {
+    pub fn new(ranges: &[(f64, f64)]) -> Self {
+        Self {
+            ranges: ranges.to_vec(),
+        }
+    }
+}
+
+/// How entries of an R*Tree must relate to the queried region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpatialRelation {
+    /// Entries sharing at least a point with the region, edges included
+    Overlaps,
+    /// Entries lying entirely inside the region
+    Within,
+    /// Entries covering the whole region
+    Contains,
+}
+
+impl SqliteService {
+    /// The entries of the R*Tree `table` that relate to `region` as
+    /// `relation` asks, ordered by `id`. Each row holds the `id` and the
+    /// bounds of the entry. Fails with [`SqliteError::RtreeDimensions`] when
+    /// `region` has another number of dimensions than the table.
+    pub async fn rtree_query(
+        &self,
+        table: &str,
+        region: &BoundingBox,
+        relation: SpatialRelation,
+    ) -> anyhow::Result<Vec<Row>> {
+        self.check_unscoped(table)?;
+        self.check_permission(table, "read")?;
+        // Read from the database, so tables created by migrations work too
+        let columns: Vec<String> = self
+            .run_query(SqlQuery::new(&format!(
+                "SELECT name FROM pragma_table_info({})",
+                quote_literal(table)
+            )))
+            .await?
+            .into_iter()
+            .filter_map(|row| match row.get("name") {
+                Some(Value::Text(name)) => Some(quote_ident(name)),
+                _ => None,
+            })
+            .collect();
+        let expected = columns.len().saturating_sub(1) / 2;
+        if expected != region.ranges.len() {
+            return Err(SqliteError::RtreeDimensions {
+                table: table.to_string(),
+                expected,
+                actual: region.ranges.len(),
+            }
+            .into());
+        }
+        let mut clauses = Vec::with_capacity(expected * 2);
+        let mut params = Params::new();
+        for (i, (min, max)) in region.ranges.iter().enumerate() {
+            let (min_column, max_column) = (&columns[1 + 2 * i], &columns[2 + 2 * i]);
+            let (lower, upper) = match relation {
+                SpatialRelation::Overlaps => (
+                    format!("{} >= :min{}", max_column, i),
+                    format!("{} <= :max{}", min_column, i),
+                ),
+                SpatialRelation::Within => (
+                    format!("{} >= :min{}", min_column, i),
+                    format!("{} <= :max{}", max_column, i),
+                ),
+                SpatialRelation::Contains => (
+                    format!("{} <= :min{}", min_column, i),
+                    format!("{} >= :max{}", max_column, i),
+                ),
+            };
+            clauses.push(lower);
+            clauses.push(upper);
+            params = params
+                .with_value(&format!("min{}", i), *min)
+                .with_value(&format!("max{}", i), *max);
+        }
+        let statement = format!(
+            "SELECT * FROM {} WHERE {} ORDER BY id",
+            quote_ident(table),
+            clauses.join(" AND ")
+        );
+        self.run_query(SqlQuery::new(&statement).with_params(params))
+            .await
+    }
+}
@@ -0,0 +1,132 @@
+//! Row-level scoping of tables, for example by tenant.
+//!
+//! A table configured with [`SqliteConfig::with_scope`] can only be reached
+//! through CRUD operations on a [`ScopedService`], which confines every
+//! operation to the rows whose scope column holds the scope value: reads,
+//! updates and deletes are filtered by it and inserts are stamped with it.
+//! The value usually comes from the authenticated caller of the request
+//! being handled, such as its tenant id. Unscoped CRUD operations, reads
+//! whose CTEs or expressions mention a scoped table, and the helpers that
+//! build their own SQL over a table, such as [`SqliteService::load`],
+//! [`SqliteService::merge`] and [`SqliteService::copy_table_to`], are
+//! rejected on scoped tables. Table names are matched case-insensitively,
+//! as SQLite does. Raw SQL is not scoped and is the caller's responsibility.
+//!
+//! [`SqliteConfig::with_scope`]: crate::sqlite::SqliteConfig::with_scope
+
+use crate::filter::Condition;
+use crate::sqlite::{CrudOperation, QueryOperator, Row, SqliteError, SqliteService, Value};
+
+/// CRUD access confined to the rows of one scope, see
+/// [`SqliteService::scoped`]
+pub struct ScopedService<'a> {
+    service: &'a SqliteService,
+    value: Value,
+}
+
+impl SqliteService {
+    /// CRUD access limited to the rows whose scope column equals `value`.
+    /// Tables without a configured scope are accessed unchanged.
+    pub fn scoped(&self, value: impl Into<Value>) -> ScopedService<'_> {
+        ScopedService {
+            service: self,
+            value: value.into(),
+        }
+    }
+
+    /// The scope column of `table`, if it is scoped
+    fn scope_column(&self, table: &str) -> Option<&String> {
+        self.config.scopes.get(&table.to_ascii_lowercase())
+    }
+
+    /// Fail if `table` is scoped
+    pub(crate) fn check_unscoped(&self, table: &str) -> Result<(), SqliteError> {
+        if self.scope_column(table).is_some() {
+            return Err(SqliteError::ScopeRequired(table.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Fail if the SQL `op` embeds, such as a CTE, mentions a scoped table:
+    /// the scope filter only applies to the target table
+    fn check_embedded_unscoped(&self, op: &CrudOperation) -> Result<(), SqliteError> {
+        op.embedded_names()
+            .iter()
+            .try_for_each(|name| self.check_unscoped(name))
+    }
+
+    /// Fail if `op` targets or otherwise reaches a scoped table
+    pub(crate) fn check_crud_unscoped(&self, op: &CrudOperation) -> Result<(), SqliteError> {
+        self.check_unscoped(op.table())?;
+        self.check_embedded_unscoped(op)
+    }
+}
+
+impl ScopedService<'_> {
+    /// The value rows must hold in their scope column
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// Perform a CRUD operation within the scope.
+    ///
+    /// Creates set the scope column, failing with
+    /// [`SqliteError::ScopeViolation`] if the row names another scope;
+    /// updates may not change the scope column at all. Upserts are rejected
+    /// the same way, as the row they would update may belong to another
+    /// scope. Reads whose CTEs or expressions mention a scoped table fail
+    /// with [`SqliteError::ScopeRequired`], as the filter cannot reach them.
+    pub async fn execute_crud(&self, op: CrudOperation) -> anyhow::Result<Vec<Row>> {
+        self.service.check_embedded_unscoped(&op)?;
+        let Some(column) = self.service.scope_column(op.table()) else {
+            return self.service.run_crud(op).await;
+        };
+        let violation = |table: &str| SqliteError::ScopeViolation {
+            table: table.to_string(),
+            column: column.clone(),
+        };
+        let filter = Condition::field(column, QueryOperator::Equal(self.value.clone()));
+        let op = match op {
+            CrudOperation::Create(mut create) => {
+                if !create.conflict_columns.is_empty() {
+                    return Err(violation(&create.table).into());
+                }
+                // SQLite matches column names without regard to case, so
+                // any spelling of the scope column names it
+                let spellings: Vec<String> = create
+                    .data
+                    .keys()
+                    .filter(|key| key.eq_ignore_ascii_case(column))
+                    .cloned()
+                    .collect();
+                for key in spellings {
+                    if create.data.remove(&key).is_some_and(|v| v != self.value) {
+                        return Err(violation(&create.table).into());
+                    }
+                }
+                create.data.insert(column.clone(), self.value.clone());
+                CrudOperation::Create(create)
+            }
+            CrudOperation::Read(mut read) => {
+                read.query = read.query.with_filter(filter);
+                CrudOperation::Read(read)
+            }
+            CrudOperation::Update(mut update) => {
+                if update
+                    .updates
+                    .keys()
+                    .any(|key| key.eq_ignore_ascii_case(column))
+                {
+                    return Err(violation(&update.table).into());
+                }
+                update.query = update.query.with_filter(filter);
+                CrudOperation::Update(update)
+            }
+            CrudOperation::Delete(mut delete) => {
+                delete.query = delete.query.with_filter(filter);
+                CrudOperation::Delete(delete)
+            }
+        };
+        self.service.run_crud(op).await
+    }
+}
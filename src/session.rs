@@ -0,0 +1,69 @@
+//! Change capture through SQLite's session extension.
+//!
+//! A changeset records the rows inserted, updated and deleted while a session
+//! is active. It can be shipped to another database with the same schema and
+//! applied there to converge both databases; this underpins replication.
+//! Only tables with a primary key are tracked by the session extension.
+
+use crate::sqlite::SqliteService;
+use rusqlite::session::{ConflictAction, ConflictType, Session};
+use rusqlite::Connection;
+
+/// How conflicts are resolved when applying a changeset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangesetConflict {
+    /// Abort and roll back the whole changeset
+    Abort,
+    /// Skip the conflicting change
+    Omit,
+    /// Overwrite the local row with the incoming change where SQLite allows
+    /// it (data and primary-key conflicts), skip it otherwise
+    Replace,
+}
+
+impl SqliteService {
+    /// Run `f` while recording changes to every table, returning its result
+    /// together with the serialized changeset
+    pub async fn capture_changeset<T>(
+        &self,
+        f: impl FnOnce(&Connection) -> anyhow::Result<T>,
+    ) -> anyhow::Result<(T, Vec<u8>)> {
+        self.with_connection(|conn| {
+            let mut session = Session::new(conn)?;
+            session.attach(None)?;
+            let result = f(conn)?;
+            let mut changeset = Vec::new();
+            session.changeset_strm(&mut changeset)?;
+            Ok((result, changeset))
+        })
+        .await
+    }
+
+    /// Apply a changeset produced by [`SqliteService::capture_changeset`]
+    pub async fn apply_changeset(
+        &self,
+        changeset: &[u8],
+        on_conflict: ChangesetConflict,
+    ) -> anyhow::Result<()> {
+        self.with_connection(|conn| {
+            let mut input = changeset;
+            conn.apply_strm(
+                &mut input,
+                None::<fn(&str) -> bool>,
+                move |conflict_type, _item| match on_conflict {
+                    ChangesetConflict::Abort => ConflictAction::SQLITE_CHANGESET_ABORT,
+                    ChangesetConflict::Omit => ConflictAction::SQLITE_CHANGESET_OMIT,
+                    ChangesetConflict::Replace => match conflict_type {
+                        ConflictType::SQLITE_CHANGESET_DATA
+                        | ConflictType::SQLITE_CHANGESET_CONFLICT => {
+                            ConflictAction::SQLITE_CHANGESET_REPLACE
+                        }
+                        _ => ConflictAction::SQLITE_CHANGESET_OMIT,
+                    },
+                },
+            )?;
+            Ok(())
+        })
+        .await
+    }
+}
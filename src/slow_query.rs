@@ -0,0 +1,222 @@
+//! Slow query log, index suggestions and unindexed filter checks.
+
+use crate::sqlite::{quote_ident, quote_literal, MissingIndexPolicy, SqliteError, SqliteService};
+use crate::transaction_log::BoundParams;
+use rusqlite::Connection;
+use std::time::{Duration, Instant};
+
+/// Number of slow queries, and of unindexed filters, kept by the service;
+/// older entries are dropped
+pub const SLOW_QUERY_LOG_CAPACITY: usize = 100;
+
+/// A query that took longer than the configured slow query threshold
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowQuery {
+    pub statement: String,
+    pub elapsed: Duration,
+    /// Indexes that might have avoided a full table scan, such as
+    /// `consider index on users(email)`. Empty unless index suggestions are
+    /// enabled.
+    pub suggestions: Vec<String>,
+}
+
+/// A query found filtering a table on a column no index starts with, see
+/// [`MissingIndexPolicy::Warn`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnindexedFilter {
+    pub statement: String,
+    pub table: String,
+    pub column: String,
+}
+
+impl SqliteService {
+    /// The most recent slow queries, oldest first
+    pub fn slow_queries(&self) -> Vec<SlowQuery> {
+        self.slow_queries.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// The most recent queries that filtered on an unindexed column, oldest
+    /// first. Empty unless the missing index policy is
+    /// [`MissingIndexPolicy::Warn`].
+    pub fn unindexed_filters(&self) -> Vec<UnindexedFilter> {
+        self.unindexed_filters
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Apply the missing index policy to `statement` before it runs
+    fn check_filter_indexes(&self, conn: &Connection, statement: &str) -> anyhow::Result<()> {
+        let policy = self.config.missing_index_policy;
+        if policy == MissingIndexPolicy::Ignore {
+            return Ok(());
+        }
+        let unindexed = unindexed_filter_columns(conn, statement)?;
+        if policy == MissingIndexPolicy::Deny {
+            if let Some((table, column)) = unindexed.into_iter().next() {
+                return Err(SqliteError::MissingIndex { table, column }.into());
+            }
+            return Ok(());
+        }
+        let mut log = self.unindexed_filters.lock().unwrap();
+        for (table, column) in unindexed {
+            if log.len() == SLOW_QUERY_LOG_CAPACITY {
+                log.pop_front();
+            }
+            log.push_back(UnindexedFilter {
+                statement: statement.to_string(),
+                table,
+                column,
+            });
+        }
+        Ok(())
+    }
+
+    /// Run `f`, which executes `statement` on `conn`, and record it in the
+    /// slow query log if it exceeds the configured threshold, and in the
+    /// transaction log when that is enabled
+    pub(crate) fn observe_query<T>(
+        &self,
+        conn: &Connection,
+        statement: &str,
+        params: BoundParams<'_>,
+        f: impl FnOnce() -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        self.check_filter_indexes(conn, statement)?;
+        if self.config.slow_query_threshold.is_none() && self.config.transaction_log_capacity == 0 {
+            return f();
+        }
+        let started = Instant::now();
+        let result = f();
+        let elapsed = started.elapsed();
+        self.log_statement(statement, params, elapsed, &result);
+        let Some(threshold) = self.config.slow_query_threshold else {
+            return result;
+        };
+        if elapsed >= threshold {
+            let suggestions = if self.config.suggest_indexes {
+                // A developer aid: failing to plan must not fail the query
+                index_suggestions(conn, statement).unwrap_or_default()
+            } else {
+                Vec::new()
+            };
+            let mut log = self.slow_queries.lock().unwrap();
+            if log.len() == SLOW_QUERY_LOG_CAPACITY {
+                log.pop_front();
+            }
+            log.push_back(SlowQuery {
+                statement: statement.to_string(),
+                elapsed,
+                suggestions,
+            });
+        }
+        result
+    }
+}
+
+/// Suggest indexes for tables the plan of `statement` scans in full
+fn index_suggestions(conn: &Connection, statement: &str) -> anyhow::Result<Vec<String>> {
+    Ok(unindexed_filter_columns(conn, statement)?
+        .into_iter()
+        .map(|(table, column)| format!("consider index on {}({})", table, column))
+        .collect())
+}
+
+/// The `(table, column)` pairs of tables the plan of `statement` scans in
+/// full, filtered on columns no index starts with.
+///
+/// A heuristic: a column is reported when it belongs to a scanned table,
+/// is not its primary key, its name appears in the `WHERE` clause, and no
+/// index starts with it.
+fn unindexed_filter_columns(
+    conn: &Connection,
+    statement: &str,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let Some(filter) = where_clause_words(statement) else {
+        return Ok(Vec::new());
+    };
+    // Parameters are left unbound; they do not change the plan
+    let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", statement))?;
+    let mut rows = stmt.raw_query();
+    let mut details: Vec<String> = Vec::new();
+    while let Some(row) = rows.next()? {
+        details.push(row.get("detail")?);
+    }
+
+    let mut unindexed = Vec::new();
+    for detail in details {
+        let Some(rest) = detail.strip_prefix("SCAN ") else {
+            continue;
+        };
+        // Scans of an index are not full table scans
+        if rest.contains(" USING ") {
+            continue;
+        }
+        let rest = rest.strip_prefix("TABLE ").unwrap_or(rest);
+        let table = rest.split(' ').next().unwrap_or_default();
+
+        let indexed = leading_index_columns(conn, table)?;
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", quote_ident(table)))?;
+        let columns = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>("name")?, row.get::<_, i64>("pk")?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        for (column, pk) in columns {
+            let lower = column.to_lowercase();
+            if pk == 0 && filter.contains(&lower) && !indexed.contains(&lower) {
+                let found = (table.to_string(), column);
+                if !unindexed.contains(&found) {
+                    unindexed.push(found);
+                }
+            }
+        }
+    }
+    Ok(unindexed)
+}
+
+/// Lowercased identifier-like words of the `WHERE` clause of `statement`
+fn where_clause_words(statement: &str) -> Option<Vec<String>> {
+    let lower = statement.to_lowercase();
+    let start = lower.find(" where ")? + " where ".len();
+    let mut filter = &lower[start..];
+    for end in [" group by ", " order by ", " limit "] {
+        if let Some(i) = filter.find(end) {
+            filter = &filter[..i];
+        }
+    }
+    Some(
+        filter
+            .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .filter(|w| !w.is_empty())
+            .map(String::from)
+            .collect(),
+    )
+}
+
+/// Lowercased first column of every index on `table`
+fn leading_index_columns(conn: &Connection, table: &str) -> anyhow::Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA index_list({})", quote_ident(table)))?;
+    let indexes = stmt
+        .query_map([], |row| row.get::<_, String>("name"))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut columns = Vec::new();
+    for index in indexes {
+        let first: Option<Option<String>> = conn
+            .query_row(
+                &format!(
+                    "SELECT name FROM pragma_index_info({}) WHERE seqno = 0",
+                    quote_literal(&index)
+                ),
+                [],
+                |row| row.get(0),
+            )
+            .ok();
+        if let Some(Some(column)) = first {
+            columns.push(column.to_lowercase());
+        }
+    }
+    Ok(columns)
+}
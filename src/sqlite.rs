@@ -1,7 +1,37 @@
-use runar_macros::service;
+use crate::cursor::{ReadChunk, ReadChunkRequest};
+use crate::describe::SchemaDescription;
+use crate::filter::Condition;
+use crate::integrity::IntegrityReport;
+use crate::json::BlobEncoding;
+use crate::loader::LoadBatch;
+use crate::migration::{apply_migrations, Migration, MigrationTracking, MIGRATIONS_TABLE};
+use crate::patch::struct_values;
+#[cfg(feature = "rtree")]
+use crate::rtree::RtreeTable;
+use crate::slow_query::{SlowQuery, UnindexedFilter};
+use crate::timer;
+use crate::transaction_log::{BoundParams, TransactionLogEntry};
+use crate::view::{RowView, ValueView};
+use crate::write_batch::WriteBatcher;
+use futures::channel::oneshot;
+use futures::future::{FutureExt, Shared};
+use futures::lock::{Mutex, MutexGuard};
+use runar_common::types::ArcValueType;
+use runar_macros::{action, service};
+use runar_node::services::RequestContext;
 use runar_node::LifecycleContext;
-use rusqlite::{params, Connection, OptionalExtension, Result};
-use std::{collections::HashMap, sync::Arc};
+use rusqlite::types::{ToSqlOutput, ValueRef};
+use rusqlite::{
+    Batch, Connection, ErrorCode, InterruptHandle, OpenFlags, OptionalExtension, Statement, ToSql,
+    Transaction, TransactionBehavior,
+};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+};
 
 /// Core value types for SQLite operations
 #[derive(Debug, Clone, PartialEq)]
@@ -14,6 +44,396 @@ pub enum Value {
     Boolean(bool),
 }
 
+impl Value {
+    /// The storage class of the value, as SQLite names it, or `BOOLEAN`
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "NULL",
+            Value::Integer(_) => "INTEGER",
+            Value::Real(_) => "REAL",
+            Value::Text(_) => "TEXT",
+            Value::Blob(_) => "BLOB",
+            Value::Boolean(_) => "BOOLEAN",
+        }
+    }
+
+    /// Render the value as an SQL literal, for the few places that cannot
+    /// take a bound parameter
+    pub(crate) fn to_sql_literal(&self) -> String {
+        match self {
+            Value::Null => "NULL".to_string(),
+            Value::Integer(i) => i.to_string(),
+            Value::Real(r) => format!("{:?}", r),
+            Value::Text(s) => quote_literal(s),
+            Value::Blob(b) => {
+                let hex: String = b.iter().map(|byte| format!("{:02X}", byte)).collect();
+                format!("X'{}'", hex)
+            }
+            Value::Boolean(b) => (*b as i64).to_string(),
+        }
+    }
+
+    /// `values` as a JSON array in a text value, for binding a list to a
+    /// single parameter read back with `json_each`. Elements read back as
+    /// the same SQL values, booleans as 0 and 1. Returns `None` if a value
+    /// has no JSON form: blobs and non-finite reals.
+    pub fn json_array(values: &[Value]) -> Option<Value> {
+        let elements = values
+            .iter()
+            .map(|value| {
+                Some(match value {
+                    Value::Null => serde_json::Value::Null,
+                    Value::Integer(i) => (*i).into(),
+                    Value::Real(r) => serde_json::Number::from_f64(*r)?.into(),
+                    Value::Text(s) => s.clone().into(),
+                    Value::Blob(_) => return None,
+                    Value::Boolean(b) => (*b as i64).into(),
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Value::Text(serde_json::Value::Array(elements).to_string()))
+    }
+}
+
+impl ToSql for Value {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(match self {
+            Value::Null => ToSqlOutput::Borrowed(ValueRef::Null),
+            Value::Integer(i) => ToSqlOutput::Borrowed(ValueRef::Integer(*i)),
+            Value::Real(r) => ToSqlOutput::Borrowed(ValueRef::Real(*r)),
+            Value::Text(s) => ToSqlOutput::Borrowed(ValueRef::Text(s.as_bytes())),
+            Value::Blob(b) => ToSqlOutput::Borrowed(ValueRef::Blob(b)),
+            Value::Boolean(b) => ToSqlOutput::Borrowed(ValueRef::Integer(*b as i64)),
+        })
+    }
+}
+
+impl From<ValueRef<'_>> for Value {
+    fn from(value: ValueRef<'_>) -> Self {
+        ValueView::from(value).into()
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(value)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::Integer(value as i64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Real(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Boolean(value)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Text(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Text(value.to_string())
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(value: Vec<u8>) -> Self {
+        Value::Blob(value)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+/// `In` lists longer than this bind as one JSON array, see
+/// [`QueryOperator::to_sql`]
+pub const JSON_IN_THRESHOLD: usize = 32;
+
+/// Key/value table for the service's own bookkeeping (e.g. the seed marker)
+pub const META_TABLE: &str = "_runar_meta";
+
+/// rusqlite's default statement cache capacity
+const STATEMENT_CACHE_CAPACITY: usize = 16;
+
+/// Pragmas that [`SqliteService::pragma`] may run. Pragmas that can corrupt
+/// the database or bypass the service, such as `writable_schema` or
+/// `schema_version`, are deliberately absent, as are `foreign_keys`,
+/// `max_page_count` and `temp_store`, which would undo the service's own
+/// configuration.
+pub const PRAGMA_ALLOWLIST: &[&str] = &[
+    "application_id",
+    "auto_vacuum",
+    "busy_timeout",
+    "cache_size",
+    "compile_options",
+    "data_version",
+    "database_list",
+    "foreign_key_check",
+    "foreign_key_list",
+    "freelist_count",
+    "incremental_vacuum",
+    "index_info",
+    "index_list",
+    "index_xinfo",
+    "integrity_check",
+    "journal_mode",
+    "journal_size_limit",
+    "optimize",
+    "page_count",
+    "page_size",
+    "quick_check",
+    "synchronous",
+    "table_info",
+    "table_list",
+    "table_xinfo",
+    "user_version",
+    "wal_autocheckpoint",
+    "wal_checkpoint",
+];
+
+/// Pragmas a single query may set with [`SqlQuery::with_pragma`]: settings
+/// that only change how statements run on the connection. `temp_store` is
+/// not one of them: changing it drops every TEMP table.
+pub const REQUEST_PRAGMA_ALLOWLIST: &[&str] = &[
+    "automatic_index",
+    "busy_timeout",
+    "cache_size",
+    "case_sensitive_like",
+    "query_only",
+    "reverse_unordered_selects",
+];
+
+/// A result row keyed by column name.
+///
+/// Every selected column has an entry, with NULLs as [`Value::Null`], so a
+/// missing key always means the column was not selected.
+pub type Row = HashMap<String, Value>;
+
+/// Rust types a result value converts into, see [`RowExt::get_as`]
+pub trait FromValue: Sized {
+    /// The kind of value converted from, for error messages
+    const EXPECTED: &'static str;
+    /// `None` when `value` is of another type
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl FromValue for i64 {
+    const EXPECTED: &'static str = "INTEGER";
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Integer(i) => Some(*i),
+            Value::Boolean(b) => Some(*b as i64),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for f64 {
+    const EXPECTED: &'static str = "REAL";
+    /// Integers convert too, as SQLite stores integral reals as integers
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Real(r) => Some(*r),
+            Value::Integer(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for bool {
+    const EXPECTED: &'static str = "BOOLEAN";
+    /// Booleans are read back as the integers 0 and 1 SQLite stores
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Boolean(b) => Some(*b),
+            Value::Integer(0) => Some(false),
+            Value::Integer(1) => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for String {
+    const EXPECTED: &'static str = "TEXT";
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Text(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for Vec<u8> {
+    const EXPECTED: &'static str = "BLOB";
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Blob(b) => Some(b.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    const EXPECTED: &'static str = T::EXPECTED;
+    /// NULL converts to `None`
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Null => Some(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+/// Typed access to the columns of a [`Row`]
+pub trait RowExt {
+    /// The value of `column` converted to `T`. Fails with
+    /// [`SqliteError::ColumnTypeMismatch`] when it holds another type, NULL
+    /// included unless `T` is an `Option`, or when the row has no such
+    /// column.
+    fn get_as<T: FromValue>(&self, column: &str) -> Result<T, SqliteError>;
+}
+
+impl RowExt for Row {
+    fn get_as<T: FromValue>(&self, column: &str) -> Result<T, SqliteError> {
+        let value = self.get(column);
+        value
+            .and_then(T::from_value)
+            .ok_or_else(|| SqliteError::ColumnTypeMismatch {
+                column: column.to_string(),
+                expected: T::EXPECTED.to_string(),
+                got: value.map_or("no value", Value::type_name).to_string(),
+            })
+    }
+}
+
+/// Errors raised by the SQLite service, in addition to underlying SQLite errors
+#[derive(Debug, thiserror::Error)]
+pub enum SqliteError {
+    #[error("sqlite service is not connected")]
+    NotConnected,
+    #[error("sqlite service is closed")]
+    Closed,
+    #[error("unknown query parameter: {0}")]
+    UnknownParameter(String),
+    #[error("foreign key cycle between tables: {}", .tables.join(", "))]
+    SchemaCycle { tables: Vec<String> },
+    #[error("database size quota of {max_bytes} bytes exceeded")]
+    QuotaExceeded { max_bytes: u64 },
+    #[error("database storage is full")]
+    StorageFull,
+    #[error("database I/O error: {0}")]
+    IoError(String),
+    #[error("pragma {0:?} is not allowed")]
+    PragmaNotAllowed(String),
+    #[error("patch of type {0} does not serialize to a struct or map")]
+    InvalidPatch(String),
+    #[error("value of type {0} does not serialize to a struct or map")]
+    InvalidRecord(String),
+    #[error("field {field} holds {value}, too large for an SQLite integer")]
+    IntegerOutOfRange { field: String, value: u64 },
+    #[error("query result exceeds the limit of {max_bytes} bytes")]
+    ResultTooLarge { max_bytes: usize },
+    #[error("invalid ORDER BY expression {expression:?}: {reason}")]
+    InvalidOrderExpression { expression: String, reason: String },
+    #[error("value {value:?} is not allowed in column {column} of {table}")]
+    InvalidEnumValue {
+        table: String,
+        column: String,
+        value: Value,
+    },
+    #[error("column {column} holds {got} where {expected} was expected")]
+    ColumnTypeMismatch {
+        column: String,
+        expected: String,
+        got: String,
+    },
+    #[error("row does not deserialize into {type_name}: {reason}")]
+    RowMapping { type_name: String, reason: String },
+    #[error("column {column} does not hold a valid serialized value: {reason}")]
+    InvalidBlob { column: String, reason: String },
+    #[error("row for table {table} is missing column {column}")]
+    MissingColumn { table: String, column: String },
+    #[error("invalid tenant id: {0:?}")]
+    InvalidTenantId(String),
+    #[error("migration version {0} is defined more than once")]
+    DuplicateMigration(u32),
+    #[error("migration version {0} has no down migration")]
+    IrreversibleMigration(u32),
+    #[error("migration version {0} is applied but not defined")]
+    UnknownMigration(u32),
+    #[error("query interrupted after exceeding the {timeout:?} query timeout")]
+    QueryTimeout { timeout: Duration },
+    #[error("statement is not in the allowlist: {0:?}")]
+    StatementNotAllowed(String),
+    #[error("no row of {table} matches")]
+    NotFound { table: String },
+    #[error("more than one row of {table} matches where one was expected")]
+    TooManyRows { table: String },
+    #[error("union of reads selecting {left} and {right} columns")]
+    UnionColumnMismatch { left: usize, right: usize },
+    #[error("database page size is {actual} bytes, configured {configured}")]
+    PageSizeMismatch { configured: u32, actual: u32 },
+    #[error("table {0} is scoped; access it through SqliteService::scoped")]
+    ScopeRequired(String),
+    #[error("foreign key {column} ({local:?}) references {foreign_column} ({foreign:?})")]
+    ForeignKeyTypeMismatch {
+        column: String,
+        local: DataType,
+        foreign_column: String,
+        foreign: DataType,
+    },
+    #[error("table {0} has no checksum column")]
+    NoChecksumColumn(String),
+    #[error("table {0} has a checksum column; write it with CRUD operations")]
+    ChecksumTable(String),
+    #[error("{operation} is not permitted on table {table}")]
+    PermissionDenied {
+        table: String,
+        operation: &'static str,
+    },
+    #[error("operation on table {table} would change its scope column {column}")]
+    ScopeViolation { table: String, column: String },
+    #[error("{failed} batched writes failed, the first with: {first}")]
+    BatchedWritesFailed { failed: usize, first: String },
+    #[error("invalid database URI {uri}: {reason}")]
+    InvalidUri { uri: String, reason: String },
+    #[error("table {0} exists with a different definition than configured")]
+    SchemaConflict(String),
+    #[error("region has {actual} dimensions, R*Tree {table} has {expected}")]
+    RtreeDimensions {
+        table: String,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("schema owner {0} must track migrations in a table, not PRAGMA user_version")]
+    SharedUserVersion(String),
+    #[error("invalid cursor: {0:?}")]
+    InvalidCursor(String),
+    #[error("cursor reads of table {0} need at least one key column")]
+    NoKeyColumns(String),
+    #[error("identifiers {first} and {second} differ only in case")]
+    IdentifierCase { first: String, second: String },
+    #[error("query filters {table} on column {column}, which no index starts with")]
+    MissingIndex { table: String, column: String },
+}
+
 /// Parameter bindings for SQL queries
 #[derive(Debug, Default, Clone, PartialEq)]
 pub struct Params {
@@ -37,6 +457,8 @@ impl Params {
 pub struct SqlQuery {
     pub statement: String,
     pub params: Params,
+    /// Pragmas set for this query only, see [`SqlQuery::with_pragma`]
+    pub pragmas: Vec<(String, Value)>,
 }
 
 impl SqlQuery {
@@ -44,12 +466,22 @@ impl SqlQuery {
         Self {
             statement: statement.to_string(),
             params: Params::new(),
+            pragmas: Vec::new(),
         }
     }
     pub fn with_params(mut self, params: Params) -> Self {
         self.params = params;
         self
     }
+    /// Set `PRAGMA name = value` on the connection running this query, and
+    /// restore the previous value once it has run, whether or not it
+    /// succeeded. `name` must be in [`REQUEST_PRAGMA_ALLOWLIST`]. Pragmas
+    /// that cannot be read back, such as `case_sensitive_like`, are restored
+    /// to 0.
+    pub fn with_pragma(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.pragmas.push((name.to_string(), value.into()));
+        self
+    }
 }
 
 /// Query operators for building advanced queries
@@ -63,12 +495,17 @@ pub enum QueryOperator {
     LessThanOrEqual(Value),
     Like(String),
     In(Vec<Value>),
+    /// Compare the column using a collation, e.g. case-insensitively with
+    /// `Collate(Collation::NoCase, Box::new(Equal(..)))`
+    Collate(Collation, Box<QueryOperator>),
 }
 
 /// Query builder for composable, immutable queries
 #[derive(Debug, Default, Clone, PartialEq)]
 pub struct Query {
     pub conditions: HashMap<String, QueryOperator>,
+    /// Arbitrary condition tree, combined with `conditions` using `AND`
+    pub filter: Option<Condition>,
 }
 
 impl Query {
@@ -79,211 +516,3593 @@ impl Query {
         self.conditions.insert(field.to_string(), op);
         self
     }
+
+    /// Add a condition tree, usually built with [`query!`](crate::query).
+    /// Calling this again requires both filters to match.
+    pub fn with_filter(mut self, filter: Condition) -> Self {
+        self.filter = Some(match self.filter.take() {
+            Some(existing) => Condition::and(vec![existing, filter]),
+            None => filter,
+        });
+        self
+    }
+
+    /// Render the conditions as a `WHERE` clause with positional `?`
+    /// parameters, or an empty string when there are no conditions.
+    /// Conditions are joined with `AND`, in field name order so the same
+    /// query always renders the same SQL.
+    pub fn to_sql(&self) -> (String, Vec<Value>) {
+        let mut clauses = Vec::with_capacity(self.conditions.len() + 1);
+        let mut params = Vec::new();
+        for (field, op) in sorted_entries(&self.conditions) {
+            let (clause, mut values) = op.to_sql(field);
+            clauses.push(clause);
+            params.append(&mut values);
+        }
+        if let Some(filter) = &self.filter {
+            let (clause, mut values) = if clauses.is_empty() {
+                filter.to_sql()
+            } else {
+                filter.operand_sql()
+            };
+            clauses.push(clause);
+            params.append(&mut values);
+        }
+        if clauses.is_empty() {
+            return (String::new(), Vec::new());
+        }
+        (format!(" WHERE {}", clauses.join(" AND ")), params)
+    }
+}
+
+impl QueryOperator {
+    /// Render the condition for `field` with positional `?` parameters.
+    ///
+    /// `In` expands to one placeholder per value. An empty list renders as
+    /// the constant `0`, which matches no row, so negating it matches every
+    /// row, `NULL`s included. Lists longer than
+    /// [`JSON_IN_THRESHOLD`] instead bind as a single JSON array read with
+    /// `IN (SELECT value FROM json_each(?))`, so the statement text does not
+    /// depend on the list length and stays cacheable; lists containing
+    /// values with no JSON form keep one placeholder per value.
+    pub fn to_sql(&self, field: &str) -> (String, Vec<Value>) {
+        self.render(&quote_ident(field))
+    }
+
+    /// Render the condition against an already quoted column expression
+    fn render(&self, column: &str) -> (String, Vec<Value>) {
+        let (op, value) = match self {
+            QueryOperator::Equal(v) => ("=", v.clone()),
+            QueryOperator::NotEqual(v) => ("!=", v.clone()),
+            QueryOperator::GreaterThan(v) => (">", v.clone()),
+            QueryOperator::GreaterThanOrEqual(v) => (">=", v.clone()),
+            QueryOperator::LessThan(v) => ("<", v.clone()),
+            QueryOperator::LessThanOrEqual(v) => ("<=", v.clone()),
+            QueryOperator::Like(pattern) => ("LIKE", Value::Text(pattern.clone())),
+            QueryOperator::In(values) => {
+                if values.is_empty() {
+                    return ("0".to_string(), Vec::new());
+                }
+                if values.len() > JSON_IN_THRESHOLD {
+                    if let Some(array) = Value::json_array(values) {
+                        return (
+                            format!("{} IN (SELECT value FROM json_each(?))", column),
+                            vec![array],
+                        );
+                    }
+                }
+                let placeholders = vec!["?"; values.len()].join(", ");
+                return (format!("{} IN ({})", column, placeholders), values.clone());
+            }
+            QueryOperator::Collate(collation, op) => {
+                return op.render(&format!("{} COLLATE {}", column, collation.as_sql()));
+            }
+        };
+        (format!("{} {} ?", column, op), vec![value])
+    }
 }
 
 /// CRUD operation types
 #[derive(Debug, Clone, PartialEq)]
 pub struct CreateOperation {
     pub table: String,
+    /// Attached database holding `table`, see [`CrudOperation::schema`]
+    pub schema: Option<String>,
     pub data: HashMap<String, Value>,
+    /// Key columns of an upsert: when the new row conflicts with an existing
+    /// one on them, that row is updated with the other columns instead
+    pub conflict_columns: Vec<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ReadOperation {
     pub table: String,
+    /// Attached database holding `table`, see [`CrudOperation::schema`]
+    pub schema: Option<String>,
     pub query: Query,
     pub fields: Option<Vec<String>>,
     pub limit: Option<u32>,
     pub offset: Option<u32>,
-    pub order_by: Option<Vec<(String, bool)>>, // (field, is_ascending)
-}
-
-#[derive(Debug, Clone, PartialEq)]
-pub struct UpdateOperation {
-    pub table: String,
-    pub query: Query,
-    pub updates: HashMap<String, Value>,
+    pub order_by: Option<Vec<(OrderTerm, bool)>>, // (term, is_ascending)
+    /// Collations applied to `order_by` column terms, by field
+    pub order_collations: HashMap<String, Collation>,
+    /// Common table expressions prepended to the `SELECT`; `table` may name one
+    pub ctes: Vec<CommonTableExpression>,
+    /// Computed columns selected after `fields`, or after every column when
+    /// `fields` is unset
+    pub expressions: Vec<SelectExpression>,
+    /// Aggregates selected after `fields`; when present, `fields` become the
+    /// `GROUP BY` columns
+    pub aggregates: Vec<Aggregate>,
 }
 
+/// A term of a read's `ORDER BY`
 #[derive(Debug, Clone, PartialEq)]
-pub struct DeleteOperation {
-    pub table: String,
-    pub query: Query,
+pub enum OrderTerm {
+    Column(String),
+    /// An SQL expression such as `LENGTH(name)`, checked by
+    /// [`OrderTerm::validate`] before the read runs. It is spliced into the
+    /// statement, so it must not come from user input all the same.
+    Expression(String),
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum CrudOperation {
-    Create(CreateOperation),
-    Read(ReadOperation),
-    Update(UpdateOperation),
-    Delete(DeleteOperation),
+impl OrderTerm {
+    /// Check that an expression term is a single, complete expression:
+    /// quotes and parentheses balanced, and no `;`, comment, comma or
+    /// clause keyword such as `LIMIT` or `UNION` outside them, so it cannot
+    /// end the `ORDER BY` early. Fails with
+    /// [`SqliteError::InvalidOrderExpression`]. Column terms always pass.
+    pub fn validate(&self) -> Result<(), SqliteError> {
+        let OrderTerm::Expression(sql) = self else {
+            return Ok(());
+        };
+        let invalid = |reason: &str| SqliteError::InvalidOrderExpression {
+            expression: sql.clone(),
+            reason: reason.to_string(),
+        };
+        let mut depth = 0usize;
+        let mut word = String::new();
+        let mut chars = sql.chars().peekable();
+        let check_word = |word: &mut String| {
+            let upper = word.to_ascii_uppercase();
+            word.clear();
+            match upper.as_str() {
+                "LIMIT" | "OFFSET" | "UNION" | "EXCEPT" | "INTERSECT" | "ASC" | "DESC"
+                | "NULLS" | "WINDOW" | "FROM" | "WHERE" => {
+                    Err(invalid(&format!("{} is not allowed", upper)))
+                }
+                _ => Ok(()),
+            }
+        };
+        while let Some(c) = chars.next() {
+            if c.is_alphanumeric() || c == '_' {
+                if depth == 0 {
+                    word.push(c);
+                }
+                continue;
+            }
+            check_word(&mut word)?;
+            match c {
+                '\'' | '"' | '`' | '[' => {
+                    let close = if c == '[' { ']' } else { c };
+                    if !chars.any(|next| next == close) {
+                        return Err(invalid("unterminated quote"));
+                    }
+                }
+                '(' => depth += 1,
+                ')' => {
+                    depth = depth
+                        .checked_sub(1)
+                        .ok_or_else(|| invalid("unbalanced parentheses"))?
+                }
+                ';' => return Err(invalid("; is not allowed")),
+                '-' if chars.peek() == Some(&'-') => {
+                    return Err(invalid("comments are not allowed"))
+                }
+                '/' if chars.peek() == Some(&'*') => {
+                    return Err(invalid("comments are not allowed"))
+                }
+                ',' if depth == 0 => {
+                    return Err(invalid("add each term separately instead of a list"))
+                }
+                _ => {}
+            }
+        }
+        check_word(&mut word)?;
+        if depth != 0 {
+            return Err(invalid("unbalanced parentheses"));
+        }
+        if sql.trim().is_empty() {
+            return Err(invalid("expression is empty"));
+        }
+        Ok(())
+    }
 }
 
-/// Schema definition for the SQLite database
+/// A computed column of a read, such as `price * quantity AS total`
 #[derive(Debug, Clone, PartialEq)]
-pub struct Schema {
-    pub tables: Vec<TableDefinition>,
+pub struct SelectExpression {
+    /// SQL expression over the table's columns. It is spliced into the
+    /// statement as written, so it must never come from user input.
+    pub sql: String,
+    /// Name of the result column
+    pub alias: String,
 }
 
-impl Schema {
-    pub fn new() -> Self {
-        Self { tables: Vec::new() }
+impl SelectExpression {
+    pub fn new(sql: &str, alias: &str) -> Self {
+        Self {
+            sql: sql.to_string(),
+            alias: alias.to_string(),
+        }
     }
-    pub fn add_table(mut self, table: TableDefinition) -> Self {
-        self.tables.push(table);
-        self
+    /// The select-list entry, `(sql) AS "alias"`
+    pub fn to_sql(&self) -> String {
+        format!("({}) AS {}", self.sql, quote_ident(&self.alias))
     }
 }
 
+/// An aggregate column of a read, such as `SUM(price) AS total`
 #[derive(Debug, Clone, PartialEq)]
-pub struct TableDefinition {
-    pub name: String,
-    pub columns: Vec<ColumnDefinition>,
-    pub primary_key: Vec<String>,
-    pub foreign_keys: Vec<ForeignKey>,
-    pub indexes: Vec<IndexDefinition>,
+pub struct Aggregate {
+    pub function: AggregateFunction,
+    /// The aggregated column; `None` only for `COUNT(*)`
+    pub column: Option<String>,
+    /// Name of the result column
+    pub alias: String,
+    pub nulls: NullHandling,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct ColumnDefinition {
-    pub name: String,
-    pub data_type: DataType,
-    pub constraints: Vec<ColumnConstraint>,
-    pub default_value: Option<DefaultValue>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum DataType {
-    Integer,
-    Text,
-    Real,
-    Blob,
+/// How an [`Aggregate`] treats NULLs in its column
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NullHandling {
+    /// Leave NULLs out, SQLite's behavior: they are not counted, not part of
+    /// an average, and a `SUM` over only NULLs is NULL
+    #[default]
+    Skip,
+    /// Count NULLs as zero by aggregating `COALESCE(column, 0)`
+    AsZero,
 }
 
+/// A named subquery placed in the `WITH` clause of a read
 #[derive(Debug, Clone, PartialEq)]
-pub enum ColumnConstraint {
-    PrimaryKey,
-    NotNull,
-    Unique,
+pub struct CommonTableExpression {
+    pub name: String,
+    /// Optional column names, required by SQLite for some recursive queries
+    pub columns: Vec<String>,
+    /// The body of the CTE, with positional `?` parameters
+    pub sql: String,
+    /// Values for the `?` parameters of `sql`, bound before the read's own
+    pub params: Vec<Value>,
+    /// Whether `sql` refers to the CTE itself
+    pub recursive: bool,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum DefaultValue {
-    Integer(i64),
-    Text(String),
-    Real(f64),
-    Null,
-    CurrentTimestamp,
+impl CommonTableExpression {
+    pub fn new(name: &str, sql: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            columns: Vec::new(),
+            sql: sql.to_string(),
+            params: Vec::new(),
+            recursive: false,
+        }
+    }
+    /// A CTE whose body refers to itself, such as a tree traversal
+    pub fn recursive(name: &str, sql: &str) -> Self {
+        Self {
+            recursive: true,
+            ..Self::new(name, sql)
+        }
+    }
+    pub fn with_columns(mut self, columns: &[&str]) -> Self {
+        self.columns = columns.iter().map(|c| c.to_string()).collect();
+        self
+    }
+    pub fn with_params(mut self, params: Vec<Value>) -> Self {
+        self.params = params;
+        self
+    }
+
+    fn to_sql(&self) -> String {
+        let columns = if self.columns.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", quote_idents(&self.columns))
+        };
+        format!("{}{} AS ({})", quote_ident(&self.name), columns, self.sql)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct ForeignKey {
-    pub column: String,
-    pub foreign_table: String,
-    pub foreign_column: String,
-    pub on_delete: ForeignKeyAction,
-    pub on_update: ForeignKeyAction,
+pub struct UpdateOperation {
+    pub table: String,
+    /// Attached database holding `table`, see [`CrudOperation::schema`]
+    pub schema: Option<String>,
+    pub query: Query,
+    /// Columns to change. A column mapped to [`Value::Null`] is set to NULL;
+    /// a column with no entry keeps its current value.
+    pub updates: HashMap<String, Value>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum ForeignKeyAction {
-    NoAction,
-    Cascade,
-    SetNull,
-    SetDefault,
-    Restrict,
+pub struct DeleteOperation {
+    pub table: String,
+    /// Attached database holding `table`, see [`CrudOperation::schema`]
+    pub schema: Option<String>,
+    pub query: Query,
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct IndexDefinition {
-    pub name: String,
-    pub columns: Vec<String>,
-    pub unique: bool,
+pub enum CrudOperation {
+    Create(CreateOperation),
+    Read(ReadOperation),
+    Update(UpdateOperation),
+    Delete(DeleteOperation),
 }
 
-// Intention: Implementation logic, connection pooling, and service trait integration will be added only after tests and documentation are aligned with this API.
-
-/// SQLite Service configuration
-#[derive(Debug, Clone, PartialEq)]
-pub struct SqliteConfig {
-    /// Path to the SQLite database file
-    pub db_path: String,
-    /// Schema definition for the database
-    pub schema: Schema,
+/// The `with_schema` setter shared by the operations
+macro_rules! with_schema {
+    () => {
+        /// Target the table in the attached database `alias`, see
+        /// [`CrudOperation::schema`]
+        pub fn with_schema(mut self, alias: &str) -> Self {
+            self.schema = Some(alias.to_string());
+            self
+        }
+    };
 }
 
-impl SqliteConfig {
-    /// Create a new SQLite config with path and schema
-    pub fn new(db_path: impl Into<String>, schema: Schema) -> Self {
+impl CreateOperation {
+    pub fn new(table: &str) -> Self {
         Self {
-            db_path: db_path.into(),
-            schema,
+            table: table.to_string(),
+            schema: None,
+            data: HashMap::new(),
+            conflict_columns: Vec::new(),
         }
     }
+    /// Insert the fields of `record`, serialized with serde, as columns.
+    ///
+    /// `None` fields are inserted as NULL. Strings, numbers and booleans map
+    /// to the matching [`Value`]; nested structs, maps and sequences are
+    /// stored as JSON text. Fails with [`SqliteError::InvalidRecord`] unless
+    /// `record` serializes to a struct or map, and with
+    /// [`SqliteError::IntegerOutOfRange`] for an unsigned integer above
+    /// `i64::MAX`.
+    pub fn from_struct<T: Serialize>(table: &str, record: &T) -> anyhow::Result<Self> {
+        let data = struct_values(record)?
+            .ok_or_else(|| SqliteError::InvalidRecord(std::any::type_name::<T>().to_string()))?;
+        Ok(Self {
+            data,
+            ..Self::new(table)
+        })
+    }
+    with_schema!();
+    pub fn with_value(mut self, field: &str, value: impl Into<Value>) -> Self {
+        self.data.insert(field.to_string(), value.into());
+        self
+    }
+    /// Upsert on `columns`, which must be the primary key or carry a unique
+    /// index, see [`CreateOperation::conflict_columns`]
+    pub fn with_upsert(mut self, columns: &[&str]) -> Self {
+        self.conflict_columns = columns.iter().map(|c| c.to_string()).collect();
+        self
+    }
 }
 
-#[service(name = "sqlite", version = "1.0.0", description = "SQLite Service")]
-pub struct SqliteService {
-    config: SqliteConfig,
-    connection: Option<Arc<Connection>>,
-}
-
-impl SqliteService {
-    /// Create a new SQLite service with the given config
-    pub fn new(config: SqliteConfig) -> Self {
+impl ReadOperation {
+    pub fn new(table: &str) -> Self {
         Self {
-            config,
-            connection: None,
+            table: table.to_string(),
+            schema: None,
+            query: Query::new(),
+            fields: None,
+            limit: None,
+            offset: None,
+            order_by: None,
+            order_collations: HashMap::new(),
+            ctes: Vec::new(),
+            expressions: Vec::new(),
+            aggregates: Vec::new(),
         }
     }
-
-    async fn start(&self, context: LifecycleContext) -> Result<()> {
-        let path = self.path();
-        context.info(format!("starting sqlite service at path: {}", path));
-        let connection = Connection::open(self.config.db_path).unwrap();
-        self.connection = Some(Arc::new(connection));
-        self.initialize_schema()?;
-        Ok(())
+    pub fn with_query(mut self, query: Query) -> Self {
+        self.query = query;
+        self
     }
-
-    async fn stop(&self, context: LifecycleContext) -> Result<()> {
-        context.info("MathService stopped".to_string());
-        Ok(())
+    with_schema!();
+    pub fn with_fields(mut self, fields: &[&str]) -> Self {
+        self.fields = Some(fields.iter().map(|f| f.to_string()).collect());
+        self
+    }
+    /// Limit and offset are bound as parameters, so pages of the same read
+    /// share one cached statement. Both are unsigned, so never negative.
+    pub fn with_limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+    pub fn with_offset(mut self, offset: u32) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+    /// Add a common table expression to the `WITH` clause
+    pub fn with_cte(mut self, cte: CommonTableExpression) -> Self {
+        self.ctes.push(cte);
+        self
+    }
+    /// Order by `field` compared using `collation`
+    pub fn with_order_by_collated(
+        mut self,
+        field: &str,
+        ascending: bool,
+        collation: Collation,
+    ) -> Self {
+        self.order_collations.insert(field.to_string(), collation);
+        self.with_order_by(field, ascending)
+    }
+    pub fn with_order_by(mut self, field: &str, ascending: bool) -> Self {
+        self.order_by
+            .get_or_insert_with(Vec::new)
+            .push((OrderTerm::Column(field.to_string()), ascending));
+        self
+    }
+    /// Order by an SQL expression such as `LENGTH(name)`, after any terms
+    /// added before. The expression is validated when the read runs, see
+    /// [`OrderTerm::validate`].
+    pub fn with_order_by_expression(mut self, sql: &str, ascending: bool) -> Self {
+        self.order_by
+            .get_or_insert_with(Vec::new)
+            .push((OrderTerm::Expression(sql.to_string()), ascending));
+        self
+    }
+    /// Render `self UNION [ALL] other` with positional `?` parameters, this
+    /// read's first. Each read becomes a subquery, so both may keep their
+    /// own `ORDER BY`, `LIMIT` and common table expressions.
+    pub fn union_sql(&self, other: &ReadOperation, all: bool) -> (String, Vec<Value>) {
+        let (left, mut params) = CrudOperation::Read(self.clone()).to_sql();
+        let (right, mut right_params) = CrudOperation::Read(other.clone()).to_sql();
+        params.append(&mut right_params);
+        let union = if all { "UNION ALL" } else { "UNION" };
+        (
+            format!(
+                "SELECT * FROM ({}) {} SELECT * FROM ({})",
+                left, union, right
+            ),
+            params,
+        )
+    }
+    /// Select the computed `sql` as `alias`, see [`SelectExpression`]
+    pub fn with_expression(mut self, sql: &str, alias: &str) -> Self {
+        self.expressions.push(SelectExpression::new(sql, alias));
+        self
+    }
+    /// Select an aggregate; any `fields` are grouped by
+    pub fn with_aggregate(mut self, aggregate: Aggregate) -> Self {
+        self.aggregates.push(aggregate);
+        self
     }
+}
 
-    fn initialize_schema(&self, conn: &Connection) -> Result<()> {
-        //
-        let schema = self.config.schema;
-        for table in schema.tables {
-            let sql = format!(
-                "CREATE TABLE {} ({});",
-                table.name,
-                table.columns.join(", ")
-            );
-            conn.execute(sql.as_str(), params![])?;
+impl Aggregate {
+    fn of(function: AggregateFunction, column: &str, alias: &str) -> Self {
+        Self {
+            function,
+            column: Some(column.to_string()),
+            alias: alias.to_string(),
+            nulls: NullHandling::default(),
         }
-        Ok(())
     }
-
-    async fn execute_sql(&self, query: SqlQuery) -> anyhow::Result<Vec<HashMap<String, Value>>> {
-        let connection = self.connection.as_ref().unwrap();
-        let mut conn = connection.lock().unwrap();
-        let rows = conn.query_map(query.statement, |row| {
-            let mut map = HashMap::new();
-            map.insert("id".to_string(), Value::Integer(row.get(0).unwrap()));
-            map.insert("name".to_string(), Value::Text(row.get(1).unwrap()));
-            map.insert("email".to_string(), Value::Text(row.get(2).unwrap()));
-            map.insert("age".to_string(), Value::Integer(row.get(3).unwrap()));
-            map
-        })?;
-        let mut result = Vec::new();
-        for row in rows {
-            result.push(row?);
+    /// `COUNT(*)`, counting rows regardless of NULLs
+    pub fn count_rows(alias: &str) -> Self {
+        Self {
+            function: AggregateFunction::Count,
+            column: None,
+            alias: alias.to_string(),
+            nulls: NullHandling::default(),
         }
-        Ok(result)
+    }
+    pub fn count(column: &str, alias: &str) -> Self {
+        Self::of(AggregateFunction::Count, column, alias)
+    }
+    pub fn sum(column: &str, alias: &str) -> Self {
+        Self::of(AggregateFunction::Sum, column, alias)
+    }
+    pub fn avg(column: &str, alias: &str) -> Self {
+        Self::of(AggregateFunction::Avg, column, alias)
+    }
+    pub fn min(column: &str, alias: &str) -> Self {
+        Self::of(AggregateFunction::Min, column, alias)
+    }
+    pub fn max(column: &str, alias: &str) -> Self {
+        Self::of(AggregateFunction::Max, column, alias)
+    }
+    pub fn with_nulls(mut self, nulls: NullHandling) -> Self {
+        self.nulls = nulls;
+        self
     }
 
-    /// Perform a CRUD operation (type-safe API)
-    async fn execute_crud(&self, op: CrudOperation) -> anyhow::Result<Vec<HashMap<String, Value>>> {
+    /// The aggregate as a result column, e.g. `SUM(COALESCE("x", 0)) AS "total"`
+    pub fn to_sql(&self) -> String {
+        let argument = match (&self.column, self.nulls) {
+            (None, _) => "*".to_string(),
+            (Some(column), NullHandling::Skip) => quote_ident(column),
+            (Some(column), NullHandling::AsZero) => format!("COALESCE({}, 0)", quote_ident(column)),
+        };
+        format!(
+            "{}({}) AS {}",
+            self.function.as_sql(),
+            argument,
+            quote_ident(&self.alias)
+        )
+    }
+}
+
+impl AggregateFunction {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            AggregateFunction::Count => "COUNT",
+            AggregateFunction::Sum => "SUM",
+            AggregateFunction::Avg => "AVG",
+            AggregateFunction::Min => "MIN",
+            AggregateFunction::Max => "MAX",
+        }
+    }
+}
+
+impl UpdateOperation {
+    pub fn new(table: &str, query: Query) -> Self {
+        Self {
+            table: table.to_string(),
+            schema: None,
+            query,
+            updates: HashMap::new(),
+        }
+    }
+    with_schema!();
+    /// Set `field` to `value`; `None` and [`Value::Null`] set it to NULL
+    pub fn with_update(mut self, field: &str, value: impl Into<Value>) -> Self {
+        self.updates.insert(field.to_string(), value.into());
+        self
+    }
+    /// Set `field` to NULL
+    pub fn with_null(self, field: &str) -> Self {
+        self.with_update(field, Value::Null)
+    }
+}
+
+impl DeleteOperation {
+    pub fn new(table: &str, query: Query) -> Self {
+        Self {
+            table: table.to_string(),
+            schema: None,
+            query,
+        }
+    }
+    with_schema!();
+}
+
+impl CrudOperation {
+    /// The kind of operation: `create`, `read`, `update` or `delete`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CrudOperation::Create(_) => "create",
+            CrudOperation::Read(_) => "read",
+            CrudOperation::Update(_) => "update",
+            CrudOperation::Delete(_) => "delete",
+        }
+    }
+
+    /// The table the operation targets
+    pub fn table(&self) -> &str {
+        match self {
+            CrudOperation::Create(op) => &op.table,
+            CrudOperation::Read(op) => &op.table,
+            CrudOperation::Update(op) => &op.table,
+            CrudOperation::Delete(op) => &op.table,
+        }
+    }
+
+    /// The names in the SQL a read embeds, through its CTEs, computed columns
+    /// and `ORDER BY` expressions, as returned by [`sql_identifiers`]. Any of
+    /// them may be a table the read also reaches.
+    pub(crate) fn embedded_names(&self) -> Vec<String> {
+        let CrudOperation::Read(read) = self else {
+            return Vec::new();
+        };
+        let ctes = read.ctes.iter().map(|cte| cte.sql.as_str());
+        let expressions = read.expressions.iter().map(|e| e.sql.as_str());
+        let order = read
+            .order_by
+            .iter()
+            .flatten()
+            .filter_map(|(term, _)| match term {
+                OrderTerm::Expression(sql) => Some(sql.as_str()),
+                OrderTerm::Column(_) => None,
+            });
+        ctes.chain(expressions)
+            .chain(order)
+            .flat_map(sql_identifiers)
+            .collect()
+    }
+
+    /// The attached database holding the target table, by alias, as set
+    /// with the `with_schema` of each operation; see
+    /// [`SqliteConfig::with_attachment`]. With `None`, SQLite resolves the
+    /// table in `temp`, then `main`, then the attachments in order.
+    pub fn schema(&self) -> Option<&str> {
+        match self {
+            CrudOperation::Create(op) => op.schema.as_deref(),
+            CrudOperation::Read(op) => op.schema.as_deref(),
+            CrudOperation::Update(op) => op.schema.as_deref(),
+            CrudOperation::Delete(op) => op.schema.as_deref(),
+        }
+    }
+
+    /// The target table, qualified with its schema alias when one is set
+    pub(crate) fn qualified_table(&self) -> String {
+        match self.schema() {
+            Some(schema) => format!("{}.{}", quote_ident(schema), quote_ident(self.table())),
+            None => quote_ident(self.table()),
+        }
+    }
+
+    /// Render the operation as a single SQL statement with positional `?`
+    /// parameters. Writes return the affected rows via `RETURNING *`.
+    /// Columns are listed in name order, so equal operations render the same
+    /// SQL and share a cached statement.
+    pub fn to_sql(&self) -> (String, Vec<Value>) {
+        let table = self.qualified_table();
+        match self {
+            CrudOperation::Create(op) => {
+                if op.data.is_empty() {
+                    return (
+                        format!("INSERT INTO {} DEFAULT VALUES RETURNING *", table),
+                        Vec::new(),
+                    );
+                }
+                let (columns, params): (Vec<String>, Vec<Value>) = sorted_entries(&op.data)
+                    .into_iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .unzip();
+                let placeholders = vec!["?"; params.len()].join(", ");
+                let upsert = match op.conflict_columns.first() {
+                    Some(first) => {
+                        let mut updated: Vec<&String> = columns
+                            .iter()
+                            .filter(|c| !op.conflict_columns.contains(c))
+                            .collect();
+                        // Set a key to itself so the existing row is returned
+                        if updated.is_empty() {
+                            updated.push(first);
+                        }
+                        let set = updated
+                            .iter()
+                            .map(|c| format!("{0} = excluded.{0}", quote_ident(c)))
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        format!(
+                            " ON CONFLICT ({}) DO UPDATE SET {}",
+                            quote_idents(&op.conflict_columns),
+                            set
+                        )
+                    }
+                    None => String::new(),
+                };
+                let sql = format!(
+                    "INSERT INTO {} ({}) VALUES ({}){} RETURNING *",
+                    table,
+                    quote_idents(&columns),
+                    placeholders,
+                    upsert
+                );
+                (sql, params)
+            }
+            CrudOperation::Read(op) => {
+                let group_by = match &op.fields {
+                    Some(fields) if !fields.is_empty() && !op.aggregates.is_empty() => {
+                        format!(" GROUP BY {}", quote_idents(fields))
+                    }
+                    _ => String::new(),
+                };
+                let mut columns: Vec<String> = match &op.fields {
+                    Some(fields) if !fields.is_empty() => {
+                        fields.iter().map(|f| quote_ident(f)).collect()
+                    }
+                    _ if op.aggregates.is_empty() => vec!["*".to_string()],
+                    _ => Vec::new(),
+                };
+                columns.extend(op.expressions.iter().map(SelectExpression::to_sql));
+                columns.extend(op.aggregates.iter().map(Aggregate::to_sql));
+                let fields = columns.join(", ");
+                let mut sql = String::new();
+                let mut params = Vec::new();
+                if !op.ctes.is_empty() {
+                    // RECURSIVE applies to the whole WITH clause
+                    let recursive = op.ctes.iter().any(|cte| cte.recursive);
+                    let ctes: Vec<String> = op.ctes.iter().map(|cte| cte.to_sql()).collect();
+                    sql.push_str(if recursive {
+                        "WITH RECURSIVE "
+                    } else {
+                        "WITH "
+                    });
+                    sql.push_str(&ctes.join(", "));
+                    sql.push(' ');
+                    for cte in &op.ctes {
+                        params.extend(cte.params.iter().cloned());
+                    }
+                }
+                let (where_clause, mut where_params) = op.query.to_sql();
+                params.append(&mut where_params);
+                sql.push_str(&format!(
+                    "SELECT {} FROM {}{}{}",
+                    fields, table, where_clause, group_by
+                ));
+                if let Some(order_by) = op.order_by.as_ref().filter(|o| !o.is_empty()) {
+                    let terms: Vec<String> = order_by
+                        .iter()
+                        .map(|(term, ascending)| {
+                            let direction = if *ascending { "ASC" } else { "DESC" };
+                            let field = match term {
+                                OrderTerm::Column(field) => field,
+                                OrderTerm::Expression(sql) => {
+                                    return format!("({}) {}", sql, direction)
+                                }
+                            };
+                            match op.order_collations.get(field) {
+                                Some(collation) => format!(
+                                    "{} COLLATE {} {}",
+                                    quote_ident(field),
+                                    collation.as_sql(),
+                                    direction
+                                ),
+                                None => format!("{} {}", quote_ident(field), direction),
+                            }
+                        })
+                        .collect();
+                    sql.push_str(&format!(" ORDER BY {}", terms.join(", ")));
+                }
+                // Bound rather than spliced in, so every page of a query
+                // shares one cached statement. SQLite only accepts OFFSET
+                // after a LIMIT; -1 means no limit.
+                if op.limit.is_some() || op.offset.is_some() {
+                    sql.push_str(" LIMIT ? OFFSET ?");
+                    params.push(Value::Integer(op.limit.map_or(-1, i64::from)));
+                    params.push(Value::Integer(op.offset.map_or(0, i64::from)));
+                }
+                (sql, params)
+            }
+            // With nothing to change, return the matching rows as they are
+            CrudOperation::Update(op) if op.updates.is_empty() => {
+                let (where_clause, params) = op.query.to_sql();
+                let sql = format!("SELECT * FROM {}{}", table, where_clause);
+                (sql, params)
+            }
+            CrudOperation::Update(op) => {
+                let (assignments, mut params): (Vec<String>, Vec<Value>) =
+                    sorted_entries(&op.updates)
+                        .into_iter()
+                        .map(|(k, v)| (format!("{} = ?", quote_ident(k)), v.clone()))
+                        .unzip();
+                let (where_clause, mut where_params) = op.query.to_sql();
+                params.append(&mut where_params);
+                let sql = format!(
+                    "UPDATE {} SET {}{} RETURNING *",
+                    table,
+                    assignments.join(", "),
+                    where_clause
+                );
+                (sql, params)
+            }
+            CrudOperation::Delete(op) => {
+                let (where_clause, params) = op.query.to_sql();
+                let sql = format!("DELETE FROM {}{} RETURNING *", table, where_clause);
+                (sql, params)
+            }
+        }
+    }
+}
+
+/// Schema definition for the SQLite database.
+///
+/// Build it with [`Schema::new`] and its methods: its fields depend on the
+/// enabled features, so it cannot be written as a struct literal.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Schema {
+    pub tables: Vec<TableDefinition>,
+    /// R*Tree virtual tables, created after `tables`
+    #[cfg(feature = "rtree")]
+    pub rtree_tables: Vec<RtreeTable>,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn add_table(mut self, table: TableDefinition) -> Self {
+        self.tables.push(table);
+        self
+    }
+    /// Declare an R*Tree virtual table, see [`crate::rtree`]
+    #[cfg(feature = "rtree")]
+    pub fn add_rtree_table(mut self, table: RtreeTable) -> Self {
+        self.rtree_tables.push(table);
+        self
+    }
+
+    /// Render the schema as a complete SQL DDL script.
+    ///
+    /// Tables are emitted before the tables that reference them through
+    /// foreign keys, each followed by its indexes. Tables that take part in a
+    /// foreign-key cycle are emitted last, in declaration order; SQLite does
+    /// not check that a referenced table exists when a table is created.
+    pub fn to_ddl(&self) -> String {
+        let (mut ordered, mut cyclic) = self.sort_tables();
+        ordered.append(&mut cyclic);
+        let mut ddl = String::new();
+        for table in ordered {
+            ddl.push_str(&table.create_table_sql());
+            ddl.push_str(";\n");
+            for index in table.create_index_sql() {
+                ddl.push_str(&index);
+                ddl.push_str(";\n");
+            }
+        }
+        #[cfg(feature = "rtree")]
+        for table in &self.rtree_tables {
+            ddl.push_str(&table.create_table_sql());
+            ddl.push_str(";\n");
+        }
+        ddl
+    }
+
+    /// A stable hash of the schema as 16 hex digits, for checking whether
+    /// artifacts derived from it are still current.
+    ///
+    /// Covers every table with its columns, constraints, foreign keys and
+    /// indexes. The order in which tables and indexes are declared does not
+    /// matter; column order does, since it shows in `SELECT *`. The hash
+    /// (FNV-1a) is the same across builds and platforms.
+    pub fn fingerprint(&self) -> String {
+        let mut tables: Vec<String> = self
+            .tables
+            .iter()
+            .map(|table| {
+                let mut canonical = table.create_table_sql();
+                #[cfg(feature = "compression")]
+                for column in table.columns.iter().filter(|c| c.compressed) {
+                    canonical.push_str(&format!("\ncompressed {}", quote_ident(&column.name)));
+                }
+                let mut indexes = table.create_index_sql();
+                indexes.sort();
+                for index in indexes {
+                    canonical.push('\n');
+                    canonical.push_str(&index);
+                }
+                canonical
+            })
+            .collect();
+        #[cfg(feature = "rtree")]
+        tables.extend(self.rtree_tables.iter().map(RtreeTable::create_table_sql));
+        tables.sort();
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in tables.join("\n\n").bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        format!("{:016x}", hash)
+    }
+
+    /// Check that identifiers are spelled with consistent case.
+    ///
+    /// SQLite matches table and column names without regard to ASCII case
+    /// but keeps them as written, so `Email` and `email` name the same
+    /// column. Fails with [`SqliteError::IdentifierCase`] when two tables,
+    /// or two columns of a table, differ only in case, or when a primary
+    /// key, index or foreign key spells a table or column of the schema
+    /// differently from its declaration.
+    pub fn check_identifier_case(&self) -> Result<(), SqliteError> {
+        let mismatch =
+            |first: String, second: String| Err(SqliteError::IdentifierCase { first, second });
+        // The declaration matching `name` ignoring case, unless it is
+        // spelled the same
+        fn respelled<'a>(declared: impl Iterator<Item = &'a str>, name: &str) -> Option<&'a str> {
+            declared
+                .filter(|d| d.eq_ignore_ascii_case(name))
+                .find(|d| *d != name)
+        }
+        for (i, table) in self.tables.iter().enumerate() {
+            if let Some(other) = self.tables[..i]
+                .iter()
+                .find(|t| t.name.eq_ignore_ascii_case(&table.name))
+            {
+                return mismatch(other.name.clone(), table.name.clone());
+            }
+            let qualified = |column: &str| format!("{}.{}", table.name, column);
+            for (j, column) in table.columns.iter().enumerate() {
+                if let Some(other) = table.columns[..j]
+                    .iter()
+                    .find(|c| c.name.eq_ignore_ascii_case(&column.name))
+                {
+                    return mismatch(qualified(&other.name), qualified(&column.name));
+                }
+            }
+            let declared = || table.columns.iter().map(|c| c.name.as_str());
+            let references = table
+                .primary_key
+                .iter()
+                .chain(table.indexes.iter().flat_map(|index| &index.columns))
+                .chain(table.foreign_keys.iter().map(|fk| &fk.column));
+            for name in references {
+                if let Some(column) = respelled(declared(), name) {
+                    return mismatch(qualified(column), qualified(name));
+                }
+            }
+            for fk in &table.foreign_keys {
+                let tables = self.tables.iter().map(|t| t.name.as_str());
+                if let Some(foreign) = respelled(tables, &fk.foreign_table) {
+                    return mismatch(foreign.to_string(), fk.foreign_table.clone());
+                }
+                let Some(foreign) = self.tables.iter().find(|t| t.name == fk.foreign_table) else {
+                    continue;
+                };
+                let columns = foreign.columns.iter().map(|c| c.name.as_str());
+                if let Some(column) = respelled(columns, &fk.foreign_column) {
+                    return mismatch(
+                        format!("{}.{}", foreign.name, column),
+                        format!("{}.{}", foreign.name, fk.foreign_column),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that each foreign key column has the type of the column it
+    /// references, when the schema declares both. Fails with
+    /// [`SqliteError::ForeignKeyTypeMismatch`] otherwise; SQLite itself
+    /// accepts such keys, but their values then rarely compare equal.
+    pub fn check_foreign_key_types(&self) -> Result<(), SqliteError> {
+        let column_type = |table: &str, column: &str| {
+            self.tables
+                .iter()
+                .find(|t| t.name == table)?
+                .columns
+                .iter()
+                .find(|c| c.name == column)
+                .map(|c| c.data_type.clone())
+        };
+        for table in &self.tables {
+            for fk in &table.foreign_keys {
+                let local = column_type(&table.name, &fk.column);
+                let foreign = column_type(&fk.foreign_table, &fk.foreign_column);
+                if let (Some(local), Some(foreign)) = (local, foreign) {
+                    if local != foreign {
+                        return Err(SqliteError::ForeignKeyTypeMismatch {
+                            column: format!("{}.{}", table.name, fk.column),
+                            local,
+                            foreign_column: format!("{}.{}", fk.foreign_table, fk.foreign_column),
+                            foreign,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The statements creating the schema, in the order
+    /// [`SqliteService::connect`] runs them: each table after the tables it
+    /// references, followed by its indexes, then the R*Tree tables. Fails
+    /// if the foreign keys form a cycle.
+    pub(crate) fn create_statements(&self) -> Result<Vec<String>, SqliteError> {
+        let mut statements = Vec::new();
+        for table in self.dependency_order()? {
+            statements.push(table.create_table_sql());
+            statements.extend(table.create_index_sql());
+        }
+        #[cfg(feature = "rtree")]
+        statements.extend(self.rtree_tables.iter().map(RtreeTable::create_table_sql));
+        Ok(statements)
+    }
+
+    /// Tables sorted so that referenced tables come before their dependents.
+    ///
+    /// Self-references and references to tables outside the schema are
+    /// ignored. Fails if the foreign keys form a cycle.
+    pub fn dependency_order(&self) -> Result<Vec<&TableDefinition>, SqliteError> {
+        let (ordered, cyclic) = self.sort_tables();
+        if cyclic.is_empty() {
+            Ok(ordered)
+        } else {
+            Err(SqliteError::SchemaCycle {
+                tables: cyclic.iter().map(|t| t.name.clone()).collect(),
+            })
+        }
+    }
+
+    /// Split tables into a dependency-ordered list and the tables left over
+    /// because they take part in a cycle (in declaration order)
+    fn sort_tables(&self) -> (Vec<&TableDefinition>, Vec<&TableDefinition>) {
+        let mut ordered = Vec::with_capacity(self.tables.len());
+        let mut pending: Vec<&TableDefinition> = self.tables.iter().collect();
+        while !pending.is_empty() {
+            let ready = pending.iter().position(|table| {
+                table.foreign_keys.iter().all(|fk| {
+                    fk.foreign_table.eq_ignore_ascii_case(&table.name)
+                        || !pending
+                            .iter()
+                            .any(|other| other.name.eq_ignore_ascii_case(&fk.foreign_table))
+                })
+            });
+            match ready {
+                Some(position) => ordered.push(pending.remove(position)),
+                None => break,
+            }
+        }
+        (ordered, pending)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableDefinition {
+    pub name: String,
+    pub columns: Vec<ColumnDefinition>,
+    pub primary_key: Vec<String>,
+    pub foreign_keys: Vec<ForeignKey>,
+    pub indexes: Vec<IndexDefinition>,
+    /// Create the table with `CREATE TEMP TABLE`. Temporary tables belong to
+    /// a single connection and vanish when it closes; with a read pool every
+    /// connection gets its own empty copy, see [`TableDefinition::with_temporary`].
+    pub temporary: bool,
+    /// Emit `IF NOT EXISTS`, so an existing table is kept; see
+    /// [`TableDefinition::with_if_not_exists`]
+    pub if_not_exists: bool,
+    /// Column holding a checksum of the rest of each row, see
+    /// [`crate::checksum`]
+    pub checksum_column: Option<String>,
+}
+
+/// A column of a [`TableDefinition`]. Build it with
+/// [`ColumnDefinition::new`] and its methods: its fields depend on the
+/// enabled features, so it cannot be written as a struct literal.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ColumnDefinition {
+    pub name: String,
+    pub data_type: DataType,
+    pub constraints: Vec<ColumnConstraint>,
+    pub default_value: Option<DefaultValue>,
+    /// Collation used by comparisons, indexes and ordering on this column
+    pub collation: Option<Collation>,
+    /// The only integers the column may hold, for enums stored as integers,
+    /// see [`ColumnDefinition::with_allowed_values`]
+    pub allowed_values: Option<Vec<i64>>,
+    /// Whether values are stored zstd-compressed, see [`crate::compression`]
+    #[cfg(feature = "compression")]
+    pub compressed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataType {
+    Integer,
+    Text,
+    Real,
+    Blob,
+}
+
+/// Built-in SQLite collating sequences
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Collation {
+    /// Byte-wise comparison, SQLite's default
+    Binary,
+    /// Case-insensitive for ASCII letters
+    NoCase,
+    /// Like `Binary`, ignoring trailing spaces
+    RTrim,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnConstraint {
+    PrimaryKey,
+    NotNull,
+    Unique,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DefaultValue {
+    Integer(i64),
+    Text(String),
+    Real(f64),
+    Null,
+    CurrentTimestamp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeignKey {
+    pub column: String,
+    pub foreign_table: String,
+    pub foreign_column: String,
+    pub on_delete: ForeignKeyAction,
+    pub on_update: ForeignKeyAction,
+    /// Check the constraint when the transaction commits instead of after
+    /// each statement, see [`ForeignKey::with_deferred`]
+    pub deferred: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ForeignKeyAction {
+    NoAction,
+    Cascade,
+    SetNull,
+    SetDefault,
+    Restrict,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexDefinition {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub unique: bool,
+    /// Collation applied to every indexed column, overriding the columns'
+    /// own. A unique index with [`Collation::NoCase`] makes values that
+    /// differ only in ASCII case conflict.
+    pub collation: Option<Collation>,
+    /// Emit `IF NOT EXISTS`, so an existing index is kept; see
+    /// [`IndexDefinition::with_if_not_exists`]
+    pub if_not_exists: bool,
+}
+
+impl TableDefinition {
+    /// Create an empty table definition
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            columns: Vec::new(),
+            primary_key: Vec::new(),
+            foreign_keys: Vec::new(),
+            indexes: Vec::new(),
+            temporary: false,
+            if_not_exists: true,
+            checksum_column: None,
+        }
+    }
+    pub fn with_column(mut self, column: ColumnDefinition) -> Self {
+        self.columns.push(column);
+        self
+    }
+    pub fn with_primary_key(mut self, columns: &[&str]) -> Self {
+        self.primary_key = columns.iter().map(|c| c.to_string()).collect();
+        self
+    }
+    pub fn with_foreign_key(mut self, foreign_key: ForeignKey) -> Self {
+        self.foreign_keys.push(foreign_key);
+        self
+    }
+    /// Add a foreign key from `column` to `foreign_column` of
+    /// `foreign_table`, as [`ForeignKey::new`] does. Follow it with
+    /// [`TableDefinition::on_delete`] or [`TableDefinition::on_update`] to
+    /// set its actions.
+    pub fn foreign_key(self, column: &str, foreign_table: &str, foreign_column: &str) -> Self {
+        self.with_foreign_key(ForeignKey::new(column, foreign_table, foreign_column))
+    }
+    /// Set the `ON DELETE` action of the foreign key declared last.
+    ///
+    /// # Panics
+    ///
+    /// If the table has no foreign key yet.
+    pub fn on_delete(mut self, action: ForeignKeyAction) -> Self {
+        self.last_foreign_key("on_delete").on_delete = action;
+        self
+    }
+    /// Set the `ON UPDATE` action of the foreign key declared last.
+    ///
+    /// # Panics
+    ///
+    /// If the table has no foreign key yet.
+    pub fn on_update(mut self, action: ForeignKeyAction) -> Self {
+        self.last_foreign_key("on_update").on_update = action;
+        self
+    }
+    fn last_foreign_key(&mut self, method: &str) -> &mut ForeignKey {
+        let table = &self.name;
+        self.foreign_keys.last_mut().unwrap_or_else(|| {
+            panic!(
+                "{} called on table {} before any foreign key",
+                method, table
+            )
+        })
+    }
+    pub fn with_index(mut self, index: IndexDefinition) -> Self {
+        self.indexes.push(index);
+        self
+    }
+    /// Add a TEXT column `column` holding a checksum of the rest of each
+    /// row, kept current by CRUD writes; see [`crate::checksum`]
+    pub fn with_checksum_column(mut self, column: &str) -> Self {
+        self.columns
+            .push(ColumnDefinition::new(column, DataType::Text));
+        self.checksum_column = Some(column.to_string());
+        self
+    }
+    /// Make this a temporary table, created on every connect.
+    ///
+    /// Each connection of the service creates its own copy: the writer and,
+    /// when `read_connections` is set, every reader. The copies are separate
+    /// tables, so rows written through the writer are not visible to reads
+    /// served by a reader. Keep the read pool disabled when reading temporary
+    /// tables back, or read them inside an open transaction with
+    /// `read_your_writes` enabled so the read stays on the writer.
+    pub fn with_temporary(mut self, temporary: bool) -> Self {
+        self.temporary = temporary;
+        self
+    }
+    /// Whether creating the table tolerates an existing one, the default.
+    ///
+    /// Without `IF NOT EXISTS`, connecting fails when the table already
+    /// exists, for tables that must only ever be created on a fresh
+    /// database. The schema is created on every connect, so such a table
+    /// fails the second connect to the same file unless it is temporary.
+    pub fn with_if_not_exists(mut self, if_not_exists: bool) -> Self {
+        self.if_not_exists = if_not_exists;
+        self
+    }
+
+    /// The `CREATE TABLE` statement for this table (without trailing `;`)
+    pub fn create_table_sql(&self) -> String {
+        let mut parts: Vec<String> = self.columns.iter().map(|c| c.to_sql()).collect();
+        let inline_primary_key = self
+            .columns
+            .iter()
+            .any(|c| c.constraints.contains(&ColumnConstraint::PrimaryKey));
+        if !self.primary_key.is_empty() && !inline_primary_key {
+            parts.push(format!("PRIMARY KEY ({})", quote_idents(&self.primary_key)));
+        }
+        parts.extend(self.foreign_keys.iter().map(|fk| fk.to_sql()));
+        format!(
+            "CREATE {}TABLE {}{} ({})",
+            if self.temporary { "TEMP " } else { "" },
+            if self.if_not_exists {
+                "IF NOT EXISTS "
+            } else {
+                ""
+            },
+            quote_ident(&self.name),
+            parts.join(", ")
+        )
+    }
+
+    /// The `CREATE INDEX` statements for this table's indexes
+    pub fn create_index_sql(&self) -> Vec<String> {
+        self.indexes
+            .iter()
+            .map(|index| index.to_sql(&self.name))
+            .collect()
+    }
+}
+
+impl ColumnDefinition {
+    /// Create a column with no constraints and no default
+    pub fn new(name: &str, data_type: DataType) -> Self {
+        Self {
+            name: name.to_string(),
+            data_type,
+            constraints: Vec::new(),
+            default_value: None,
+            collation: None,
+            allowed_values: None,
+            #[cfg(feature = "compression")]
+            compressed: false,
+        }
+    }
+    pub fn with_constraint(mut self, constraint: ColumnConstraint) -> Self {
+        self.constraints.push(constraint);
+        self
+    }
+    pub fn with_default(mut self, default_value: DefaultValue) -> Self {
+        self.default_value = Some(default_value);
+        self
+    }
+    pub fn with_collation(mut self, collation: Collation) -> Self {
+        self.collation = Some(collation);
+        self
+    }
+    /// Restrict the column to `values`, as for an enum stored as integers.
+    ///
+    /// The column gets a `CHECK` constraint, so no statement can write
+    /// another value; NULL stays allowed unless the column is `NOT NULL`.
+    /// Writes and reads through [`SqliteService::execute_crud`] fail with
+    /// [`SqliteError::InvalidEnumValue`] on other values, including ones
+    /// written before the constraint existed.
+    pub fn with_allowed_values(mut self, values: &[i64]) -> Self {
+        self.allowed_values = Some(values.to_vec());
+        self
+    }
+    /// Store the column's values compressed. Declare such columns as
+    /// [`DataType::Blob`]; see [`crate::compression`] for the query limits.
+    #[cfg(feature = "compression")]
+    pub fn with_compressed(mut self, compressed: bool) -> Self {
+        self.compressed = compressed;
+        self
+    }
+
+    /// The column definition as it appears inside `CREATE TABLE`
+    pub fn to_sql(&self) -> String {
+        let mut sql = format!("{} {}", quote_ident(&self.name), self.data_type.as_sql());
+        for constraint in &self.constraints {
+            sql.push(' ');
+            sql.push_str(constraint.as_sql());
+        }
+        if let Some(default_value) = &self.default_value {
+            sql.push_str(" DEFAULT ");
+            sql.push_str(&default_value.to_sql());
+        }
+        if let Some(collation) = self.collation {
+            sql.push_str(" COLLATE ");
+            sql.push_str(collation.as_sql());
+        }
+        if let Some(values) = &self.allowed_values {
+            let values: Vec<String> = values.iter().map(i64::to_string).collect();
+            sql.push_str(&format!(
+                " CHECK ({} IN ({}))",
+                quote_ident(&self.name),
+                values.join(", ")
+            ));
+        }
+        sql
+    }
+
+    /// Whether `value` may be stored in the column: any value without
+    /// [`ColumnDefinition::allowed_values`], otherwise NULL or one of them
+    pub(crate) fn allows(&self, value: &Value) -> bool {
+        let Some(allowed) = &self.allowed_values else {
+            return true;
+        };
+        match value {
+            Value::Null => true,
+            Value::Integer(i) => allowed.contains(i),
+            Value::Boolean(b) => allowed.contains(&(*b as i64)),
+            _ => false,
+        }
+    }
+}
+
+impl Collation {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            Collation::Binary => "BINARY",
+            Collation::NoCase => "NOCASE",
+            Collation::RTrim => "RTRIM",
+        }
+    }
+}
+
+impl DataType {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            DataType::Integer => "INTEGER",
+            DataType::Text => "TEXT",
+            DataType::Real => "REAL",
+            DataType::Blob => "BLOB",
+        }
+    }
+}
+
+impl ColumnConstraint {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            ColumnConstraint::PrimaryKey => "PRIMARY KEY",
+            ColumnConstraint::NotNull => "NOT NULL",
+            ColumnConstraint::Unique => "UNIQUE",
+        }
+    }
+}
+
+impl DefaultValue {
+    pub fn to_sql(&self) -> String {
+        match self {
+            DefaultValue::Integer(i) => i.to_string(),
+            DefaultValue::Text(s) => quote_literal(s),
+            DefaultValue::Real(r) => format!("{:?}", r),
+            DefaultValue::Null => "NULL".to_string(),
+            DefaultValue::CurrentTimestamp => "CURRENT_TIMESTAMP".to_string(),
+        }
+    }
+}
+
+impl ForeignKey {
+    /// A key from `column` to `foreign_column` of `foreign_table`, with
+    /// `NO ACTION` on delete and update
+    pub fn new(column: &str, foreign_table: &str, foreign_column: &str) -> Self {
+        Self {
+            column: column.to_string(),
+            foreign_table: foreign_table.to_string(),
+            foreign_column: foreign_column.to_string(),
+            on_delete: ForeignKeyAction::NoAction,
+            on_update: ForeignKeyAction::NoAction,
+            deferred: false,
+        }
+    }
+    pub fn with_on_delete(mut self, action: ForeignKeyAction) -> Self {
+        self.on_delete = action;
+        self
+    }
+    pub fn with_on_update(mut self, action: ForeignKeyAction) -> Self {
+        self.on_update = action;
+        self
+    }
+
+    /// Declare the constraint `DEFERRABLE INITIALLY DEFERRED`, so rows may
+    /// reference parents inserted later in the same transaction, as long as
+    /// they exist when it commits. A violation then fails the commit, which
+    /// leaves the transaction open to be fixed or rolled back. Statements
+    /// run outside a transaction are still checked one by one.
+    pub fn with_deferred(mut self, deferred: bool) -> Self {
+        self.deferred = deferred;
+        self
+    }
+
+    /// The table-level `FOREIGN KEY` clause
+    pub fn to_sql(&self) -> String {
+        format!(
+            "FOREIGN KEY ({}) REFERENCES {} ({}) ON DELETE {} ON UPDATE {}{}",
+            quote_ident(&self.column),
+            quote_ident(&self.foreign_table),
+            quote_ident(&self.foreign_column),
+            self.on_delete.as_sql(),
+            self.on_update.as_sql(),
+            if self.deferred {
+                " DEFERRABLE INITIALLY DEFERRED"
+            } else {
+                ""
+            }
+        )
+    }
+}
+
+impl ForeignKeyAction {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            ForeignKeyAction::NoAction => "NO ACTION",
+            ForeignKeyAction::Cascade => "CASCADE",
+            ForeignKeyAction::SetNull => "SET NULL",
+            ForeignKeyAction::SetDefault => "SET DEFAULT",
+            ForeignKeyAction::Restrict => "RESTRICT",
+        }
+    }
+}
+
+impl IndexDefinition {
+    pub fn new(name: &str, columns: &[&str], unique: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            unique,
+            collation: None,
+            if_not_exists: true,
+        }
+    }
+    pub fn with_collation(mut self, collation: Collation) -> Self {
+        self.collation = Some(collation);
+        self
+    }
+    /// Whether creating the index tolerates an existing one, the default.
+    /// Without `IF NOT EXISTS` connecting fails when it exists, as for
+    /// [`TableDefinition::with_if_not_exists`].
+    pub fn with_if_not_exists(mut self, if_not_exists: bool) -> Self {
+        self.if_not_exists = if_not_exists;
+        self
+    }
+
+    /// The `CREATE INDEX` statement for this index on `table`
+    pub fn to_sql(&self, table: &str) -> String {
+        let columns = match self.collation {
+            Some(collation) => self
+                .columns
+                .iter()
+                .map(|column| format!("{} COLLATE {}", quote_ident(column), collation.as_sql()))
+                .collect::<Vec<_>>()
+                .join(", "),
+            None => quote_idents(&self.columns),
+        };
+        format!(
+            "CREATE {}INDEX {}{} ON {} ({})",
+            if self.unique { "UNIQUE " } else { "" },
+            if self.if_not_exists {
+                "IF NOT EXISTS "
+            } else {
+                ""
+            },
+            quote_ident(&self.name),
+            quote_ident(table),
+            columns
+        )
+    }
+}
+
+/// Quote an SQL identifier, escaping embedded double quotes
+pub(crate) fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Quote and comma-join a list of identifiers
+pub(crate) fn quote_idents(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| quote_ident(name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Quote an SQL string literal, escaping embedded single quotes
+pub(crate) fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// The identifiers and keywords of `sql`, ASCII lowercased and unquoted,
+/// skipping string literals and comments. Lexical only: it cannot tell a
+/// table name from a column name or keyword, so callers treat every word as
+/// a possible table.
+pub(crate) fn sql_identifiers(sql: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        let close = match c {
+            '\'' => {
+                while let Some(c) = chars.next() {
+                    if c == '\'' && chars.next_if_eq(&'\'').is_none() {
+                        break;
+                    }
+                }
+                continue;
+            }
+            '-' if chars.next_if_eq(&'-').is_some() => {
+                chars.by_ref().find(|&c| c == '\n');
+                continue;
+            }
+            '/' if chars.next_if_eq(&'*').is_some() => {
+                while let Some(c) = chars.next() {
+                    if c == '*' && chars.next_if_eq(&'/').is_some() {
+                        break;
+                    }
+                }
+                continue;
+            }
+            '"' => '"',
+            '`' => '`',
+            '[' => ']',
+            c if c.is_alphabetic() || c == '_' => {
+                let mut name = c.to_string();
+                while let Some(c) = chars.next_if(|&c| c.is_alphanumeric() || c == '_' || c == '$')
+                {
+                    name.push(c);
+                }
+                names.push(name.to_ascii_lowercase());
+                continue;
+            }
+            _ => continue,
+        };
+        let mut name = String::new();
+        while let Some(c) = chars.next() {
+            if c == close && (close == ']' || chars.next_if_eq(&close).is_none()) {
+                break;
+            }
+            name.push(c);
+        }
+        names.push(name.to_ascii_lowercase());
+    }
+    names
+}
+
+// Intention: Implementation logic, connection pooling, and service trait integration will be added only after tests and documentation are aligned with this API.
+
+/// SQLite Service configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct SqliteConfig {
+    /// Path to the SQLite database file, until changed with
+    /// [`SqliteService::reopen`]
+    pub db_path: String,
+    /// Schema definition for the database
+    pub schema: Schema,
+    /// Versioned migrations applied after the schema is initialized
+    pub migrations: Vec<Migration>,
+    /// How applied migrations are recorded
+    pub migration_tracking: MigrationTracking,
+    /// What to do when a configured table already exists with a different
+    /// definition
+    pub schema_conflict: SchemaConflict,
+    /// Seed data inserted once, after migrations, into a fresh database
+    pub seeds: Vec<SqlQuery>,
+    /// Name under which migrations and seeds are recorded, for services
+    /// sharing one database file, see [`SqliteConfig::with_schema_owner`]
+    pub schema_owner: Option<String>,
+    /// Flags passed to `Connection::open_with_flags`
+    pub open_flags: OpenFlags,
+    /// Create the database file if it does not exist. When false, opening a
+    /// missing file fails instead of silently creating an empty database.
+    pub create_if_missing: bool,
+    /// Create the missing directories leading to `db_path` before opening
+    /// it. Off by default; ignored for in-memory databases and URI filenames.
+    pub create_parent_dir: bool,
+    /// Upper bound on the database size, enforced with `PRAGMA max_page_count`.
+    /// Writes that would grow the file past it fail with
+    /// [`SqliteError::QuotaExceeded`].
+    pub max_size_bytes: Option<u64>,
+    /// Page size for a newly created database, see
+    /// [`SqliteConfig::with_page_size`]
+    pub page_size: Option<u32>,
+    /// How long to wait for a lock held by another connection before failing
+    /// with `SQLITE_BUSY`. Only covers lock waits, not slow statements.
+    pub busy_timeout: Option<Duration>,
+    /// Wall-clock limit for a single call into the service. The crate's
+    /// timer thread interrupts the running statement once it is exceeded
+    /// and the call fails with [`SqliteError::QueryTimeout`]. Independent
+    /// of `busy_timeout`.
+    pub query_timeout: Option<Duration>,
+    /// Upper bound on the bytes of values a query may return, counting text
+    /// and blobs by length and numbers as 8 bytes. A result growing past it
+    /// is abandoned with [`SqliteError::ResultTooLarge`] while being read.
+    /// Only read-only statements are limited: abandoning a write's
+    /// `RETURNING` rows would not undo the write. Folds over rows keep none
+    /// and are not limited either.
+    pub max_result_bytes: Option<usize>,
+    /// How [`SqliteService::query_json`] writes blobs as JSON strings
+    pub blob_encoding: BlobEncoding,
+    /// Number of read-only connections used for queries alongside the single
+    /// writer. When non-zero the database is switched to WAL mode so readers
+    /// do not block on the writer. Requires a file database.
+    pub read_connections: usize,
+    /// Route reads to the writer whenever a reader could miss a write made
+    /// through this service, see [`SqliteConfig::with_read_your_writes`]
+    pub read_your_writes: bool,
+    /// Record queries taking at least this long, see
+    /// [`SqliteService::slow_queries`]
+    pub slow_query_threshold: Option<Duration>,
+    /// Attach index suggestions to slow queries that scan a table in full
+    pub suggest_indexes: bool,
+    /// What to do with queries filtering on unindexed columns
+    pub missing_index_policy: MissingIndexPolicy,
+    /// Run [`Schema::check_identifier_case`] on connect
+    pub check_identifier_case: bool,
+    /// Run [`Schema::check_foreign_key_types`] on connect
+    pub check_foreign_key_types: bool,
+    /// Statements kept by the transaction log; `0` disables it, see
+    /// [`crate::transaction_log`]
+    pub transaction_log_capacity: usize,
+    /// Statements prepared into every connection's statement cache on
+    /// connect, see [`SqliteConfig::with_warmup_queries`]
+    pub warmup_queries: Vec<String>,
+    /// When set, the only raw SQL accepted, normalized; see
+    /// [`SqliteConfig::with_allowed_statements`]
+    pub allowed_statements: Option<HashSet<String>>,
+    /// Where temporary tables, indexes and spilled sorts are kept
+    pub temp_store: Option<TempStore>,
+    /// Directory for temporary files when they are kept on disk
+    pub temp_store_directory: Option<String>,
+    /// Attempts made by [`SqliteService::transaction_retry`], including the first
+    pub transaction_max_attempts: u32,
+    /// Delay before the first retry of [`SqliteService::transaction_retry`],
+    /// doubled after each further conflict
+    pub transaction_retry_backoff: Duration,
+    /// Column each scoped table is filtered by, see [`SqliteConfig::with_scope`]
+    pub scopes: HashMap<String, String>,
+    /// Operations the CRUD API may run per table; tables not listed allow
+    /// every operation. See [`SqliteConfig::with_permissions`].
+    pub permissions: HashMap<String, TablePermissions>,
+    /// Longest a write queued with [`SqliteService::queue_write`] waits for
+    /// its batch to commit; `None` disables batching
+    pub write_batch_interval: Option<Duration>,
+    /// Queued writes that commit their batch at once, without waiting for
+    /// the interval
+    pub write_batch_max_operations: usize,
+    /// Databases attached to every connection, as `(alias, path)` pairs
+    pub attachments: Vec<(String, String)>,
+}
+
+/// What connecting does when a configured table already exists in the
+/// database with a different definition.
+///
+/// Definitions are compared as SQLite stores them: the table's
+/// `CREATE TABLE` statement and those of its configured indexes. A table
+/// altered since, for example by a migration, no longer matches, so
+/// `Error` and `Recreate` suit schemas that only change through
+/// configuration. Tables and indexes that do not exist yet are always
+/// created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchemaConflict {
+    /// Keep the existing table as it is
+    #[default]
+    Skip,
+    /// Fail to connect with [`SqliteError::SchemaConflict`]
+    Error,
+    /// Drop the table, deleting its rows, and create it as configured. All
+    /// changed tables are recreated in one transaction, with foreign keys
+    /// unchecked meanwhile; rows elsewhere referencing a recreated table
+    /// are left as they are.
+    Recreate,
+}
+
+/// What to do with a query that filters a table it scans in full on a
+/// column no index starts with.
+///
+/// Checking plans each query with `EXPLAIN QUERY PLAN` before running it,
+/// using the same heuristic as index suggestions. SQLite scans tables that
+/// have no suitable index whatever their size, so small tables are reported
+/// too. Meant to catch missing indexes in development and tests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingIndexPolicy {
+    /// Run the query unchecked
+    #[default]
+    Ignore,
+    /// Run the query and record it, see [`SqliteService::unindexed_filters`]
+    Warn,
+    /// Fail the query with [`SqliteError::MissingIndex`] without running it
+    Deny,
+}
+
+/// Values of `PRAGMA temp_store`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TempStore {
+    /// The compile-time default, which is a file for the bundled SQLite
+    Default,
+    File,
+    Memory,
+}
+
+impl TempStore {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            TempStore::Default => "DEFAULT",
+            TempStore::File => "FILE",
+            TempStore::Memory => "MEMORY",
+        }
+    }
+}
+
+/// The CRUD operations allowed on a table, see
+/// [`SqliteConfig::with_permissions`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TablePermissions {
+    pub read: bool,
+    pub create: bool,
+    pub update: bool,
+    pub delete: bool,
+}
+
+impl TablePermissions {
+    pub fn read_only() -> Self {
+        Self {
+            read: true,
+            create: false,
+            update: false,
+            delete: false,
+        }
+    }
+
+    pub fn read_write() -> Self {
+        Self {
+            read: true,
+            create: true,
+            update: true,
+            delete: true,
+        }
+    }
+
+    /// Whether `op` may run. An upsert may update an existing row, so it
+    /// needs `update` as well as `create`.
+    pub fn allows(&self, op: &CrudOperation) -> bool {
+        match op {
+            CrudOperation::Create(create) => {
+                self.create && (create.conflict_columns.is_empty() || self.update)
+            }
+            _ => self.grants(op.kind()),
+        }
+    }
+
+    /// Whether the operation `kind`, as named by [`CrudOperation::kind`], is
+    /// allowed
+    fn grants(&self, kind: &str) -> bool {
+        match kind {
+            "create" => self.create,
+            "read" => self.read,
+            "update" => self.update,
+            "delete" => self.delete,
+            _ => false,
+        }
+    }
+}
+
+impl SqliteConfig {
+    /// Create a new SQLite config with path and schema
+    pub fn new(db_path: impl Into<String>, schema: Schema) -> Self {
+        Self {
+            db_path: db_path.into(),
+            schema,
+            migrations: Vec::new(),
+            migration_tracking: MigrationTracking::default(),
+            schema_conflict: SchemaConflict::default(),
+            seeds: Vec::new(),
+            schema_owner: None,
+            open_flags: OpenFlags::default(),
+            create_if_missing: true,
+            create_parent_dir: false,
+            max_size_bytes: None,
+            page_size: None,
+            busy_timeout: None,
+            query_timeout: None,
+            max_result_bytes: None,
+            blob_encoding: BlobEncoding::default(),
+            read_connections: 0,
+            read_your_writes: false,
+            slow_query_threshold: None,
+            suggest_indexes: false,
+            missing_index_policy: MissingIndexPolicy::default(),
+            check_identifier_case: false,
+            check_foreign_key_types: false,
+            transaction_log_capacity: 0,
+            warmup_queries: Vec::new(),
+            allowed_statements: None,
+            temp_store: None,
+            temp_store_directory: None,
+            transaction_max_attempts: 5,
+            transaction_retry_backoff: Duration::from_millis(10),
+            scopes: HashMap::new(),
+            permissions: HashMap::new(),
+            write_batch_interval: None,
+            write_batch_max_operations: 1000,
+            attachments: Vec::new(),
+        }
+    }
+    /// Set the migrations to apply on connect
+    pub fn with_migrations(mut self, migrations: Vec<Migration>) -> Self {
+        self.migrations = migrations;
+        self
+    }
+    /// Set how applied migrations are recorded
+    pub fn with_migration_tracking(mut self, tracking: MigrationTracking) -> Self {
+        self.migration_tracking = tracking;
+        self
+    }
+    /// Set what happens to tables whose existing definition differs from
+    /// the configured one
+    pub fn with_schema_conflict(mut self, policy: SchemaConflict) -> Self {
+        self.schema_conflict = policy;
+        self
+    }
+    /// Refuse to connect when the schema spells a table or column name with
+    /// inconsistent case, see [`Schema::check_identifier_case`]
+    pub fn with_identifier_case_check(mut self, check: bool) -> Self {
+        self.check_identifier_case = check;
+        self
+    }
+    /// Refuse to connect when a foreign key column and the column it
+    /// references have different types, see [`Schema::check_foreign_key_types`]
+    pub fn with_foreign_key_type_check(mut self, check: bool) -> Self {
+        self.check_foreign_key_types = check;
+        self
+    }
+    /// Set the seed queries to run once on a fresh database
+    pub fn with_seeds(mut self, seeds: Vec<SqlQuery>) -> Self {
+        self.seeds = seeds;
+        self
+    }
+    /// Share the database file with services declaring other tables.
+    ///
+    /// A service only ever creates, checks and recreates the tables it
+    /// declares. With an owner, it also records its applied migrations in
+    /// `_runar_migrations_<owner>` and its seed marker as `seeded:<owner>`,
+    /// so the migrations and seeds of one service never count as those of
+    /// another. Track migrations with [`MigrationTracking::Table`]: `PRAGMA
+    /// user_version` belongs to the whole file, so connecting with
+    /// [`MigrationTracking::UserVersion`] fails with
+    /// [`SqliteError::SharedUserVersion`].
+    pub fn with_schema_owner(mut self, owner: &str) -> Self {
+        self.schema_owner = Some(owner.to_string());
+        self
+    }
+    /// The name of the table recording applied migrations
+    pub fn migrations_table(&self) -> String {
+        match &self.schema_owner {
+            Some(owner) => format!("{}_{}", MIGRATIONS_TABLE, owner),
+            None => MIGRATIONS_TABLE.to_string(),
+        }
+    }
+    /// The key of the seed marker in [`META_TABLE`]
+    fn seed_marker(&self) -> String {
+        match &self.schema_owner {
+            Some(owner) => format!("seeded:{}", owner),
+            None => "seeded".to_string(),
+        }
+    }
+    /// Set the flags used to open connections
+    pub fn with_open_flags(mut self, open_flags: OpenFlags) -> Self {
+        self.open_flags = open_flags;
+        self
+    }
+    /// Set whether a `db_path` starting with `file:` is opened as an SQLite
+    /// URI filename. On by default.
+    ///
+    /// URIs carry open options inline, for example
+    /// `file:data.db?mode=ro` or `file:cache?mode=memory&cache=shared` for an
+    /// in-memory database shared by every connection of the process that
+    /// opens the same name. The accepted parameters are checked on connect:
+    ///
+    /// - `mode`: `ro`, `rw`, `rwc` or `memory`
+    /// - `cache`: `shared` or `private`
+    /// - `vfs`: the name of a registered VFS
+    /// - `immutable`, `nolock` and `psow`: a boolean such as `1` or `0`
+    ///
+    /// A `mode` cannot grant more than the open flags do; in particular read
+    /// connections are read-only, so `mode=rw` and `mode=rwc` do not combine
+    /// with [`SqliteConfig::with_read_connections`].
+    pub fn with_uri_filenames(mut self, enabled: bool) -> Self {
+        self.open_flags.set(OpenFlags::SQLITE_OPEN_URI, enabled);
+        self
+    }
+    /// Set whether a missing database file is created on open
+    pub fn with_create_if_missing(mut self, create_if_missing: bool) -> Self {
+        self.create_if_missing = create_if_missing;
+        self
+    }
+    /// Set whether missing parent directories of `db_path` are created on
+    /// open, see [`SqliteConfig::create_parent_dir`]
+    pub fn with_create_parent_dir(mut self, create_parent_dir: bool) -> Self {
+        self.create_parent_dir = create_parent_dir;
+        self
+    }
+
+    /// Limit the size of the database file
+    pub fn with_max_size_bytes(mut self, max_size_bytes: u64) -> Self {
+        self.max_size_bytes = Some(max_size_bytes);
+        self
+    }
+
+    /// Create the database with pages of `page_size` bytes, a power of two
+    /// from 512 to 65536. SQLite fixes the page size when the first table is
+    /// created, so connecting to an existing database with a different page
+    /// size fails with [`SqliteError::PageSizeMismatch`] rather than
+    /// rebuilding it; run `PRAGMA page_size = N; VACUUM;` outside WAL mode to
+    /// convert it deliberately.
+    pub fn with_page_size(mut self, page_size: u32) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Set how long to wait on locks held by other connections
+    pub fn with_busy_timeout(mut self, busy_timeout: Duration) -> Self {
+        self.busy_timeout = Some(busy_timeout);
+        self
+    }
+
+    /// Set the wall-clock limit after which a running query is interrupted
+    pub fn with_query_timeout(mut self, query_timeout: Duration) -> Self {
+        self.query_timeout = Some(query_timeout);
+        self
+    }
+
+    /// Abort queries whose results exceed `max_bytes`, see
+    /// [`SqliteConfig::max_result_bytes`]
+    pub fn with_max_result_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_result_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Set how blobs are written in JSON results
+    pub fn with_blob_encoding(mut self, encoding: BlobEncoding) -> Self {
+        self.blob_encoding = encoding;
+        self
+    }
+
+    /// Serve read-only queries from a pool of `read_connections` connections
+    pub fn with_read_connections(mut self, read_connections: usize) -> Self {
+        self.read_connections = read_connections;
+        self
+    }
+
+    /// Make every read observe the writes made through this service before it.
+    ///
+    /// Readers only see committed data, and a reader that is still inside a
+    /// read transaction sees the snapshot it started with. With this option a
+    /// read is sent to the writer instead while the writer has an open
+    /// transaction, and whenever the chosen reader is pinned to an older
+    /// snapshot. The cost is that those reads queue behind writes instead of
+    /// running in parallel with them; while a long transaction is open, all
+    /// reads are effectively serialized. Has no effect without
+    /// `read_connections`.
+    pub fn with_read_your_writes(mut self, read_your_writes: bool) -> Self {
+        self.read_your_writes = read_your_writes;
+        self
+    }
+
+    /// Record queries that take at least `threshold` in the slow query log
+    pub fn with_slow_query_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_query_threshold = Some(threshold);
+        self
+    }
+
+    /// Run `EXPLAIN QUERY PLAN` on slow queries and suggest indexes for
+    /// filtered columns of tables they scan in full. Suggestions only; no
+    /// index is ever created. Has no effect without a slow query threshold.
+    pub fn with_index_suggestions(mut self, suggest_indexes: bool) -> Self {
+        self.suggest_indexes = suggest_indexes;
+        self
+    }
+
+    /// Set what happens to queries that filter a table they scan in full on
+    /// a column without an index
+    pub fn with_missing_index_policy(mut self, policy: MissingIndexPolicy) -> Self {
+        self.missing_index_policy = policy;
+        self
+    }
+
+    /// Keep the last `capacity` statements run by the service in memory,
+    /// see [`SqliteService::transaction_log`]. Meant for development.
+    pub fn with_transaction_log(mut self, capacity: usize) -> Self {
+        self.transaction_log_capacity = capacity;
+        self
+    }
+
+    /// Prepare these statements when connecting, so the first requests that
+    /// run them skip parsing and planning. Each statement must match the SQL
+    /// later executed byte for byte, and must be valid against the schema
+    /// after migrations, otherwise connecting fails.
+    pub fn with_warmup_queries(mut self, queries: Vec<String>) -> Self {
+        self.warmup_queries = queries;
+        self
+    }
+
+    /// Only accept these statements as raw SQL, rejecting anything else with
+    /// [`SqliteError::StatementNotAllowed`].
+    ///
+    /// Entries are templates: callers vary the bound parameters, never the
+    /// text. Matching ignores differences in whitespace and a trailing `;`.
+    /// Applies to [`SqliteService::execute_sql`], scripts (where the whole
+    /// script must be an entry), the query of
+    /// [`SqliteService::create_table_as`], [`SqliteService::pragma`] (where
+    /// the rendered `PRAGMA` statement must be an entry) and request
+    /// transactions. CRUD operations and the service's own statements are
+    /// not affected, nor is SQL run by closures handed a connection, as in
+    /// [`SqliteService::transaction_retry`] and `capture_changeset`.
+    pub fn with_allowed_statements(mut self, statements: Vec<String>) -> Self {
+        self.allowed_statements = Some(
+            statements
+                .iter()
+                .map(|statement| normalize_statement(statement))
+                .collect(),
+        );
+        self
+    }
+
+    /// Keep temporary storage, including large sorts and joins that spill
+    /// out of the page cache, in memory or in files. Applied to every
+    /// connection on connect.
+    pub fn with_temp_store(mut self, temp_store: TempStore) -> Self {
+        self.temp_store = Some(temp_store);
+        self
+    }
+
+    /// Put temporary files in `directory` instead of the system default.
+    /// SQLite keeps this setting process-wide, so it also affects other
+    /// connections in the process.
+    pub fn with_temp_store_directory(mut self, directory: impl Into<String>) -> Self {
+        self.temp_store_directory = Some(directory.into());
+        self
+    }
+
+    /// Make up to `max_attempts` attempts in
+    /// [`SqliteService::transaction_retry`], waiting `backoff` before the
+    /// first retry and twice as long before each one after it
+    pub fn with_transaction_retry(mut self, max_attempts: u32, backoff: Duration) -> Self {
+        self.transaction_max_attempts = max_attempts.max(1);
+        self.transaction_retry_backoff = backoff;
+        self
+    }
+
+    /// Batch writes queued with [`SqliteService::queue_write`], committing
+    /// them together every `interval` or once `max_operations` are waiting,
+    /// whichever comes first; see [`crate::write_batch`]
+    pub fn with_write_batching(mut self, interval: Duration, max_operations: usize) -> Self {
+        self.write_batch_interval = Some(interval);
+        self.write_batch_max_operations = max_operations.max(1);
+        self
+    }
+
+    /// Attach the database at `path` to every connection as `alias`, so raw
+    /// SQL can name its tables as `alias.table` and CRUD operations can
+    /// target them with `with_schema(alias)`. `path` may be a URI, see
+    /// [`SqliteConfig::with_uri_filenames`]. Read connections attach it
+    /// read-only.
+    pub fn with_attachment(mut self, alias: &str, path: impl Into<String>) -> Self {
+        self.attachments.push((alias.to_string(), path.into()));
+        self
+    }
+
+    /// Scope the rows of `table` by `column`, typically a tenant id. CRUD
+    /// operations on the table must then go through
+    /// [`SqliteService::scoped`]; see [`crate::scope`].
+    pub fn with_scope(mut self, table: &str, column: &str) -> Self {
+        self.scopes
+            .insert(table.to_ascii_lowercase(), column.to_string());
+        self
+    }
+
+    /// Restrict the CRUD operations allowed on `table`. Others fail with
+    /// [`SqliteError::PermissionDenied`] before any SQL is built, as do reads
+    /// whose CTEs or expressions mention a table that may not be read, and
+    /// the bulk helpers such as [`SqliteService::merge`] for each kind of
+    /// write they make. The table name is matched case-insensitively. Raw SQL
+    /// is not checked; restrict it with
+    /// [`SqliteConfig::with_allowed_statements`].
+    pub fn with_permissions(mut self, table: &str, permissions: TablePermissions) -> Self {
+        self.permissions
+            .insert(table.to_ascii_lowercase(), permissions);
+        self
+    }
+
+    /// Fail unless `sql` may run as raw SQL under the allowlist
+    pub(crate) fn check_statement_allowed(&self, sql: &str) -> Result<(), SqliteError> {
+        match &self.allowed_statements {
+            Some(allowed) if !allowed.contains(&normalize_statement(sql)) => {
+                Err(SqliteError::StatementNotAllowed(sql.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The effective open flags, with `SQLITE_OPEN_CREATE` set only when
+    /// `create_if_missing` is true and the flags open the database
+    /// read-write: SQLite rejects it combined with `SQLITE_OPEN_READ_ONLY`
+    pub fn effective_open_flags(&self) -> OpenFlags {
+        let mut flags = self.open_flags;
+        flags.set(
+            OpenFlags::SQLITE_OPEN_CREATE,
+            self.create_if_missing && flags.contains(OpenFlags::SQLITE_OPEN_READ_WRITE),
+        );
+        flags
+    }
+
+    /// Check the parameters of `db_path` when it is opened as a URI, see
+    /// [`SqliteConfig::with_uri_filenames`]
+    pub fn validate_uri(&self) -> Result<(), SqliteError> {
+        if !self.open_flags.contains(OpenFlags::SQLITE_OPEN_URI) {
+            return Ok(());
+        }
+        let Some(uri) = self.db_path.strip_prefix("file:") else {
+            return Ok(());
+        };
+        let invalid = |reason: String| SqliteError::InvalidUri {
+            uri: self.db_path.clone(),
+            reason,
+        };
+        let query = uri.split_once('#').map_or(uri, |(uri, _)| uri);
+        let Some((_, query)) = query.split_once('?') else {
+            return Ok(());
+        };
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let valid = match key {
+                "mode" => ["ro", "rw", "rwc", "memory"].contains(&value),
+                "cache" => ["shared", "private"].contains(&value),
+                "vfs" => !value.is_empty(),
+                "immutable" | "nolock" | "psow" => {
+                    ["0", "1", "true", "false", "yes", "no", "on", "off"]
+                        .contains(&value.to_ascii_lowercase().as_str())
+                }
+                _ => return Err(invalid(format!("unknown parameter {}", key))),
+            };
+            if !valid {
+                return Err(invalid(format!("invalid value {:?} for {}", value, key)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Create the missing directories leading to `db_path`
+    fn ensure_parent_dir(&self) -> std::io::Result<()> {
+        let is_uri = self.open_flags.contains(OpenFlags::SQLITE_OPEN_URI)
+            && self.db_path.starts_with("file:");
+        if is_uri || self.db_path.is_empty() || self.db_path == ":memory:" {
+            return Ok(());
+        }
+        match std::path::Path::new(&self.db_path).parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => std::fs::create_dir_all(parent),
+            _ => Ok(()),
+        }
+    }
+
+    /// Open a connection to the configured database
+    pub fn open_connection(&self) -> rusqlite::Result<Connection> {
+        Connection::open_with_flags(&self.db_path, self.effective_open_flags())
+    }
+}
+
+#[derive(Clone)]
+pub struct SqliteService {
+    pub(crate) config: SqliteConfig,
+    connection: Arc<Mutex<Option<Connection>>>,
+    /// Idle read-only connections; empty unless `read_connections` is set
+    readers: Arc<std::sync::Mutex<Vec<Connection>>>,
+    /// Whether the writer was left inside a transaction by the last call
+    writer_in_transaction: Arc<AtomicBool>,
+    pub(crate) slow_queries: Arc<std::sync::Mutex<VecDeque<SlowQuery>>>,
+    pub(crate) unindexed_filters: Arc<std::sync::Mutex<VecDeque<UnindexedFilter>>>,
+    pub(crate) transaction_log: Arc<std::sync::Mutex<VecDeque<TransactionLogEntry>>>,
+    /// Open [`SqliteService::load`] batches by table
+    pub(crate) load_batches: Arc<std::sync::Mutex<HashMap<String, LoadBatch>>>,
+    /// Fired by the first successful connect, see [`SqliteService::ready`]
+    ready_sender: Arc<std::sync::Mutex<Option<oneshot::Sender<()>>>>,
+    ready: Shared<oneshot::Receiver<()>>,
+    /// Writes queued by [`SqliteService::queue_write`]
+    pub(crate) write_batcher: WriteBatcher,
+    /// The database file in use, see [`SqliteService::reopen`]
+    db_path: Arc<std::sync::Mutex<String>>,
+    /// Incremented by every reopen, so readers busy meanwhile are closed
+    /// rather than returned to the new pool
+    reader_generation: Arc<AtomicU64>,
+    /// Set by [`SqliteService::close`], cleared by the next connect
+    closed: Arc<AtomicBool>,
+}
+
+#[service(
+    name = "sqlite",
+    path = "sqlite",
+    version = "1.0.0",
+    description = "SQLite Service"
+)]
+impl SqliteService {
+    /// Create a new SQLite service with the given config
+    pub fn new(config: SqliteConfig) -> Self {
+        let (ready_sender, ready) = oneshot::channel();
+        let connection = Arc::new(Mutex::new(None));
+        Self {
+            write_batcher: WriteBatcher::default(),
+            connection,
+            readers: Arc::new(std::sync::Mutex::new(Vec::new())),
+            writer_in_transaction: Arc::new(AtomicBool::new(false)),
+            slow_queries: Arc::new(std::sync::Mutex::new(VecDeque::new())),
+            unindexed_filters: Arc::new(std::sync::Mutex::new(VecDeque::new())),
+            transaction_log: Arc::new(std::sync::Mutex::new(VecDeque::new())),
+            load_batches: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            ready_sender: Arc::new(std::sync::Mutex::new(Some(ready_sender))),
+            ready: ready.shared(),
+            db_path: Arc::new(std::sync::Mutex::new(config.db_path.clone())),
+            reader_generation: Arc::new(AtomicU64::new(0)),
+            closed: Arc::new(AtomicBool::new(false)),
+            config,
+        }
+    }
+
+    async fn start(&self, context: LifecycleContext) -> anyhow::Result<()> {
+        context.info(format!(
+            "starting sqlite service at path: {}",
+            self.db_path()
+        ));
+        self.connect().await
+    }
+
+    async fn stop(&self, context: LifecycleContext) -> anyhow::Result<()> {
+        let closed = self.close().await;
+        context.info("sqlite service stopped".to_string());
+        closed
+    }
+
+    /// Check the database for corruption and foreign key violations
+    #[action(path = "integrity")]
+    async fn integrity(&self, ctx: &RequestContext) -> anyhow::Result<IntegrityReport> {
+        let report = self.integrity_check().await?;
+        if !report.is_ok() {
+            ctx.warn(format!(
+                "sqlite integrity check found problems: {:?}",
+                report
+            ));
+        }
+        Ok(report)
+    }
+
+    /// Describe the tables, columns, indexes and foreign keys of the database
+    #[action(path = "describe")]
+    async fn describe(&self, _ctx: &RequestContext) -> anyhow::Result<SchemaDescription> {
+        self.describe_schema().await
+    }
+
+    /// Read a chunk of a table, continuing after the cursor of the previous
+    /// chunk, see [`SqliteService::read_rows_chunk`]
+    #[action(path = "read_chunk")]
+    async fn read_chunk(
+        &self,
+        request: ReadChunkRequest,
+        _ctx: &RequestContext,
+    ) -> anyhow::Result<ReadChunk> {
+        self.read_rows_chunk(&request).await
+    }
+
+    /// Open the database and initialize the configured schema.
+    ///
+    /// Called by `start`; exposed so the service can be used without a node.
+    /// Does nothing while the service is connected, so racing calls open the
+    /// database once: the first opens it while holding the writer, and the
+    /// others find it open when they get it.
+    pub async fn connect(&self) -> anyhow::Result<()> {
+        let mut connection = self.connection.lock().await;
+        if connection.is_some() {
+            return Ok(());
+        }
+        let (conn, readers) = self.open_database(&self.db_path())?;
+        *connection = Some(conn);
+        *self.readers.lock().unwrap() = readers;
+        self.closed.store(false, Ordering::SeqCst);
+        if let Some(sender) = self.ready_sender.lock().unwrap().take() {
+            let _ = sender.send(());
+        }
+        Ok(())
+    }
+
+    /// Release the database, for example so its file can be deleted.
+    ///
+    /// Queued writes are flushed, the WAL is checkpointed into the database
+    /// file and every connection is closed; reads already running finish
+    /// first on their own reader. Later operations fail with
+    /// [`SqliteError::Closed`] until [`SqliteService::connect`] opens the
+    /// database again. Queued writes are flushed on a best-effort basis:
+    /// the service closes even if some fail, and returns their error.
+    pub async fn close(&self) -> anyhow::Result<()> {
+        let flushed = self.flush().await;
+        let mut writer = self.connection.lock().await;
+        self.closed.store(true, Ordering::SeqCst);
+        {
+            let mut pool = self.readers.lock().unwrap();
+            self.reader_generation.fetch_add(1, Ordering::SeqCst);
+            pool.clear();
+        }
+        if let Some(conn) = writer.take() {
+            self.writer_in_transaction.store(false, Ordering::SeqCst);
+            if conn.is_autocommit() {
+                conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+            }
+            conn.close().map_err(|(_, e)| e)?;
+        }
+        flushed
+    }
+
+    /// Fail with the error of [`SqliteService::disconnected`] once the
+    /// service is closed, for operations that do not take the writer at once
+    pub(crate) fn check_not_closed(&self) -> Result<(), SqliteError> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(self.disconnected());
+        }
+        Ok(())
+    }
+
+    /// The error for an operation finding no writer
+    fn disconnected(&self) -> SqliteError {
+        if self.closed.load(Ordering::SeqCst) {
+            SqliteError::Closed
+        } else {
+            SqliteError::NotConnected
+        }
+    }
+
+    /// The database file the service uses
+    pub fn db_path(&self) -> String {
+        self.db_path.lock().unwrap().clone()
+    }
+
+    /// Switch the service to the database at `new_path` without recreating
+    /// it, for example to swap in a restored or migrated file.
+    ///
+    /// The new database is opened and set up like on connect, with pragmas,
+    /// schema, migrations and seeds, while the current one keeps serving.
+    /// Queued writes are flushed first. Once the new database is ready,
+    /// writes wait for the swap; the current writer is checkpointed and
+    /// closed, rolling back a transaction left open on it, and the read pool
+    /// is replaced. Reads already running finish against the old file. When
+    /// opening `new_path` fails, the error is returned and the service stays
+    /// on its current database. A closed service is usable again once
+    /// reopened.
+    pub async fn reopen(&self, new_path: impl Into<String>) -> anyhow::Result<()> {
+        let new_path = new_path.into();
+        self.flush().await?;
+        let (conn, readers) = self.open_database(&new_path)?;
+        let mut writer = self.connection.lock().await;
+        if let Some(old) = writer.as_ref() {
+            // Move WAL content into the old file before it is left behind
+            if old.is_autocommit() {
+                old.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+            }
+        }
+        {
+            // Under the pool's lock, so every reader is either taken before
+            // the swap or from the new pool
+            let mut pool = self.readers.lock().unwrap();
+            self.reader_generation.fetch_add(1, Ordering::SeqCst);
+            *pool = readers;
+        }
+        *writer = Some(conn);
+        self.writer_in_transaction.store(false, Ordering::SeqCst);
+        self.closed.store(false, Ordering::SeqCst);
+        *self.db_path.lock().unwrap() = new_path;
+        Ok(())
+    }
+
+    /// Open the database at `path` with the configured settings, set up its
+    /// schema, and open its readers
+    fn open_database(&self, path: &str) -> anyhow::Result<(Connection, Vec<Connection>)> {
+        let config = SqliteConfig {
+            db_path: path.to_string(),
+            ..self.config.clone()
+        };
+        config.validate_uri()?;
+        if let Some(owner) = &config.schema_owner {
+            if config.migration_tracking == MigrationTracking::UserVersion {
+                return Err(SqliteError::SharedUserVersion(owner.clone()).into());
+            }
+        }
+        if config.check_identifier_case {
+            config.schema.check_identifier_case()?;
+        }
+        if config.check_foreign_key_types {
+            config.schema.check_foreign_key_types()?;
+        }
+        if config.create_parent_dir {
+            config.ensure_parent_dir()?;
+        }
+        let conn = config.open_connection()?;
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        if let Some(busy_timeout) = self.config.busy_timeout {
+            conn.busy_timeout(busy_timeout)?;
+        }
+        if let Some(directory) = &self.config.temp_store_directory {
+            conn.execute_batch(&format!(
+                "PRAGMA temp_store_directory = {}",
+                quote_literal(directory)
+            ))?;
+        }
+        self.apply_temp_store(&conn)?;
+        self.attach_databases(&conn)?;
+        if let Some(page_size) = self.config.page_size {
+            apply_page_size(&conn, page_size)?;
+        }
+        if let Some(max_bytes) = self.config.max_size_bytes {
+            let page_size: u64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+            let max_pages = max_bytes.div_ceil(page_size).max(1);
+            conn.query_row(
+                &format!("PRAGMA max_page_count = {}", max_pages),
+                [],
+                |_| Ok(()),
+            )?;
+        }
+        self.initialize_schema(&conn)?;
+        apply_migrations(&conn, &self.config)?;
+        self.apply_seeds(&conn)?;
+        let readers = self.open_readers(&conn, path)?;
+        self.warm_up(&conn, false)?;
+        for reader in &readers {
+            self.warm_up(reader, true)?;
+        }
+        Ok((conn, readers))
+    }
+
+    /// The DDL [`SqliteService::connect`] runs to create the configured
+    /// tables, indexes and R*Tree tables, in execution order, without running
+    /// anything. Statements carry `IF NOT EXISTS` unless disabled per table
+    /// or index. Tables dropped and recreated under
+    /// [`SchemaConflict::Recreate`] depend on the database and are not
+    /// listed; migrations and seeds run afterwards. Fails if the foreign
+    /// keys form a cycle.
+    pub fn planned_ddl(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self.config.schema.create_statements()?)
+    }
+
+    /// Resolves once the service has connected for the first time, with the
+    /// schema created, migrations applied and seeds run. Resolves at once
+    /// if that already happened. Lets other code started alongside the
+    /// service wait before its first query.
+    pub async fn ready(&self) {
+        // The sender lives as long as this service, so it is never cancelled
+        let _ = self.ready.clone().await;
+    }
+
+    /// Switch to WAL and open the configured number of read-only connections
+    fn open_readers(&self, writer: &Connection, path: &str) -> anyhow::Result<Vec<Connection>> {
+        if self.config.read_connections == 0 {
+            return Ok(Vec::new());
+        }
+        writer.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+        let flags = (self.config.open_flags
+            - OpenFlags::SQLITE_OPEN_READ_WRITE
+            - OpenFlags::SQLITE_OPEN_CREATE)
+            | OpenFlags::SQLITE_OPEN_READ_ONLY;
+        (0..self.config.read_connections)
+            .map(|_| {
+                let reader = Connection::open_with_flags(path, flags)?;
+                if let Some(busy_timeout) = self.config.busy_timeout {
+                    reader.busy_timeout(busy_timeout)?;
+                }
+                self.apply_temp_store(&reader)?;
+                self.attach_databases(&reader)?;
+                // Read-only connections can still write to their temp schema
+                for table in self.config.schema.tables.iter().filter(|t| t.temporary) {
+                    reader.execute(&table.create_table_sql(), [])?;
+                    for index in table.create_index_sql() {
+                        reader.execute(&index, [])?;
+                    }
+                }
+                Ok(reader)
+            })
+            .collect()
+    }
+
+    /// Attach the configured databases to `conn`
+    fn attach_databases(&self, conn: &Connection) -> rusqlite::Result<()> {
+        for (alias, path) in &self.config.attachments {
+            conn.execute(
+                &format!("ATTACH DATABASE ?1 AS {}", quote_ident(alias)),
+                [path],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Set `PRAGMA temp_store` on `conn` when configured
+    fn apply_temp_store(&self, conn: &Connection) -> rusqlite::Result<()> {
+        match self.config.temp_store {
+            Some(temp_store) => {
+                conn.execute_batch(&format!("PRAGMA temp_store = {}", temp_store.as_sql()))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Prepare the warmup queries into the statement cache of `conn`. Readers
+    /// only get the queries that would be routed to them.
+    fn warm_up(&self, conn: &Connection, reader: bool) -> anyhow::Result<()> {
+        let queries = &self.config.warmup_queries;
+        if queries.is_empty() {
+            return Ok(());
+        }
+        // Keep room for the statements cached by regular traffic
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY + queries.len());
+        for sql in queries {
+            let stmt = conn.prepare_cached(sql)?;
+            if reader && !is_query(&stmt) {
+                stmt.discard();
+            }
+        }
+        Ok(())
+    }
+
+    /// Create all tables and indexes, referenced tables first, resolving
+    /// existing tables with other definitions per [`SchemaConflict`]
+    pub(crate) fn initialize_schema(&self, conn: &Connection) -> anyhow::Result<()> {
+        let statements = self.config.schema.create_statements()?;
+        let tables = self.config.schema.dependency_order()?;
+        let changed = match self.config.schema_conflict {
+            SchemaConflict::Skip => Vec::new(),
+            SchemaConflict::Error | SchemaConflict::Recreate => changed_tables(conn, &tables)?,
+        };
+        if self.config.schema_conflict == SchemaConflict::Error {
+            if let Some(table) = changed.first() {
+                return Err(SqliteError::SchemaConflict(table.clone()).into());
+            }
+        }
+        // Foreign keys can only be toggled outside a transaction; with them
+        // on, dropping a referenced table would fail or cascade
+        if !changed.is_empty() {
+            conn.execute_batch("PRAGMA foreign_keys = OFF")?;
+        }
+        let created = (|| {
+            let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+            for table in &changed {
+                tx.execute(&format!("DROP TABLE {}", quote_ident(table)), [])?;
+            }
+            for statement in &statements {
+                tx.execute(statement, [])?;
+            }
+            tx.commit()
+        })();
+        if !changed.is_empty() {
+            conn.execute_batch("PRAGMA foreign_keys = ON")?;
+        }
+        created?;
+        Ok(())
+    }
+
+    /// Run the seed queries unless the database has already been seeded.
+    ///
+    /// The seeds and the marker recording them commit together, so a failed
+    /// seed is retried on the next start. The write lock is held from the
+    /// check onwards, so services starting at once seed only once.
+    pub(crate) fn apply_seeds(&self, conn: &Connection) -> anyhow::Result<()> {
+        if self.config.seeds.is_empty() {
+            return Ok(());
+        }
+        let marker = self.config.seed_marker();
+        let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+        tx.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (key TEXT PRIMARY KEY, value TEXT)",
+            META_TABLE
+        ))?;
+        let seeded = tx
+            .query_row(
+                &format!("SELECT 1 FROM {} WHERE key = ?1", META_TABLE),
+                [&marker],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        if seeded {
+            return Ok(());
+        }
+        for seed in &self.config.seeds {
+            run_sql(&tx, seed, None)?;
+        }
+        tx.execute(
+            &format!(
+                "INSERT INTO {} (key, value) VALUES (?1, CURRENT_TIMESTAMP)",
+                META_TABLE
+            ),
+            [&marker],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Run `f` against the open connection
+    pub(crate) async fn with_connection<T>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let mut guard = self.connection.lock().await;
+        let conn = guard.as_mut().ok_or_else(|| self.disconnected())?;
+        let handle = conn.get_interrupt_handle();
+        let mut result = run_with_query_timeout(handle, self.config.query_timeout, || f(conn));
+        if let Err(e) = &result {
+            if is_storage_failure(e) && !conn.is_autocommit() {
+                // SQLite does not always roll back on its own after these;
+                // never leave a half-written transaction open
+                if let Err(rollback) = conn.execute_batch("ROLLBACK") {
+                    result = Err(anyhow::Error::from(rollback).context(e.to_string()));
+                }
+            }
+        }
+        self.writer_in_transaction
+            .store(!conn.is_autocommit(), Ordering::SeqCst);
+        result.map_err(|e| self.map_error(e))
+    }
+
+    /// Run `f` on an idle reader, if one should serve this read.
+    ///
+    /// Returns `None` when the read has to go to the writer instead: there
+    /// are no idle readers, read-your-writes requires it, `f` itself
+    /// declines by returning `None` (for example because the statement
+    /// writes), or the reader lacks a table `f` uses. The last covers
+    /// tables only the writer sees, such as a `TEMP` table created on it
+    /// through raw SQL.
+    async fn with_reader<T>(
+        &self,
+        f: impl FnOnce(&Connection) -> anyhow::Result<Option<T>>,
+    ) -> anyhow::Result<Option<T>> {
+        if self.config.read_your_writes && self.writer_in_transaction.load(Ordering::SeqCst) {
+            return Ok(None);
+        }
+        let (generation, reader) = {
+            let mut readers = self.readers.lock().unwrap();
+            (self.reader_generation.load(Ordering::SeqCst), readers.pop())
+        };
+        let Some(reader) = reader else {
+            return Ok(None);
+        };
+        let result = if self.config.read_your_writes && !reader.is_autocommit() {
+            Ok(None)
+        } else {
+            let handle = reader.get_interrupt_handle();
+            match run_with_query_timeout(handle, self.config.query_timeout, || f(&reader)) {
+                Err(e) if is_missing_table(&e) => Ok(None),
+                result => result,
+            }
+        };
+        {
+            let mut readers = self.readers.lock().unwrap();
+            if self.reader_generation.load(Ordering::SeqCst) == generation {
+                readers.push(reader);
+            }
+        }
+        result.map_err(|e| self.map_error(e))
+    }
+
+    /// Translate SQLite failures that callers need to tell apart into
+    /// [`SqliteError`] variants
+    pub(crate) fn map_error(&self, err: anyhow::Error) -> anyhow::Error {
+        let code = match err.downcast_ref::<rusqlite::Error>() {
+            Some(rusqlite::Error::SqliteFailure(e, _)) => e.code,
+            _ => return err,
+        };
+        match (code, self.config.max_size_bytes) {
+            (ErrorCode::DiskFull, Some(max_bytes)) => {
+                SqliteError::QuotaExceeded { max_bytes }.into()
+            }
+            (ErrorCode::DiskFull, None) => SqliteError::StorageFull.into(),
+            (ErrorCode::SystemIoFailure, _) => SqliteError::IoError(err.to_string()).into(),
+            _ => err,
+        }
+    }
+
+    /// Execute a raw SQL statement with named parameters, returning any rows
+    ///
+    /// Queries that only read are served by the read pool when one is
+    /// configured; everything else runs on the writer.
+    pub async fn execute_sql(&self, query: SqlQuery) -> anyhow::Result<Vec<Row>> {
+        self.config.check_statement_allowed(&query.statement)?;
+        self.run_query(query).await
+    }
+
+    /// [`SqliteService::execute_sql`] for the service's own statements,
+    /// which bypass the statement allowlist
+    pub(crate) async fn run_query(&self, query: SqlQuery) -> anyhow::Result<Vec<Row>> {
+        let read = self
+            .with_reader(|conn| {
+                with_pragmas(conn, &query.pragmas, || {
+                    let mut stmt = conn.prepare_cached(&query.statement)?;
+                    if !is_query(&stmt) {
+                        stmt.discard();
+                        return Ok(None);
+                    }
+                    bind_named_params(&mut stmt, &query.params)?;
+                    self.observe_query(
+                        conn,
+                        &query.statement,
+                        BoundParams::Named(&query.params),
+                        || collect_rows(&mut stmt, self.config.max_result_bytes),
+                    )
+                    .map(Some)
+                })
+            })
+            .await?;
+        match read {
+            Some(rows) => Ok(rows),
+            None => {
+                self.with_connection(|conn| {
+                    with_pragmas(conn, &query.pragmas, || {
+                        self.observe_query(
+                            conn,
+                            &query.statement,
+                            BoundParams::Named(&query.params),
+                            || run_sql(conn, &query, self.config.max_result_bytes),
+                        )
+                    })
+                })
+                .await
+            }
+        }
+    }
+
+    /// Run a raw SQL query and fold its rows into `init` one at a time.
+    ///
+    /// Rows are handed to `f` as they are read rather than collected first,
+    /// so memory stays flat however many rows match. The connection is held
+    /// for the whole fold, so `f` should be quick. Routed like
+    /// [`SqliteService::execute_sql`].
+    pub async fn fold_rows<A>(
+        &self,
+        query: SqlQuery,
+        init: A,
+        mut f: impl FnMut(A, Row) -> A,
+    ) -> anyhow::Result<A> {
+        self.fold_query(query, init, &mut |acc, view| f(acc, view.to_row()))
+            .await
+    }
+
+    /// Fold the rows of a raw SQL query as views, routed like
+    /// [`SqliteService::execute_sql`]
+    pub(crate) async fn fold_query<A>(
+        &self,
+        query: SqlQuery,
+        init: A,
+        f: &mut impl FnMut(A, RowView<'_>) -> A,
+    ) -> anyhow::Result<A> {
+        self.config.check_statement_allowed(&query.statement)?;
+        let mut init = Some(init);
+        let read = self
+            .with_reader(|conn| {
+                with_pragmas(conn, &query.pragmas, || {
+                    let mut stmt = conn.prepare_cached(&query.statement)?;
+                    if !is_query(&stmt) {
+                        stmt.discard();
+                        return Ok(None);
+                    }
+                    bind_named_params(&mut stmt, &query.params)?;
+                    let acc = init.take().expect("the fold starts once");
+                    self.observe_query(
+                        conn,
+                        &query.statement,
+                        BoundParams::Named(&query.params),
+                        || fold_statement(&mut stmt, acc, f),
+                    )
+                    .map(Some)
+                })
+            })
+            .await?;
+        match read {
+            Some(acc) => Ok(acc),
+            None => {
+                self.with_connection(|conn| {
+                    with_pragmas(conn, &query.pragmas, || {
+                        let mut stmt = conn.prepare_cached(&query.statement)?;
+                        bind_named_params(&mut stmt, &query.params)?;
+                        let acc = init.take().expect("the fold starts once");
+                        self.observe_query(
+                            conn,
+                            &query.statement,
+                            BoundParams::Named(&query.params),
+                            || fold_statement(&mut stmt, acc, f),
+                        )
+                    })
+                })
+                .await
+            }
+        }
+    }
+
+    /// Run `PRAGMA name` or `PRAGMA name(arg)` and return any result rows.
+    ///
+    /// `name` must be in [`PRAGMA_ALLOWLIST`] (case-insensitively). Pragma
+    /// arguments cannot be bound as parameters, so `arg` is rendered as an
+    /// SQL literal. Under [`SqliteConfig::with_allowed_statements`] the
+    /// statement, such as `PRAGMA user_version(3)`, must also be listed.
+    pub async fn pragma(&self, name: &str, arg: Option<Value>) -> anyhow::Result<Vec<Row>> {
+        let name = name.to_ascii_lowercase();
+        if !PRAGMA_ALLOWLIST.contains(&name.as_str()) {
+            return Err(SqliteError::PragmaNotAllowed(name).into());
+        }
+        let sql = match arg {
+            Some(arg) => format!("PRAGMA {}({})", name, arg.to_sql_literal()),
+            None => format!("PRAGMA {}", name),
+        };
+        self.config.check_statement_allowed(&sql)?;
+        self.with_connection(|conn| {
+            let mut stmt = conn.prepare(&sql)?;
+            collect_rows(&mut stmt, self.config.max_result_bytes)
+        })
+        .await
+    }
+
+    /// The share of the database's pages that are free, from 0 to 1.
+    ///
+    /// Deleting rows frees their pages into the freelist rather than
+    /// shrinking the file; new rows reuse them, and `VACUUM` returns them to
+    /// the filesystem. An empty database reports 0. With `auto_vacuum` on,
+    /// free pages are released at each commit and this stays near 0.
+    pub async fn fragmentation(&self) -> anyhow::Result<f64> {
+        self.with_connection(|conn| {
+            let page_count: u64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+            let free: u64 = conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))?;
+            Ok(if page_count == 0 {
+                0.0
+            } else {
+                free as f64 / page_count as f64
+            })
+        })
+        .await
+    }
+
+    /// Whether [`SqliteService::fragmentation`] has reached `threshold`, a
+    /// share of free pages from 0 to 1, so a `VACUUM` would be worth its cost
+    pub async fn needs_vacuum(&self, threshold: f64) -> anyhow::Result<bool> {
+        Ok(self.fragmentation().await? >= threshold)
+    }
+
+    /// Execute a script of `;`-separated statements, returning one result set
+    /// per statement that returns columns (such as a `SELECT`), in script
+    /// order. A query matching no rows still contributes an empty result set,
+    /// so positions line up with the queries in the script; statements
+    /// without a result, such as `INSERT` or `CREATE`, contribute nothing.
+    /// Statements run in autocommit mode; a failing statement stops the
+    /// script without undoing the ones before it.
+    pub async fn execute_script_with_results(&self, sql: &str) -> anyhow::Result<Vec<Vec<Row>>> {
+        self.config.check_statement_allowed(sql)?;
+        self.with_connection(|conn| {
+            let mut results = Vec::new();
+            let mut batch = Batch::new(conn, sql);
+            while let Some(mut stmt) = batch.next()? {
+                let rows = collect_rows(&mut stmt, self.config.max_result_bytes)?;
+                if stmt.column_count() > 0 {
+                    results.push(rows);
+                }
+            }
+            Ok(results)
+        })
+        .await
+    }
+
+    /// Create `new_table` holding the result of `select`, with its named
+    /// parameters bound.
+    ///
+    /// Column names come from the query's result columns and column types
+    /// from their declared affinity; the new table has no constraints,
+    /// indexes or primary key. Fails if `new_table` already exists.
+    pub async fn create_table_as(&self, new_table: &str, select: SqlQuery) -> anyhow::Result<()> {
+        self.config.check_statement_allowed(&select.statement)?;
+        let sql = format!(
+            "CREATE TABLE {} AS {}",
+            quote_ident(new_table),
+            select.statement
+        );
+        self.with_connection(|conn| {
+            let mut stmt = conn.prepare(&sql)?;
+            bind_named_params(&mut stmt, &select.params)?;
+            stmt.raw_execute()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Begin a transaction bound to the request being handled.
+    ///
+    /// The returned [`RequestTransaction`] holds this service's connection
+    /// until it is committed or dropped; dropping it without calling
+    /// `commit` rolls back every write made through it. Downstream calls made
+    /// through [`RequestTransaction::request`] take part only in the sense that
+    /// their failure aborts the local transaction: remote services cannot
+    /// join a local SQLite transaction, so their own side effects are not
+    /// undone. Requests routed back into this same service while the
+    /// transaction is open wait for the connection and would deadlock; issue
+    /// those statements through the transaction instead.
+    pub async fn begin_request_transaction<'a>(
+        &'a self,
+        context: &'a RequestContext,
+    ) -> anyhow::Result<RequestTransaction<'a>> {
+        let guard = self.connection.lock().await;
+        guard
+            .as_ref()
+            .ok_or_else(|| self.disconnected())?
+            .execute_batch("BEGIN")?;
+        self.writer_in_transaction.store(true, Ordering::SeqCst);
+        Ok(RequestTransaction {
+            service: self,
+            conn: guard,
+            context,
+            finished: false,
+        })
+    }
+
+    /// Run a read that should match exactly one row and return that row.
+    ///
+    /// Fails with [`SqliteError::NotFound`] when nothing matches and with
+    /// [`SqliteError::TooManyRows`] when more than one row does. Any limit
+    /// on `op` is replaced; an offset is kept.
+    pub async fn read_one(&self, mut op: ReadOperation) -> anyhow::Result<Row> {
+        let table = op.table.clone();
+        op.limit = Some(2);
+        let mut rows = self.execute_crud(CrudOperation::Read(op)).await?;
+        match rows.len() {
+            0 => Err(SqliteError::NotFound { table }.into()),
+            1 => Ok(rows.remove(0)),
+            _ => Err(SqliteError::TooManyRows { table }.into()),
+        }
+    }
+
+    /// Set `field` to `value` on every row of `table` matching `query` and
+    /// return how many rows changed. Shorthand for a single-column
+    /// [`UpdateOperation`] run through [`SqliteService::execute_crud`].
+    pub async fn set_field(
+        &self,
+        table: &str,
+        field: &str,
+        value: impl Into<Value>,
+        query: Query,
+    ) -> anyhow::Result<usize> {
+        let update = UpdateOperation::new(table, query).with_update(field, value);
+        let rows = self.execute_crud(CrudOperation::Update(update)).await?;
+        Ok(rows.len())
+    }
+
+    /// Run two reads and return the rows of both, as `UNION ALL` when `all`
+    /// is set and otherwise as `UNION`, which also drops duplicate rows.
+    ///
+    /// Each read keeps its own filters, ordering and limit; the combined rows
+    /// are in no particular order and take the column names of `a`. Both
+    /// reads must select the same number of columns, otherwise this fails
+    /// with [`SqliteError::UnionColumnMismatch`]. Both reads are checked as
+    /// by [`SqliteService::execute_crud`], and the combined rows are
+    /// decompressed and checked as rows of `a`'s table.
+    pub async fn union(
+        &self,
+        a: ReadOperation,
+        b: ReadOperation,
+        all: bool,
+    ) -> anyhow::Result<Vec<Row>> {
+        let prepare = |read: ReadOperation| -> anyhow::Result<ReadOperation> {
+            let op = CrudOperation::Read(read);
+            self.check_crud_unscoped(&op)?;
+            let CrudOperation::Read(read) = self.prepare_crud(op)? else {
+                unreachable!("prepare_crud keeps the kind of operation");
+            };
+            Ok(read)
+        };
+        let (a, b) = (prepare(a)?, prepare(b)?);
+        let (a_op, b_op) = (
+            CrudOperation::Read(a.clone()),
+            CrudOperation::Read(b.clone()),
+        );
+        let (a_sql, _) = a_op.to_sql();
+        let (b_sql, _) = b_op.to_sql();
+        let (sql, params) = a.union_sql(&b, all);
+        let run = |conn: &Connection| {
+            let left = conn.prepare_cached(&a_sql)?.column_count();
+            let right = conn.prepare_cached(&b_sql)?.column_count();
+            if left != right {
+                return Err(SqliteError::UnionColumnMismatch { left, right }.into());
+            }
+            let mut stmt = conn.prepare_cached(&sql)?;
+            for (i, value) in params.iter().enumerate() {
+                stmt.raw_bind_parameter(i + 1, value)?;
+            }
+            self.observe_query(conn, &sql, BoundParams::Positional(&params), || {
+                collect_rows(&mut stmt, self.config.max_result_bytes)
+            })
+        };
+        let rows = match self.with_reader(|conn| run(conn).map(Some)).await? {
+            Some(rows) => rows,
+            None => self.with_connection(|conn| run(conn)).await?,
+        };
+        self.finish_crud(&a.table, rows)
+    }
+
+    /// Run a read and report whether more rows exist past its limit.
+    ///
+    /// One extra row is fetched to find out and is not returned. Without a
+    /// limit every row is returned and `has_more` is false.
+    pub async fn read_page(&self, mut op: ReadOperation) -> anyhow::Result<ReadPage> {
+        let Some(limit) = op.limit else {
+            let rows = self.execute_crud(CrudOperation::Read(op)).await?;
+            return Ok(ReadPage {
+                rows,
+                has_more: false,
+            });
+        };
+        op.limit = Some(limit.saturating_add(1));
+        let mut rows = self.execute_crud(CrudOperation::Read(op)).await?;
+        let has_more = rows.len() > limit as usize;
+        rows.truncate(limit as usize);
+        Ok(ReadPage { rows, has_more })
+    }
+
+    /// Perform a CRUD operation (type-safe API)
+    ///
+    /// Operations on a scoped table are rejected with
+    /// [`SqliteError::ScopeRequired`]; run them through
+    /// [`SqliteService::scoped`] instead.
+    pub async fn execute_crud(&self, op: CrudOperation) -> anyhow::Result<Vec<Row>> {
+        self.check_crud_unscoped(&op)?;
+        self.run_crud(op).await
+    }
+
+    /// [`SqliteService::execute_crud`] without the scope check
+    pub(crate) async fn run_crud(&self, op: CrudOperation) -> anyhow::Result<Vec<Row>> {
+        let op = self.prepare_crud(op)?;
+        let mut rows = None;
+        if let CrudOperation::Read(_) = op {
+            rows = self
+                .with_reader(|conn| self.run_crud_on(conn, &op).map(Some))
+                .await?;
+        }
+        let rows = match rows {
+            Some(rows) => rows,
+            None => {
+                self.with_connection(|conn| self.run_crud_on(conn, &op))
+                    .await?
+            }
+        };
+        self.finish_crud(op.table(), rows)
+    }
+
+    /// Run `ops` in one transaction, returning the rows of each in order.
+    ///
+    /// The operations may target different tables, for example a parent row
+    /// and its children. They run one after the other, so later ones see the
+    /// writes of earlier ones; reads included run on the writer. When any
+    /// fails, every operation is rolled back and the error is returned with
+    /// the index of the failed operation as context. Checks apply as for
+    /// [`SqliteService::execute_crud`], scoped tables included.
+    pub async fn execute_batch_ops(
+        &self,
+        ops: Vec<CrudOperation>,
+    ) -> anyhow::Result<Vec<OpResult>> {
+        let ops = ops
+            .into_iter()
+            .map(|op| {
+                self.check_crud_unscoped(&op)?;
+                self.prepare_crud(op)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        self.with_connection(|conn| {
+            let tx = conn.transaction()?;
+            let mut results = Vec::with_capacity(ops.len());
+            for (i, op) in ops.iter().enumerate() {
+                let rows = self
+                    .run_crud_on(&tx, op)
+                    .and_then(|rows| self.finish_crud(op.table(), rows))
+                    .map_err(|e| e.context(format!("operation {} of the batch failed", i)))?;
+                results.push(OpResult {
+                    table: op.table().to_string(),
+                    rows,
+                });
+            }
+            tx.commit()?;
+            Ok(results)
+        })
+        .await
+    }
+
+    /// Check `op` against the configuration before it runs, and compress
+    /// its values when compression is enabled
+    fn prepare_crud(&self, op: CrudOperation) -> anyhow::Result<CrudOperation> {
+        self.check_crud_permissions(&op)?;
+        if let CrudOperation::Read(read) = &op {
+            for (term, _) in read.order_by.iter().flatten() {
+                term.validate()?;
+            }
+        }
+        match &op {
+            CrudOperation::Create(create) => {
+                self.check_allowed_values(&create.table, &create.data)?
+            }
+            CrudOperation::Update(update) => {
+                self.check_allowed_values(&update.table, &update.updates)?
+            }
+            _ => {}
+        }
+        #[cfg(feature = "compression")]
+        let op = self.compress_operation(op)?;
+        Ok(op)
+    }
+
+    /// Fail with [`SqliteError::PermissionDenied`] unless the configured
+    /// permissions of `table` allow `operation`, one of the names returned
+    /// by [`CrudOperation::kind`]
+    pub(crate) fn check_permission(
+        &self,
+        table: &str,
+        operation: &'static str,
+    ) -> Result<(), SqliteError> {
+        match self.config.permissions.get(&table.to_ascii_lowercase()) {
+            Some(permissions) if !permissions.grants(operation) => {
+                Err(SqliteError::PermissionDenied {
+                    table: table.to_string(),
+                    operation,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check `op` against the configured permissions: its own kind on its
+    /// table, `update` too for an upsert, and `read` on every table its
+    /// embedded SQL may mention
+    fn check_crud_permissions(&self, op: &CrudOperation) -> Result<(), SqliteError> {
+        self.check_permission(op.table(), op.kind())?;
+        if let CrudOperation::Create(create) = op {
+            if !create.conflict_columns.is_empty() {
+                self.check_permission(&create.table, "update")?;
+            }
+        }
+        op.embedded_names()
+            .iter()
+            .try_for_each(|name| self.check_permission(name, "read"))
+    }
+
+    /// Run a prepared operation on `conn`, stamping row checksums on writes
+    fn run_crud_on(&self, conn: &Connection, op: &CrudOperation) -> anyhow::Result<Vec<Row>> {
+        let (sql, params) = op.to_sql();
+        let run = |sql: &str| {
+            let mut stmt = conn.prepare_cached(sql)?;
+            for (i, value) in params.iter().enumerate() {
+                stmt.raw_bind_parameter(i + 1, value)?;
+            }
+            self.observe_query(conn, sql, BoundParams::Positional(&params), || {
+                collect_rows(&mut stmt, self.config.max_result_bytes)
+            })
+        };
+        match op {
+            CrudOperation::Create(_) | CrudOperation::Update(_) => {
+                self.write_with_checksums(conn, op, &sql, run)
+            }
+            _ => run(&sql),
+        }
+    }
+
+    /// Decompress and check the rows an operation on `table` returned
+    fn finish_crud(&self, table: &str, rows: Vec<Row>) -> anyhow::Result<Vec<Row>> {
+        #[cfg(feature = "compression")]
+        let rows = self.decompress_rows(table, rows)?;
+        for row in &rows {
+            self.check_allowed_values(table, row)?;
+        }
+        Ok(rows)
+    }
+
+    /// Fail with [`SqliteError::InvalidEnumValue`] unless every value of a
+    /// column of `table` with allowed values is one of them
+    pub(crate) fn check_allowed_values(
+        &self,
+        table: &str,
+        values: &HashMap<String, Value>,
+    ) -> Result<(), SqliteError> {
+        let Some(definition) = self.config.schema.tables.iter().find(|t| t.name == table) else {
+            return Ok(());
+        };
+        for column in definition
+            .columns
+            .iter()
+            .filter(|c| c.allowed_values.is_some())
+        {
+            match values.get(&column.name) {
+                Some(value) if !column.allows(value) => {
+                    return Err(SqliteError::InvalidEnumValue {
+                        table: table.to_string(),
+                        column: column.name.clone(),
+                        value: value.clone(),
+                    })
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rows returned by [`SqliteService::read_page`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadPage {
+    pub rows: Vec<Row>,
+    /// Whether more rows match beyond the limit
+    pub has_more: bool,
+}
+
+/// The rows one operation of [`SqliteService::execute_batch_ops`] returned
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpResult {
+    pub table: String,
+    pub rows: Vec<Row>,
+}
+
+/// A transaction bound to a [`RequestContext`], see
+/// [`SqliteService::begin_request_transaction`]
+pub struct RequestTransaction<'a> {
+    service: &'a SqliteService,
+    conn: MutexGuard<'a, Option<Connection>>,
+    context: &'a RequestContext,
+    finished: bool,
+}
+
+impl RequestTransaction<'_> {
+    fn conn(&self) -> &Connection {
+        self.conn
+            .as_ref()
+            .expect("connection is checked when the transaction begins")
+    }
+
+    /// Execute a raw SQL statement inside the transaction
+    pub fn execute_sql(&self, query: SqlQuery) -> anyhow::Result<Vec<Row>> {
+        self.service
+            .config
+            .check_statement_allowed(&query.statement)?;
+        let conn = self.conn();
+        run_with_query_timeout(
+            conn.get_interrupt_handle(),
+            self.service.config.query_timeout,
+            || run_sql(conn, &query, self.service.config.max_result_bytes),
+        )
+        .map_err(|e| self.service.map_error(e))
+    }
+
+    /// Forward a request through the bound context; an error here should be
+    /// propagated so the transaction is dropped and rolled back
+    pub async fn request(
+        &self,
+        path: &str,
+        params: Option<ArcValueType>,
+    ) -> anyhow::Result<Option<ArcValueType>> {
+        self.context.request(path, params).await
+    }
+
+    /// Commit all writes made through this transaction
+    pub fn commit(mut self) -> anyhow::Result<()> {
+        self.conn().execute_batch("COMMIT")?;
+        self.finished = true;
+        Ok(())
+    }
+
+    /// Discard all writes made through this transaction
+    pub fn rollback(mut self) -> anyhow::Result<()> {
+        self.finished = true;
+        self.conn().execute_batch("ROLLBACK")?;
+        Ok(())
+    }
+}
+
+impl Drop for RequestTransaction<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.conn().execute_batch("ROLLBACK");
+        }
+        self.service
+            .writer_in_transaction
+            .store(!self.conn().is_autocommit(), Ordering::SeqCst);
+    }
+}
+
+/// Run `f`, interrupting its connection through `handle` from the timer
+/// thread if it is still running after `timeout`. An interruption caused
+/// by the timer is reported as [`SqliteError::QueryTimeout`].
+fn run_with_query_timeout<T>(
+    handle: InterruptHandle,
+    timeout: Option<Duration>,
+    f: impl FnOnce() -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let Some(timeout) = timeout else {
+        return f();
+    };
+    let finished = Arc::new(std::sync::Mutex::new(false));
+    let fired = Arc::new(AtomicBool::new(false));
+    timer::schedule(timeout, {
+        let (finished, fired) = (finished.clone(), fired.clone());
+        move || {
+            // Interrupt under the lock, so it cannot land on a later call
+            let finished = finished.lock().unwrap();
+            if !*finished {
+                handle.interrupt();
+                fired.store(true, Ordering::SeqCst);
+            }
+        }
+    });
+    let result = f();
+    // Disarm the interrupt. One that landed after `f` returned is harmless:
+    // SQLite clears the flag when the next statement starts on an idle
+    // connection.
+    *finished.lock().unwrap() = true;
+    let fired = fired.load(Ordering::SeqCst);
+    match result {
+        Err(e) if fired && is_interrupted(&e) => Err(SqliteError::QueryTimeout { timeout }.into()),
+        result => result,
+    }
+}
+
+/// Whether the error is `SQLITE_FULL` or `SQLITE_IOERR`
+fn is_storage_failure(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<rusqlite::Error>(),
+        Some(rusqlite::Error::SqliteFailure(e, _))
+            if matches!(e.code, ErrorCode::DiskFull | ErrorCode::SystemIoFailure)
+    )
+}
+
+/// Whether `err` is SQLite reporting a table that does not exist
+pub(crate) fn is_missing_table(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<rusqlite::Error>(),
+        Some(rusqlite::Error::SqliteFailure(_, Some(message)))
+            if message.starts_with("no such table")
+    )
+}
+
+/// Run `f` on `conn`, and if a table it uses is missing, run `create` and
+/// then `f` again. Tables the service keeps for itself are created this way
+/// on first use, rather than by DDL ahead of every statement.
+pub(crate) fn create_on_missing<T>(
+    conn: &Connection,
+    create: impl FnOnce(&Connection) -> rusqlite::Result<()>,
+    mut f: impl FnMut(&Connection) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    match f(conn) {
+        Err(e) if is_missing_table(&e) => {
+            create(conn)?;
+            f(conn)
+        }
+        result => result,
+    }
+}
+
+fn is_interrupted(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<rusqlite::Error>(),
+        Some(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::OperationInterrupted
+    )
+}
+
+/// Whether a statement only reads and returns rows, so it can run on a
+/// reader. Transaction control such as `BEGIN` is read-only but returns no
+/// columns, so it stays on the writer.
+fn is_query(stmt: &Statement<'_>) -> bool {
+    stmt.readonly() && stmt.column_count() > 0
+}
+
+/// Collapse whitespace runs and drop a trailing `;`, for allowlist matching
+fn normalize_statement(sql: &str) -> String {
+    let sql = sql.trim().trim_end_matches(';');
+    sql.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Set the page size of a database that has no pages yet, and check that
+/// the database ends up with it
+fn apply_page_size(conn: &Connection, page_size: u32) -> anyhow::Result<()> {
+    let page_count: u64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+    if page_count == 0 {
+        conn.execute_batch(&format!("PRAGMA page_size = {}", page_size))?;
+    }
+    let actual: u32 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+    if actual != page_size {
+        return Err(SqliteError::PageSizeMismatch {
+            configured: page_size,
+            actual,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// The entries of `map` ordered by key, for rendering stable SQL
+fn sorted_entries<V>(map: &HashMap<String, V>) -> Vec<(&String, &V)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+/// Prepare, bind and run a raw SQL query, see [`collect_rows`] for
+/// `max_bytes`
+pub(crate) fn run_sql(
+    conn: &Connection,
+    query: &SqlQuery,
+    max_bytes: Option<usize>,
+) -> anyhow::Result<Vec<Row>> {
+    let mut stmt = conn.prepare_cached(&query.statement)?;
+    bind_named_params(&mut stmt, &query.params)?;
+    collect_rows(&mut stmt, max_bytes)
+}
+
+/// Run `f` with the pragmas of a query set on `conn`, restoring their
+/// previous values afterwards, also when `f` or a later pragma fails
+fn with_pragmas<T>(
+    conn: &Connection,
+    pragmas: &[(String, Value)],
+    f: impl FnOnce() -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let mut previous = Vec::with_capacity(pragmas.len());
+    let mut result = (|| {
+        for (name, value) in pragmas {
+            let name = name.to_ascii_lowercase();
+            if !REQUEST_PRAGMA_ALLOWLIST.contains(&name.as_str()) {
+                return Err(SqliteError::PragmaNotAllowed(name).into());
+            }
+            let old = conn
+                .query_row(&format!("PRAGMA {}", name), [], |row| {
+                    row.get_ref(0).map(Value::from)
+                })
+                .optional()?
+                .unwrap_or(Value::Integer(0));
+            run_pragma(conn, &name, value)?;
+            previous.push((name, old));
+        }
+        Ok(())
+    })()
+    .and_then(|()| f());
+    for (name, old) in previous.iter().rev() {
+        if let Err(e) = run_pragma(conn, name, old) {
+            result = result.and(Err(e.into()));
+        }
+    }
+    result
+}
+
+/// Set a pragma, ignoring any rows it returns
+fn run_pragma(conn: &Connection, name: &str, value: &Value) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA {} = {}", name, value.to_sql_literal()))?;
+    let mut rows = stmt.raw_query();
+    while rows.next()?.is_some() {}
+    Ok(())
+}
+
+/// Names of the existing `tables` whose stored definition, or that of one of
+/// their configured indexes, differs from the configured one. The configured
+/// statements are run in a scratch database to get them in the form SQLite
+/// stores them.
+fn changed_tables(conn: &Connection, tables: &[&TableDefinition]) -> anyhow::Result<Vec<String>> {
+    let scratch = Connection::open_in_memory()?;
+    let stored_sql = |conn: &Connection, name: &str| {
+        conn.query_row(
+            "SELECT sql FROM sqlite_master WHERE name = ?1",
+            [name],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+    };
+    let mut changed = Vec::new();
+    for table in tables.iter().filter(|t| !t.temporary) {
+        if stored_sql(conn, &table.name)?.is_none() {
+            continue;
+        }
+        scratch.execute(&table.create_table_sql(), [])?;
+        let mut names = vec![table.name.as_str()];
+        for (index, sql) in table.indexes.iter().zip(table.create_index_sql()) {
+            scratch.execute(&sql, [])?;
+            names.push(&index.name);
+        }
+        for name in names {
+            let existing = stored_sql(conn, name)?;
+            if existing.is_some() && existing != stored_sql(&scratch, name)? {
+                changed.push(table.name.clone());
+                break;
+            }
+        }
+    }
+    Ok(changed)
+}
+
+/// Bind named parameters; names may omit the leading `:`
+pub(crate) fn bind_named_params(stmt: &mut Statement<'_>, params: &Params) -> anyhow::Result<()> {
+    for (name, value) in &params.values {
+        let key = if name.starts_with([':', '@', '$']) {
+            name.clone()
+        } else {
+            format!(":{}", name)
+        };
+        let index = stmt
+            .parameter_index(&key)?
+            .ok_or_else(|| SqliteError::UnknownParameter(name.clone()))?;
+        stmt.raw_bind_parameter(index, value)?;
+    }
+    Ok(())
+}
+
+/// Step a bound statement to completion, collecting every row by column
+/// name. With `max_bytes` and a read-only statement, fails with
+/// [`SqliteError::ResultTooLarge`] as soon as the values read add up to more,
+/// see [`SqliteConfig::max_result_bytes`].
+fn collect_rows(stmt: &mut Statement<'_>, max_bytes: Option<usize>) -> anyhow::Result<Vec<Row>> {
+    let max_bytes = max_bytes.filter(|_| stmt.readonly());
+    let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let mut rows = stmt.raw_query();
+    let mut collected = Vec::new();
+    let mut bytes = 0usize;
+    while let Some(row) = rows.next()? {
+        if let Some(max_bytes) = max_bytes {
+            for i in 0..columns.len() {
+                bytes = bytes.saturating_add(match row.get_ref(i)? {
+                    ValueRef::Null => 0,
+                    ValueRef::Integer(_) | ValueRef::Real(_) => 8,
+                    ValueRef::Text(bytes) | ValueRef::Blob(bytes) => bytes.len(),
+                });
+            }
+            if bytes > max_bytes {
+                return Err(SqliteError::ResultTooLarge { max_bytes }.into());
+            }
+        }
+        collected.push(RowView::new(&columns, row).to_row());
+    }
+    Ok(collected)
+}
+
+/// Run a bound statement, folding a view of each row into `acc` as it is
+/// read. Column names are copied once, not per row.
+fn fold_statement<A>(
+    stmt: &mut Statement<'_>,
+    mut acc: A,
+    f: &mut impl FnMut(A, RowView<'_>) -> A,
+) -> anyhow::Result<A> {
+    let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let mut rows = stmt.raw_query();
+    while let Some(row) = rows.next()? {
+        acc = f(acc, RowView::new(&columns, row));
     }
+    Ok(acc)
 }
@@ -0,0 +1,95 @@
+//! One database file per tenant, sharing a schema.
+//!
+//! [`TenantManager`] opens tenant databases on demand under a base directory,
+//! applying the schema, migrations and seeds of a template config to each.
+//! At most `capacity` tenant services are kept open; the least recently used
+//! one is evicted when a new tenant is opened. An evicted tenant's connection
+//! is closed once no caller still holds a clone of its service.
+
+use crate::sqlite::{SqliteConfig, SqliteError, SqliteService};
+use futures::lock::Mutex;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+
+/// Opens and caches a [`SqliteService`] per tenant
+pub struct TenantManager {
+    base_dir: PathBuf,
+    template: SqliteConfig,
+    capacity: usize,
+    open: Mutex<OpenTenants>,
+}
+
+#[derive(Default)]
+struct OpenTenants {
+    services: HashMap<String, SqliteService>,
+    /// Tenant ids from least to most recently used
+    recency: VecDeque<String>,
+}
+
+impl OpenTenants {
+    fn touch(&mut self, tenant_id: &str) {
+        self.recency.retain(|id| id != tenant_id);
+        self.recency.push_back(tenant_id.to_string());
+    }
+}
+
+impl TenantManager {
+    /// Create a manager storing `<tenant_id>.db` files in `base_dir`.
+    ///
+    /// The `db_path` of `template` is ignored. `capacity` is clamped to at
+    /// least one open tenant.
+    pub fn new(base_dir: impl Into<PathBuf>, template: SqliteConfig, capacity: usize) -> Self {
+        Self {
+            base_dir: base_dir.into(),
+            template,
+            capacity: capacity.max(1),
+            open: Mutex::new(OpenTenants::default()),
+        }
+    }
+
+    /// The database file for a tenant. Tenant ids are restricted to ASCII
+    /// letters, digits, `-` and `_` so they cannot escape the base directory.
+    pub fn tenant_path(&self, tenant_id: &str) -> Result<PathBuf, SqliteError> {
+        let valid = !tenant_id.is_empty()
+            && tenant_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(SqliteError::InvalidTenantId(tenant_id.to_string()));
+        }
+        Ok(self.base_dir.join(format!("{}.db", tenant_id)))
+    }
+
+    /// The service for a tenant, opening (and creating) its database if needed
+    pub async fn get(&self, tenant_id: &str) -> anyhow::Result<SqliteService> {
+        let path = self.tenant_path(tenant_id)?;
+        let mut open = self.open.lock().await;
+        if let Some(service) = open.services.get(tenant_id).cloned() {
+            open.touch(tenant_id);
+            return Ok(service);
+        }
+
+        std::fs::create_dir_all(&self.base_dir)?;
+        let mut config = self.template.clone();
+        config.db_path = path.to_string_lossy().into_owned();
+        let service = SqliteService::new(config);
+        service.connect().await?;
+
+        while open.services.len() >= self.capacity {
+            match open.recency.pop_front() {
+                Some(evicted) => {
+                    open.services.remove(&evicted);
+                }
+                None => break,
+            }
+        }
+        open.services.insert(tenant_id.to_string(), service.clone());
+        open.touch(tenant_id);
+        Ok(service)
+    }
+
+    /// Ids of the currently open tenants, least recently used first
+    pub async fn open_tenants(&self) -> Vec<String> {
+        self.open.lock().await.recency.iter().cloned().collect()
+    }
+}
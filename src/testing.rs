@@ -0,0 +1,124 @@
+//! Helpers for tests of code built on the SQLite service.
+//!
+//! Only compiled with the `testing` feature; enable it from
+//! `[dev-dependencies]`.
+
+use crate::migration::{apply_migrations, set_user_version, MigrationTracking};
+use crate::sqlite::{bind_named_params, quote_ident, SqlQuery, SqliteService};
+use rusqlite::{ffi, Connection};
+use std::ffi::CStr;
+
+impl SqliteService {
+    /// The `detail` lines of `EXPLAIN QUERY PLAN` for `query`, in plan order
+    pub async fn query_plan(&self, query: &SqlQuery) -> anyhow::Result<Vec<String>> {
+        self.with_connection(|conn| {
+            let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", query.statement))?;
+            bind_named_params(&mut stmt, &query.params)?;
+            let mut rows = stmt.raw_query();
+            let mut details = Vec::new();
+            while let Some(row) = rows.next()? {
+                details.push(row.get("detail")?);
+            }
+            Ok(details)
+        })
+        .await
+    }
+
+    /// The SQL of every statement currently prepared on the writer
+    /// connection, which includes its statement cache
+    pub async fn prepared_statements(&self) -> anyhow::Result<Vec<String>> {
+        self.with_connection(|conn| {
+            let mut statements = Vec::new();
+            // SAFETY: the connection is locked for the duration of the walk,
+            // and the SQL text is copied before moving to the next statement
+            unsafe {
+                let db = conn.handle();
+                let mut stmt = ffi::sqlite3_next_stmt(db, std::ptr::null_mut());
+                while !stmt.is_null() {
+                    let sql = ffi::sqlite3_sql(stmt);
+                    if !sql.is_null() {
+                        statements.push(CStr::from_ptr(sql).to_string_lossy().into_owned());
+                    }
+                    stmt = ffi::sqlite3_next_stmt(db, stmt);
+                }
+            }
+            Ok(statements)
+        })
+        .await
+    }
+
+    /// Panic unless the plan for `query` uses the index `index_name`.
+    ///
+    /// Meant to pin intended plans in tests so that schema changes which
+    /// silently turn an index lookup into a table scan are caught.
+    pub async fn assert_uses_index(&self, query: &SqlQuery, index_name: &str) {
+        let plan = self
+            .query_plan(query)
+            .await
+            .unwrap_or_else(|e| panic!("failed to plan {:?}: {}", query.statement, e));
+        let uses_index = plan.iter().any(|detail| {
+            detail
+                .split_once(" INDEX ")
+                .is_some_and(|(_, rest)| rest.split(' ').next() == Some(index_name))
+        });
+        assert!(
+            uses_index,
+            "expected query {:?} to use index {:?}, plan was:\n{}",
+            query.statement,
+            index_name,
+            plan.join("\n")
+        );
+    }
+
+    /// Drop every table and view, then rebuild the database as a first
+    /// connect would: the declared schema, the migrations and the seeds.
+    ///
+    /// The service's own tables are dropped too, so the applied migrations
+    /// and the seed marker are recorded afresh and always describe the
+    /// rebuilt schema. `PRAGMA user_version` is only cleared when it tracks
+    /// migrations ([`MigrationTracking::UserVersion`]).
+    pub async fn reset(&self) -> anyhow::Result<()> {
+        self.with_connection(|conn| {
+            // Foreign keys can only be toggled outside a transaction; with
+            // them on, dropping a referenced table would fail
+            conn.execute_batch("PRAGMA foreign_keys = OFF")?;
+            let dropped = drop_all(conn);
+            conn.execute_batch("PRAGMA foreign_keys = ON")?;
+            dropped?;
+            if self.config.migration_tracking == MigrationTracking::UserVersion {
+                set_user_version(conn, 0)?;
+            }
+            self.initialize_schema(conn)?;
+            apply_migrations(conn, &self.config)?;
+            self.apply_seeds(conn)
+        })
+        .await
+    }
+}
+
+/// Drop every view, then every table, in one transaction
+fn drop_all(conn: &Connection) -> anyhow::Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    let objects = {
+        let mut stmt = tx.prepare(
+            "SELECT type, name FROM sqlite_master WHERE type IN ('view', 'table') \
+             AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' \
+             ORDER BY type DESC, sql LIKE 'CREATE VIRTUAL%' DESC",
+        )?;
+        let objects = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        objects
+    };
+    for (kind, name) in objects {
+        tx.execute_batch(&format!(
+            "DROP {} IF EXISTS {}",
+            kind.to_uppercase(),
+            quote_ident(&name)
+        ))?;
+    }
+    tx.commit()?;
+    Ok(())
+}
@@ -0,0 +1,73 @@
+//! Delayed work on one shared background thread.
+//!
+//! The crate does not depend on a particular async runtime, so it cannot ask
+//! one for timers. Work due later is instead handed to a single thread,
+//! started on first use, that sleeps until the earliest deadline. Tasks run
+//! on that thread one after another, so a slow one, such as a batch commit
+//! waiting for the writer, delays those due after it.
+
+use futures::channel::oneshot;
+use std::collections::BTreeMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+type Task = Box<dyn FnOnce() + Send>;
+
+static TIMER: OnceLock<Mutex<Sender<(Instant, Task)>>> = OnceLock::new();
+
+/// Run `task` on the timer thread once `delay` has passed
+pub(crate) fn schedule(delay: Duration, task: impl FnOnce() + Send + 'static) {
+    let sender = TIMER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("runar-sqlite-timer".to_string())
+            .spawn(move || run(receiver))
+            .expect("failed to spawn the timer thread");
+        Mutex::new(sender)
+    });
+    let now = Instant::now();
+    // A delay too long to represent is as good as forever
+    let deadline = now
+        .checked_add(delay)
+        .unwrap_or_else(|| now + Duration::from_secs(u64::from(u32::MAX)));
+    let _ = sender.lock().unwrap().send((deadline, Box::new(task)));
+}
+
+/// Wait without blocking the executor or depending on a particular runtime
+pub(crate) async fn sleep(duration: Duration) {
+    let (sender, receiver) = oneshot::channel();
+    schedule(duration, move || {
+        let _ = sender.send(());
+    });
+    let _ = receiver.await;
+}
+
+/// The timer thread: collect tasks and run each once its deadline passes
+fn run(receiver: Receiver<(Instant, Task)>) {
+    // Keyed by deadline, then by arrival so equal deadlines run in order
+    let mut due: BTreeMap<(Instant, u64), Task> = BTreeMap::new();
+    let mut arrivals = 0u64;
+    loop {
+        let received = match due.keys().next() {
+            Some(&(deadline, _)) => {
+                receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok((deadline, task)) => {
+                due.insert((deadline, arrivals), task);
+                arrivals += 1;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        let now = Instant::now();
+        while let Some(entry) = due.first_entry().filter(|entry| entry.key().0 <= now) {
+            // A panicking task must not take the timer down with it
+            let _ = std::panic::catch_unwind(AssertUnwindSafe(entry.remove()));
+        }
+    }
+}
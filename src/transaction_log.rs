@@ -0,0 +1,91 @@
+//! In-memory log of recent statements, for debugging.
+//!
+//! With [`SqliteConfig::with_transaction_log`], every statement the service
+//! runs for a query or CRUD operation is recorded with its parameters, how
+//! long it took and whether it failed. Only the most recent entries are
+//! kept, so the log can stay enabled through a long session; read it with
+//! [`SqliteService::transaction_log`] when a flow misbehaves. Parameter
+//! values are kept as bound, so avoid enabling it where they are sensitive.
+//!
+//! [`SqliteConfig::with_transaction_log`]: crate::sqlite::SqliteConfig::with_transaction_log
+
+use crate::sqlite::{Params, SqliteService, Value};
+use std::time::Duration;
+
+/// A statement recorded in the transaction log
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionLogEntry {
+    pub statement: String,
+    /// Bound parameters: named ones sorted by name, positional ones named
+    /// `?1`, `?2` and so on
+    pub params: Vec<(String, Value)>,
+    pub elapsed: Duration,
+    /// The error the statement failed with, `None` when it succeeded
+    pub error: Option<String>,
+}
+
+/// Parameters of a statement being observed
+#[derive(Clone, Copy)]
+pub(crate) enum BoundParams<'a> {
+    Named(&'a Params),
+    Positional(&'a [Value]),
+}
+
+impl BoundParams<'_> {
+    fn to_log(self) -> Vec<(String, Value)> {
+        match self {
+            BoundParams::Named(params) => {
+                let mut named: Vec<_> = params
+                    .values
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect();
+                named.sort_by(|a, b| a.0.cmp(&b.0));
+                named
+            }
+            BoundParams::Positional(values) => values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| (format!("?{}", i + 1), value.clone()))
+                .collect(),
+        }
+    }
+}
+
+impl SqliteService {
+    /// The most recent statements run by the service, oldest first. Empty
+    /// unless the transaction log is enabled.
+    pub fn transaction_log(&self) -> Vec<TransactionLogEntry> {
+        self.transaction_log
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Record a statement, evicting the oldest entry when the log is full
+    pub(crate) fn log_statement<T>(
+        &self,
+        statement: &str,
+        params: BoundParams<'_>,
+        elapsed: Duration,
+        result: &anyhow::Result<T>,
+    ) {
+        let capacity = self.config.transaction_log_capacity;
+        if capacity == 0 {
+            return;
+        }
+        let entry = TransactionLogEntry {
+            statement: statement.to_string(),
+            params: params.to_log(),
+            elapsed,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        };
+        let mut log = self.transaction_log.lock().unwrap();
+        while log.len() >= capacity {
+            log.pop_front();
+        }
+        log.push_back(entry);
+    }
+}
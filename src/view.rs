@@ -0,0 +1,148 @@
+//! Borrowed views of result values and rows, for reads that only inspect.
+//!
+//! Collecting a [`Row`] allocates for every cell: the column name is copied
+//! into the map key, and text and blob values into a `String` or `Vec<u8>`.
+//! A [`RowView`] borrows both from the statement instead, so folding over
+//! views allocates nothing per row. On a read of 1,000 rows of 20 integer
+//! columns this takes the allocations from about 21,000 to 24, all of them
+//! made once per query (see `benches/wide_read.rs`).
+
+use crate::sqlite::{Row, SqlQuery, SqliteService, Value};
+use std::borrow::Cow;
+
+/// A result value borrowed from the row it was read from
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueView<'a> {
+    Null,
+    Integer(i64),
+    Real(f64),
+    /// Borrowed when the text is valid UTF-8; otherwise invalid sequences
+    /// are replaced, as when reading into a [`Value`]
+    Text(Cow<'a, str>),
+    Blob(&'a [u8]),
+}
+
+impl ValueView<'_> {
+    pub fn is_null(&self) -> bool {
+        matches!(self, ValueView::Null)
+    }
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            ValueView::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            ValueView::Real(r) => Some(*r),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            ValueView::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+    pub fn as_blob(&self) -> Option<&[u8]> {
+        match self {
+            ValueView::Blob(blob) => Some(blob),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> From<rusqlite::types::ValueRef<'a>> for ValueView<'a> {
+    fn from(value: rusqlite::types::ValueRef<'a>) -> Self {
+        use rusqlite::types::ValueRef;
+        match value {
+            ValueRef::Null => ValueView::Null,
+            ValueRef::Integer(i) => ValueView::Integer(i),
+            ValueRef::Real(r) => ValueView::Real(r),
+            ValueRef::Text(t) => ValueView::Text(String::from_utf8_lossy(t)),
+            ValueRef::Blob(b) => ValueView::Blob(b),
+        }
+    }
+}
+
+impl<'a> From<&'a Value> for ValueView<'a> {
+    /// Booleans view as the integers SQLite stores them as
+    fn from(value: &'a Value) -> Self {
+        match value {
+            Value::Null => ValueView::Null,
+            Value::Integer(i) => ValueView::Integer(*i),
+            Value::Real(r) => ValueView::Real(*r),
+            Value::Text(s) => ValueView::Text(Cow::Borrowed(s)),
+            Value::Blob(b) => ValueView::Blob(b),
+            Value::Boolean(b) => ValueView::Integer(*b as i64),
+        }
+    }
+}
+
+impl From<ValueView<'_>> for Value {
+    fn from(value: ValueView<'_>) -> Self {
+        match value {
+            ValueView::Null => Value::Null,
+            ValueView::Integer(i) => Value::Integer(i),
+            ValueView::Real(r) => Value::Real(r),
+            ValueView::Text(t) => Value::Text(t.into_owned()),
+            ValueView::Blob(b) => Value::Blob(b.to_vec()),
+        }
+    }
+}
+
+/// A result row borrowed from the statement being stepped
+#[derive(Clone, Copy)]
+pub struct RowView<'a> {
+    columns: &'a [String],
+    row: &'a rusqlite::Row<'a>,
+}
+
+impl<'a> RowView<'a> {
+    pub(crate) fn new(columns: &'a [String], row: &'a rusqlite::Row<'a>) -> Self {
+        Self { columns, row }
+    }
+
+    /// Column names, in result order
+    pub fn columns(&self) -> &'a [String] {
+        self.columns
+    }
+
+    /// The value of the column named `column`, if the result has one
+    pub fn get(&self, column: &str) -> Option<ValueView<'a>> {
+        let index = self.columns.iter().position(|c| c == column)?;
+        self.get_index(index)
+    }
+
+    /// The value of the column at `index`, in result order
+    pub fn get_index(&self, index: usize) -> Option<ValueView<'a>> {
+        self.row.get_ref(index).ok().map(ValueView::from)
+    }
+
+    /// Copy the row out of the statement
+    pub fn to_row(&self) -> Row {
+        let mut row = Row::with_capacity(self.columns.len());
+        for (i, column) in self.columns.iter().enumerate() {
+            row.insert(
+                column.clone(),
+                ValueView::from(self.row.get_ref_unwrap(i)).into(),
+            );
+        }
+        row
+    }
+}
+
+impl SqliteService {
+    /// Like [`SqliteService::fold_rows`], but each row is a [`RowView`]
+    /// borrowed from the statement rather than a copied [`Row`]. Read what
+    /// is needed from the view; values that must outlive it have to be
+    /// converted into [`Value`]s.
+    pub async fn fold_row_views<A>(
+        &self,
+        query: SqlQuery,
+        init: A,
+        mut f: impl FnMut(A, RowView<'_>) -> A,
+    ) -> anyhow::Result<A> {
+        self.fold_query(query, init, &mut f).await
+    }
+}
@@ -0,0 +1,184 @@
+//! Coalescing small writes into periodic transactions.
+//!
+//! Every commit of a write transaction waits for the journal to reach the
+//! disk, which caps the rate of individually committed inserts. With
+//! [`SqliteConfig::with_write_batching`], writes handed to
+//! [`SqliteService::queue_write`] are queued instead and committed together
+//! in one transaction, once the configured interval has passed since the
+//! first of them or as soon as enough are waiting. Throughput goes up at
+//! the cost of latency: a queued write is neither visible to reads nor
+//! durable until its batch commits.
+//!
+//! Each write in a batch runs in its own savepoint, so a failing write is
+//! rolled back on its own and the rest of the batch still commits. Failures
+//! are reported by the next [`SqliteService::flush`], which also commits
+//! whatever is still queued; call it before shutting down. Stopping the
+//! service flushes too.
+//!
+//! [`SqliteConfig::with_write_batching`]: crate::sqlite::SqliteConfig::with_write_batching
+
+use crate::sqlite::{run_sql, SqlQuery, SqliteError, SqliteService};
+use crate::timer;
+use crate::transaction_log::BoundParams;
+use rusqlite::Connection;
+use std::sync::Arc;
+
+/// Counters of the batches committed by a service
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteBatchStats {
+    /// Transactions committed for queued writes
+    pub batches: u64,
+    /// Queued writes committed, not counting failed ones
+    pub writes: u64,
+}
+
+#[derive(Default)]
+struct PendingWrites {
+    /// Incremented by every flush, so a timer can tell whether the batch it
+    /// was started for is still waiting
+    batch: u64,
+    queries: Vec<SqlQuery>,
+    failed: usize,
+    first_error: Option<String>,
+    stats: WriteBatchStats,
+}
+
+impl PendingWrites {
+    fn record_failure(&mut self, count: usize, error: &anyhow::Error) {
+        self.failed += count;
+        self.first_error.get_or_insert_with(|| error.to_string());
+    }
+}
+
+/// The write queue of a service, shared by its clones
+#[derive(Clone, Default)]
+pub(crate) struct WriteBatcher {
+    pending: Arc<std::sync::Mutex<PendingWrites>>,
+}
+
+impl WriteBatcher {
+    fn is_empty(&self) -> bool {
+        self.pending.lock().unwrap().queries.is_empty()
+    }
+}
+
+impl SqliteService {
+    /// Commit the queued writes in one transaction on the writer, recording
+    /// failures for the next [`SqliteService::flush`]. With `only_batch`, do
+    /// nothing unless that batch is still the one waiting. Fails only when
+    /// there is no writer, leaving the writes queued.
+    async fn commit_queued(&self, only_batch: Option<u64>) -> anyhow::Result<()> {
+        if self.write_batcher.is_empty() {
+            return Ok(());
+        }
+        // Taking the writer first keeps batches in the order queued
+        self.with_connection(|conn| {
+            let queries = {
+                let mut pending = self.write_batcher.pending.lock().unwrap();
+                if only_batch.is_some_and(|batch| batch != pending.batch) {
+                    return Ok(());
+                }
+                pending.batch += 1;
+                std::mem::take(&mut pending.queries)
+            };
+            if queries.is_empty() {
+                return Ok(());
+            }
+            let result = self.commit_batch(conn, &queries);
+            let mut pending = self.write_batcher.pending.lock().unwrap();
+            match result {
+                Ok(failures) => {
+                    pending.stats.batches += 1;
+                    pending.stats.writes += (queries.len() - failures.len()) as u64;
+                    for error in &failures {
+                        pending.record_failure(1, error);
+                    }
+                }
+                Err(e) => pending.record_failure(queries.len(), &e),
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Run `queries` in one transaction, each in a savepoint, returning the
+    /// errors of the ones rolled back
+    fn commit_batch(
+        &self,
+        conn: &mut Connection,
+        queries: &[SqlQuery],
+    ) -> anyhow::Result<Vec<anyhow::Error>> {
+        let tx = conn.transaction()?;
+        let mut failures = Vec::new();
+        for query in queries {
+            tx.execute_batch("SAVEPOINT queued_write")?;
+            let params = BoundParams::Named(&query.params);
+            match self.observe_query(&tx, &query.statement, params, || run_sql(&tx, query, None)) {
+                Ok(_) => tx.execute_batch("RELEASE queued_write")?,
+                Err(e) => {
+                    tx.execute_batch("ROLLBACK TO queued_write; RELEASE queued_write")?;
+                    failures.push(e);
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(failures)
+    }
+}
+
+impl SqliteService {
+    /// Queue a write to be committed with others in one transaction.
+    ///
+    /// Returns once the write is queued, before it is committed; see
+    /// [`crate::write_batch`]. Without
+    /// [`SqliteConfig::with_write_batching`] the write runs at once, like
+    /// [`SqliteService::execute_sql`].
+    ///
+    /// [`SqliteConfig::with_write_batching`]: crate::sqlite::SqliteConfig::with_write_batching
+    pub async fn queue_write(&self, query: SqlQuery) -> anyhow::Result<()> {
+        let Some(interval) = self.config.write_batch_interval else {
+            return self.execute_sql(query).await.map(|_| ());
+        };
+        self.check_not_closed()?;
+        self.config.check_statement_allowed(&query.statement)?;
+        let (full, new_batch) = {
+            let mut pending = self.write_batcher.pending.lock().unwrap();
+            pending.queries.push(query);
+            let queued = pending.queries.len();
+            (
+                queued >= self.config.write_batch_max_operations,
+                (queued == 1).then_some(pending.batch),
+            )
+        };
+        if full {
+            self.commit_queued(None).await?;
+        } else if let Some(batch) = new_batch {
+            // Failures to commit stay queued for the next flush to report
+            let service = self.clone();
+            timer::schedule(interval, move || {
+                let _ = futures::executor::block_on(service.commit_queued(Some(batch)));
+            });
+        }
+        Ok(())
+    }
+
+    /// Commit every queued write now, then report the queued writes that
+    /// failed since the last flush with [`SqliteError::BatchedWritesFailed`]
+    pub async fn flush(&self) -> anyhow::Result<()> {
+        self.commit_queued(None).await?;
+        let mut pending = self.write_batcher.pending.lock().unwrap();
+        match pending.first_error.take() {
+            Some(first) => Err(SqliteError::BatchedWritesFailed {
+                failed: std::mem::take(&mut pending.failed),
+                first,
+            }
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Batches and writes committed through [`SqliteService::queue_write`]
+    pub fn write_batch_stats(&self) -> WriteBatchStats {
+        self.write_batcher.pending.lock().unwrap().stats
+    }
+}
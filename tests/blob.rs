@@ -0,0 +1,118 @@
+use rust_sqlite::blob::{BlobCodec, TypedBlob};
+use rust_sqlite::sqlite::{
+    ColumnConstraint, ColumnDefinition, CreateOperation, CrudOperation, DataType, Query,
+    QueryOperator, ReadOperation, Schema, SqliteConfig, SqliteError, SqliteService,
+    TableDefinition, Value,
+};
+use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Settings {
+    theme: String,
+    font_size: u32,
+    shortcuts: Vec<(String, String)>,
+    backup: Option<String>,
+}
+
+async fn profiles_service(dir: &TempDir) -> SqliteService {
+    let schema = Schema::new().add_table(
+        TableDefinition::new("profiles")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("settings", DataType::Blob)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    service
+}
+
+async fn round_trip(codec: BlobCodec) {
+    let dir = TempDir::new().unwrap();
+    let service = profiles_service(&dir).await;
+    let blob = TypedBlob::<Settings>::new("settings", codec);
+    let settings = Settings {
+        theme: "dark".to_string(),
+        font_size: 14,
+        shortcuts: vec![("save".to_string(), "ctrl+s".to_string())],
+        backup: None,
+    };
+    service
+        .execute_crud(CrudOperation::Create(
+            CreateOperation::new("profiles")
+                .with_value("id", 1)
+                .with_value("settings", blob.to_value(&settings).unwrap()),
+        ))
+        .await
+        .unwrap();
+    service
+        .execute_crud(CrudOperation::Create(
+            CreateOperation::new("profiles")
+                .with_value("id", 2)
+                .with_value("settings", Value::Null),
+        ))
+        .await
+        .unwrap();
+
+    let rows = service
+        .execute_crud(CrudOperation::Read(
+            ReadOperation::new("profiles").with_query(
+                Query::new().with_condition("id", QueryOperator::Equal(Value::Integer(1))),
+            ),
+        ))
+        .await
+        .unwrap();
+    assert!(matches!(rows[0].get("settings"), Some(Value::Blob(_))));
+    assert_eq!(blob.read(&rows[0]).unwrap(), settings);
+
+    let rows = service
+        .execute_crud(CrudOperation::Read(
+            ReadOperation::new("profiles").with_query(
+                Query::new().with_condition("id", QueryOperator::Equal(Value::Integer(2))),
+            ),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(blob.read_optional(&rows[0]).unwrap(), None);
+    let err = blob.read(&rows[0]).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::InvalidBlob { column, .. }) if column == "settings"
+    ));
+}
+
+#[tokio::test]
+async fn test_struct_round_trips_through_json_blob() {
+    round_trip(BlobCodec::Json).await;
+}
+
+#[cfg(feature = "bincode")]
+#[tokio::test]
+async fn test_struct_round_trips_through_bincode_blob() {
+    round_trip(BlobCodec::Bincode).await;
+}
+
+#[cfg(feature = "cbor")]
+#[tokio::test]
+async fn test_struct_round_trips_through_cbor_blob() {
+    round_trip(BlobCodec::Cbor).await;
+}
+
+#[test]
+fn test_blob_of_another_type_fails_to_decode() {
+    let blob = TypedBlob::<Settings>::new("settings", BlobCodec::Json);
+    let row = [(
+        "settings".to_string(),
+        Value::Blob(serde_json::to_vec(&[1, 2, 3]).unwrap()),
+    )]
+    .into_iter()
+    .collect();
+    let err = blob.read(&row).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::InvalidBlob { .. })
+    ));
+}
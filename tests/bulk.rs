@@ -0,0 +1,211 @@
+use rust_sqlite::bulk::MergeResult;
+use rust_sqlite::sqlite::{
+    ColumnConstraint, ColumnDefinition, DataType, Row, Schema, SqlQuery, SqliteConfig, SqliteError,
+    SqliteService, TableDefinition, Value,
+};
+use tempfile::TempDir;
+
+async fn product_service(dir: &TempDir) -> SqliteService {
+    let schema = Schema::new().add_table(
+        TableDefinition::new("products")
+            .with_column(
+                ColumnDefinition::new("sku", DataType::Text)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("name", DataType::Text))
+            .with_column(ColumnDefinition::new("price", DataType::Real)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    service
+}
+
+fn product(sku: &str, name: &str, price: f64) -> Row {
+    Row::from([
+        ("sku".to_string(), Value::from(sku)),
+        ("name".to_string(), Value::from(name)),
+        ("price".to_string(), Value::from(price)),
+    ])
+}
+
+async fn products(service: &SqliteService) -> Vec<Row> {
+    service
+        .execute_sql(SqlQuery::new(
+            "SELECT sku, name, price FROM products ORDER BY sku",
+        ))
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_merge_syncs_table_to_desired_state() {
+    let dir = TempDir::new().unwrap();
+    let service = product_service(&dir).await;
+    service
+        .execute_sql(SqlQuery::new(
+            "INSERT INTO products (sku, name, price) VALUES
+                ('a', 'Apple', 1.0), ('b', 'Banana', 2.0), ('c', 'Cherry', 3.0)",
+        ))
+        .await
+        .unwrap();
+
+    let desired = vec![
+        product("a", "Apple", 1.5),
+        product("c", "Cherry", 3.0),
+        product("d", "Date", 4.0),
+    ];
+    let result = service
+        .merge("products", &["sku"], desired.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        result,
+        MergeResult {
+            inserted: 1,
+            updated: 2,
+            deleted: 1,
+        }
+    );
+    assert_eq!(products(&service).await, desired);
+
+    // Merging the same state again changes nothing
+    let result = service
+        .merge("products", &["sku"], desired.clone())
+        .await
+        .unwrap();
+    assert_eq!(result.inserted + result.deleted, 0);
+    assert_eq!(products(&service).await, desired);
+}
+
+#[tokio::test]
+async fn test_merge_rolls_back_when_a_row_lacks_a_key() {
+    let dir = TempDir::new().unwrap();
+    let service = product_service(&dir).await;
+    service
+        .execute_sql(SqlQuery::new(
+            "INSERT INTO products (sku, name, price) VALUES ('a', 'Apple', 1.0)",
+        ))
+        .await
+        .unwrap();
+
+    let mut keyless = product("x", "Unknown", 0.0);
+    keyless.remove("sku");
+    assert!(service
+        .merge(
+            "products",
+            &["sku"],
+            vec![product("b", "Banana", 2.0), keyless]
+        )
+        .await
+        .is_err());
+    assert_eq!(products(&service).await, vec![product("a", "Apple", 1.0)]);
+}
+
+#[tokio::test]
+async fn test_copy_table_to_fresh_service_in_batches() {
+    let source_dir = TempDir::new().unwrap();
+    let source = product_service(&source_dir).await;
+    let rows: Vec<Row> = (0..25)
+        .map(|i| {
+            product(
+                &format!("sku-{:02}", i),
+                &format!("product {}", i),
+                i as f64,
+            )
+        })
+        .collect();
+    source.merge("products", &["sku"], rows).await.unwrap();
+
+    let destination_dir = TempDir::new().unwrap();
+    let destination = product_service(&destination_dir).await;
+    let copied = source
+        .copy_table_to(&destination, "products", 10)
+        .await
+        .unwrap();
+    assert_eq!(copied, 25);
+    assert_eq!(products(&destination).await, products(&source).await);
+
+    // An empty table copies nothing
+    source
+        .execute_sql(SqlQuery::new("DELETE FROM products"))
+        .await
+        .unwrap();
+    let copied = source
+        .copy_table_to(&destination, "products", 10)
+        .await
+        .unwrap();
+    assert_eq!(copied, 0);
+}
+
+#[tokio::test]
+async fn test_update_values_sets_distinct_values_in_one_statement() {
+    let dir = TempDir::new().unwrap();
+    let service = product_service(&dir).await;
+    let rows: Vec<Row> = (0..500)
+        .map(|i| product(&format!("sku-{:03}", i), "item", 1.0))
+        .collect();
+    service.merge("products", &["sku"], rows).await.unwrap();
+
+    let prices = (0..400).map(|i| (format!("sku-{:03}", i), i as f64 * 2.5));
+    let updated = service
+        .update_values("products", "sku", "price", prices)
+        .await
+        .unwrap();
+    assert_eq!(updated, 400);
+
+    let rows = products(&service).await;
+    for (i, row) in rows.iter().enumerate() {
+        let expected = if i < 400 { i as f64 * 2.5 } else { 1.0 };
+        assert_eq!(row.get("price"), Some(&Value::Real(expected)));
+    }
+    let none: Vec<(String, f64)> = Vec::new();
+    assert_eq!(
+        service
+            .update_values("products", "sku", "price", none)
+            .await
+            .unwrap(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn test_update_values_rejects_disallowed_values() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("tasks")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(
+                ColumnDefinition::new("status", DataType::Integer).with_allowed_values(&[0, 1, 2]),
+            ),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    service
+        .execute_sql(SqlQuery::new(
+            "INSERT INTO tasks (id, status) VALUES (1, 0), (2, 0)",
+        ))
+        .await
+        .unwrap();
+
+    let err = service
+        .update_values("tasks", "id", "status", [(1, 2), (2, 7)])
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::InvalidEnumValue { column, value: Value::Integer(7), .. })
+            if column == "status"
+    ));
+    let rows = service
+        .execute_sql(SqlQuery::new("SELECT status FROM tasks ORDER BY id"))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["status"], Value::Integer(0));
+    assert_eq!(rows[1]["status"], Value::Integer(0));
+}
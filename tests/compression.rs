@@ -0,0 +1,247 @@
+#![cfg(feature = "compression")]
+
+use rust_sqlite::sqlite::{
+    ColumnConstraint, ColumnDefinition, CreateOperation, CrudOperation, DataType, Query,
+    QueryOperator, ReadOperation, Row, Schema, SqlQuery, SqliteConfig, SqliteService,
+    TableDefinition, UpdateOperation, Value,
+};
+use tempfile::TempDir;
+
+async fn documents_service(dir: &TempDir) -> SqliteService {
+    let schema = Schema::new().add_table(
+        TableDefinition::new("documents")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("body", DataType::Blob).with_compressed(true))
+            .with_column(ColumnDefinition::new("attachment", DataType::Blob).with_compressed(true)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    service
+}
+
+fn by_id(id: i64) -> Query {
+    Query::new().with_condition("id", QueryOperator::Equal(Value::Integer(id)))
+}
+
+#[tokio::test]
+async fn test_compressed_text_round_trips_and_is_stored_smaller() {
+    let dir = TempDir::new().unwrap();
+    let service = documents_service(&dir).await;
+    let body = "the quick brown fox jumps over the lazy dog. ".repeat(2_000);
+
+    let created = service
+        .execute_crud(CrudOperation::Create(
+            CreateOperation::new("documents")
+                .with_value("id", Value::Integer(1))
+                .with_value("body", Value::Text(body.clone())),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(created[0]["body"], Value::Text(body.clone()));
+
+    let rows = service
+        .execute_crud(CrudOperation::Read(
+            ReadOperation::new("documents").with_query(by_id(1)),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["body"], Value::Text(body.clone()));
+
+    let stored = service
+        .execute_sql(SqlQuery::new(
+            "SELECT typeof(body) AS kind, length(body) AS size FROM documents",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(stored[0]["kind"], Value::Text("blob".to_string()));
+    let Value::Integer(size) = stored[0]["size"] else {
+        panic!("unexpected size {:?}", stored[0]["size"]);
+    };
+    assert!((size as usize) < body.len() / 10, "stored {} bytes", size);
+
+    let loaded = service.load("documents", 1).await.unwrap().unwrap();
+    assert_eq!(loaded["body"], Value::Text(body.clone()));
+
+    let read = || ReadOperation::new("documents").with_fields(&["id", "body"]);
+    let combined = service.union(read(), read(), false).await.unwrap();
+    assert_eq!(combined.len(), 1);
+    assert_eq!(combined[0]["body"], Value::Text(body));
+}
+
+#[tokio::test]
+async fn test_compressed_blobs_and_nulls_round_trip_through_updates() {
+    let dir = TempDir::new().unwrap();
+    let service = documents_service(&dir).await;
+    let attachment: Vec<u8> = (0..50_000u32).map(|i| (i % 7) as u8).collect();
+    service
+        .execute_crud(CrudOperation::Create(
+            CreateOperation::new("documents").with_value("id", Value::Integer(1)),
+        ))
+        .await
+        .unwrap();
+
+    let updated = service
+        .execute_crud(CrudOperation::Update(
+            UpdateOperation::new("documents", by_id(1))
+                .with_update("attachment", Value::Blob(attachment.clone())),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(updated[0]["attachment"], Value::Blob(attachment));
+    assert_eq!(updated[0]["body"], Value::Null);
+}
+
+#[tokio::test]
+async fn test_update_values_compresses_values() {
+    let dir = TempDir::new().unwrap();
+    let service = documents_service(&dir).await;
+    let body = "a bulk update of a compressed column. ".repeat(1_000);
+    for id in 1..=2 {
+        service
+            .execute_crud(CrudOperation::Create(
+                CreateOperation::new("documents").with_value("id", Value::Integer(id)),
+            ))
+            .await
+            .unwrap();
+    }
+
+    let updated = service
+        .update_values(
+            "documents",
+            "id",
+            "body",
+            [(1, body.clone()), (2, body.clone())],
+        )
+        .await
+        .unwrap();
+    assert_eq!(updated, 2);
+    let rows = service
+        .execute_crud(CrudOperation::Read(ReadOperation::new("documents")))
+        .await
+        .unwrap();
+    assert!(rows
+        .iter()
+        .all(|row| row["body"] == Value::Text(body.clone())));
+    let stored = service
+        .execute_sql(SqlQuery::new(
+            "SELECT max(length(body)) AS size FROM documents",
+        ))
+        .await
+        .unwrap();
+    let Value::Integer(size) = stored[0]["size"] else {
+        panic!("unexpected size {:?}", stored[0]["size"]);
+    };
+    assert!((size as usize) < body.len() / 10, "stored {} bytes", size);
+}
+
+#[tokio::test]
+async fn test_merge_compresses_values() {
+    let dir = TempDir::new().unwrap();
+    let service = documents_service(&dir).await;
+    let body = "a merged document body. ".repeat(1_000);
+    let rows = (1..=2)
+        .map(|id| {
+            Row::from([
+                ("id".to_string(), Value::Integer(id)),
+                ("body".to_string(), Value::Text(body.clone())),
+            ])
+        })
+        .collect();
+    service.merge("documents", &["id"], rows).await.unwrap();
+
+    let rows = service
+        .execute_crud(CrudOperation::Read(ReadOperation::new("documents")))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 2);
+    assert!(rows
+        .iter()
+        .all(|row| row["body"] == Value::Text(body.clone())));
+    let stored = service
+        .execute_sql(SqlQuery::new(
+            "SELECT DISTINCT typeof(body) AS kind FROM documents",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(
+        stored,
+        vec![Row::from([("kind".to_string(), Value::from("blob"))])]
+    );
+}
+
+#[tokio::test]
+async fn test_copy_table_to_converts_compressed_columns() {
+    let dir = TempDir::new().unwrap();
+    let compressed = documents_service(&dir).await;
+    let schema = Schema::new().add_table(
+        TableDefinition::new("documents")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("body", DataType::Blob))
+            .with_column(ColumnDefinition::new("attachment", DataType::Blob).with_compressed(true)),
+    );
+    let path = dir.path().join("plain.db").to_str().unwrap().to_string();
+    let plain = SqliteService::new(SqliteConfig::new(path, schema));
+    plain.connect().await.unwrap();
+    let body = "a copied document body. ".repeat(1_000);
+    let attachment: Vec<u8> = (0..10_000u32).map(|i| (i % 5) as u8).collect();
+    plain
+        .execute_crud(CrudOperation::Create(
+            CreateOperation::new("documents")
+                .with_value("id", Value::Integer(1))
+                .with_value("body", Value::Text(body.clone()))
+                .with_value("attachment", Value::Blob(attachment.clone())),
+        ))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        plain
+            .copy_table_to(&compressed, "documents", 10)
+            .await
+            .unwrap(),
+        1
+    );
+    let rows = compressed
+        .execute_crud(CrudOperation::Read(
+            ReadOperation::new("documents").with_query(by_id(1)),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["body"], Value::Text(body.clone()));
+    assert_eq!(rows[0]["attachment"], Value::Blob(attachment.clone()));
+    let stored = compressed
+        .execute_sql(SqlQuery::new("SELECT typeof(body) AS kind FROM documents"))
+        .await
+        .unwrap();
+    assert_eq!(stored[0]["kind"], Value::from("blob"));
+
+    // And back into a table that stores the column plain
+    plain
+        .execute_sql(SqlQuery::new("DELETE FROM documents"))
+        .await
+        .unwrap();
+    assert_eq!(
+        compressed
+            .copy_table_to(&plain, "documents", 10)
+            .await
+            .unwrap(),
+        1
+    );
+    let stored = plain
+        .execute_sql(SqlQuery::new("SELECT body FROM documents"))
+        .await
+        .unwrap();
+    assert_eq!(stored[0]["body"], Value::Text(body));
+    let rows = plain
+        .execute_crud(CrudOperation::Read(ReadOperation::new("documents")))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["attachment"], Value::Blob(attachment));
+}
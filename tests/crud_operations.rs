@@ -0,0 +1,1584 @@
+use rust_sqlite::cursor::CursorPage;
+use rust_sqlite::datetime::{datetime_ago, format_datetime, modifier_ago};
+use rust_sqlite::sqlite::{
+    Aggregate, Collation, ColumnConstraint, ColumnDefinition, CommonTableExpression,
+    CreateOperation, CrudOperation, DataType, DefaultValue, DeleteOperation, ForeignKey,
+    NullHandling, Params, Query, QueryOperator, ReadOperation, Row, RowExt, Schema, SqlQuery,
+    SqliteConfig, SqliteError, SqliteService, TableDefinition, TablePermissions, UpdateOperation,
+    Value,
+};
+use serde::Serialize;
+use std::time::{Duration, UNIX_EPOCH};
+use tempfile::TempDir;
+
+async fn items_service(dir: &TempDir, count: i64) -> SqliteService {
+    let schema = Schema::new().add_table(
+        TableDefinition::new("items")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("name", DataType::Text))
+            .with_column(ColumnDefinition::new("price", DataType::Real)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    for id in 1..=count {
+        service
+            .execute_crud(CrudOperation::Create(
+                CreateOperation::new("items")
+                    .with_value("id", id)
+                    .with_value("name", format!("item {}", id)),
+            ))
+            .await
+            .unwrap();
+    }
+    service
+}
+
+async fn ids_in(service: &SqliteService, values: Vec<Value>) -> Vec<Value> {
+    let read = ReadOperation::new("items")
+        .with_query(Query::new().with_condition("id", QueryOperator::In(values)))
+        .with_fields(&["id"])
+        .with_order_by("id", true);
+    service
+        .execute_crud(CrudOperation::Read(read))
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|mut row| row.remove("id").unwrap())
+        .collect()
+}
+
+#[test]
+fn test_in_expands_one_placeholder_per_value() {
+    let (sql, params) = QueryOperator::In(vec![]).to_sql("id");
+    assert_eq!(sql, "0");
+    assert!(params.is_empty());
+
+    let (sql, params) = QueryOperator::In(vec![Value::Integer(7)]).to_sql("id");
+    assert_eq!(sql, "\"id\" IN (?)");
+    assert_eq!(params, vec![Value::Integer(7)]);
+
+    let (sql, params) = QueryOperator::In(vec![
+        Value::Integer(1),
+        Value::Integer(2),
+        Value::Integer(3),
+    ])
+    .to_sql("id");
+    assert_eq!(sql, "\"id\" IN (?, ?, ?)");
+    assert_eq!(params.len(), 3);
+}
+
+#[tokio::test]
+async fn test_empty_in_list_matches_no_rows() {
+    let dir = TempDir::new().unwrap();
+    let service = items_service(&dir, 3).await;
+    assert!(ids_in(&service, vec![]).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_single_value_in_list() {
+    let dir = TempDir::new().unwrap();
+    let service = items_service(&dir, 3).await;
+    assert_eq!(
+        ids_in(&service, vec![Value::Integer(2)]).await,
+        vec![Value::Integer(2)]
+    );
+}
+
+#[tokio::test]
+async fn test_large_in_list() {
+    let dir = TempDir::new().unwrap();
+    let service = items_service(&dir, 50).await;
+    // Every even id plus many values that match nothing
+    let values: Vec<Value> = (0..5000).map(|i| Value::Integer(i * 2)).collect();
+    let expected: Vec<Value> = (1..=25).map(|i| Value::Integer(i * 2)).collect();
+    assert_eq!(ids_in(&service, values).await, expected);
+}
+
+fn by_id(id: i64) -> Query {
+    Query::new().with_condition("id", QueryOperator::Equal(Value::Integer(id)))
+}
+
+#[tokio::test]
+async fn test_update_with_null_sets_column_to_null() {
+    let dir = TempDir::new().unwrap();
+    let service = items_service(&dir, 2).await;
+
+    let rows = service
+        .execute_crud(CrudOperation::Update(
+            UpdateOperation::new("items", by_id(1)).with_null("name"),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["name"], Value::Null);
+
+    // Only the matched row changed
+    let rows = service
+        .execute_crud(CrudOperation::Read(
+            ReadOperation::new("items").with_query(by_id(2)),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["name"], Value::Text("item 2".to_string()));
+}
+
+#[tokio::test]
+async fn test_update_leaves_omitted_columns_unchanged() {
+    let dir = TempDir::new().unwrap();
+    let service = items_service(&dir, 1).await;
+
+    let rows = service
+        .execute_crud(CrudOperation::Update(
+            UpdateOperation::new("items", by_id(1)).with_update("price", 9.5),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["price"], Value::Real(9.5));
+    assert_eq!(rows[0]["name"], Value::Text("item 1".to_string()));
+
+    // An update with no columns changes nothing and returns the matched rows
+    let rows = service
+        .execute_crud(CrudOperation::Update(UpdateOperation::new(
+            "items",
+            by_id(1),
+        )))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["price"], Value::Real(9.5));
+    assert_eq!(rows[0]["name"], Value::Text("item 1".to_string()));
+}
+
+#[tokio::test]
+async fn test_set_field_updates_matching_rows_and_counts_them() {
+    let dir = TempDir::new().unwrap();
+    let service = items_service(&dir, 5).await;
+
+    let changed = service
+        .set_field(
+            "items",
+            "name",
+            "archived",
+            Query::new().with_condition("id", QueryOperator::GreaterThan(Value::Integer(3))),
+        )
+        .await
+        .unwrap();
+    assert_eq!(changed, 2);
+    let archived = service
+        .execute_crud(CrudOperation::Read(
+            ReadOperation::new("items")
+                .with_query(
+                    Query::new()
+                        .with_condition("name", QueryOperator::Equal(Value::from("archived"))),
+                )
+                .with_fields(&["id"])
+                .with_order_by("id", true),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(archived.len(), 2);
+    assert_eq!(archived[0]["id"], Value::Integer(4));
+
+    let changed = service
+        .set_field(
+            "items",
+            "name",
+            "none",
+            Query::new().with_condition("id", QueryOperator::Equal(Value::Integer(99))),
+        )
+        .await
+        .unwrap();
+    assert_eq!(changed, 0);
+}
+
+#[tokio::test]
+async fn test_recursive_cte_traverses_a_tree() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("categories")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("parent_id", DataType::Integer)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    //     1          5
+    //    / \         |
+    //   2   3        6
+    //   |
+    //   4
+    for (id, parent) in [
+        (1, None),
+        (2, Some(1)),
+        (3, Some(1)),
+        (4, Some(2)),
+        (5, None),
+        (6, Some(5)),
+    ] {
+        service
+            .execute_crud(CrudOperation::Create(
+                CreateOperation::new("categories")
+                    .with_value("id", id)
+                    .with_value("parent_id", parent),
+            ))
+            .await
+            .unwrap();
+    }
+
+    // Descendants of 1 below the root, with their depth
+    let subtree = CommonTableExpression::recursive(
+        "subtree",
+        "SELECT id, 0 FROM categories WHERE id = ? \
+         UNION ALL \
+         SELECT c.id, s.depth + 1 FROM categories c JOIN subtree s ON c.parent_id = s.id",
+    )
+    .with_columns(&["id", "depth"])
+    .with_params(vec![Value::Integer(1)]);
+    let read = ReadOperation::new("subtree")
+        .with_cte(subtree)
+        .with_query(Query::new().with_condition("depth", QueryOperator::GreaterThan(0.into())))
+        .with_order_by("id", true);
+
+    let (sql, params) = CrudOperation::Read(read.clone()).to_sql();
+    assert!(sql.starts_with("WITH RECURSIVE \"subtree\" (\"id\", \"depth\") AS (SELECT"));
+    // CTE parameters come before the read's own
+    assert_eq!(params, vec![Value::Integer(1), Value::Integer(0)]);
+
+    let rows = service
+        .execute_crud(CrudOperation::Read(read))
+        .await
+        .unwrap();
+    let found: Vec<(Value, Value)> = rows
+        .into_iter()
+        .map(|mut r| (r.remove("id").unwrap(), r.remove("depth").unwrap()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (Value::Integer(2), Value::Integer(1)),
+            (Value::Integer(3), Value::Integer(1)),
+            (Value::Integer(4), Value::Integer(2)),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_nocase_collation_on_columns_and_in_queries() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("tags")
+            .with_column(
+                ColumnDefinition::new("label", DataType::Text).with_collation(Collation::NoCase),
+            )
+            .with_column(ColumnDefinition::new("code", DataType::Text)),
+    );
+    assert!(schema.to_ddl().contains("\"label\" TEXT COLLATE NOCASE"));
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    for (label, code) in [("Rust", "b"), ("sqlite", "A"), ("RUNAR", "c")] {
+        service
+            .execute_crud(CrudOperation::Create(
+                CreateOperation::new("tags")
+                    .with_value("label", label)
+                    .with_value("code", code),
+            ))
+            .await
+            .unwrap();
+    }
+
+    // The column collation makes equality case-insensitive
+    let read = ReadOperation::new("tags")
+        .with_query(Query::new().with_condition("label", QueryOperator::Equal("rust".into())));
+    let rows = service
+        .execute_crud(CrudOperation::Read(read))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["label"], Value::Text("Rust".to_string()));
+
+    // A binary column needs the collation in the query
+    let binary = ReadOperation::new("tags")
+        .with_query(Query::new().with_condition("code", QueryOperator::Equal("a".into())));
+    assert!(service
+        .execute_crud(CrudOperation::Read(binary))
+        .await
+        .unwrap()
+        .is_empty());
+    let collated = ReadOperation::new("tags").with_query(Query::new().with_condition(
+        "code",
+        QueryOperator::Collate(
+            Collation::NoCase,
+            Box::new(QueryOperator::Equal("a".into())),
+        ),
+    ));
+    let (sql, _) = CrudOperation::Read(collated.clone()).to_sql();
+    assert!(sql.ends_with("WHERE \"code\" COLLATE NOCASE = ?"));
+    let rows = service
+        .execute_crud(CrudOperation::Read(collated))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+
+    // Ordering with a collation
+    let ordered = ReadOperation::new("tags")
+        .with_fields(&["code"])
+        .with_order_by_collated("code", true, Collation::NoCase);
+    let rows = service
+        .execute_crud(CrudOperation::Read(ordered))
+        .await
+        .unwrap();
+    let codes: Vec<&Value> = rows.iter().map(|r| &r["code"]).collect();
+    assert_eq!(
+        codes,
+        vec![
+            &Value::Text("A".to_string()),
+            &Value::Text("b".to_string()),
+            &Value::Text("c".to_string()),
+        ]
+    );
+}
+
+#[derive(Serialize)]
+struct ItemPatch {
+    name: Option<String>,
+    price: Option<f64>,
+}
+
+#[tokio::test]
+async fn test_update_patch_sets_only_present_fields() {
+    let dir = TempDir::new().unwrap();
+    let service = items_service(&dir, 2).await;
+    service
+        .execute_crud(CrudOperation::Update(
+            UpdateOperation::new("items", by_id(1)).with_update("price", 5.0),
+        ))
+        .await
+        .unwrap();
+
+    let patch = ItemPatch {
+        name: Some("renamed".to_string()),
+        price: None,
+    };
+    let rows = service
+        .update_patch("items", by_id(1), &patch)
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["name"], Value::Text("renamed".to_string()));
+    assert_eq!(rows[0]["price"], Value::Real(5.0));
+
+    // Rows outside the query are untouched
+    let rows = service
+        .execute_crud(CrudOperation::Read(
+            ReadOperation::new("items").with_query(by_id(2)),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["name"], Value::Text("item 2".to_string()));
+
+    // Only structs and maps make sense as patches
+    let err = service
+        .update_patch("items", by_id(1), &42)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::InvalidPatch(_))
+    ));
+    let patch = std::collections::HashMap::from([("name", u64::MAX)]);
+    let err = service
+        .update_patch("items", by_id(1), &patch)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::IntegerOutOfRange { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_changed_since_returns_rows_updated_after_checkpoint() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("notes")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("body", DataType::Text))
+            .with_column(ColumnDefinition::new("updated_at", DataType::Text)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    for id in 1..=4 {
+        service
+            .execute_crud(CrudOperation::Create(
+                CreateOperation::new("notes")
+                    .with_value("id", id)
+                    .with_value("body", "draft")
+                    .with_value("updated_at", "2024-01-01 00:00:00"),
+            ))
+            .await
+            .unwrap();
+    }
+    let checkpoint = "2024-01-01 00:00:00";
+
+    for (id, updated_at) in [(3, "2024-01-02 09:00:00"), (1, "2024-01-02 08:00:00")] {
+        service
+            .execute_crud(CrudOperation::Update(
+                UpdateOperation::new(
+                    "notes",
+                    Query::new().with_condition("id", QueryOperator::Equal(Value::Integer(id))),
+                )
+                .with_update("body", "edited")
+                .with_update("updated_at", updated_at),
+            ))
+            .await
+            .unwrap();
+    }
+
+    let changed = service.changed_since("notes", checkpoint).await.unwrap();
+    let ids: Vec<&Value> = changed.iter().map(|row| &row["id"]).collect();
+    assert_eq!(ids, [&Value::Integer(1), &Value::Integer(3)]);
+    assert!(changed
+        .iter()
+        .all(|row| row["body"] == Value::Text("edited".to_string())));
+
+    let latest = changed.last().unwrap()["updated_at"].clone();
+    assert!(service
+        .changed_since("notes", latest)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_read_page_reports_whether_more_rows_exist() {
+    let dir = TempDir::new().unwrap();
+    let service = items_service(&dir, 5).await;
+    let page = |offset| {
+        ReadOperation::new("items")
+            .with_order_by("id", true)
+            .with_limit(2)
+            .with_offset(offset)
+    };
+
+    let first = service.read_page(page(0)).await.unwrap();
+    assert_eq!(first.rows.len(), 2);
+    assert!(first.has_more);
+    let last = service.read_page(page(4)).await.unwrap();
+    assert_eq!(last.rows.len(), 1);
+    assert!(!last.has_more);
+    // Exactly `limit` rows left
+    let exact = service.read_page(page(3)).await.unwrap();
+    assert_eq!(exact.rows.len(), 2);
+    assert!(!exact.has_more);
+
+    let all = service
+        .read_page(ReadOperation::new("items"))
+        .await
+        .unwrap();
+    assert_eq!(all.rows.len(), 5);
+    assert!(!all.has_more);
+}
+
+#[tokio::test]
+async fn test_aggregates_skip_nulls_or_count_them_as_zero() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("scores")
+            .with_column(ColumnDefinition::new("team", DataType::Text))
+            .with_column(ColumnDefinition::new("points", DataType::Integer)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    for (team, points) in [("a", Some(10)), ("a", None), ("a", Some(20)), ("b", None)] {
+        service
+            .execute_crud(CrudOperation::Create(
+                CreateOperation::new("scores")
+                    .with_value("team", team)
+                    .with_value("points", points),
+            ))
+            .await
+            .unwrap();
+    }
+
+    let read = ReadOperation::new("scores")
+        .with_fields(&["team"])
+        .with_order_by("team", true)
+        .with_aggregate(Aggregate::count_rows("rows"))
+        .with_aggregate(Aggregate::count("points", "counted"))
+        .with_aggregate(Aggregate::sum("points", "sum"))
+        .with_aggregate(Aggregate::avg("points", "avg"))
+        .with_aggregate(Aggregate::count("points", "counted_zero").with_nulls(NullHandling::AsZero))
+        .with_aggregate(Aggregate::sum("points", "sum_zero").with_nulls(NullHandling::AsZero))
+        .with_aggregate(Aggregate::avg("points", "avg_zero").with_nulls(NullHandling::AsZero));
+    let rows = service
+        .execute_crud(CrudOperation::Read(read))
+        .await
+        .unwrap();
+
+    assert_eq!(rows.len(), 2);
+    let (a, b) = (&rows[0], &rows[1]);
+    assert_eq!(a["team"], Value::Text("a".to_string()));
+    assert_eq!(a["rows"], Value::Integer(3));
+    // Skipped: the NULL is neither counted nor averaged
+    assert_eq!(a["counted"], Value::Integer(2));
+    assert_eq!(a["sum"], Value::Integer(30));
+    assert_eq!(a["avg"], Value::Real(15.0));
+    // As zero: the NULL counts and lowers the average
+    assert_eq!(a["counted_zero"], Value::Integer(3));
+    assert_eq!(a["sum_zero"], Value::Integer(30));
+    assert_eq!(a["avg_zero"], Value::Real(10.0));
+    // A sum over only NULLs is NULL unless NULLs count as zero
+    assert_eq!(b["sum"], Value::Null);
+    assert_eq!(b["sum_zero"], Value::Integer(0));
+}
+
+#[test]
+fn test_equal_operations_render_identical_sql() {
+    let fields = ["name", "price", "id", "stock", "category", "sku"];
+    let build = |reverse: bool| {
+        let mut fields = fields.to_vec();
+        if reverse {
+            fields.reverse();
+        }
+        let mut create = CreateOperation::new("items");
+        let mut query = Query::new();
+        let mut update = UpdateOperation::new("items", Query::new());
+        for field in fields {
+            create = create.with_value(field, field);
+            query = query.with_condition(field, QueryOperator::Equal(Value::from(field)));
+            update = update.with_update(field, field);
+        }
+        update.query = query.clone();
+        [
+            CrudOperation::Create(create).to_sql(),
+            CrudOperation::Read(ReadOperation::new("items").with_query(query)).to_sql(),
+            CrudOperation::Update(update).to_sql(),
+        ]
+    };
+    // Each build gets fresh maps, and with them a fresh iteration order
+    let expected = build(false);
+    for i in 0..20 {
+        assert_eq!(build(i % 2 == 1), expected);
+    }
+}
+
+#[tokio::test]
+async fn test_large_in_list_binds_one_json_array() {
+    let values: Vec<Value> = (0..300).map(|i| Value::Integer(i * 2)).collect();
+    let (sql, params) = QueryOperator::In(values.clone()).to_sql("id");
+    assert_eq!(sql, "\"id\" IN (SELECT value FROM json_each(?))");
+    assert_eq!(params.len(), 1);
+    // The statement text does not depend on the list length
+    let (longer, _) = QueryOperator::In(vec![Value::Integer(1); 1000]).to_sql("id");
+    assert_eq!(longer, sql);
+    // Blobs have no JSON form and keep their placeholders
+    let (sql, params) = QueryOperator::In(vec![Value::Blob(vec![1]); 40]).to_sql("id");
+    assert!(sql.ends_with("?)") && !sql.contains("json_each"));
+    assert_eq!(params.len(), 40);
+
+    let dir = TempDir::new().unwrap();
+    let service = items_service(&dir, 100).await;
+    let expected: Vec<Value> = (1..=50).map(|i| Value::Integer(i * 2)).collect();
+    assert_eq!(ids_in(&service, values).await, expected);
+
+    let names: Vec<Value> = (1..=40)
+        .map(|i| Value::Text(format!("item {}", i * 3)))
+        .collect();
+    let read = ReadOperation::new("items")
+        .with_query(Query::new().with_condition("name", QueryOperator::In(names)))
+        .with_fields(&["id"]);
+    let rows = service
+        .execute_crud(CrudOperation::Read(read))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 33);
+}
+
+#[tokio::test]
+async fn test_union_combines_two_filtered_reads() {
+    let dir = TempDir::new().unwrap();
+    let service = items_service(&dir, 10).await;
+    let cheap = ReadOperation::new("items")
+        .with_query(Query::new().with_condition("id", QueryOperator::LessThanOrEqual(3.into())))
+        .with_fields(&["id", "name"]);
+    let recent = ReadOperation::new("items")
+        .with_query(Query::new().with_condition("id", QueryOperator::GreaterThan(2.into())))
+        .with_fields(&["id", "name"])
+        .with_order_by("id", false)
+        .with_limit(2);
+
+    let ids = |rows: Vec<Row>| {
+        let mut ids: Vec<Value> = rows
+            .into_iter()
+            .map(|mut r| r.remove("id").unwrap())
+            .collect();
+        ids.sort_by_key(|v| match v {
+            Value::Integer(i) => *i,
+            _ => 0,
+        });
+        ids
+    };
+    let all = service
+        .union(cheap.clone(), recent.clone(), true)
+        .await
+        .unwrap();
+    assert_eq!(ids(all), [1, 2, 3, 9, 10].map(Value::Integer));
+
+    let overlapping = ReadOperation::new("items")
+        .with_query(Query::new().with_condition("id", QueryOperator::In(vec![3.into(), 4.into()])))
+        .with_fields(&["id", "name"]);
+    let all = service
+        .union(cheap.clone(), overlapping.clone(), true)
+        .await
+        .unwrap();
+    assert_eq!(ids(all), [1, 2, 3, 3, 4].map(Value::Integer));
+    let distinct = service
+        .union(cheap.clone(), overlapping, false)
+        .await
+        .unwrap();
+    assert_eq!(ids(distinct), [1, 2, 3, 4].map(Value::Integer));
+
+    let err = service
+        .union(
+            cheap,
+            ReadOperation::new("items").with_fields(&["id"]),
+            true,
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::UnionColumnMismatch { left: 2, right: 1 })
+    ));
+
+    // Each side is checked like any other read
+    let err = service
+        .union(
+            recent.with_order_by_expression("id LIMIT 1", true),
+            ReadOperation::new("items").with_fields(&["id", "name"]),
+            true,
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::InvalidOrderExpression { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_read_one_errors_unless_exactly_one_row_matches() {
+    let dir = TempDir::new().unwrap();
+    let service = items_service(&dir, 3).await;
+    let with_id = |op: QueryOperator| {
+        ReadOperation::new("items").with_query(Query::new().with_condition("id", op))
+    };
+
+    // Plain reads return an empty Vec when nothing matches
+    let rows = service
+        .execute_crud(CrudOperation::Read(with_id(QueryOperator::Equal(9.into()))))
+        .await
+        .unwrap();
+    assert!(rows.is_empty());
+
+    let row = service
+        .read_one(with_id(QueryOperator::Equal(2.into())))
+        .await
+        .unwrap();
+    assert_eq!(row["name"], Value::Text("item 2".to_string()));
+
+    let err = service
+        .read_one(with_id(QueryOperator::Equal(9.into())))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::NotFound { table }) if table == "items"
+    ));
+    let err = service
+        .read_one(with_id(QueryOperator::GreaterThan(1.into())))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::TooManyRows { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_pages_share_one_statement_with_bound_limit_and_offset() {
+    let dir = TempDir::new().unwrap();
+    let service = items_service(&dir, 7).await;
+    let page = |offset: u32| {
+        ReadOperation::new("items")
+            .with_fields(&["id"])
+            .with_order_by("id", true)
+            .with_limit(3)
+            .with_offset(offset)
+    };
+
+    let (first_sql, params) = CrudOperation::Read(page(0)).to_sql();
+    assert!(first_sql.ends_with(" LIMIT ? OFFSET ?"), "{}", first_sql);
+    assert_eq!(params, vec![Value::Integer(3), Value::Integer(0)]);
+
+    let mut ids = Vec::new();
+    for offset in [0, 3, 6] {
+        let op = CrudOperation::Read(page(offset));
+        assert_eq!(op.to_sql().0, first_sql);
+        let rows = service.execute_crud(op).await.unwrap();
+        ids.extend(rows.into_iter().map(|mut row| row.remove("id").unwrap()));
+    }
+    assert_eq!(ids, (1..=7).map(Value::Integer).collect::<Vec<_>>());
+
+    // An offset alone reads every remaining row
+    let (sql, params) = CrudOperation::Read(ReadOperation::new("items").with_offset(5)).to_sql();
+    assert!(sql.ends_with(" LIMIT ? OFFSET ?"), "{}", sql);
+    assert_eq!(params, vec![Value::Integer(-1), Value::Integer(5)]);
+    let rows = service
+        .execute_crud(CrudOperation::Read(
+            ReadOperation::new("items").with_offset(5),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 2);
+}
+
+#[tokio::test]
+async fn test_crud_on_attached_schema() {
+    let dir = TempDir::new().unwrap();
+    let archive = dir.path().join("archive.db").to_str().unwrap().to_string();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("items")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("name", DataType::Text)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service =
+        SqliteService::new(SqliteConfig::new(path, schema).with_attachment("archive", archive));
+    service.connect().await.unwrap();
+    service
+        .execute_sql(SqlQuery::new(
+            "CREATE TABLE archive.items (id INTEGER PRIMARY KEY, name TEXT)",
+        ))
+        .await
+        .unwrap();
+
+    let by_id = |id: i64| Query::new().with_condition("id", QueryOperator::Equal(id.into()));
+    let create = CreateOperation::new("items")
+        .with_schema("archive")
+        .with_value("id", 1)
+        .with_value("name", "old");
+    assert_eq!(
+        CrudOperation::Create(create.clone()).to_sql().0,
+        "INSERT INTO \"archive\".\"items\" (\"id\", \"name\") VALUES (?, ?) RETURNING *"
+    );
+    service
+        .execute_crud(CrudOperation::Create(create))
+        .await
+        .unwrap();
+    service
+        .execute_crud(CrudOperation::Update(
+            UpdateOperation::new("items", by_id(1))
+                .with_schema("archive")
+                .with_update("name", "older"),
+        ))
+        .await
+        .unwrap();
+    let archived = service
+        .read_one(ReadOperation::new("items").with_schema("archive"))
+        .await
+        .unwrap();
+    assert_eq!(archived["name"], Value::Text("older".to_string()));
+
+    // The table of the same name in main is untouched
+    let main = service
+        .execute_crud(CrudOperation::Read(
+            ReadOperation::new("items").with_schema("main"),
+        ))
+        .await
+        .unwrap();
+    assert!(main.is_empty());
+
+    let deleted = service
+        .execute_crud(CrudOperation::Delete(
+            DeleteOperation::new("items", by_id(1)).with_schema("archive"),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(deleted.len(), 1);
+}
+
+#[tokio::test]
+async fn test_null_columns_are_present_and_unselected_ones_absent() {
+    let dir = TempDir::new().unwrap();
+    // Items are created without a price, leaving it NULL
+    let service = items_service(&dir, 1).await;
+
+    let rows = service
+        .execute_crud(CrudOperation::Read(
+            ReadOperation::new("items").with_fields(&["id", "price"]),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(rows[0].get("price"), Some(&Value::Null));
+    assert_eq!(rows[0].get("name"), None);
+
+    let rows = service
+        .execute_sql(SqlQuery::new("SELECT price, NULL AS empty FROM items"))
+        .await
+        .unwrap();
+    assert_eq!(rows[0].get("price"), Some(&Value::Null));
+    assert_eq!(rows[0].get("empty"), Some(&Value::Null));
+    assert_eq!(rows[0].len(), 2);
+
+    // Writes return every column of the row, NULLs included
+    let rows = service
+        .execute_crud(CrudOperation::Create(
+            CreateOperation::new("items").with_value("id", 2),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(rows[0].get("name"), Some(&Value::Null));
+    assert_eq!(rows[0].get("price"), Some(&Value::Null));
+}
+
+#[tokio::test]
+async fn test_computed_expressions_are_read_by_alias() {
+    let dir = TempDir::new().unwrap();
+    let service = items_service(&dir, 2).await;
+    service
+        .execute_sql(SqlQuery::new("UPDATE items SET price = id * 1.5"))
+        .await
+        .unwrap();
+
+    let rows = service
+        .execute_crud(CrudOperation::Read(
+            ReadOperation::new("items")
+                .with_fields(&["id"])
+                .with_expression("price * 2", "doubled")
+                .with_expression("UPPER(name)", "upper_name")
+                .with_order_by("id", true),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1]["doubled"], Value::Real(6.0));
+    assert_eq!(rows[1]["upper_name"], Value::from("ITEM 2"));
+    assert_eq!(rows[1].len(), 3);
+
+    // Without fields, expressions follow every column of the table
+    let rows = service
+        .execute_crud(CrudOperation::Read(
+            ReadOperation::new("items")
+                .with_expression("price * 2", "doubled")
+                .with_limit(1),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["name"], Value::from("item 1"));
+    assert_eq!(rows[0]["doubled"], Value::Real(3.0));
+}
+
+#[derive(Serialize)]
+struct User {
+    id: i64,
+    name: String,
+    email: Option<String>,
+    active: bool,
+}
+
+async fn users_service(dir: &TempDir) -> SqliteService {
+    let schema = Schema::new().add_table(
+        TableDefinition::new("users")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("name", DataType::Text))
+            .with_column(
+                ColumnDefinition::new("email", DataType::Text)
+                    .with_default(DefaultValue::Text("none".to_string())),
+            )
+            .with_column(ColumnDefinition::new("active", DataType::Integer)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    service
+}
+
+#[tokio::test]
+async fn test_create_from_struct_inserts_fields_and_none_as_null() {
+    let dir = TempDir::new().unwrap();
+    let service = users_service(&dir).await;
+
+    let user = User {
+        id: 7,
+        name: "Ann".to_string(),
+        email: None,
+        active: true,
+    };
+    let create = CreateOperation::from_struct("users", &user).unwrap();
+    assert_eq!(create.data["email"], Value::Null);
+    let rows = service
+        .execute_crud(CrudOperation::Create(create))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["id"], Value::Integer(7));
+    assert_eq!(rows[0]["name"], Value::from("Ann"));
+    // NULL is inserted explicitly rather than falling back to the default
+    assert_eq!(rows[0]["email"], Value::Null);
+    assert_eq!(rows[0]["active"], Value::Integer(1));
+
+    let err = CreateOperation::from_struct("users", &[1, 2]).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::InvalidRecord(_))
+    ));
+
+    // An unsigned integer SQLite cannot store exactly is rejected
+    let record = std::collections::HashMap::from([("id", u64::MAX)]);
+    let err = CreateOperation::from_struct("users", &record).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::IntegerOutOfRange { field, value })
+            if field == "id" && *value == u64::MAX
+    ));
+}
+
+#[tokio::test]
+async fn test_save_inserts_new_rows_and_updates_existing_ones() {
+    let dir = TempDir::new().unwrap();
+    let service = users_service(&dir).await;
+    let mut user = User {
+        id: 1,
+        name: "Ann".to_string(),
+        email: Some("ann@example.com".to_string()),
+        active: true,
+    };
+
+    let saved = service.save("users", &["id"], &user).await.unwrap();
+    assert_eq!(saved["email"], Value::from("ann@example.com"));
+
+    user.name = "Anne".to_string();
+    user.email = None;
+    let saved = service.save("users", &["id"], &user).await.unwrap();
+    assert_eq!(saved["id"], Value::Integer(1));
+    assert_eq!(saved["name"], Value::from("Anne"));
+    assert_eq!(saved["email"], Value::Null);
+
+    let rows = service
+        .execute_crud(CrudOperation::Read(ReadOperation::new("users")))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0], saved);
+
+    let err = service.save("users", &["uuid"], &user).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::MissingColumn { column, .. }) if column == "uuid"
+    ));
+}
+
+#[tokio::test]
+async fn test_cursor_tokens_round_trip_across_pages() {
+    let dir = TempDir::new().unwrap();
+    let service = items_service(&dir, 7).await;
+    let read = || {
+        ReadOperation::new("items")
+            .with_fields(&["id"])
+            .with_limit(3)
+    };
+    let ids =
+        |page: &CursorPage| -> Vec<Value> { page.rows.iter().map(|r| r["id"].clone()).collect() };
+
+    let mut pages = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let page = service
+            .read_cursor_page(read(), &["id"], cursor.as_deref())
+            .await
+            .unwrap();
+        pages.push(ids(&page));
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(
+        pages,
+        [
+            vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)],
+            vec![Value::Integer(4), Value::Integer(5), Value::Integer(6)],
+            vec![Value::Integer(7)],
+        ]
+    );
+
+    // Tokens expose no column names and reject tampering or reuse elsewhere
+    let first = service
+        .read_cursor_page(read(), &["id"], None)
+        .await
+        .unwrap();
+    let token = first.next_cursor.unwrap();
+    assert!(!token.contains("id"));
+    let mut tampered = token.clone().into_bytes();
+    tampered[0] = if tampered[0] == b'A' { b'B' } else { b'A' };
+    for bad in [
+        String::from_utf8(tampered).unwrap(),
+        "not a cursor".to_string(),
+    ] {
+        let err = service
+            .read_cursor_page(read(), &["id"], Some(&bad))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SqliteError>(),
+            Some(SqliteError::InvalidCursor(_))
+        ));
+    }
+    let err = service
+        .read_cursor_page(
+            read().with_fields(&["id", "name"]),
+            &["name", "id"],
+            Some(&token),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::InvalidCursor(_))
+    ));
+
+    // Without key columns no page could continue after the previous one
+    let err = service
+        .read_cursor_page(read(), &[], Some(&token))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::NoKeyColumns(table)) if table == "items"
+    ));
+}
+
+#[tokio::test]
+async fn test_mismatched_column_type_is_a_typed_error() {
+    let dir = TempDir::new().unwrap();
+    let service = users_service(&dir).await;
+    // SQLite keeps text that does not look numeric in an INTEGER column
+    service
+        .execute_sql(SqlQuery::new(
+            "INSERT INTO users (id, name, active) VALUES (1, 'ada', 'yes')",
+        ))
+        .await
+        .unwrap();
+    let rows = service
+        .execute_crud(CrudOperation::Read(ReadOperation::new("users")))
+        .await
+        .unwrap();
+    let row = &rows[0];
+
+    assert_eq!(row.get_as::<i64>("id").unwrap(), 1);
+    assert_eq!(row.get_as::<String>("name").unwrap(), "ada");
+    assert_eq!(row.get_as::<Option<f64>>("id").unwrap(), Some(1.0));
+    match row.get_as::<i64>("active") {
+        Err(SqliteError::ColumnTypeMismatch {
+            column,
+            expected,
+            got,
+        }) => {
+            assert_eq!(column, "active");
+            assert_eq!(expected, "INTEGER");
+            assert_eq!(got, "TEXT");
+        }
+        other => panic!("expected a column type mismatch, got {:?}", other),
+    }
+    assert!(matches!(
+        row.get_as::<bool>("missing"),
+        Err(SqliteError::ColumnTypeMismatch { got, .. }) if got == "no value"
+    ));
+}
+
+#[tokio::test]
+async fn test_integer_enum_column_rejects_unknown_values() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("tasks")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(
+                ColumnDefinition::new("status", DataType::Integer).with_allowed_values(&[0, 1, 2]),
+            ),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+
+    service
+        .execute_crud(CrudOperation::Create(
+            CreateOperation::new("tasks")
+                .with_value("id", 1)
+                .with_value("status", 2),
+        ))
+        .await
+        .unwrap();
+    let err = service
+        .execute_crud(CrudOperation::Create(
+            CreateOperation::new("tasks")
+                .with_value("id", 2)
+                .with_value("status", 7),
+        ))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::InvalidEnumValue { column, value: Value::Integer(7), .. })
+            if column == "status"
+    ));
+
+    // The CHECK constraint covers raw SQL too
+    let err = service
+        .execute_sql(SqlQuery::new("UPDATE tasks SET status = 5 WHERE id = 1"))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<rusqlite::Error>()
+            .and_then(rusqlite::Error::sqlite_error_code),
+        Some(rusqlite::ErrorCode::ConstraintViolation)
+    );
+
+    // A value slipped past the constraint fails the read instead of passing
+    for sql in [
+        "PRAGMA ignore_check_constraints = ON",
+        "INSERT INTO tasks (id, status) VALUES (3, 9)",
+    ] {
+        service.execute_sql(SqlQuery::new(sql)).await.unwrap();
+    }
+    let err = service
+        .execute_crud(CrudOperation::Read(ReadOperation::new("tasks")))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::InvalidEnumValue { value: Value::Integer(9), .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_order_by_expression_and_mixed_directions() {
+    let dir = TempDir::new().unwrap();
+    let service = users_service(&dir).await;
+    for (id, name, active) in [
+        (1, "bo", 1),
+        (2, "alexandra", 0),
+        (3, "cy", 0),
+        (4, "dee", 1),
+    ] {
+        service
+            .execute_crud(CrudOperation::Create(
+                CreateOperation::new("users")
+                    .with_value("id", id)
+                    .with_value("name", name)
+                    .with_value("active", active),
+            ))
+            .await
+            .unwrap();
+    }
+    let ids = |rows: Vec<Row>| -> Vec<i64> {
+        rows.iter()
+            .map(|row| row.get_as::<i64>("id").unwrap())
+            .collect()
+    };
+
+    // Longest name first, ties broken by id
+    let rows = service
+        .execute_crud(CrudOperation::Read(
+            ReadOperation::new("users")
+                .with_order_by_expression("LENGTH(name)", false)
+                .with_order_by("id", true),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(ids(rows), [2, 4, 1, 3]);
+
+    let rows = service
+        .execute_crud(CrudOperation::Read(
+            ReadOperation::new("users")
+                .with_order_by("active", false)
+                .with_order_by("id", false),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(ids(rows), [4, 1, 3, 2]);
+
+    for expression in [
+        "name; DROP TABLE users",
+        "id LIMIT 1",
+        "id, name",
+        "(id",
+        "'open",
+        "id -- comment",
+    ] {
+        let err = service
+            .execute_crud(CrudOperation::Read(
+                ReadOperation::new("users").with_order_by_expression(expression, true),
+            ))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<SqliteError>(),
+                Some(SqliteError::InvalidOrderExpression { .. })
+            ),
+            "{} was accepted",
+            expression
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_relative_time_window_filters_by_bound_datetime() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("events")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("created_at", DataType::Text)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    for (id, age) in [(1, "-10 days"), (2, "-3 days"), (3, "-1 hours")] {
+        service
+            .execute_sql(
+                SqlQuery::new("INSERT INTO events VALUES (:id, datetime('now', :age))")
+                    .with_params(Params::new().with_value("id", id).with_value("age", age)),
+            )
+            .await
+            .unwrap();
+    }
+    let week = Duration::from_secs(7 * 24 * 60 * 60);
+
+    let recent = ReadOperation::new("events")
+        .with_query(Query::new().with_condition(
+            "created_at",
+            QueryOperator::GreaterThanOrEqual(datetime_ago(week)),
+        ))
+        .with_fields(&["id"])
+        .with_order_by("id", true);
+    let rows = service
+        .execute_crud(CrudOperation::Read(recent))
+        .await
+        .unwrap();
+    let ids: Vec<_> = rows.iter().map(|row| row["id"].clone()).collect();
+    assert_eq!(ids, vec![Value::Integer(2), Value::Integer(3)]);
+
+    let rows = service
+        .execute_sql(
+            SqlQuery::new(
+                "SELECT id FROM events WHERE created_at >= datetime('now', :since) ORDER BY id",
+            )
+            .with_params(Params::new().with_value("since", modifier_ago(week))),
+        )
+        .await
+        .unwrap();
+    let ids: Vec<_> = rows.iter().map(|row| row["id"].clone()).collect();
+    assert_eq!(ids, vec![Value::Integer(2), Value::Integer(3)]);
+
+    assert_eq!(
+        format_datetime(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+        "2023-11-14 22:13:20"
+    );
+    assert_eq!(
+        format_datetime(UNIX_EPOCH - Duration::from_secs(86_400)),
+        "1969-12-31 00:00:00"
+    );
+}
+
+#[tokio::test]
+async fn test_writes_to_read_only_table_are_denied() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new()
+        .add_table(
+            TableDefinition::new("countries")
+                .with_column(
+                    ColumnDefinition::new("code", DataType::Text)
+                        .with_constraint(ColumnConstraint::PrimaryKey),
+                )
+                .with_column(ColumnDefinition::new("name", DataType::Text)),
+        )
+        .add_table(
+            TableDefinition::new("notes")
+                .with_column(ColumnDefinition::new("body", DataType::Text)),
+        );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let config = SqliteConfig::new(path, schema)
+        .with_permissions("countries", TablePermissions::read_only());
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+    service
+        .execute_sql(SqlQuery::new(
+            "INSERT INTO countries VALUES ('fr', 'France')",
+        ))
+        .await
+        .unwrap();
+
+    let rows = service
+        .execute_crud(CrudOperation::Read(ReadOperation::new("countries")))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+
+    let by_code = Query::new().with_condition("code", QueryOperator::Equal("fr".into()));
+    let writes = [
+        (
+            CrudOperation::Create(
+                CreateOperation::new("countries")
+                    .with_value("code", "de")
+                    .with_value("name", "Germany"),
+            ),
+            "create",
+        ),
+        (
+            CrudOperation::Update(
+                UpdateOperation::new("countries", by_code.clone())
+                    .with_update("name", "Frankreich"),
+            ),
+            "update",
+        ),
+        (
+            CrudOperation::Delete(DeleteOperation::new("countries", by_code)),
+            "delete",
+        ),
+    ];
+    for (op, kind) in writes {
+        let err = service.execute_crud(op).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SqliteError>(),
+            Some(SqliteError::PermissionDenied { table, operation })
+                if table == "countries" && *operation == kind
+        ));
+    }
+    let rows = service
+        .execute_crud(CrudOperation::Read(ReadOperation::new("countries")))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["name"], Value::from("France"));
+
+    // Tables without permissions allow everything
+    service
+        .execute_crud(CrudOperation::Create(
+            CreateOperation::new("notes").with_value("body", "hi"),
+        ))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_permissions_apply_to_every_path() {
+    let schema = || {
+        Schema::new()
+            .add_table(
+                TableDefinition::new("countries")
+                    .with_column(
+                        ColumnDefinition::new("code", DataType::Text)
+                            .with_constraint(ColumnConstraint::PrimaryKey),
+                    )
+                    .with_column(ColumnDefinition::new("name", DataType::Text)),
+            )
+            .add_table(
+                TableDefinition::new("notes")
+                    .with_column(ColumnDefinition::new("body", DataType::Text)),
+            )
+    };
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let create_only = TablePermissions {
+        read: false,
+        create: true,
+        update: false,
+        delete: false,
+    };
+    let service = SqliteService::new(
+        SqliteConfig::new(path, schema()).with_permissions("Countries", create_only),
+    );
+    service.connect().await.unwrap();
+    let other_path = dir.path().join("other.db").to_str().unwrap().to_string();
+    let other = SqliteService::new(
+        SqliteConfig::new(other_path, schema())
+            .with_permissions("NOTES", TablePermissions::read_only()),
+    );
+    other.connect().await.unwrap();
+    let denied = |err: anyhow::Error, kind: &str| {
+        matches!(
+            err.downcast_ref::<SqliteError>(),
+            Some(SqliteError::PermissionDenied { operation, .. }) if *operation == kind
+        )
+    };
+
+    // Reads, whatever the case of the name or however the table is reached
+    let err = service
+        .execute_crud(CrudOperation::Read(ReadOperation::new("COUNTRIES")))
+        .await
+        .unwrap_err();
+    assert!(denied(err, "read"));
+    let via_cte = ReadOperation::new("all_countries").with_cte(CommonTableExpression::new(
+        "all_countries",
+        "SELECT * FROM countries",
+    ));
+    let err = service
+        .execute_crud(CrudOperation::Read(via_cte))
+        .await
+        .unwrap_err();
+    assert!(denied(err, "read"));
+    let err = service
+        .union(
+            ReadOperation::new("notes"),
+            ReadOperation::new("countries").with_fields(&["name"]),
+            true,
+        )
+        .await
+        .unwrap_err();
+    assert!(denied(err, "read"));
+    let err = service
+        .copy_table_to(&other, "countries", 100)
+        .await
+        .unwrap_err();
+    assert!(denied(err, "read"));
+    let err = service
+        .copy_table_to(&other, "notes", 100)
+        .await
+        .unwrap_err();
+    assert!(denied(err, "create"));
+
+    // Writes that may update existing rows need the update permission
+    let err = service
+        .execute_crud(CrudOperation::Create(
+            CreateOperation::new("countries")
+                .with_value("code", "fr")
+                .with_value("name", "France")
+                .with_upsert(&["code"]),
+        ))
+        .await
+        .unwrap_err();
+    assert!(denied(err, "update"));
+    #[derive(Serialize)]
+    struct Country {
+        code: String,
+        name: String,
+    }
+    let country = Country {
+        code: "fr".to_string(),
+        name: "France".to_string(),
+    };
+    let err = service
+        .save("countries", &["code"], &country)
+        .await
+        .unwrap_err();
+    assert!(denied(err, "update"));
+    let err = service
+        .update_values("countries", "code", "name", [("fr", "France")])
+        .await
+        .unwrap_err();
+    assert!(denied(err, "update"));
+    let err = service
+        .merge("countries", &["code"], Vec::new())
+        .await
+        .unwrap_err();
+    assert!(denied(err, "update"));
+
+    // A plain create is still allowed
+    service
+        .execute_crud(CrudOperation::Create(
+            CreateOperation::new("countries")
+                .with_value("code", "fr")
+                .with_value("name", "France"),
+        ))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_batch_ops_commit_together_or_not_at_all() {
+    let dir = TempDir::new().unwrap();
+    let id = || {
+        ColumnDefinition::new("id", DataType::Integer).with_constraint(ColumnConstraint::PrimaryKey)
+    };
+    let schema = Schema::new()
+        .add_table(TableDefinition::new("authors").with_column(id()))
+        .add_table(
+            TableDefinition::new("books")
+                .with_column(id())
+                .with_column(
+                    ColumnDefinition::new("author_id", DataType::Integer)
+                        .with_constraint(ColumnConstraint::NotNull),
+                )
+                .with_foreign_key(ForeignKey::new("author_id", "authors", "id")),
+        );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    let author =
+        |id: i64| CrudOperation::Create(CreateOperation::new("authors").with_value("id", id));
+    let book = |id: i64, author_id: i64| {
+        CrudOperation::Create(
+            CreateOperation::new("books")
+                .with_value("id", id)
+                .with_value("author_id", author_id),
+        )
+    };
+    let count = |table: &'static str| {
+        let service = service.clone();
+        async move {
+            service
+                .execute_crud(CrudOperation::Read(ReadOperation::new(table)))
+                .await
+                .unwrap()
+                .len()
+        }
+    };
+
+    let results = service
+        .execute_batch_ops(vec![
+            author(1),
+            book(1, 1),
+            book(2, 1),
+            CrudOperation::Read(ReadOperation::new("books")),
+        ])
+        .await
+        .unwrap();
+    let tables: Vec<_> = results.iter().map(|r| r.table.as_str()).collect();
+    assert_eq!(tables, ["authors", "books", "books", "books"]);
+    assert_eq!(results[1].rows[0]["author_id"], Value::Integer(1));
+    // Reads see the writes made earlier in the batch
+    assert_eq!(results[3].rows.len(), 2);
+
+    // The last book references a missing author, undoing the whole batch
+    let err = service
+        .execute_batch_ops(vec![author(2), book(3, 2), book(4, 99)])
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("operation 2"));
+    assert_eq!(count("authors").await, 1);
+    assert_eq!(count("books").await, 2);
+}
@@ -0,0 +1,212 @@
+use runar_node::{Node, NodeConfig};
+use rust_sqlite::describe::{
+    ColumnDescription, ForeignKeyDescription, IndexDescription, ResultColumn, SchemaDescription,
+};
+use rust_sqlite::migration::Migration;
+use rust_sqlite::sqlite::{
+    ColumnConstraint, ColumnDefinition, DataType, DefaultValue, ForeignKey, ForeignKeyAction,
+    IndexDefinition, Schema, SqlQuery, SqliteConfig, SqliteService, TableDefinition,
+};
+use std::collections::HashMap;
+use tempfile::TempDir;
+
+fn column(name: &str, data_type: &str, nullable: bool, primary_key: bool) -> ColumnDescription {
+    ColumnDescription {
+        name: name.to_string(),
+        data_type: data_type.to_string(),
+        nullable,
+        default: None,
+        primary_key,
+    }
+}
+
+#[tokio::test]
+async fn test_describe_reflects_configured_schema() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new()
+        .add_table(
+            TableDefinition::new("users")
+                .with_column(
+                    ColumnDefinition::new("id", DataType::Integer)
+                        .with_constraint(ColumnConstraint::PrimaryKey),
+                )
+                .with_column(
+                    ColumnDefinition::new("email", DataType::Text)
+                        .with_constraint(ColumnConstraint::NotNull),
+                )
+                .with_index(IndexDefinition::new("idx_users_email", &["email"], true)),
+        )
+        .add_table(
+            TableDefinition::new("posts")
+                .with_column(
+                    ColumnDefinition::new("id", DataType::Integer)
+                        .with_constraint(ColumnConstraint::PrimaryKey),
+                )
+                .with_column(ColumnDefinition::new("user_id", DataType::Integer))
+                .with_column(
+                    ColumnDefinition::new("title", DataType::Text)
+                        .with_default(DefaultValue::Text("untitled".to_string())),
+                )
+                .with_foreign_key(ForeignKey {
+                    column: "user_id".to_string(),
+                    foreign_table: "users".to_string(),
+                    foreign_column: "id".to_string(),
+                    on_delete: ForeignKeyAction::Cascade,
+                    on_update: ForeignKeyAction::NoAction,
+                    deferred: false,
+                }),
+        );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let config = SqliteConfig::new(path, schema).with_migrations(vec![Migration::new(
+        1,
+        "add tags",
+        "CREATE TABLE tags (name TEXT)",
+    )]);
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+    let mut node_config = NodeConfig::new("test-node", "test_network");
+    node_config.network_config = None;
+    let mut node = Node::new(node_config).await.unwrap();
+    node.add_service(service).await.unwrap();
+    node.start().await.unwrap();
+
+    let response = node.request("sqlite/describe", None).await.unwrap();
+    let description = response.unwrap().as_type::<SchemaDescription>().unwrap();
+    let names: Vec<&str> = description.tables.iter().map(|t| t.name.as_str()).collect();
+    // Bookkeeping tables are hidden; migrated tables are included
+    assert_eq!(names, vec!["posts", "tags", "users"]);
+
+    let posts = &description.tables[0];
+    let mut title = column("title", "TEXT", true, false);
+    title.default = Some("'untitled'".to_string());
+    assert_eq!(
+        posts.columns,
+        vec![
+            column("id", "INTEGER", true, true),
+            column("user_id", "INTEGER", true, false),
+            title,
+        ]
+    );
+    assert!(posts.indexes.is_empty());
+    assert_eq!(
+        posts.foreign_keys,
+        vec![ForeignKeyDescription {
+            columns: vec!["user_id".to_string()],
+            foreign_table: "users".to_string(),
+            foreign_columns: vec!["id".to_string()],
+            on_delete: "CASCADE".to_string(),
+            on_update: "NO ACTION".to_string(),
+        }]
+    );
+
+    let users = &description.tables[2];
+    assert_eq!(
+        users.columns,
+        vec![
+            column("id", "INTEGER", true, true),
+            column("email", "TEXT", false, false),
+        ]
+    );
+    assert_eq!(
+        users.indexes,
+        vec![IndexDescription {
+            name: "idx_users_email".to_string(),
+            columns: vec!["email".to_string()],
+            unique: true,
+        }]
+    );
+    assert!(users.foreign_keys.is_empty());
+}
+
+#[tokio::test]
+async fn test_table_counts_match_inserts() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new()
+        .add_table(
+            TableDefinition::new("a").with_column(ColumnDefinition::new("x", DataType::Integer)),
+        )
+        .add_table(
+            TableDefinition::new("b").with_column(ColumnDefinition::new("x", DataType::Integer)),
+        );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    let insert_into_a = |rows: usize| {
+        let values = vec!["(1)"; rows].join(", ");
+        SqlQuery::new(&format!("INSERT INTO a (x) VALUES {}", values))
+    };
+    service.execute_sql(insert_into_a(3)).await.unwrap();
+    service
+        .append_event("audit", "bookkeeping tables are not counted")
+        .await
+        .unwrap();
+
+    let counts = service.table_counts(true).await.unwrap();
+    assert_eq!(
+        counts,
+        HashMap::from([("a".to_string(), 3), ("b".to_string(), 0)])
+    );
+
+    // Estimates come from the last ANALYZE and can be stale
+    service.execute_sql(SqlQuery::new("ANALYZE")).await.unwrap();
+    service.execute_sql(insert_into_a(2)).await.unwrap();
+    let estimated = service.table_counts(false).await.unwrap();
+    assert_eq!(estimated["a"], 3);
+    assert_eq!(estimated["b"], 0);
+    assert!(
+        estimated.keys().all(|table| !table.starts_with("_runar_")),
+        "{:?}",
+        estimated
+    );
+    assert_eq!(service.table_counts(true).await.unwrap()["a"], 5);
+}
+
+#[tokio::test]
+async fn test_result_metadata_matches_schema() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("users")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(
+                ColumnDefinition::new("email", DataType::Text)
+                    .with_constraint(ColumnConstraint::NotNull),
+            )
+            .with_column(ColumnDefinition::new("score", DataType::Real)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+
+    // Metadata is available even when no row matches
+    let result = service
+        .execute_sql_with_metadata(SqlQuery::new(
+            "SELECT email AS address, score, score * 2 AS doubled FROM users",
+        ))
+        .await
+        .unwrap();
+    assert!(result.rows.is_empty());
+    let from_users = |name: &str, column: &str, declared_type: &str, nullable: bool| ResultColumn {
+        name: name.to_string(),
+        declared_type: Some(declared_type.to_string()),
+        table: Some("users".to_string()),
+        column: Some(column.to_string()),
+        nullable: Some(nullable),
+    };
+    assert_eq!(
+        result.columns,
+        vec![
+            from_users("address", "email", "TEXT", false),
+            from_users("score", "score", "REAL", true),
+            ResultColumn {
+                name: "doubled".to_string(),
+                declared_type: None,
+                table: None,
+                column: None,
+                nullable: None,
+            },
+        ]
+    );
+}
@@ -0,0 +1,70 @@
+use rust_sqlite::events::EVENTS_TABLE;
+use rust_sqlite::sqlite::{Schema, SqlQuery, SqliteConfig, SqliteService, Value};
+use tempfile::TempDir;
+
+async fn service(dir: &TempDir) -> SqliteService {
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, Schema::new()));
+    service.connect().await.unwrap();
+    service
+}
+
+#[tokio::test]
+async fn test_events_replay_in_order_per_stream() {
+    let dir = TempDir::new().unwrap();
+    let service = service(&dir).await;
+    assert!(service
+        .read_events("orders", 0, 10)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let mut order_seqs = Vec::new();
+    for i in 0..5 {
+        order_seqs.push(
+            service
+                .append_event("orders", format!("order {}", i))
+                .await
+                .unwrap(),
+        );
+        service.append_event("payments", i).await.unwrap();
+    }
+    assert!(order_seqs.windows(2).all(|w| w[0] < w[1]));
+
+    let first = service.read_events("orders", 0, 3).await.unwrap();
+    let payloads: Vec<_> = first.iter().map(|e| e.payload.clone()).collect();
+    assert_eq!(
+        payloads,
+        vec![
+            Value::Text("order 0".to_string()),
+            Value::Text("order 1".to_string()),
+            Value::Text("order 2".to_string()),
+        ]
+    );
+    assert!(first.iter().all(|e| e.stream == "orders"));
+
+    let rest = service
+        .read_events("orders", first.last().unwrap().seq, 10)
+        .await
+        .unwrap();
+    let seqs: Vec<i64> = first.iter().chain(&rest).map(|e| e.seq).collect();
+    assert_eq!(seqs, order_seqs);
+}
+
+#[tokio::test]
+async fn test_events_reject_updates_and_deletes() {
+    let dir = TempDir::new().unwrap();
+    let service = service(&dir).await;
+    service.append_event("audit", "created").await.unwrap();
+
+    for sql in [
+        format!("UPDATE {} SET payload = 'tampered'", EVENTS_TABLE),
+        format!("DELETE FROM {}", EVENTS_TABLE),
+    ] {
+        let err = service.execute_sql(SqlQuery::new(&sql)).await.unwrap_err();
+        assert!(err.to_string().contains("append-only"), "{}", err);
+    }
+    let events = service.read_events("audit", 0, 10).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].payload, Value::Text("created".to_string()));
+}
@@ -0,0 +1,201 @@
+use rust_sqlite::checksum::row_checksum;
+use rust_sqlite::sqlite::{
+    ColumnConstraint, ColumnDefinition, CreateOperation, CrudOperation, DataType, ForeignKey,
+    ForeignKeyAction, Query, QueryOperator, Schema, SqlQuery, SqliteConfig, SqliteError,
+    SqliteService, TableDefinition, UpdateOperation, Value,
+};
+use tempfile::TempDir;
+
+async fn blog_service(dir: &TempDir) -> SqliteService {
+    let id = || {
+        ColumnDefinition::new("id", DataType::Integer).with_constraint(ColumnConstraint::PrimaryKey)
+    };
+    let schema = Schema::new()
+        .add_table(TableDefinition::new("users").with_column(id()))
+        .add_table(
+            TableDefinition::new("posts")
+                .with_column(id())
+                .with_column(ColumnDefinition::new("user_id", DataType::Integer))
+                .with_foreign_key(ForeignKey {
+                    column: "user_id".to_string(),
+                    foreign_table: "users".to_string(),
+                    foreign_column: "id".to_string(),
+                    on_delete: ForeignKeyAction::Cascade,
+                    on_update: ForeignKeyAction::NoAction,
+                    deferred: false,
+                }),
+        );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    service
+}
+
+async fn exec(service: &SqliteService, sql: &str) {
+    service.execute_sql(SqlQuery::new(sql)).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_healthy_database_reports_ok() {
+    let dir = TempDir::new().unwrap();
+    let service = blog_service(&dir).await;
+    exec(&service, "INSERT INTO users (id) VALUES (1)").await;
+    exec(&service, "INSERT INTO posts (id, user_id) VALUES (1, 1)").await;
+
+    let report = service.integrity_check().await.unwrap();
+    assert_eq!(report.integrity, vec!["ok".to_string()]);
+    assert!(report.foreign_key_violations.is_empty());
+    assert!(report.is_ok());
+}
+
+#[tokio::test]
+async fn test_deferred_foreign_key_violation_is_detected() {
+    let dir = TempDir::new().unwrap();
+    let service = blog_service(&dir).await;
+
+    exec(&service, "BEGIN").await;
+    exec(&service, "PRAGMA defer_foreign_keys = ON").await;
+    exec(&service, "INSERT INTO posts (id, user_id) VALUES (7, 42)").await;
+
+    let report = service.integrity_check().await.unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(report.foreign_key_violations.len(), 1);
+    let violation = &report.foreign_key_violations[0];
+    assert_eq!(violation.table, "posts");
+    assert_eq!(violation.rowid, Some(7));
+    assert_eq!(violation.parent, "users");
+
+    exec(&service, "ROLLBACK").await;
+    assert!(service.integrity_check().await.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn test_row_changed_outside_the_service_fails_checksum_verification() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("accounts")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("balance", DataType::Integer))
+            .with_checksum_column("checksum"),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    for id in 1..=3 {
+        let rows = service
+            .execute_crud(CrudOperation::Create(
+                CreateOperation::new("accounts")
+                    .with_value("id", id)
+                    .with_value("balance", 100),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            rows[0]["checksum"],
+            Value::Text(row_checksum(&rows[0], "checksum"))
+        );
+        assert_eq!(rows[0].len(), 3);
+    }
+    service
+        .execute_crud(CrudOperation::Update(
+            UpdateOperation::new(
+                "accounts",
+                Query::new().with_condition("id", QueryOperator::Equal(Value::Integer(1))),
+            )
+            .with_update("balance", 50),
+        ))
+        .await
+        .unwrap();
+    assert!(service
+        .verify_row_checksums("accounts")
+        .await
+        .unwrap()
+        .is_empty());
+
+    service
+        .execute_sql(SqlQuery::new(
+            "UPDATE accounts SET balance = 1000000 WHERE id = 2",
+        ))
+        .await
+        .unwrap();
+    let mismatches = service.verify_row_checksums("accounts").await.unwrap();
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0]["id"], Value::Integer(2));
+
+    let err = service.verify_row_checksums("missing").await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::NoChecksumColumn(table)) if table == "missing"
+    ));
+
+    // Bulk writers cannot stamp checksums, so they refuse the table
+    let err = service
+        .update_values("accounts", "id", "balance", [(3, 0)])
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::ChecksumTable(table)) if table == "accounts"
+    ));
+    let err = service
+        .merge("accounts", &["id"], Vec::new())
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::ChecksumTable(_))
+    ));
+    let mismatches = service.verify_row_checksums("accounts").await.unwrap();
+    assert_eq!(mismatches.len(), 1);
+}
+
+#[tokio::test]
+async fn test_checksum_is_stamped_in_the_written_schema() {
+    let dir = TempDir::new().unwrap();
+    let archive = dir.path().join("archive.db").to_str().unwrap().to_string();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("accounts")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("balance", DataType::Integer))
+            .with_checksum_column("checksum"),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service =
+        SqliteService::new(SqliteConfig::new(path, schema).with_attachment("archive", archive));
+    service.connect().await.unwrap();
+    exec(
+        &service,
+        "CREATE TABLE archive.accounts (id INTEGER PRIMARY KEY, balance INTEGER, checksum TEXT)",
+    )
+    .await;
+    exec(
+        &service,
+        "INSERT INTO accounts (id, balance) VALUES (1, 100)",
+    )
+    .await;
+
+    let rows = service
+        .execute_crud(CrudOperation::Create(
+            CreateOperation::new("accounts")
+                .with_schema("archive")
+                .with_value("id", 1)
+                .with_value("balance", 5),
+        ))
+        .await
+        .unwrap();
+    let stored = service
+        .execute_sql(SqlQuery::new(
+            "SELECT 'archive' AS db, checksum FROM archive.accounts
+             UNION ALL SELECT 'main', checksum FROM main.accounts ORDER BY db",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(stored[0]["checksum"], rows[0]["checksum"]);
+    assert_eq!(stored[1]["checksum"], Value::Null);
+}
@@ -0,0 +1,191 @@
+use rust_sqlite::json::{row_from_json, BlobEncoding};
+use rust_sqlite::sqlite::{
+    Aggregate, ColumnDefinition, DataType, Params, ReadOperation, Schema, SqlQuery, SqliteConfig,
+    SqliteError, SqliteService, TableDefinition, Value,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn test_query_json_returns_array_of_objects() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, Schema::new()));
+    service.connect().await.unwrap();
+
+    let json = service
+        .query_json(SqlQuery::new(
+            "SELECT 1 AS id, 'ada' AS name, 2.5 AS score, X'68690A' AS avatar, NULL AS note \
+             UNION ALL SELECT 2, 'bob', NULL, NULL, 'new'",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(
+        json,
+        json!([
+            {"id": 1, "name": "ada", "score": 2.5, "avatar": "aGkK", "note": null},
+            {"id": 2, "name": "bob", "score": null, "avatar": null, "note": "new"},
+        ])
+    );
+
+    let empty = service
+        .query_json(SqlQuery::new("SELECT 1 AS id WHERE 0"))
+        .await
+        .unwrap();
+    assert_eq!(empty, json!([]));
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Stats {
+    count: i64,
+    avg_age: f64,
+}
+
+#[tokio::test]
+async fn test_aggregates_map_into_a_typed_struct() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("people").with_column(ColumnDefinition::new("age", DataType::Integer)),
+    );
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    service
+        .execute_sql(SqlQuery::new(
+            "INSERT INTO people (age) VALUES (30), (41), (52)",
+        ))
+        .await
+        .unwrap();
+
+    let stats: Vec<Stats> = service
+        .read_as(
+            ReadOperation::new("people")
+                .with_aggregate(Aggregate::count_rows("count"))
+                .with_aggregate(Aggregate::avg("age", "avg_age")),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        stats,
+        [Stats {
+            count: 3,
+            avg_age: 41.0
+        }]
+    );
+
+    let stats: Vec<Stats> = service
+        .query_as(SqlQuery::new(
+            "SELECT COUNT(*) AS count, AVG(age) AS avg_age FROM people WHERE age > 40",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(
+        stats,
+        [Stats {
+            count: 2,
+            avg_age: 46.5
+        }]
+    );
+
+    // AVG over no rows is NULL, which only an Option field accepts
+    let err = service
+        .query_as::<Stats>(SqlQuery::new(
+            "SELECT COUNT(*) AS count, AVG(age) AS avg_age FROM people WHERE age > 99",
+        ))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::RowMapping { .. })
+    ));
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Person {
+    id: i64,
+    name: String,
+    age: i64,
+    nickname: Option<String>,
+}
+
+#[tokio::test]
+async fn test_projection_maps_into_struct_with_defaulted_fields() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, Schema::new()));
+    service.connect().await.unwrap();
+    let query = "SELECT 7 AS id, 'ada' AS name, 'extra' AS unmapped";
+
+    let people: Vec<Person> = service
+        .query_as_with_defaults(SqlQuery::new(query))
+        .await
+        .unwrap();
+    assert_eq!(
+        people,
+        [Person {
+            id: 7,
+            name: "ada".to_string(),
+            age: 0,
+            nickname: None,
+        }]
+    );
+
+    // Without defaults, the missing age is an error
+    let err = service
+        .query_as::<Person>(SqlQuery::new(query))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::RowMapping { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_blob_round_trips_through_json_as_base64url() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(
+        SqliteConfig::new(path, Schema::new()).with_blob_encoding(BlobEncoding::Base64Url),
+    );
+    service.connect().await.unwrap();
+    service
+        .execute_sql(SqlQuery::new(
+            "CREATE TABLE files (id INTEGER PRIMARY KEY, data BLOB)",
+        ))
+        .await
+        .unwrap();
+    let data = vec![0xfb, 0xff, 0x00, 0x3e];
+    service
+        .execute_sql(
+            SqlQuery::new("INSERT INTO files VALUES (1, :data)")
+                .with_params(Params::new().with_value("data", Value::Blob(data.clone()))),
+        )
+        .await
+        .unwrap();
+
+    let json = service
+        .query_json(SqlQuery::new("SELECT id, data FROM files"))
+        .await
+        .unwrap();
+    assert_eq!(json, json!([{"id": 1, "data": "-_8APg"}]));
+
+    let row = row_from_json(&json[0], &["data"], BlobEncoding::Base64Url).unwrap();
+    assert_eq!(row["data"], Value::Blob(data.clone()));
+    assert_eq!(row["id"], Value::Integer(1));
+
+    // Standard base64 of the same bytes uses other characters and padding
+    let err = row_from_json(
+        &json!({"data": "+/8APg=="}),
+        &["data"],
+        BlobEncoding::Base64Url,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::InvalidBlob { column, .. }) if column == "data"
+    ));
+    assert_eq!(BlobEncoding::Hex.encode(&data), "fbff003e");
+    assert_eq!(BlobEncoding::Hex.decode("FBFF003E").unwrap(), data);
+}
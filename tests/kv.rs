@@ -0,0 +1,103 @@
+use rust_sqlite::sqlite::{Schema, SqlQuery, SqliteConfig, SqliteService, Value};
+use std::time::Duration;
+use tempfile::TempDir;
+
+async fn service(dir: &TempDir) -> SqliteService {
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, Schema::new()));
+    service.connect().await.unwrap();
+    service
+}
+
+async fn stored_rows(service: &SqliteService) -> Value {
+    service
+        .execute_sql(SqlQuery::new("SELECT COUNT(*) AS n FROM kv"))
+        .await
+        .unwrap()
+        .remove(0)
+        .remove("n")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_set_get_and_delete() {
+    let dir = TempDir::new().unwrap();
+    let service = service(&dir).await;
+    let kv = service.kv_store("kv");
+
+    assert_eq!(kv.get("greeting").await.unwrap(), None);
+    kv.set("greeting", b"hello").await.unwrap();
+    assert_eq!(kv.get("greeting").await.unwrap(), Some(b"hello".to_vec()));
+    kv.set("greeting", b"hi").await.unwrap();
+    assert_eq!(kv.get("greeting").await.unwrap(), Some(b"hi".to_vec()));
+
+    assert!(kv.delete("greeting").await.unwrap());
+    assert!(!kv.delete("greeting").await.unwrap());
+    assert_eq!(kv.get("greeting").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_scan_prefix_returns_matching_keys_in_order() {
+    let dir = TempDir::new().unwrap();
+    let service = service(&dir).await;
+    let kv = service.kv_store("kv");
+    for key in ["user:2", "user:1", "users", "User:3", "order:1", "user"] {
+        kv.set(key, key.as_bytes()).await.unwrap();
+    }
+
+    let keys: Vec<String> = kv
+        .scan_prefix("user:")
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect();
+    assert_eq!(keys, ["user:1", "user:2"]);
+    assert_eq!(kv.scan_prefix("").await.unwrap().len(), 6);
+    assert_eq!(
+        kv.scan_prefix("order").await.unwrap(),
+        [("order:1".to_string(), b"order:1".to_vec())]
+    );
+}
+
+#[tokio::test]
+async fn test_expired_entries_are_hidden_then_swept() {
+    let dir = TempDir::new().unwrap();
+    let service = service(&dir).await;
+    let kv = service.kv_store("kv");
+    kv.set("session", b"token").await.unwrap();
+    kv.set_with_ttl("otp", b"1234", Duration::from_millis(50))
+        .await
+        .unwrap();
+    assert_eq!(kv.get("otp").await.unwrap(), Some(b"1234".to_vec()));
+    // A TTL too long to represent never expires
+    kv.set_with_ttl("forever", b"1", Duration::MAX)
+        .await
+        .unwrap();
+    assert_eq!(kv.get("forever").await.unwrap(), Some(b"1".to_vec()));
+    assert!(kv.delete("forever").await.unwrap());
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(kv.get("otp").await.unwrap(), None);
+    assert_eq!(kv.scan_prefix("").await.unwrap().len(), 1);
+    assert_eq!(stored_rows(&service).await, Value::Integer(2));
+    assert_eq!(kv.sweep_expired().await.unwrap(), 1);
+    assert_eq!(stored_rows(&service).await, Value::Integer(1));
+
+    // A sweeper removes entries as they expire
+    let sweeper = kv.spawn_sweeper(Duration::from_millis(20));
+    kv.set_with_ttl("otp", b"5678", Duration::from_millis(10))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(stored_rows(&service).await, Value::Integer(1));
+    drop(sweeper);
+    assert_eq!(kv.get("session").await.unwrap(), Some(b"token".to_vec()));
+
+    // Once the handle is dropped, expired entries stay until swept
+    kv.set_with_ttl("otp", b"9012", Duration::from_millis(10))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(stored_rows(&service).await, Value::Integer(2));
+}
@@ -0,0 +1,107 @@
+use futures::future::join_all;
+use rust_sqlite::sqlite::{
+    ColumnConstraint, ColumnDefinition, DataType, Schema, SqlQuery, SqliteConfig, SqliteService,
+    TableDefinition, Value,
+};
+use std::time::Duration;
+use tempfile::TempDir;
+
+async fn users_service(dir: &TempDir) -> SqliteService {
+    let schema = Schema::new().add_table(
+        TableDefinition::new("users")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("name", DataType::Text)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    // A zero threshold records every query, which lets the test count them
+    let config = SqliteConfig::new(path, schema).with_slow_query_threshold(Duration::ZERO);
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+    service
+}
+
+#[tokio::test]
+async fn test_concurrent_loads_run_a_single_query() {
+    let dir = TempDir::new().unwrap();
+    let service = users_service(&dir).await;
+    let mut values = Vec::new();
+    for id in 1..=20 {
+        values.push(format!("({}, 'user {}')", id, id));
+    }
+    service
+        .execute_sql(SqlQuery::new(&format!(
+            "INSERT INTO users (id, name) VALUES {}",
+            values.join(", ")
+        )))
+        .await
+        .unwrap();
+    let before = service.slow_queries().len();
+
+    // Keys 1..=25: the last five do not exist; 3 is requested twice
+    let keys: Vec<i64> = (1..=25).chain([3]).collect();
+    let rows = join_all(keys.iter().map(|id| service.load("users", *id))).await;
+
+    let queries = &service.slow_queries()[before..];
+    assert_eq!(
+        queries.len(),
+        1,
+        "expected one batched query: {:?}",
+        queries
+    );
+    assert!(queries[0].statement.contains(" IN ("));
+
+    for (id, row) in keys.iter().zip(rows) {
+        let row = row.unwrap();
+        if *id <= 20 {
+            let row = row.expect("existing user");
+            assert_eq!(row["id"], Value::Integer(*id));
+            assert_eq!(row["name"], Value::Text(format!("user {}", id)));
+            assert!(!row.contains_key("_runar_load_key"));
+        } else {
+            assert!(row.is_none());
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_sequential_loads_each_query() {
+    let dir = TempDir::new().unwrap();
+    let service = users_service(&dir).await;
+    service
+        .execute_sql(SqlQuery::new(
+            "INSERT INTO users (id, name) VALUES (1, 'a')",
+        ))
+        .await
+        .unwrap();
+    let before = service.slow_queries().len();
+
+    assert!(service.load("users", 1).await.unwrap().is_some());
+    assert!(service.load("users", 2).await.unwrap().is_none());
+    assert_eq!(service.slow_queries().len() - before, 2);
+}
+
+#[tokio::test]
+async fn test_numeric_keys_match_whatever_their_type() {
+    let dir = TempDir::new().unwrap();
+    let service = users_service(&dir).await;
+    service
+        .execute_sql(SqlQuery::new(
+            "INSERT INTO users (id, name) VALUES (1, 'a'), (2, 'b')",
+        ))
+        .await
+        .unwrap();
+
+    let keys = [Value::Real(1.0), Value::Boolean(true), Value::Real(2.5)];
+    let rows = join_all(keys.iter().map(|key| service.load("users", key.clone()))).await;
+    let ids: Vec<Option<Value>> = rows
+        .into_iter()
+        .map(|row| row.unwrap().map(|row| row["id"].clone()))
+        .collect();
+    assert_eq!(
+        ids,
+        vec![Some(Value::Integer(1)), Some(Value::Integer(1)), None]
+    );
+}
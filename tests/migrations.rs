@@ -0,0 +1,133 @@
+use rust_sqlite::migration::{Migration, MigrationTracking};
+use rust_sqlite::sqlite::{Schema, SqliteConfig, SqliteError, SqliteService, SqlQuery, Value};
+use tempfile::TempDir;
+
+fn temp_db_path(dir: &TempDir) -> String {
+    dir.path().join("test.db").to_str().unwrap().to_string()
+}
+
+fn migrations() -> Vec<Migration> {
+    vec![
+        Migration::new(
+            1,
+            "create users",
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)",
+        )
+        .with_down("DROP TABLE users"),
+        Migration::new(2, "add email", "ALTER TABLE users ADD COLUMN email TEXT")
+            .with_down("ALTER TABLE users DROP COLUMN email"),
+    ]
+}
+
+async fn user_columns(service: &SqliteService) -> Vec<Value> {
+    service
+        .execute_sql(SqlQuery::new("SELECT name FROM pragma_table_info('users')"))
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|mut row| row.remove("name").unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_migrate_up_then_roll_back_one_version() {
+    let dir = TempDir::new().unwrap();
+    let config = SqliteConfig::new(temp_db_path(&dir), Schema::new()).with_migrations(migrations());
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+
+    assert_eq!(service.migration_version().await.unwrap(), 2);
+    assert_eq!(
+        user_columns(&service).await,
+        vec![
+            Value::Text("id".to_string()),
+            Value::Text("name".to_string()),
+            Value::Text("email".to_string()),
+        ]
+    );
+
+    service.rollback(1).await.unwrap();
+
+    assert_eq!(service.migration_version().await.unwrap(), 1);
+    assert_eq!(
+        user_columns(&service).await,
+        vec![Value::Text("id".to_string()), Value::Text("name".to_string())]
+    );
+
+    // Rolling back to the current version is a no-op
+    service.rollback(1).await.unwrap();
+    assert_eq!(service.migration_version().await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_migration_version_is_zero_without_migrations() {
+    let dir = TempDir::new().unwrap();
+    let service = SqliteService::new(SqliteConfig::new(temp_db_path(&dir), Schema::new()));
+    service.connect().await.unwrap();
+    assert_eq!(service.migration_version().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_rollback_requires_down_migrations() {
+    let dir = TempDir::new().unwrap();
+    let config = SqliteConfig::new(temp_db_path(&dir), Schema::new()).with_migrations(vec![
+        Migration::new(1, "create users", "CREATE TABLE users (id INTEGER PRIMARY KEY)"),
+    ]);
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+
+    let err = service.rollback(0).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::IrreversibleMigration(1))
+    ));
+    assert_eq!(service.migration_version().await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_user_version_tracking_advances_and_is_not_reapplied() {
+    let dir = TempDir::new().unwrap();
+    let config = SqliteConfig::new(temp_db_path(&dir), Schema::new())
+        .with_migrations(migrations())
+        .with_migration_tracking(MigrationTracking::UserVersion);
+    let service = SqliteService::new(config.clone());
+    service.connect().await.unwrap();
+
+    assert_eq!(service.schema_version().await.unwrap(), 2);
+    assert_eq!(service.migration_version().await.unwrap(), 2);
+    // No migrations table is created
+    let rows = service
+        .execute_sql(SqlQuery::new(
+            "SELECT name FROM sqlite_master WHERE name = '_runar_migrations'",
+        ))
+        .await
+        .unwrap();
+    assert!(rows.is_empty());
+
+    // Reconnecting does not rerun the migrations, which would fail on the
+    // existing table
+    let restarted = SqliteService::new(config);
+    restarted.connect().await.unwrap();
+    assert_eq!(restarted.schema_version().await.unwrap(), 2);
+
+    restarted.rollback(1).await.unwrap();
+    assert_eq!(restarted.schema_version().await.unwrap(), 1);
+    assert_eq!(
+        user_columns(&restarted).await,
+        vec![
+            Value::Text("id".to_string()),
+            Value::Text("name".to_string())
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_set_schema_version() {
+    let dir = TempDir::new().unwrap();
+    let service = SqliteService::new(SqliteConfig::new(temp_db_path(&dir), Schema::new()));
+    service.connect().await.unwrap();
+
+    assert_eq!(service.schema_version().await.unwrap(), 0);
+    service.set_schema_version(42).await.unwrap();
+    assert_eq!(service.schema_version().await.unwrap(), 42);
+}
@@ -0,0 +1,73 @@
+use rust_sqlite::outbox::{enqueue_outbox, OutboxMessage};
+use rust_sqlite::sqlite::{
+    ColumnConstraint, ColumnDefinition, DataType, Schema, SqlQuery, SqliteConfig, SqliteService,
+    TableDefinition, Value,
+};
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tempfile::TempDir;
+
+async fn orders_service(dir: &TempDir) -> SqliteService {
+    let schema = Schema::new().add_table(
+        TableDefinition::new("orders").with_column(
+            ColumnDefinition::new("id", DataType::Integer)
+                .with_constraint(ColumnConstraint::PrimaryKey),
+        ),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    service
+}
+
+async fn place_order(service: &SqliteService, id: i64) -> anyhow::Result<()> {
+    service
+        .transaction_retry(|tx| {
+            tx.execute("INSERT INTO orders (id) VALUES (?1)", [id])?;
+            enqueue_outbox(tx, "orders/placed", &json!({ "id": id }))?;
+            Ok(())
+        })
+        .await
+}
+
+#[tokio::test]
+async fn test_outbox_events_commit_with_their_writes_and_drain() {
+    let dir = TempDir::new().unwrap();
+    let service = orders_service(&dir).await;
+    for id in 1..=3 {
+        place_order(&service, id).await.unwrap();
+    }
+    // A rolled-back transaction leaves no event behind
+    assert!(place_order(&service, 1).await.is_err());
+
+    // Publishing order 3 fails once
+    let bus_down = AtomicBool::new(true);
+    let published = Mutex::new(Vec::new());
+    let publish = |message: OutboxMessage| {
+        let result = if message.payload["id"] == 3 && bus_down.swap(false, Ordering::SeqCst) {
+            Err(anyhow::anyhow!("bus unavailable"))
+        } else {
+            published.lock().unwrap().push(message.payload);
+            Ok(())
+        };
+        async move { result }
+    };
+
+    // The failed publish stops the drain; its event stays pending
+    assert!(service.drain_outbox(10, publish).await.is_err());
+    assert_eq!(service.drain_outbox(10, publish).await.unwrap(), 1);
+    assert_eq!(service.drain_outbox(10, publish).await.unwrap(), 0);
+
+    assert_eq!(
+        *published.lock().unwrap(),
+        vec![json!({"id": 1}), json!({"id": 2}), json!({"id": 3})]
+    );
+    let rows = service
+        .execute_sql(SqlQuery::new(
+            "SELECT COUNT(*) AS pending FROM _runar_outbox WHERE sent_at IS NULL",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["pending"], Value::Integer(0));
+}
@@ -0,0 +1,128 @@
+use rust_sqlite::filter::Condition;
+use rust_sqlite::query;
+use rust_sqlite::sqlite::{
+    ColumnDefinition, CreateOperation, CrudOperation, DataType, Query, QueryOperator,
+    ReadOperation, Schema, SqliteConfig, SqliteService, TableDefinition, Value,
+};
+use tempfile::TempDir;
+
+#[test]
+fn test_macro_matches_hand_built_condition() {
+    let built = query!(age > 18 && (country == "US" || country == "CA"));
+    let expected = Condition::And(vec![
+        Condition::field("age", QueryOperator::GreaterThan(18.into())),
+        Condition::Or(vec![
+            Condition::field("country", QueryOperator::Equal("US".into())),
+            Condition::field("country", QueryOperator::Equal("CA".into())),
+        ]),
+    ]);
+    assert_eq!(built, expected);
+
+    let (sql, params) = built.to_sql();
+    assert_eq!(sql, expected.to_sql().0);
+    assert_eq!(sql, "\"age\" > ? AND (\"country\" = ? OR \"country\" = ?)");
+    assert_eq!(
+        params,
+        vec![
+            Value::Integer(18),
+            Value::Text("US".to_string()),
+            Value::Text("CA".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_and_binds_tighter_than_or() {
+    let built = query!(a == 1 || b == 2 && c != 3);
+    assert_eq!(
+        built,
+        Condition::Or(vec![
+            Condition::field("a", QueryOperator::Equal(1.into())),
+            Condition::And(vec![
+                Condition::field("b", QueryOperator::Equal(2.into())),
+                Condition::field("c", QueryOperator::NotEqual(3.into())),
+            ]),
+        ])
+    );
+    assert_eq!(built.to_sql().0, "\"a\" = ? OR (\"b\" = ? AND \"c\" != ?)");
+}
+
+#[test]
+fn test_negation_lists_patterns_and_variables() {
+    let max = 10;
+    let built = query!(!(score >= max || score <= -1) && id in [1, 2, 3] && name like "a%");
+    assert_eq!(
+        built,
+        Condition::And(vec![
+            !Condition::Or(vec![
+                Condition::field("score", QueryOperator::GreaterThanOrEqual(10.into())),
+                Condition::field("score", QueryOperator::LessThanOrEqual((-1).into())),
+            ]),
+            Condition::field("id", QueryOperator::In(vec![1.into(), 2.into(), 3.into()])),
+            Condition::field("name", QueryOperator::Like("a%".to_string())),
+        ])
+    );
+    assert_eq!(
+        built.to_sql().0,
+        "NOT (\"score\" >= ? OR \"score\" <= ?) AND \"id\" IN (?, ?, ?) AND \"name\" LIKE ?"
+    );
+}
+
+#[tokio::test]
+async fn test_values_are_bound_not_interpolated() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("people")
+            .with_column(ColumnDefinition::new("name", DataType::Text))
+            .with_column(ColumnDefinition::new("age", DataType::Integer)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    for (name, age) in [("alice", 30), ("bob", 12), ("o'brien", 40)] {
+        service
+            .execute_crud(CrudOperation::Create(
+                CreateOperation::new("people")
+                    .with_value("name", name)
+                    .with_value("age", age),
+            ))
+            .await
+            .unwrap();
+    }
+
+    let hostile = "o'brien";
+    let read = ReadOperation::new("people")
+        .with_query(
+            Query::new().with_filter(query!(age > 18 && (name == hostile || name == "alice"))),
+        )
+        .with_fields(&["name"])
+        .with_order_by("name", true);
+    let rows = service
+        .execute_crud(CrudOperation::Read(read))
+        .await
+        .unwrap();
+    let names: Vec<&Value> = rows.iter().map(|r| &r["name"]).collect();
+    assert_eq!(
+        names,
+        vec![
+            &Value::Text("alice".to_string()),
+            &Value::Text("o'brien".to_string())
+        ]
+    );
+
+    // An empty list matches no row, so its negation matches every row
+    let count = |filter: Condition| {
+        let read = ReadOperation::new("people").with_query(Query::new().with_filter(filter));
+        let service = service.clone();
+        async move {
+            service
+                .execute_crud(CrudOperation::Read(read))
+                .await
+                .unwrap()
+                .len()
+        }
+    };
+    let empty = Condition::field("age", QueryOperator::In(vec![]));
+    assert_eq!(count(empty.clone()).await, 0);
+    assert_eq!(count(!empty).await, 3);
+}
@@ -0,0 +1,47 @@
+#![cfg(feature = "testing")]
+
+use rust_sqlite::sqlite::{
+    ColumnDefinition, DataType, IndexDefinition, Params, Schema, SqlQuery, SqliteConfig,
+    SqliteService, TableDefinition,
+};
+use tempfile::TempDir;
+
+async fn users_service(dir: &TempDir) -> SqliteService {
+    let schema = Schema::new().add_table(
+        TableDefinition::new("users")
+            .with_column(ColumnDefinition::new("email", DataType::Text))
+            .with_column(ColumnDefinition::new("name", DataType::Text))
+            .with_index(IndexDefinition::new("idx_users_email", &["email"], true)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    service
+}
+
+#[tokio::test]
+async fn test_assert_uses_index_passes_for_indexed_lookup() {
+    let dir = TempDir::new().unwrap();
+    let service = users_service(&dir).await;
+    let query = SqlQuery::new("SELECT name FROM users WHERE email = :email")
+        .with_params(Params::new().with_value("email", "a@example.com"));
+    service.assert_uses_index(&query, "idx_users_email").await;
+}
+
+#[tokio::test]
+#[should_panic(expected = "to use index \"idx_users_email\"")]
+async fn test_assert_uses_index_fails_for_table_scan() {
+    let dir = TempDir::new().unwrap();
+    let service = users_service(&dir).await;
+    let query = SqlQuery::new("SELECT email FROM users WHERE name = 'x'");
+    service.assert_uses_index(&query, "idx_users_email").await;
+}
+
+#[tokio::test]
+#[should_panic(expected = "to use index \"idx_users\"")]
+async fn test_assert_uses_index_requires_the_exact_name() {
+    let dir = TempDir::new().unwrap();
+    let service = users_service(&dir).await;
+    let query = SqlQuery::new("SELECT name FROM users WHERE email = 'x'");
+    service.assert_uses_index(&query, "idx_users").await;
+}
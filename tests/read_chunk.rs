@@ -0,0 +1,94 @@
+// Tests for the sqlite/read_chunk action, called through a node
+//
+// A large table is read a bounded chunk at a time, each request continuing
+// after the cursor returned by the previous one.
+
+use runar_common::types::ArcValueType;
+use runar_node::{Node, NodeConfig};
+use rust_sqlite::cursor::{ReadChunk, ReadChunkRequest};
+use rust_sqlite::sqlite::{
+    ColumnConstraint, ColumnDefinition, DataType, Schema, SqlQuery, SqliteConfig, SqliteService,
+    TableDefinition,
+};
+use tempfile::TempDir;
+
+async fn items_node(dir: &TempDir) -> Node {
+    let schema = Schema::new().add_table(
+        TableDefinition::new("items")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("name", DataType::Text)),
+    );
+    let path = dir.path().join("items.db").to_str().unwrap().to_string();
+    let db = SqliteService::new(SqliteConfig::new(path, schema));
+    db.connect().await.unwrap();
+    db.execute_sql(SqlQuery::new(
+        "WITH RECURSIVE n(id) AS (SELECT 1 UNION ALL SELECT id + 1 FROM n WHERE id < 2500) \
+         INSERT INTO items (id, name) SELECT id, 'item ' || id FROM n",
+    ))
+    .await
+    .unwrap();
+
+    let mut config = NodeConfig::new("test-node", "test_network");
+    config.network_config = None;
+    let mut node = Node::new(config).await.unwrap();
+    node.add_service(db).await.unwrap();
+    node.start().await.unwrap();
+    node
+}
+
+async fn read_chunk(node: &Node, request: &ReadChunkRequest) -> anyhow::Result<ReadChunk> {
+    let response = node
+        .request(
+            "sqlite/read_chunk",
+            Some(ArcValueType::from_struct(request.clone())),
+        )
+        .await?;
+    response.unwrap().as_type::<ReadChunk>()
+}
+
+#[tokio::test]
+async fn test_large_table_is_read_in_chunks() {
+    let dir = TempDir::new().unwrap();
+    let node = items_node(&dir).await;
+
+    let mut request = ReadChunkRequest {
+        table: "items".to_string(),
+        key_columns: vec!["id".to_string()],
+        fields: Some(vec!["id".to_string(), "name".to_string()]),
+        chunk_size: 5000,
+        cursor: None,
+    };
+    let mut chunk_sizes = Vec::new();
+    let mut ids = Vec::new();
+    loop {
+        let chunk = read_chunk(&node, &request).await.unwrap();
+        chunk_sizes.push(chunk.rows.len());
+        ids.extend(chunk.rows.iter().map(|row| row["id"].as_i64().unwrap()));
+        match chunk.next_cursor {
+            Some(cursor) => request.cursor = Some(cursor),
+            None => break,
+        }
+    }
+    // The requested size is clamped to the maximum chunk
+    assert_eq!(chunk_sizes, vec![1000, 1000, 500]);
+    assert_eq!(ids, (1..=2500).collect::<Vec<i64>>());
+}
+
+#[tokio::test]
+async fn test_read_chunk_requires_key_columns() {
+    let dir = TempDir::new().unwrap();
+    let node = items_node(&dir).await;
+
+    let request = ReadChunkRequest {
+        table: "items".to_string(),
+        key_columns: Vec::new(),
+        fields: None,
+        chunk_size: 100,
+        cursor: None,
+    };
+    let err = read_chunk(&node, &request).await.unwrap_err();
+    assert!(err.to_string().contains("key column"));
+}
@@ -0,0 +1,120 @@
+// Test for binding a SQLite transaction to a request context
+//
+// An order service writes to the database and then calls a downstream
+// action; when the downstream call fails the local write is rolled back.
+
+use anyhow::{anyhow, Result};
+use runar_common::types::ArcValueType;
+use runar_macros::{action, service};
+use runar_node::services::RequestContext;
+use runar_node::{Node, NodeConfig};
+use rust_sqlite::sqlite::{
+    ColumnConstraint, ColumnDefinition, DataType, Params, Schema, SqliteConfig, SqliteService,
+    SqlQuery, TableDefinition, Value,
+};
+use std::collections::HashMap;
+use tempfile::TempDir;
+
+#[derive(Clone)]
+pub struct MathService;
+
+#[service(
+    name = "Math Service",
+    path = "math",
+    description = "Downstream math service",
+    version = "0.0.1"
+)]
+impl MathService {
+    #[action]
+    async fn divide(&self, a: f64, b: f64, _ctx: &RequestContext) -> Result<f64> {
+        if b == 0.0 {
+            return Err(anyhow!("Division by zero"));
+        }
+        Ok(a / b)
+    }
+}
+
+#[derive(Clone)]
+pub struct OrderService {
+    db: SqliteService,
+}
+
+#[service(
+    name = "Order Service",
+    path = "orders",
+    description = "Writes orders inside a request transaction",
+    version = "0.0.1"
+)]
+impl OrderService {
+    #[action]
+    async fn place(&self, quantity: f64, ctx: &RequestContext) -> Result<f64> {
+        let tx = self.db.begin_request_transaction(ctx).await?;
+        tx.execute_sql(
+            SqlQuery::new("INSERT INTO orders (quantity) VALUES (:quantity)")
+                .with_params(Params::new().with_value("quantity", quantity)),
+        )?;
+
+        let mut map = HashMap::new();
+        map.insert("a".to_string(), 100.0);
+        map.insert("b".to_string(), quantity);
+        let response = tx
+            .request("math/divide", Some(ArcValueType::new_map(map)))
+            .await?;
+
+        tx.commit()?;
+        response.unwrap().as_type::<f64>()
+    }
+}
+
+async fn order_count(db: &SqliteService) -> Value {
+    let mut rows = db
+        .execute_sql(SqlQuery::new("SELECT COUNT(*) AS count FROM orders"))
+        .await
+        .unwrap();
+    rows.remove(0).remove("count").unwrap()
+}
+
+#[tokio::test]
+async fn test_failed_downstream_request_rolls_back_local_writes() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("orders")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("quantity", DataType::Real)),
+    );
+    let db_path = dir.path().join("orders.db").to_str().unwrap().to_string();
+    let db = SqliteService::new(SqliteConfig::new(db_path, schema));
+    db.connect().await.unwrap();
+
+    let mut config = NodeConfig::new("test-node", "test_network");
+    config.network_config = None;
+    let mut node = Node::new(config).await.unwrap();
+    node.add_service(MathService).await.unwrap();
+    node.add_service(OrderService { db: db.clone() }).await.unwrap();
+    node.start().await.unwrap();
+
+    // Successful downstream call: the order is committed
+    let mut map = HashMap::new();
+    map.insert("quantity".to_string(), 4.0);
+    let response = node
+        .request("orders/place", Some(ArcValueType::new_map(map)))
+        .await
+        .unwrap();
+    assert_eq!(response.unwrap().as_type::<f64>().unwrap(), 25.0);
+    assert_eq!(order_count(&db).await, Value::Integer(1));
+
+    // Failing downstream call: the insert made before it is rolled back
+    let mut map = HashMap::new();
+    map.insert("quantity".to_string(), 0.0);
+    let response = node
+        .request("orders/place", Some(ArcValueType::new_map(map)))
+        .await;
+    assert!(response
+        .unwrap_err()
+        .to_string()
+        .contains("Division by zero"));
+    assert_eq!(order_count(&db).await, Value::Integer(1));
+}
@@ -0,0 +1,64 @@
+#![cfg(feature = "testing")]
+
+use rust_sqlite::migration::{Migration, MIGRATIONS_TABLE};
+use rust_sqlite::sqlite::{
+    ColumnConstraint, ColumnDefinition, DataType, Schema, SqlQuery, SqliteConfig, SqliteService,
+    TableDefinition, Value,
+};
+use tempfile::TempDir;
+
+async fn count(service: &SqliteService, table: &str) -> Value {
+    let mut rows = service
+        .execute_sql(SqlQuery::new(&format!(
+            "SELECT COUNT(*) AS n FROM {}",
+            table
+        )))
+        .await
+        .unwrap();
+    rows.remove(0).remove("n").unwrap()
+}
+
+#[tokio::test]
+async fn test_reset_clears_data_and_rebuilds_schema() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("users")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("name", DataType::Text)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let config = SqliteConfig::new(path, schema).with_migrations(vec![Migration::new(
+        1,
+        "create posts",
+        "CREATE TABLE posts (id INTEGER PRIMARY KEY, user_id INTEGER NOT NULL REFERENCES users (id))",
+    )]);
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+    for sql in [
+        "INSERT INTO users (id, name) VALUES (1, 'ada')",
+        "INSERT INTO posts (user_id) VALUES (1)",
+        "CREATE TABLE scratch (x)",
+    ] {
+        service.execute_sql(SqlQuery::new(sql)).await.unwrap();
+    }
+
+    service.reset().await.unwrap();
+
+    assert_eq!(count(&service, "users").await, Value::Integer(0));
+    assert_eq!(count(&service, "posts").await, Value::Integer(0));
+    assert!(service
+        .execute_sql(SqlQuery::new("SELECT * FROM scratch"))
+        .await
+        .is_err());
+    // The migration ran again and is recorded exactly once
+    assert_eq!(service.migration_version().await.unwrap(), 1);
+    assert_eq!(count(&service, MIGRATIONS_TABLE).await, Value::Integer(1));
+    // Foreign keys are enforced again
+    assert!(service
+        .execute_sql(SqlQuery::new("INSERT INTO posts (user_id) VALUES (99)"))
+        .await
+        .is_err());
+}
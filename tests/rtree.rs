@@ -0,0 +1,77 @@
+#![cfg(feature = "rtree")]
+
+use rust_sqlite::rtree::{BoundingBox, RtreeTable, SpatialRelation};
+use rust_sqlite::sqlite::{Row, Schema, SqlQuery, SqliteConfig, SqliteError, SqliteService, Value};
+use tempfile::TempDir;
+
+async fn boxes_service(dir: &TempDir) -> SqliteService {
+    let schema = Schema::new().add_rtree_table(RtreeTable::new("boxes", &["x", "y"]));
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema));
+    service.connect().await.unwrap();
+    // Three unit squares along the diagonal and one large box
+    service
+        .execute_sql(SqlQuery::new(
+            "INSERT INTO boxes (id, min_x, max_x, min_y, max_y) VALUES \
+             (1, 0, 1, 0, 1), (2, 2, 3, 2, 3), (3, 4, 5, 4, 5), (4, -10, 10, -10, 10)",
+        ))
+        .await
+        .unwrap();
+    service
+}
+
+fn ids(rows: &[Row]) -> Vec<i64> {
+    rows.iter()
+        .map(|row| match row["id"] {
+            Value::Integer(id) => id,
+            _ => panic!("id is an integer"),
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_rtree_query_finds_boxes_overlapping_a_region() {
+    let dir = TempDir::new().unwrap();
+    let service = boxes_service(&dir).await;
+    let region = BoundingBox::new(&[(0.5, 2.5), (0.5, 2.5)]);
+
+    let overlapping = service
+        .rtree_query("boxes", &region, SpatialRelation::Overlaps)
+        .await
+        .unwrap();
+    assert_eq!(ids(&overlapping), [1, 2, 4]);
+    assert_eq!(overlapping[0]["max_x"], Value::Real(1.0));
+
+    let within = service
+        .rtree_query(
+            "boxes",
+            &BoundingBox::new(&[(1.5, 5.5), (1.5, 5.5)]),
+            SpatialRelation::Within,
+        )
+        .await
+        .unwrap();
+    assert_eq!(ids(&within), [2, 3]);
+
+    let containing = service
+        .rtree_query("boxes", &region, SpatialRelation::Contains)
+        .await
+        .unwrap();
+    assert_eq!(ids(&containing), [4]);
+
+    let err = service
+        .rtree_query(
+            "boxes",
+            &BoundingBox::new(&[(0.0, 1.0)]),
+            SpatialRelation::Overlaps,
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::RtreeDimensions {
+            expected: 2,
+            actual: 1,
+            ..
+        })
+    ));
+}
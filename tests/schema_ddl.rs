@@ -0,0 +1,216 @@
+use rusqlite::Connection;
+use rust_sqlite::sqlite::{
+    ColumnConstraint, ColumnDefinition, DataType, DefaultValue, ForeignKey, ForeignKeyAction,
+    IndexDefinition, Schema, SqliteConfig, SqliteError, SqliteService, TableDefinition,
+};
+
+// Schema with the dependent table declared before the table it references
+fn blog_schema() -> Schema {
+    Schema::new()
+        .add_table(
+            TableDefinition::new("posts")
+                .with_column(
+                    ColumnDefinition::new("id", DataType::Integer)
+                        .with_constraint(ColumnConstraint::PrimaryKey),
+                )
+                .with_column(
+                    ColumnDefinition::new("user_id", DataType::Integer)
+                        .with_constraint(ColumnConstraint::NotNull),
+                )
+                .with_column(
+                    ColumnDefinition::new("title", DataType::Text)
+                        .with_default(DefaultValue::Text("untitled".to_string())),
+                )
+                .with_foreign_key(ForeignKey {
+                    column: "user_id".to_string(),
+                    foreign_table: "users".to_string(),
+                    foreign_column: "id".to_string(),
+                    on_delete: ForeignKeyAction::Cascade,
+                    on_update: ForeignKeyAction::NoAction,
+                    deferred: false,
+                })
+                .with_index(IndexDefinition::new("idx_posts_user", &["user_id"], false)),
+        )
+        .add_table(
+            TableDefinition::new("users")
+                .with_column(
+                    ColumnDefinition::new("id", DataType::Integer)
+                        .with_constraint(ColumnConstraint::PrimaryKey),
+                )
+                .with_column(
+                    ColumnDefinition::new("email", DataType::Text)
+                        .with_constraint(ColumnConstraint::NotNull),
+                )
+                .with_column(ColumnDefinition::new("score", DataType::Real))
+                .with_index(IndexDefinition::new("idx_users_email", &["email"], true)),
+        )
+}
+
+fn table_columns(conn: &Connection, table: &str) -> Vec<(String, String)> {
+    let mut stmt = conn
+        .prepare(&format!("PRAGMA table_info(\"{}\")", table))
+        .unwrap();
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(1)?, row.get(2)?)))
+        .unwrap();
+    rows.map(|r| r.unwrap()).collect()
+}
+
+fn table_indexes(conn: &Connection, table: &str) -> Vec<(String, bool)> {
+    let mut stmt = conn
+        .prepare(&format!("PRAGMA index_list(\"{}\")", table))
+        .unwrap();
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(1)?, row.get(2)?)))
+        .unwrap();
+    let mut indexes: Vec<(String, bool)> = rows
+        .map(|r| r.unwrap())
+        .filter(|(name, _): &(String, bool)| !name.starts_with("sqlite_autoindex"))
+        .collect();
+    indexes.sort();
+    indexes
+}
+
+#[test]
+fn test_to_ddl_orders_referenced_tables_first() {
+    let ddl = blog_schema().to_ddl();
+
+    let users = ddl.find("CREATE TABLE IF NOT EXISTS \"users\"").unwrap();
+    let posts = ddl.find("CREATE TABLE IF NOT EXISTS \"posts\"").unwrap();
+    assert!(users < posts, "users must be created before posts:\n{}", ddl);
+    assert!(ddl.contains(
+        "FOREIGN KEY (\"user_id\") REFERENCES \"users\" (\"id\") ON DELETE CASCADE ON UPDATE NO ACTION"
+    ));
+    assert!(ddl.contains("CREATE UNIQUE INDEX IF NOT EXISTS \"idx_users_email\" ON \"users\" (\"email\")"));
+    assert!(ddl.contains("\"title\" TEXT DEFAULT 'untitled'"));
+}
+
+#[test]
+fn test_to_ddl_recreates_equivalent_schema() {
+    let schema = blog_schema();
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch("PRAGMA foreign_keys = ON;").unwrap();
+    conn.execute_batch(&schema.to_ddl()).unwrap();
+
+    for table in &schema.tables {
+        let expected: Vec<(String, String)> = table
+            .columns
+            .iter()
+            .map(|c| (c.name.clone(), c.data_type.as_sql().to_string()))
+            .collect();
+        assert_eq!(table_columns(&conn, &table.name), expected);
+
+        let mut expected_indexes: Vec<(String, bool)> = table
+            .indexes
+            .iter()
+            .map(|i| (i.name.clone(), i.unique))
+            .collect();
+        expected_indexes.sort();
+        assert_eq!(table_indexes(&conn, &table.name), expected_indexes);
+    }
+
+    // The foreign key survives the round trip
+    conn.execute("INSERT INTO users (id, email) VALUES (1, 'a@example.com')", [])
+        .unwrap();
+    conn.execute("INSERT INTO posts (user_id) VALUES (1)", []).unwrap();
+    assert!(conn
+        .execute("INSERT INTO posts (user_id) VALUES (2)", [])
+        .is_err());
+
+    // Executing the script a second time is a no-op
+    conn.execute_batch(&schema.to_ddl()).unwrap();
+}
+
+#[test]
+fn test_fingerprint_ignores_declaration_order_but_not_column_changes() {
+    let schema = blog_schema();
+    assert_eq!(schema.fingerprint(), blog_schema().fingerprint());
+    assert_eq!(schema.fingerprint().len(), 16);
+
+    let mut reordered = blog_schema();
+    reordered.tables.reverse();
+    assert_eq!(reordered.fingerprint(), schema.fingerprint());
+
+    let mut changed = blog_schema();
+    changed.tables[1].columns[2] = ColumnDefinition::new("score", DataType::Integer);
+    assert_ne!(changed.fingerprint(), schema.fingerprint());
+
+    let mut indexed = blog_schema();
+    indexed.tables[1] = indexed.tables[1].clone().with_index(IndexDefinition::new(
+        "idx_users_score",
+        &["score"],
+        false,
+    ));
+    assert_ne!(indexed.fingerprint(), schema.fingerprint());
+}
+
+#[test]
+fn test_planned_ddl_lists_statements_in_execution_order() {
+    let service = SqliteService::new(SqliteConfig::new(":memory:", blog_schema()));
+    // Nothing runs: the service is not even connected
+    let ddl = service.planned_ddl().unwrap();
+    assert_eq!(
+        ddl,
+        [
+            r#"CREATE TABLE IF NOT EXISTS "users" ("id" INTEGER PRIMARY KEY, "email" TEXT NOT NULL, "score" REAL)"#,
+            r#"CREATE UNIQUE INDEX IF NOT EXISTS "idx_users_email" ON "users" ("email")"#,
+            r#"CREATE TABLE IF NOT EXISTS "posts" ("id" INTEGER PRIMARY KEY, "user_id" INTEGER NOT NULL, "title" TEXT DEFAULT 'untitled', FOREIGN KEY ("user_id") REFERENCES "users" ("id") ON DELETE CASCADE ON UPDATE NO ACTION)"#,
+            r#"CREATE INDEX IF NOT EXISTS "idx_posts_user" ON "posts" ("user_id")"#,
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_foreign_key_builder_and_type_check() {
+    let fk = ForeignKey::new("user_id", "users", "id")
+        .with_on_delete(ForeignKeyAction::Cascade)
+        .with_on_update(ForeignKeyAction::Restrict);
+    assert_eq!(
+        fk.to_sql(),
+        r#"FOREIGN KEY ("user_id") REFERENCES "users" ("id") ON DELETE CASCADE ON UPDATE RESTRICT"#
+    );
+    assert_eq!(
+        ForeignKey::new("user_id", "users", "id").to_sql(),
+        r#"FOREIGN KEY ("user_id") REFERENCES "users" ("id") ON DELETE NO ACTION ON UPDATE NO ACTION"#
+    );
+
+    let schema = |user_id_type: DataType| {
+        Schema::new()
+            .add_table(
+                TableDefinition::new("users").with_column(
+                    ColumnDefinition::new("id", DataType::Integer)
+                        .with_constraint(ColumnConstraint::PrimaryKey),
+                ),
+            )
+            .add_table(
+                TableDefinition::new("posts")
+                    .with_column(ColumnDefinition::new("user_id", user_id_type))
+                    .foreign_key("user_id", "users", "id")
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Restrict),
+            )
+    };
+    // The table builder declares the same key
+    assert_eq!(schema(DataType::Integer).tables[1].foreign_keys, vec![fk]);
+    assert!(schema(DataType::Integer).check_foreign_key_types().is_ok());
+    let err = schema(DataType::Text)
+        .check_foreign_key_types()
+        .unwrap_err();
+    assert!(matches!(
+        &err,
+        SqliteError::ForeignKeyTypeMismatch { column, local: DataType::Text, foreign_column, foreign: DataType::Integer }
+            if column == "posts.user_id" && foreign_column == "users.id"
+    ));
+
+    // Connecting refuses the mismatched schema only when asked to check
+    let service = SqliteService::new(SqliteConfig::new(":memory:", schema(DataType::Text)));
+    service.connect().await.unwrap();
+    let service = SqliteService::new(
+        SqliteConfig::new(":memory:", schema(DataType::Text)).with_foreign_key_type_check(true),
+    );
+    let err = service.connect().await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::ForeignKeyTypeMismatch { .. })
+    ));
+}
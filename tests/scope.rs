@@ -0,0 +1,272 @@
+use rust_sqlite::sqlite::{
+    ColumnConstraint, ColumnDefinition, CommonTableExpression, CreateOperation, CrudOperation,
+    DataType, DeleteOperation, Query, QueryOperator, ReadOperation, Schema, SqliteConfig,
+    SqliteError, SqliteService, TableDefinition, UpdateOperation, Value,
+};
+use tempfile::TempDir;
+
+async fn documents_service(dir: &TempDir) -> SqliteService {
+    let schema = Schema::new().add_table(
+        TableDefinition::new("documents")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("tenant_id", DataType::Text))
+            .with_column(ColumnDefinition::new("title", DataType::Text)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let config = SqliteConfig::new(path, schema).with_scope("documents", "tenant_id");
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+    for (id, tenant) in [(1, "acme"), (2, "acme"), (3, "globex")] {
+        service
+            .scoped(tenant)
+            .execute_crud(CrudOperation::Create(
+                CreateOperation::new("documents")
+                    .with_value("id", id)
+                    .with_value("title", format!("doc {}", id)),
+            ))
+            .await
+            .unwrap();
+    }
+    service
+}
+
+fn by_id(id: i64) -> Query {
+    Query::new().with_condition("id", QueryOperator::Equal(Value::Integer(id)))
+}
+
+#[tokio::test]
+async fn test_scoped_operations_only_see_their_own_rows() {
+    let dir = TempDir::new().unwrap();
+    let service = documents_service(&dir).await;
+    let acme = service.scoped("acme");
+
+    let rows = acme
+        .execute_crud(CrudOperation::Read(ReadOperation::new("documents")))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 2);
+    assert!(rows
+        .iter()
+        .all(|row| row["tenant_id"] == Value::Text("acme".to_string())));
+
+    // Row 3 belongs to globex: invisible, and untouched by updates and deletes
+    let read = acme
+        .execute_crud(CrudOperation::Read(
+            ReadOperation::new("documents").with_query(by_id(3)),
+        ))
+        .await
+        .unwrap();
+    assert!(read.is_empty());
+    let updated = acme
+        .execute_crud(CrudOperation::Update(
+            UpdateOperation::new("documents", by_id(3)).with_update("title", "stolen"),
+        ))
+        .await
+        .unwrap();
+    assert!(updated.is_empty());
+    let deleted = acme
+        .execute_crud(CrudOperation::Delete(DeleteOperation::new(
+            "documents",
+            Query::new(),
+        )))
+        .await
+        .unwrap();
+    assert_eq!(deleted.len(), 2);
+
+    let globex = service
+        .scoped("globex")
+        .execute_crud(CrudOperation::Read(ReadOperation::new("documents")))
+        .await
+        .unwrap();
+    assert_eq!(globex.len(), 1);
+    assert_eq!(globex[0]["title"], Value::Text("doc 3".to_string()));
+}
+
+#[tokio::test]
+async fn test_scope_cannot_be_bypassed_or_changed() {
+    let dir = TempDir::new().unwrap();
+    let service = documents_service(&dir).await;
+
+    let err = service
+        .execute_crud(CrudOperation::Read(ReadOperation::new("documents")))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::ScopeRequired(table)) if table == "documents"
+    ));
+    assert!(service.load("documents", 1).await.is_err());
+
+    let acme = service.scoped("acme");
+    let err = acme
+        .execute_crud(CrudOperation::Create(
+            CreateOperation::new("documents")
+                .with_value("id", 4)
+                .with_value("tenant_id", "globex"),
+        ))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::ScopeViolation { column, .. }) if column == "tenant_id"
+    ));
+    let err = acme
+        .execute_crud(CrudOperation::Update(
+            UpdateOperation::new("documents", by_id(1)).with_update("tenant_id", "globex"),
+        ))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::ScopeViolation { .. })
+    ));
+
+    // SQLite ignores the case of column names, and so does the scope
+    let err = acme
+        .execute_crud(CrudOperation::Create(
+            CreateOperation::new("documents")
+                .with_value("id", 4)
+                .with_value("TENANT_ID", "globex"),
+        ))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::ScopeViolation { .. })
+    ));
+    let err = acme
+        .execute_crud(CrudOperation::Update(
+            UpdateOperation::new("documents", by_id(1)).with_update("TENANT_ID", "globex"),
+        ))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::ScopeViolation { .. })
+    ));
+    let rows = acme
+        .execute_crud(CrudOperation::Create(
+            CreateOperation::new("documents")
+                .with_value("id", 5)
+                .with_value("Tenant_Id", "acme"),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["tenant_id"], Value::Text("acme".to_string()));
+}
+
+#[tokio::test]
+async fn test_upsert_cannot_take_over_another_scopes_row() {
+    let dir = TempDir::new().unwrap();
+    let service = documents_service(&dir).await;
+
+    // Row 3 belongs to globex; an acme upsert on its id must not move it
+    let err = service
+        .scoped("acme")
+        .execute_crud(CrudOperation::Create(
+            CreateOperation::new("documents")
+                .with_value("id", 3)
+                .with_value("title", "taken")
+                .with_upsert(&["id"]),
+        ))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::ScopeViolation { table, column })
+            if table == "documents" && column == "tenant_id"
+    ));
+
+    let globex = service
+        .scoped("globex")
+        .execute_crud(CrudOperation::Read(
+            ReadOperation::new("documents").with_query(by_id(3)),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(globex.len(), 1);
+    assert_eq!(globex[0]["title"], Value::Text("doc 3".to_string()));
+}
+
+#[tokio::test]
+async fn test_scope_applies_to_any_case_and_to_ctes() {
+    let dir = TempDir::new().unwrap();
+    let service = documents_service(&dir).await;
+    let scope_required = |err: anyhow::Error| {
+        matches!(
+            err.downcast_ref::<SqliteError>(),
+            Some(SqliteError::ScopeRequired(_))
+        )
+    };
+
+    let err = service
+        .execute_crud(CrudOperation::Read(ReadOperation::new("DOCUMENTS")))
+        .await
+        .unwrap_err();
+    assert!(scope_required(err));
+
+    // A CTE over the scoped table is not filtered, scoped service or not
+    let via_cte = || {
+        ReadOperation::new("everything").with_cte(CommonTableExpression::new(
+            "everything",
+            "SELECT * FROM \"Documents\"",
+        ))
+    };
+    let err = service
+        .execute_crud(CrudOperation::Read(via_cte()))
+        .await
+        .unwrap_err();
+    assert!(scope_required(err));
+    let err = service
+        .scoped("acme")
+        .execute_crud(CrudOperation::Read(via_cte()))
+        .await
+        .unwrap_err();
+    assert!(scope_required(err));
+
+    let rows = service
+        .scoped("acme")
+        .execute_crud(CrudOperation::Read(ReadOperation::new("Documents")))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 2);
+}
+
+#[tokio::test]
+async fn test_bulk_helpers_reject_scoped_tables() {
+    let dir = TempDir::new().unwrap();
+    let service = documents_service(&dir).await;
+
+    let err = service
+        .merge("documents", &["id"], Vec::new())
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::ScopeRequired(_))
+    ));
+
+    let other_dir = TempDir::new().unwrap();
+    let other = documents_service(&other_dir).await;
+    let err = service
+        .copy_table_to(&other, "documents", 100)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::ScopeRequired(_))
+    ));
+
+    // Every tenant's rows are still there
+    for (tenant, count) in [("acme", 2), ("globex", 1)] {
+        let rows = service
+            .scoped(tenant)
+            .execute_crud(CrudOperation::Read(ReadOperation::new("documents")))
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), count);
+    }
+}
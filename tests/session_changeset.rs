@@ -0,0 +1,66 @@
+#![cfg(feature = "session")]
+
+use rust_sqlite::session::ChangesetConflict;
+use rust_sqlite::sqlite::{
+    ColumnConstraint, ColumnDefinition, DataType, Schema, SqliteConfig, SqliteService, SqlQuery,
+    TableDefinition,
+};
+use tempfile::TempDir;
+
+fn schema() -> Schema {
+    Schema::new().add_table(
+        TableDefinition::new("items")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("name", DataType::Text)),
+    )
+}
+
+async fn open(dir: &TempDir, name: &str) -> SqliteService {
+    let path = dir.path().join(name).to_str().unwrap().to_string();
+    let service = SqliteService::new(SqliteConfig::new(path, schema()));
+    service.connect().await.unwrap();
+    service
+}
+
+async fn items(service: &SqliteService) -> Vec<rust_sqlite::sqlite::Row> {
+    service
+        .execute_sql(SqlQuery::new("SELECT id, name FROM items ORDER BY id"))
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_changeset_applied_to_second_database_converges_state() {
+    let dir = TempDir::new().unwrap();
+    let primary = open(&dir, "primary.db").await;
+    let replica = open(&dir, "replica.db").await;
+
+    // Both start from the same row
+    for service in [&primary, &replica] {
+        service
+            .execute_sql(SqlQuery::new("INSERT INTO items (id, name) VALUES (1, 'one')"))
+            .await
+            .unwrap();
+    }
+
+    let (_, changeset) = primary
+        .capture_changeset(|conn| {
+            conn.execute("INSERT INTO items (id, name) VALUES (2, 'two')", [])?;
+            conn.execute("UPDATE items SET name = 'uno' WHERE id = 1", [])?;
+            Ok(())
+        })
+        .await
+        .unwrap();
+    assert!(!changeset.is_empty());
+
+    replica
+        .apply_changeset(&changeset, ChangesetConflict::Abort)
+        .await
+        .unwrap();
+
+    assert_eq!(items(&primary).await, items(&replica).await);
+    assert_eq!(items(&replica).await.len(), 2);
+}
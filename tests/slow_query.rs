@@ -0,0 +1,124 @@
+use rust_sqlite::sqlite::{
+    ColumnDefinition, CrudOperation, DataType, IndexDefinition, MissingIndexPolicy, Params, Query,
+    QueryOperator, ReadOperation, Schema, SqlQuery, SqliteConfig, SqliteError, SqliteService,
+    TableDefinition, Value,
+};
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn users_config(dir: &TempDir) -> SqliteConfig {
+    let schema = Schema::new().add_table(
+        TableDefinition::new("users")
+            .with_column(ColumnDefinition::new("email", DataType::Text))
+            .with_column(ColumnDefinition::new("name", DataType::Text))
+            .with_index(IndexDefinition::new("idx_users_name", &["name"], false)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    SqliteConfig::new(path, schema)
+}
+
+async fn users_service(dir: &TempDir, suggest_indexes: bool) -> SqliteService {
+    // Every query counts as slow
+    let config = users_config(dir)
+        .with_slow_query_threshold(Duration::ZERO)
+        .with_index_suggestions(suggest_indexes);
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+    service
+}
+
+#[tokio::test]
+async fn test_scan_on_unindexed_filter_column_is_suggested() {
+    let dir = TempDir::new().unwrap();
+    let service = users_service(&dir, true).await;
+
+    let query = SqlQuery::new("SELECT name FROM users WHERE email = :email")
+        .with_params(Params::new().with_value("email", "a@example.com"));
+    service.execute_sql(query).await.unwrap();
+
+    let log = service.slow_queries();
+    assert_eq!(log.len(), 1);
+    assert_eq!(
+        log[0].statement,
+        "SELECT name FROM users WHERE email = :email"
+    );
+    assert_eq!(
+        log[0].suggestions,
+        vec!["consider index on users(email)".to_string()]
+    );
+}
+
+#[tokio::test]
+async fn test_indexed_lookups_produce_no_suggestion() {
+    let dir = TempDir::new().unwrap();
+    let service = users_service(&dir, true).await;
+
+    service
+        .execute_sql(SqlQuery::new("SELECT email FROM users WHERE name = 'x'"))
+        .await
+        .unwrap();
+    let log = service.slow_queries();
+    assert_eq!(log.len(), 1);
+    assert!(log[0].suggestions.is_empty());
+}
+
+#[tokio::test]
+async fn test_suggestions_are_off_by_default() {
+    let dir = TempDir::new().unwrap();
+    let service = users_service(&dir, false).await;
+
+    service
+        .execute_sql(SqlQuery::new("SELECT name FROM users WHERE email = 'x'"))
+        .await
+        .unwrap();
+    let log = service.slow_queries();
+    assert_eq!(log.len(), 1);
+    assert!(log[0].suggestions.is_empty());
+}
+
+fn by_email() -> CrudOperation {
+    CrudOperation::Read(ReadOperation::new("users").with_query(
+        Query::new().with_condition("email", QueryOperator::Equal(Value::from("a@example.com"))),
+    ))
+}
+
+#[tokio::test]
+async fn test_deny_policy_fails_filters_on_unindexed_columns() {
+    let dir = TempDir::new().unwrap();
+    let config = users_config(&dir).with_missing_index_policy(MissingIndexPolicy::Deny);
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+
+    let err = service.execute_crud(by_email()).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::MissingIndex { table, column }) if table == "users" && column == "email"
+    ));
+    // Indexed filters and unfiltered reads still run
+    service
+        .execute_sql(SqlQuery::new("SELECT email FROM users WHERE name = 'x'"))
+        .await
+        .unwrap();
+    service
+        .execute_sql(SqlQuery::new("SELECT email FROM users"))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_warn_policy_records_unindexed_filters() {
+    let dir = TempDir::new().unwrap();
+    let config = users_config(&dir).with_missing_index_policy(MissingIndexPolicy::Warn);
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+
+    service.execute_crud(by_email()).await.unwrap();
+    let filters = service.unindexed_filters();
+    assert_eq!(filters.len(), 1);
+    assert_eq!(
+        (filters[0].table.as_str(), filters[0].column.as_str()),
+        ("users", "email")
+    );
+    assert!(filters[0].statement.contains("WHERE"));
+    assert!(service.slow_queries().is_empty());
+}
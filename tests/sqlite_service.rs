@@ -0,0 +1,1398 @@
+use rusqlite::OpenFlags;
+use rust_sqlite::migration::{Migration, MigrationTracking};
+use rust_sqlite::sqlite::{
+    Collation, ColumnConstraint, ColumnDefinition, DataType, ForeignKey, ForeignKeyAction,
+    IndexDefinition, Params, Row, Schema, SchemaConflict, SqliteConfig, SqliteError, SqliteService,
+    SqlQuery, TableDefinition, TempStore, Value,
+};
+use rust_sqlite::view::ValueView;
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+fn temp_db_path(dir: &TempDir) -> String {
+    dir.path().join("test.db").to_str().unwrap().to_string()
+}
+
+fn id_column() -> ColumnDefinition {
+    ColumnDefinition::new("id", DataType::Integer).with_constraint(ColumnConstraint::PrimaryKey)
+}
+
+fn references(column: &str, table: &str) -> ForeignKey {
+    ForeignKey {
+        column: column.to_string(),
+        foreign_table: table.to_string(),
+        foreign_column: "id".to_string(),
+        on_delete: ForeignKeyAction::Cascade,
+        on_update: ForeignKeyAction::NoAction,
+        deferred: false,
+    }
+}
+
+#[tokio::test]
+async fn test_initialize_schema_with_tables_in_reverse_dependency_order() {
+    let dir = TempDir::new().unwrap();
+    // comments -> posts -> users, declared child-first
+    let schema = Schema::new()
+        .add_table(
+            TableDefinition::new("comments")
+                .with_column(id_column())
+                .with_column(ColumnDefinition::new("post_id", DataType::Integer))
+                .with_foreign_key(references("post_id", "posts")),
+        )
+        .add_table(
+            TableDefinition::new("posts")
+                .with_column(id_column())
+                .with_column(ColumnDefinition::new("user_id", DataType::Integer))
+                .with_foreign_key(references("user_id", "users")),
+        )
+        .add_table(TableDefinition::new("users").with_column(id_column()));
+
+    let order: Vec<&str> = schema
+        .dependency_order()
+        .unwrap()
+        .iter()
+        .map(|t| t.name.as_str())
+        .collect();
+    assert_eq!(order, vec!["users", "posts", "comments"]);
+
+    let service = SqliteService::new(SqliteConfig::new(temp_db_path(&dir), schema));
+    service.connect().await.unwrap();
+
+    service
+        .execute_sql(SqlQuery::new("INSERT INTO users (id) VALUES (1)"))
+        .await
+        .unwrap();
+    service
+        .execute_sql(SqlQuery::new("INSERT INTO posts (id, user_id) VALUES (1, 1)"))
+        .await
+        .unwrap();
+    // Foreign keys are enforced
+    assert!(service
+        .execute_sql(SqlQuery::new("INSERT INTO comments (id, post_id) VALUES (1, 42)"))
+        .await
+        .is_err());
+
+    let rows = service
+        .execute_sql(SqlQuery::new(
+            "SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name",
+        ))
+        .await
+        .unwrap();
+    let names: Vec<Value> = rows.into_iter().map(|mut r| r.remove("name").unwrap()).collect();
+    assert_eq!(
+        names,
+        vec![
+            Value::Text("comments".to_string()),
+            Value::Text("posts".to_string()),
+            Value::Text("users".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_initialize_schema_rejects_foreign_key_cycles() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new()
+        .add_table(
+            TableDefinition::new("a")
+                .with_column(id_column())
+                .with_column(ColumnDefinition::new("b_id", DataType::Integer))
+                .with_foreign_key(references("b_id", "b")),
+        )
+        .add_table(
+            TableDefinition::new("b")
+                .with_column(id_column())
+                .with_column(ColumnDefinition::new("a_id", DataType::Integer))
+                .with_foreign_key(references("a_id", "a")),
+        )
+        // Self-references are not cycles
+        .add_table(
+            TableDefinition::new("tree")
+                .with_column(id_column())
+                .with_column(ColumnDefinition::new("parent_id", DataType::Integer))
+                .with_foreign_key(references("parent_id", "tree")),
+        );
+
+    let service = SqliteService::new(SqliteConfig::new(temp_db_path(&dir), schema));
+    let err = service.connect().await.unwrap_err();
+    match err.downcast_ref::<SqliteError>() {
+        Some(SqliteError::SchemaCycle { tables }) => {
+            assert_eq!(tables, &vec!["a".to_string(), "b".to_string()])
+        }
+        other => panic!("expected SchemaCycle, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_seeds_run_once_and_not_again_on_restart() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("countries")
+            .with_column(
+                ColumnDefinition::new("code", DataType::Text)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("name", DataType::Text)),
+    );
+    let config = SqliteConfig::new(temp_db_path(&dir), schema).with_seeds(vec![
+        SqlQuery::new("INSERT INTO countries (code, name) VALUES ('US', 'United States')"),
+        SqlQuery::new("INSERT INTO countries (code, name) VALUES ('CA', 'Canada')"),
+    ]);
+
+    let count_query = SqlQuery::new("SELECT COUNT(*) AS count FROM countries");
+    let service = SqliteService::new(config.clone());
+    service.connect().await.unwrap();
+    let rows = service.execute_sql(count_query.clone()).await.unwrap();
+    assert_eq!(rows[0]["count"], Value::Integer(2));
+
+    // Remove a seeded row; a restart must not bring it back or re-insert
+    // (which would fail on the primary key)
+    service
+        .execute_sql(SqlQuery::new("DELETE FROM countries WHERE code = 'CA'"))
+        .await
+        .unwrap();
+    let restarted = SqliteService::new(config);
+    restarted.connect().await.unwrap();
+    let rows = restarted.execute_sql(count_query).await.unwrap();
+    assert_eq!(rows[0]["count"], Value::Integer(1));
+}
+
+#[tokio::test]
+async fn test_open_fails_when_create_is_disabled_and_file_is_missing() {
+    let dir = TempDir::new().unwrap();
+    let path = temp_db_path(&dir);
+    let config = SqliteConfig::new(path.clone(), Schema::new()).with_create_if_missing(false);
+    assert!(!config
+        .effective_open_flags()
+        .contains(OpenFlags::SQLITE_OPEN_CREATE));
+
+    let service = SqliteService::new(config);
+    assert!(service.connect().await.is_err());
+    assert!(!std::path::Path::new(&path).exists());
+
+    // Once the file exists the same config opens it
+    rusqlite::Connection::open(&path).unwrap();
+    service.connect().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_parent_directories_are_created_when_enabled() {
+    let dir = TempDir::new().unwrap();
+    let nested = dir.path().join("data").join("nested");
+    let path = nested.join("test.db").to_str().unwrap().to_string();
+
+    let service = SqliteService::new(SqliteConfig::new(path.clone(), Schema::new()));
+    assert!(service.connect().await.is_err());
+    assert!(!nested.exists());
+
+    let service = SqliteService::new(
+        SqliteConfig::new(path.clone(), Schema::new()).with_create_parent_dir(true),
+    );
+    service.connect().await.unwrap();
+    service
+        .execute_sql(SqlQuery::new("CREATE TABLE t (id INTEGER)"))
+        .await
+        .unwrap();
+    assert!(std::path::Path::new(&path).is_file());
+}
+
+#[tokio::test]
+async fn test_concurrent_connects_open_the_database_once() {
+    let dir = TempDir::new().unwrap();
+    let service = SqliteService::new(SqliteConfig::new(temp_db_path(&dir), Schema::new()));
+    let results = futures::future::join_all((0..8).map(|_| {
+        let service = service.clone();
+        async move { service.connect().await }
+    }))
+    .await;
+    assert!(results.iter().all(|r| r.is_ok()));
+
+    // A temp table lives on its connection, so it survives only if later
+    // connects keep the writer that is open
+    service
+        .execute_sql(SqlQuery::new("CREATE TEMP TABLE marker (id INTEGER)"))
+        .await
+        .unwrap();
+    service.connect().await.unwrap();
+    service
+        .execute_sql(SqlQuery::new("INSERT INTO temp.marker VALUES (1)"))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_close_releases_the_database_file() {
+    let dir = TempDir::new().unwrap();
+    let path = temp_db_path(&dir);
+    let service =
+        SqliteService::new(SqliteConfig::new(path.clone(), Schema::new()).with_read_connections(2));
+    service.connect().await.unwrap();
+    service
+        .execute_sql(SqlQuery::new("CREATE TABLE t (id INTEGER)"))
+        .await
+        .unwrap();
+    service
+        .execute_sql(SqlQuery::new("INSERT INTO t VALUES (1)"))
+        .await
+        .unwrap();
+
+    service.close().await.unwrap();
+    let err = service
+        .execute_sql(SqlQuery::new("SELECT * FROM t"))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::Closed)
+    ));
+    // The WAL was checkpointed: the database file alone holds the row
+    let _ = std::fs::remove_file(format!("{}-wal", path));
+    let reopened = rusqlite::Connection::open(&path).unwrap();
+    let count: i64 = reopened
+        .query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 1);
+    drop(reopened);
+    // Nothing holds the file open
+    std::fs::remove_file(&path).unwrap();
+
+    // Connecting again starts over on a fresh file
+    service.connect().await.unwrap();
+    assert!(service
+        .execute_sql(SqlQuery::new("SELECT * FROM t"))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_custom_open_flags_are_used() {
+    let dir = TempDir::new().unwrap();
+    let path = temp_db_path(&dir);
+    // Create the file, then open it read-only through the service
+    SqliteService::new(SqliteConfig::new(path.clone(), Schema::new()))
+        .connect()
+        .await
+        .unwrap();
+
+    let config = SqliteConfig::new(path, Schema::new())
+        .with_open_flags(OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+        .with_create_if_missing(false);
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+    assert!(service
+        .execute_sql(SqlQuery::new("CREATE TABLE t (id INTEGER)"))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_read_only_flags_open_without_disabling_create() {
+    let dir = TempDir::new().unwrap();
+    let path = temp_db_path(&dir);
+    SqliteService::new(SqliteConfig::new(path.clone(), Schema::new()))
+        .connect()
+        .await
+        .unwrap();
+
+    // create_if_missing is left at its default of true
+    let config = SqliteConfig::new(path, Schema::new())
+        .with_open_flags(OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX);
+    assert!(!config
+        .effective_open_flags()
+        .contains(OpenFlags::SQLITE_OPEN_CREATE));
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+    let rows = service
+        .execute_sql(SqlQuery::new("SELECT 1 AS one"))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["one"], Value::Integer(1));
+}
+
+#[tokio::test]
+async fn test_shared_cache_memory_uri_is_shared_between_services() {
+    let uri = "file:uri_shared_test?mode=memory&cache=shared";
+    let schema = Schema::new().add_table(
+        TableDefinition::new("notes")
+            .with_column(id_column())
+            .with_column(ColumnDefinition::new("body", DataType::Text)),
+    );
+    let first = SqliteService::new(SqliteConfig::new(uri, schema.clone()));
+    first.connect().await.unwrap();
+    first
+        .execute_sql(SqlQuery::new(
+            "INSERT INTO notes (id, body) VALUES (1, 'shared')",
+        ))
+        .await
+        .unwrap();
+
+    // A second service opening the same URI sees the same database, with
+    // nothing written to disk
+    let second = SqliteService::new(SqliteConfig::new(uri, schema));
+    second.connect().await.unwrap();
+    let rows = second
+        .execute_sql(SqlQuery::new("SELECT body FROM notes"))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["body"], Value::Text("shared".to_string()));
+    assert!(!std::path::Path::new("uri_shared_test").exists());
+}
+
+#[tokio::test]
+async fn test_invalid_uri_parameters_are_rejected() {
+    for uri in ["file:x.db?mode=readonly", "file:x.db?cahce=shared"] {
+        let err = SqliteService::new(SqliteConfig::new(uri, Schema::new()))
+            .connect()
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<SqliteError>(),
+                Some(SqliteError::InvalidUri { .. })
+            ),
+            "{}",
+            err
+        );
+    }
+    // With URI filenames off the path is taken literally and never parsed
+    let config =
+        SqliteConfig::new("file:x.db?mode=readonly", Schema::new()).with_uri_filenames(false);
+    assert!(config.validate_uri().is_ok());
+}
+
+#[tokio::test]
+async fn test_writes_beyond_the_size_quota_fail_with_quota_exceeded() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("blobs")
+            .with_column(id_column())
+            .with_column(ColumnDefinition::new("data", DataType::Blob)),
+    );
+    let config = SqliteConfig::new(temp_db_path(&dir), schema).with_max_size_bytes(64 * 1024);
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+
+    let insert = SqlQuery::new("INSERT INTO blobs (data) VALUES (:data)")
+        .with_params(Params::new().with_value("data", vec![0u8; 8 * 1024]));
+    let mut error = None;
+    for _ in 0..32 {
+        if let Err(e) = service.execute_sql(insert.clone()).await {
+            error = Some(e);
+            break;
+        }
+    }
+    let error = error.expect("quota was never hit");
+    assert!(matches!(
+        error.downcast_ref::<SqliteError>(),
+        Some(SqliteError::QuotaExceeded { max_bytes: 65536 })
+    ));
+
+    // Rows written before the quota was hit are intact
+    let rows = service
+        .execute_sql(SqlQuery::new("SELECT COUNT(*) AS count FROM blobs"))
+        .await
+        .unwrap();
+    assert!(matches!(rows[0]["count"], Value::Integer(n) if n > 0));
+}
+
+#[tokio::test]
+async fn test_query_timeout_interrupts_slow_query_independently_of_busy_timeout() {
+    let dir = TempDir::new().unwrap();
+    let config = SqliteConfig::new(temp_db_path(&dir), Schema::new())
+        .with_busy_timeout(Duration::from_secs(30))
+        .with_query_timeout(Duration::from_millis(100));
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+
+    // No locks are involved; the query is simply slow
+    let started = Instant::now();
+    let err = service
+        .execute_sql(SqlQuery::new(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1000000000) \
+             SELECT COUNT(*) FROM n",
+        ))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::QueryTimeout { timeout }) if *timeout == Duration::from_millis(100)
+    ));
+    assert!(started.elapsed() < Duration::from_secs(10));
+
+    // The connection is usable afterwards
+    let rows = service
+        .execute_sql(SqlQuery::new("SELECT 1 AS one"))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["one"], Value::Integer(1));
+}
+
+#[tokio::test]
+async fn test_script_returns_each_result_set() {
+    let dir = TempDir::new().unwrap();
+    let service = SqliteService::new(SqliteConfig::new(temp_db_path(&dir), Schema::new()));
+    service.connect().await.unwrap();
+
+    let results = service
+        .execute_script_with_results(
+            "CREATE TABLE t (n INTEGER);
+             INSERT INTO t (n) VALUES (1), (2), (3);
+             SELECT COUNT(*) AS count FROM t;
+             SELECT n FROM t WHERE n > 1 ORDER BY n;",
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].len(), 1);
+    assert_eq!(results[0][0]["count"], Value::Integer(3));
+    let values: Vec<&Value> = results[1].iter().map(|row| &row["n"]).collect();
+    assert_eq!(values, vec![&Value::Integer(2), &Value::Integer(3)]);
+}
+
+async fn pooled_service(dir: &TempDir, read_your_writes: bool) -> SqliteService {
+    let schema = Schema::new().add_table(
+        TableDefinition::new("events")
+            .with_column(id_column())
+            .with_column(ColumnDefinition::new("name", DataType::Text)),
+    );
+    let config = SqliteConfig::new(temp_db_path(dir), schema)
+        .with_read_connections(2)
+        .with_read_your_writes(read_your_writes);
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+    service
+}
+
+async fn event_count(service: &SqliteService) -> Value {
+    let rows = service
+        .execute_sql(SqlQuery::new("SELECT COUNT(*) AS count FROM events"))
+        .await
+        .unwrap();
+    rows[0]["count"].clone()
+}
+
+#[tokio::test]
+async fn test_read_your_writes_sees_writes_inside_an_open_transaction() {
+    let dir = TempDir::new().unwrap();
+    let service = pooled_service(&dir, true).await;
+
+    // Write then read in autocommit mode
+    service
+        .execute_sql(SqlQuery::new("INSERT INTO events (name) VALUES ('a')"))
+        .await
+        .unwrap();
+    assert_eq!(event_count(&service).await, Value::Integer(1));
+
+    // A read after an uncommitted write is routed to the writer
+    service.execute_sql(SqlQuery::new("BEGIN")).await.unwrap();
+    service
+        .execute_sql(SqlQuery::new("INSERT INTO events (name) VALUES ('b')"))
+        .await
+        .unwrap();
+    assert_eq!(event_count(&service).await, Value::Integer(2));
+    service.execute_sql(SqlQuery::new("COMMIT")).await.unwrap();
+    assert_eq!(event_count(&service).await, Value::Integer(2));
+}
+
+#[tokio::test]
+async fn test_pooled_reads_without_read_your_writes_see_committed_data_only() {
+    let dir = TempDir::new().unwrap();
+    let service = pooled_service(&dir, false).await;
+
+    service.execute_sql(SqlQuery::new("BEGIN")).await.unwrap();
+    service
+        .execute_sql(SqlQuery::new("INSERT INTO events (name) VALUES ('a')"))
+        .await
+        .unwrap();
+    // Served by a reader, which cannot see the open transaction
+    assert_eq!(event_count(&service).await, Value::Integer(0));
+    service.execute_sql(SqlQuery::new("COMMIT")).await.unwrap();
+    assert_eq!(event_count(&service).await, Value::Integer(1));
+}
+
+#[tokio::test]
+async fn test_pooled_reads_of_tables_only_the_writer_has_go_to_the_writer() {
+    let dir = TempDir::new().unwrap();
+    let service = pooled_service(&dir, false).await;
+
+    // A TEMP table created through raw SQL exists on the writer alone
+    service
+        .execute_sql(SqlQuery::new("CREATE TEMP TABLE scratch (n INTEGER)"))
+        .await
+        .unwrap();
+    service
+        .execute_sql(SqlQuery::new("INSERT INTO scratch (n) VALUES (7)"))
+        .await
+        .unwrap();
+    let rows = service
+        .execute_sql(SqlQuery::new("SELECT n FROM scratch"))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["n"], Value::Integer(7));
+
+    // A table missing everywhere still fails
+    assert!(service
+        .execute_sql(SqlQuery::new("SELECT * FROM missing"))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_full_storage_fails_with_storage_full_and_rolls_back() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("blobs")
+            .with_column(id_column())
+            .with_column(ColumnDefinition::new("data", DataType::Blob)),
+    );
+    let service = SqliteService::new(SqliteConfig::new(temp_db_path(&dir), schema));
+    service.connect().await.unwrap();
+    let insert = SqlQuery::new("INSERT INTO blobs (data) VALUES (:data)")
+        .with_params(Params::new().with_value("data", vec![0u8; 4 * 1024]));
+    service.execute_sql(insert.clone()).await.unwrap();
+
+    // Simulate a full disk without configuring a quota
+    service
+        .execute_sql(SqlQuery::new("PRAGMA max_page_count = 16"))
+        .await
+        .unwrap();
+    service.execute_sql(SqlQuery::new("BEGIN")).await.unwrap();
+    let mut error = None;
+    for _ in 0..32 {
+        if let Err(e) = service.execute_sql(insert.clone()).await {
+            error = Some(e);
+            break;
+        }
+    }
+    let error = error.expect("storage never filled up");
+    assert!(matches!(
+        error.downcast_ref::<SqliteError>(),
+        Some(SqliteError::StorageFull)
+    ));
+
+    // The transaction was rolled back: only the row committed before it
+    // remains, and a new transaction can begin
+    let rows = service
+        .execute_sql(SqlQuery::new("SELECT COUNT(*) AS count FROM blobs"))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["count"], Value::Integer(1));
+    service.execute_sql(SqlQuery::new("BEGIN")).await.unwrap();
+    service
+        .execute_sql(SqlQuery::new("ROLLBACK"))
+        .await
+        .unwrap();
+    assert!(service.integrity_check().await.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn test_temporary_tables_are_scoped_to_the_connection() {
+    let dir = TempDir::new().unwrap();
+    let path = temp_db_path(&dir);
+    let schema = Schema::new()
+        .add_table(TableDefinition::new("items").with_column(id_column()))
+        .add_table(
+            TableDefinition::new("scratch")
+                .with_column(ColumnDefinition::new("n", DataType::Integer))
+                .with_temporary(true),
+        );
+    let config = SqliteConfig::new(path.clone(), schema);
+    let ddl = config.schema.to_ddl();
+    assert!(ddl.contains("CREATE TEMP TABLE IF NOT EXISTS \"scratch\""));
+
+    let service = SqliteService::new(config.clone());
+    service.connect().await.unwrap();
+    service
+        .execute_sql(SqlQuery::new(
+            "INSERT INTO scratch (n) VALUES (1), (2), (3)",
+        ))
+        .await
+        .unwrap();
+    let rows = service
+        .execute_sql(SqlQuery::new("SELECT SUM(n) AS total FROM scratch"))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["total"], Value::Integer(6));
+
+    // Another connection to the same file sees the regular table only
+    let other = rusqlite::Connection::open(&path).unwrap();
+    assert!(other.prepare("SELECT id FROM items").is_ok());
+    assert!(other.prepare("SELECT n FROM scratch").is_err());
+
+    // A new service connection recreates the temporary table empty
+    let restarted = SqliteService::new(config);
+    restarted.connect().await.unwrap();
+    let rows = restarted
+        .execute_sql(SqlQuery::new("SELECT COUNT(*) AS count FROM scratch"))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["count"], Value::Integer(0));
+}
+
+#[tokio::test]
+async fn test_pragma_reads_and_sets_allowed_pragmas() {
+    let dir = TempDir::new().unwrap();
+    let service = SqliteService::new(SqliteConfig::new(temp_db_path(&dir), Schema::new()));
+    service.connect().await.unwrap();
+
+    let rows = service.pragma("user_version", None).await.unwrap();
+    assert_eq!(rows[0]["user_version"], Value::Integer(0));
+
+    service
+        .pragma("user_version", Some(Value::Integer(7)))
+        .await
+        .unwrap();
+    let rows = service.pragma("USER_VERSION", None).await.unwrap();
+    assert_eq!(rows[0]["user_version"], Value::Integer(7));
+
+    let rows = service.pragma("quick_check", None).await.unwrap();
+    assert_eq!(rows[0]["quick_check"], Value::Text("ok".to_string()));
+
+    let err = service
+        .pragma("writable_schema", Some(Value::Integer(1)))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::PragmaNotAllowed(name)) if name == "writable_schema"
+    ));
+    // These would undo the service's own configuration
+    for name in ["foreign_keys", "max_page_count", "temp_store"] {
+        let err = service
+            .pragma(name, Some(Value::Integer(0)))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SqliteError>(),
+            Some(SqliteError::PragmaNotAllowed(_))
+        ));
+    }
+}
+
+#[tokio::test]
+async fn test_allowlist_rejects_unlisted_statements() {
+    let dir = TempDir::new().unwrap();
+    let config = SqliteConfig::new(temp_db_path(&dir), Schema::new())
+        .with_allowed_statements(vec!["SELECT :a + :b AS sum".to_string()]);
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+
+    // Listed templates run with any parameters, whitespace aside
+    let rows = service
+        .execute_sql(
+            SqlQuery::new("SELECT :a +  :b AS sum;")
+                .with_params(Params::new().with_value("a", 2).with_value("b", 3)),
+        )
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["sum"], Value::Integer(5));
+
+    let err = service
+        .execute_sql(SqlQuery::new("SELECT name FROM sqlite_master"))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::StatementNotAllowed(sql)) if sql == "SELECT name FROM sqlite_master"
+    ));
+    let err = service
+        .execute_script_with_results("SELECT :a + :b AS sum; DROP TABLE users")
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::StatementNotAllowed(_))
+    ));
+    // Pragmas are raw SQL too
+    let err = service
+        .pragma("user_version", Some(Value::Integer(3)))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::StatementNotAllowed(sql)) if sql == "PRAGMA user_version(3)"
+    ));
+}
+
+#[tokio::test]
+async fn test_temp_store_applies_to_every_connection() {
+    let dir = TempDir::new().unwrap();
+    let config = SqliteConfig::new(temp_db_path(&dir), Schema::new())
+        .with_read_connections(1)
+        .with_temp_store(TempStore::Memory);
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+
+    // 2 is MEMORY; the transaction runs on the writer, the raw query on a reader
+    let temp_store: i64 = service
+        .transaction_retry(|tx| Ok(tx.query_row("PRAGMA temp_store", [], |row| row.get(0))?))
+        .await
+        .unwrap();
+    assert_eq!(temp_store, 2);
+    let rows = service
+        .execute_sql(SqlQuery::new("PRAGMA temp_store"))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["temp_store"], Value::Integer(2));
+}
+
+#[tokio::test]
+async fn test_create_table_as_copies_filtered_rows() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("users")
+            .with_column(id_column())
+            .with_column(ColumnDefinition::new("name", DataType::Text))
+            .with_column(ColumnDefinition::new("active", DataType::Integer)),
+    );
+    let service = SqliteService::new(SqliteConfig::new(temp_db_path(&dir), schema));
+    service.connect().await.unwrap();
+    service
+        .execute_sql(SqlQuery::new(
+            "INSERT INTO users (id, name, active) VALUES (1, 'ada', 1), (2, 'bob', 0), (3, 'cy', 1)",
+        ))
+        .await
+        .unwrap();
+
+    service
+        .create_table_as(
+            "active_users",
+            SqlQuery::new("SELECT id, name FROM users WHERE active = :active")
+                .with_params(Params::new().with_value("active", 1)),
+        )
+        .await
+        .unwrap();
+
+    let rows = service
+        .execute_sql(SqlQuery::new("SELECT * FROM active_users ORDER BY id"))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].len(), 2);
+    assert_eq!(rows[0]["name"], Value::Text("ada".to_string()));
+    assert_eq!(rows[1]["name"], Value::Text("cy".to_string()));
+
+    // The copy is independent of later changes to the source
+    service
+        .execute_sql(SqlQuery::new("DELETE FROM users"))
+        .await
+        .unwrap();
+    let rows = service
+        .execute_sql(SqlQuery::new("SELECT COUNT(*) AS count FROM active_users"))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["count"], Value::Integer(2));
+}
+
+#[tokio::test]
+async fn test_fold_rows_computes_custom_aggregate() {
+    let dir = TempDir::new().unwrap();
+    let config = SqliteConfig::new(temp_db_path(&dir), Schema::new()).with_read_connections(1);
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+
+    // A histogram of i % 10, built without materializing the rows
+    let histogram = service
+        .fold_rows(
+            SqlQuery::new(
+                "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < :count) \
+                 SELECT i FROM n",
+            )
+            .with_params(Params::new().with_value("count", 10_000)),
+            [0u32; 10],
+            |mut buckets, row| {
+                if let Value::Integer(i) = row["i"] {
+                    buckets[(i % 10) as usize] += 1;
+                }
+                buckets
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(histogram, [1_000; 10]);
+}
+
+#[tokio::test]
+async fn test_fold_row_views_borrows_values_matching_rows() {
+    let dir = TempDir::new().unwrap();
+    let service = SqliteService::new(SqliteConfig::new(temp_db_path(&dir), Schema::new()));
+    service.connect().await.unwrap();
+    let query = || SqlQuery::new("SELECT 1 AS n, 2.5 AS r, 'héllo' AS t, X'0102' AS b, NULL AS z");
+
+    let (texts, rows) = service
+        .fold_row_views(
+            query(),
+            (Vec::new(), Vec::new()),
+            |(mut texts, mut rows), view| {
+                assert_eq!(view.columns(), ["n", "r", "t", "b", "z"]);
+                assert_eq!(view.get("n").and_then(|v| v.as_i64()), Some(1));
+                assert_eq!(view.get("r").and_then(|v| v.as_f64()), Some(2.5));
+                assert!(matches!(
+                    view.get("t"),
+                    Some(ValueView::Text(Cow::Borrowed(_)))
+                ));
+                assert_eq!(view.get("b").unwrap().as_blob(), Some(&[1u8, 2][..]));
+                assert!(view.get("z").unwrap().is_null());
+                assert!(view.get("missing").is_none());
+                texts.push(view.get("t").unwrap().as_str().unwrap().to_string());
+                rows.push(view.to_row());
+                (texts, rows)
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(texts, ["héllo"]);
+    assert_eq!(rows, service.execute_sql(query()).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_page_size_is_set_on_new_databases_only() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(TableDefinition::new("t").with_column(id_column()));
+    let config = SqliteConfig::new(temp_db_path(&dir), schema).with_page_size(8192);
+    let service = SqliteService::new(config.clone());
+    service.connect().await.unwrap();
+    let rows = service.pragma("page_size", None).await.unwrap();
+    assert_eq!(rows[0]["page_size"], Value::Integer(8192));
+    drop(service);
+
+    // Reopening with the same size is fine; a different size is rejected
+    SqliteService::new(config.clone()).connect().await.unwrap();
+    let err = SqliteService::new(config.with_page_size(16384))
+        .connect()
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::PageSizeMismatch {
+            configured: 16384,
+            actual: 8192
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_ready_resolves_after_connect() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(TableDefinition::new("t").with_column(id_column()));
+    let service = SqliteService::new(SqliteConfig::new(temp_db_path(&dir), schema));
+
+    let waiter = {
+        let service = service.clone();
+        tokio::spawn(async move {
+            service.ready().await;
+            // Once ready, the schema is in place
+            service
+                .execute_sql(SqlQuery::new("SELECT COUNT(*) AS count FROM t"))
+                .await
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!waiter.is_finished());
+
+    service.connect().await.unwrap();
+    let rows = waiter.await.unwrap().unwrap();
+    assert_eq!(rows[0]["count"], Value::Integer(0));
+    // Later calls resolve immediately
+    service.ready().await;
+}
+
+fn items_schema(with_price: bool) -> Schema {
+    let mut items = TableDefinition::new("items")
+        .with_column(id_column())
+        .with_column(ColumnDefinition::new("name", DataType::Text));
+    if with_price {
+        items = items.with_column(ColumnDefinition::new("price", DataType::Real));
+    }
+    Schema::new().add_table(items)
+}
+
+/// Connect with the schema without a price column and insert one row
+async fn existing_items(dir: &TempDir) {
+    let service = SqliteService::new(SqliteConfig::new(temp_db_path(dir), items_schema(false)));
+    service.connect().await.unwrap();
+    service
+        .execute_sql(SqlQuery::new(
+            "INSERT INTO items (id, name) VALUES (1, 'kept')",
+        ))
+        .await
+        .unwrap();
+}
+
+async fn item_columns(service: &SqliteService) -> Vec<Value> {
+    service
+        .execute_sql(SqlQuery::new("SELECT name FROM pragma_table_info('items')"))
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|mut row| row.remove("name").unwrap())
+        .collect()
+}
+
+async fn item_count(service: &SqliteService) -> Value {
+    let rows = service
+        .execute_sql(SqlQuery::new("SELECT COUNT(*) AS n FROM items"))
+        .await
+        .unwrap();
+    rows[0]["n"].clone()
+}
+
+#[tokio::test]
+async fn test_schema_conflict_skip_keeps_existing_table() {
+    let dir = TempDir::new().unwrap();
+    existing_items(&dir).await;
+    let config = SqliteConfig::new(temp_db_path(&dir), items_schema(true));
+    assert_eq!(config.schema_conflict, SchemaConflict::Skip);
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+    assert_eq!(item_columns(&service).await.len(), 2);
+    assert_eq!(item_count(&service).await, Value::Integer(1));
+}
+
+#[tokio::test]
+async fn test_schema_conflict_error_fails_on_changed_table() {
+    let dir = TempDir::new().unwrap();
+    existing_items(&dir).await;
+    let err = SqliteService::new(
+        SqliteConfig::new(temp_db_path(&dir), items_schema(true))
+            .with_schema_conflict(SchemaConflict::Error),
+    )
+    .connect()
+    .await
+    .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::SchemaConflict(table)) if table == "items"
+    ));
+
+    // An unchanged definition is not a conflict
+    SqliteService::new(
+        SqliteConfig::new(temp_db_path(&dir), items_schema(false))
+            .with_schema_conflict(SchemaConflict::Error),
+    )
+    .connect()
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_schema_conflict_recreate_replaces_changed_table() {
+    let dir = TempDir::new().unwrap();
+    existing_items(&dir).await;
+
+    // Unchanged tables keep their rows
+    let unchanged = SqliteService::new(
+        SqliteConfig::new(temp_db_path(&dir), items_schema(false))
+            .with_schema_conflict(SchemaConflict::Recreate),
+    );
+    unchanged.connect().await.unwrap();
+    assert_eq!(item_count(&unchanged).await, Value::Integer(1));
+    drop(unchanged);
+
+    let service = SqliteService::new(
+        SqliteConfig::new(temp_db_path(&dir), items_schema(true))
+            .with_schema_conflict(SchemaConflict::Recreate),
+    );
+    service.connect().await.unwrap();
+    assert_eq!(item_columns(&service).await.len(), 3);
+    assert_eq!(item_count(&service).await, Value::Integer(0));
+    let foreign_keys: i64 = service
+        .transaction_retry(|tx| Ok(tx.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?))
+        .await
+        .unwrap();
+    assert_eq!(foreign_keys, 1);
+}
+
+#[tokio::test]
+async fn test_query_pragmas_apply_to_one_query_only() {
+    let dir = TempDir::new().unwrap();
+    let service = SqliteService::new(SqliteConfig::new(temp_db_path(&dir), Schema::new()));
+    service.connect().await.unwrap();
+    service
+        .execute_sql(SqlQuery::new(
+            "CREATE TABLE words AS SELECT 'Apple' AS w UNION ALL SELECT 'apricot'",
+        ))
+        .await
+        .unwrap();
+    let like = || SqlQuery::new("SELECT COUNT(*) AS n FROM words WHERE w LIKE 'a%'");
+    let count = |rows: Vec<Row>| rows[0]["n"].clone();
+
+    let rows = service.execute_sql(like()).await.unwrap();
+    assert_eq!(count(rows), Value::Integer(2));
+    let rows = service
+        .execute_sql(like().with_pragma("case_sensitive_like", true))
+        .await
+        .unwrap();
+    assert_eq!(count(rows), Value::Integer(1));
+    let rows = service.execute_sql(like()).await.unwrap();
+    assert_eq!(count(rows), Value::Integer(2));
+
+    // Reset also happens when the query fails
+    service
+        .execute_sql(
+            SqlQuery::new("SELECT * FROM missing").with_pragma("case_sensitive_like", true),
+        )
+        .await
+        .unwrap_err();
+    let rows = service.execute_sql(like()).await.unwrap();
+    assert_eq!(count(rows), Value::Integer(2));
+
+    // Changing temp_store would drop every TEMP table
+    for pragma in ["writable_schema", "temp_store"] {
+        let err = service
+            .execute_sql(like().with_pragma(pragma, 1))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SqliteError>(),
+            Some(SqliteError::PragmaNotAllowed(_))
+        ));
+    }
+}
+
+#[tokio::test]
+async fn test_nocase_unique_index_rejects_case_varying_duplicates() {
+    let dir = TempDir::new().unwrap();
+    let index =
+        IndexDefinition::new("idx_users_email", &["email"], true).with_collation(Collation::NoCase);
+    assert_eq!(
+        index.to_sql("users"),
+        "CREATE UNIQUE INDEX IF NOT EXISTS \"idx_users_email\" ON \"users\" (\"email\" COLLATE NOCASE)"
+    );
+    let schema = Schema::new().add_table(
+        TableDefinition::new("users")
+            .with_column(id_column())
+            .with_column(ColumnDefinition::new("email", DataType::Text))
+            .with_index(index),
+    );
+    let service = SqliteService::new(SqliteConfig::new(temp_db_path(&dir), schema));
+    service.connect().await.unwrap();
+
+    let insert = |email: &str| {
+        SqlQuery::new("INSERT INTO users (email) VALUES (:email)")
+            .with_params(Params::new().with_value("email", email))
+    };
+    service.execute_sql(insert("A@x.com")).await.unwrap();
+    let err = service.execute_sql(insert("a@x.com")).await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<rusqlite::Error>()
+            .and_then(|e| e.sqlite_error_code()),
+        Some(rusqlite::ErrorCode::ConstraintViolation)
+    );
+    service.execute_sql(insert("b@x.com")).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_identifier_case_check_rejects_case_colliding_schemas() {
+    let dir = TempDir::new().unwrap();
+    let users = || {
+        TableDefinition::new("users")
+            .with_column(id_column())
+            .with_column(ColumnDefinition::new("email", DataType::Text))
+    };
+    let colliding = Schema::new()
+        .add_table(users().with_column(ColumnDefinition::new("Email", DataType::Text)));
+    let config = SqliteConfig::new(temp_db_path(&dir), colliding).with_identifier_case_check(true);
+    let err = SqliteService::new(config).connect().await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::IdentifierCase { first, second })
+            if first == "users.email" && second == "users.Email"
+    ));
+
+    // A reference spelled differently from its declaration is caught too
+    let respelled = Schema::new().add_table(users()).add_table(
+        TableDefinition::new("posts")
+            .with_column(id_column())
+            .with_column(ColumnDefinition::new("user_id", DataType::Integer))
+            .with_foreign_key(references("user_id", "Users")),
+    );
+    assert!(matches!(
+        respelled.check_identifier_case(),
+        Err(SqliteError::IdentifierCase { first, second }) if first == "users" && second == "Users"
+    ));
+
+    let consistent = Schema::new().add_table(users());
+    assert!(consistent.check_identifier_case().is_ok());
+    let config = SqliteConfig::new(temp_db_path(&dir), consistent).with_identifier_case_check(true);
+    SqliteService::new(config).connect().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_tables_without_if_not_exists_fail_when_they_exist() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new()
+        .add_table(TableDefinition::new("kept").with_column(id_column()))
+        .add_table(
+            TableDefinition::new("fresh_only")
+                .with_column(id_column())
+                .with_if_not_exists(false),
+        );
+    let config = SqliteConfig::new(temp_db_path(&dir), schema.clone());
+    SqliteService::new(config.clone()).connect().await.unwrap();
+
+    let err = SqliteService::new(config).connect().await.unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("table \"fresh_only\" already exists"));
+
+    // With only idempotent tables, reconnecting is fine
+    let kept = Schema::new().add_table(schema.tables[0].clone());
+    let config = SqliteConfig::new(temp_db_path(&dir), kept);
+    SqliteService::new(config).connect().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_indexes_without_if_not_exists_fail_when_they_exist() {
+    let dir = TempDir::new().unwrap();
+    let users = |index: IndexDefinition| {
+        Schema::new().add_table(
+            TableDefinition::new("users")
+                .with_column(id_column())
+                .with_column(ColumnDefinition::new("email", DataType::Text))
+                .with_index(index),
+        )
+    };
+    let index = IndexDefinition::new("idx_users_email", &["email"], false);
+    let config = SqliteConfig::new(temp_db_path(&dir), users(index.clone()));
+    SqliteService::new(config.clone()).connect().await.unwrap();
+    SqliteService::new(config).connect().await.unwrap();
+
+    let config = SqliteConfig::new(temp_db_path(&dir), users(index.with_if_not_exists(false)));
+    let err = SqliteService::new(config).connect().await.unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("index idx_users_email already exists"));
+}
+
+#[tokio::test]
+async fn test_reopen_switches_queries_to_the_new_file() {
+    let dir = TempDir::new().unwrap();
+    let service = pooled_service(&dir, false).await;
+    let insert = SqlQuery::new("INSERT INTO events (name) VALUES ('a')");
+    service.execute_sql(insert.clone()).await.unwrap();
+
+    let other_dir = TempDir::new().unwrap();
+    let other = pooled_service(&other_dir, false).await;
+    for _ in 0..3 {
+        other.execute_sql(insert.clone()).await.unwrap();
+    }
+    other.reopen(temp_db_path(&dir)).await.unwrap();
+    assert_eq!(event_count(&other).await, Value::Integer(1));
+
+    // Reads running alongside the swap succeed against either file
+    let (swapped, counts) = futures::join!(
+        service.reopen(temp_db_path(&other_dir)),
+        futures::future::join_all((0..8).map(|_| event_count(&service)))
+    );
+    swapped.unwrap();
+    assert!(counts
+        .iter()
+        .all(|c| *c == Value::Integer(1) || *c == Value::Integer(3)));
+    assert_eq!(service.db_path(), temp_db_path(&other_dir));
+    // Both the writer and every reader now use the new file
+    service.execute_sql(insert).await.unwrap();
+    for _ in 0..4 {
+        assert_eq!(event_count(&service).await, Value::Integer(4));
+    }
+
+    let missing = dir.path().join("missing").join("test.db");
+    let err = service.reopen(missing.to_str().unwrap()).await.unwrap_err();
+    assert!(err.downcast_ref::<rusqlite::Error>().is_some());
+    assert_eq!(service.db_path(), temp_db_path(&other_dir));
+    assert_eq!(event_count(&service).await, Value::Integer(4));
+}
+
+fn module_config(path: &str, owner: &str, table: &str) -> SqliteConfig {
+    let schema = Schema::new().add_table(
+        TableDefinition::new(table)
+            .with_column(id_column())
+            .with_column(ColumnDefinition::new("name", DataType::Text)),
+    );
+    SqliteConfig::new(path, schema)
+        .with_schema_owner(owner)
+        .with_migrations(vec![Migration::new(
+            1,
+            "add note",
+            &format!("ALTER TABLE {} ADD COLUMN note TEXT", table),
+        )])
+        .with_seeds(vec![SqlQuery::new(&format!(
+            "INSERT INTO {} (name) VALUES ('{}')",
+            table, owner
+        ))])
+}
+
+#[tokio::test]
+async fn test_services_owning_disjoint_tables_share_one_file() {
+    let dir = TempDir::new().unwrap();
+    let path = temp_db_path(&dir);
+    let billing = SqliteService::new(module_config(&path, "billing", "invoices"));
+    let accounts = SqliteService::new(module_config(&path, "accounts", "users"));
+    let (a, b) = tokio::join!(billing.connect(), accounts.connect());
+    a.unwrap();
+    b.unwrap();
+
+    // Each service applied its own migration and seed, despite sharing
+    // version numbers and the seed marker table
+    for (service, table, owner) in [
+        (&billing, "invoices", "billing"),
+        (&accounts, "users", "accounts"),
+    ] {
+        assert_eq!(service.migration_version().await.unwrap(), 1);
+        let rows = service
+            .execute_sql(SqlQuery::new(&format!("SELECT name, note FROM {}", table)))
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get("name"), Some(&Value::from(owner)));
+    }
+
+    // Restarting one service leaves the other's tables and data alone
+    drop(billing);
+    let billing = SqliteService::new(module_config(&path, "billing", "invoices"));
+    billing.connect().await.unwrap();
+    let users = accounts
+        .execute_sql(SqlQuery::new("SELECT name FROM users"))
+        .await
+        .unwrap();
+    assert_eq!(users.len(), 1);
+    let invoices = billing
+        .execute_sql(SqlQuery::new("SELECT name FROM invoices"))
+        .await
+        .unwrap();
+    assert_eq!(invoices.len(), 1);
+}
+
+#[tokio::test]
+async fn test_schema_owner_cannot_track_migrations_in_user_version() {
+    let dir = TempDir::new().unwrap();
+    let config = module_config(&temp_db_path(&dir), "billing", "invoices")
+        .with_migration_tracking(MigrationTracking::UserVersion);
+    let err = SqliteService::new(config).connect().await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::SharedUserVersion(owner)) if owner == "billing"
+    ));
+}
+
+#[tokio::test]
+async fn test_deferred_foreign_key_is_checked_at_commit() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new()
+        .add_table(TableDefinition::new("parents").with_column(id_column()))
+        .add_table(
+            TableDefinition::new("children")
+                .with_column(id_column())
+                .with_column(ColumnDefinition::new("parent_id", DataType::Integer))
+                .with_foreign_key(references("parent_id", "parents").with_deferred(true)),
+        );
+    let service = SqliteService::new(SqliteConfig::new(temp_db_path(&dir), schema));
+    service.connect().await.unwrap();
+
+    // The child goes in before its parent exists
+    service
+        .transaction_retry(|tx| {
+            tx.execute("INSERT INTO children (id, parent_id) VALUES (1, 10)", [])?;
+            tx.execute("INSERT INTO parents (id) VALUES (10)", [])?;
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+    // A parent still missing at commit fails the whole transaction
+    let err = service
+        .transaction_retry(|tx| {
+            tx.execute("INSERT INTO children (id, parent_id) VALUES (2, 20)", [])?;
+            Ok(())
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<rusqlite::Error>()
+            .and_then(rusqlite::Error::sqlite_error_code),
+        Some(rusqlite::ErrorCode::ConstraintViolation)
+    );
+    let children = service
+        .execute_sql(SqlQuery::new("SELECT id FROM children"))
+        .await
+        .unwrap();
+    assert_eq!(children.len(), 1);
+}
+
+#[tokio::test]
+async fn test_heavy_deletes_increase_fragmentation() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("notes")
+            .with_column(id_column())
+            .with_column(ColumnDefinition::new("body", DataType::Text)),
+    );
+    let service = SqliteService::new(SqliteConfig::new(temp_db_path(&dir), schema));
+    service.connect().await.unwrap();
+    service
+        .execute_sql(SqlQuery::new(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 2000) \
+             INSERT INTO notes (body) SELECT printf('%.500c', 'x') FROM n",
+        ))
+        .await
+        .unwrap();
+    let before = service.fragmentation().await.unwrap();
+    assert!(before < 0.05, "fresh database is {} fragmented", before);
+    assert!(!service.needs_vacuum(0.5).await.unwrap());
+
+    service
+        .execute_sql(SqlQuery::new("DELETE FROM notes WHERE id > 200"))
+        .await
+        .unwrap();
+    let after = service.fragmentation().await.unwrap();
+    assert!(after > 0.5, "only {} fragmented after deleting 90%", after);
+    assert!(service.needs_vacuum(0.5).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_result_over_byte_budget_is_aborted() {
+    let dir = TempDir::new().unwrap();
+    let config = SqliteConfig::new(temp_db_path(&dir), Schema::new()).with_max_result_bytes(10_000);
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+    let rows = |count: usize| {
+        SqlQuery::new(&format!(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < {}) \
+             SELECT i, printf('%.100c', 'x') AS body FROM n",
+            count
+        ))
+    };
+
+    // 50 rows of 108 bytes fit, 1,000 do not
+    assert_eq!(service.execute_sql(rows(50)).await.unwrap().len(), 50);
+    let err = service.execute_sql(rows(1_000)).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::ResultTooLarge { max_bytes: 10_000 })
+    ));
+
+    // Folding keeps no rows, so it is not limited
+    let count = service
+        .fold_rows(rows(1_000), 0, |count, _| count + 1)
+        .await
+        .unwrap();
+    assert_eq!(count, 1_000);
+
+    // Writes are not limited: failing would not undo them
+    service
+        .execute_sql(SqlQuery::new("CREATE TABLE notes (body TEXT)"))
+        .await
+        .unwrap();
+    let inserted = service
+        .execute_sql(SqlQuery::new(
+            "INSERT INTO notes (body) SELECT printf('%.100c', 'x') FROM \
+             (WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1000) \
+             SELECT i FROM n) RETURNING body",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(inserted.len(), 1_000);
+}
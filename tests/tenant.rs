@@ -0,0 +1,84 @@
+use rust_sqlite::sqlite::{
+    ColumnConstraint, ColumnDefinition, DataType, Params, Schema, SqlQuery, SqliteConfig,
+    SqliteError, TableDefinition, Value,
+};
+use rust_sqlite::tenant::TenantManager;
+use tempfile::TempDir;
+
+fn template() -> SqliteConfig {
+    let schema = Schema::new().add_table(
+        TableDefinition::new("notes")
+            .with_column(
+                ColumnDefinition::new("id", DataType::Integer)
+                    .with_constraint(ColumnConstraint::PrimaryKey),
+            )
+            .with_column(ColumnDefinition::new("body", DataType::Text)),
+    );
+    SqliteConfig::new("", schema)
+}
+
+fn note_count_query() -> SqlQuery {
+    SqlQuery::new("SELECT COUNT(*) AS count FROM notes")
+}
+
+#[tokio::test]
+async fn test_tenant_databases_are_isolated() {
+    let dir = TempDir::new().unwrap();
+    let manager = TenantManager::new(dir.path().join("tenants"), template(), 8);
+
+    let acme = manager.get("acme").await.unwrap();
+    let globex = manager.get("globex").await.unwrap();
+    acme.execute_sql(
+        SqlQuery::new("INSERT INTO notes (body) VALUES (:body)")
+            .with_params(Params::new().with_value("body", "acme only")),
+    )
+    .await
+    .unwrap();
+
+    let rows = acme.execute_sql(note_count_query()).await.unwrap();
+    assert_eq!(rows[0]["count"], Value::Integer(1));
+    let rows = globex.execute_sql(note_count_query()).await.unwrap();
+    assert_eq!(rows[0]["count"], Value::Integer(0));
+
+    assert!(manager.tenant_path("acme").unwrap().exists());
+    assert!(manager.tenant_path("globex").unwrap().exists());
+}
+
+#[tokio::test]
+async fn test_least_recently_used_tenant_is_evicted() {
+    let dir = TempDir::new().unwrap();
+    let manager = TenantManager::new(dir.path(), template(), 2);
+
+    let a = manager.get("a").await.unwrap();
+    a.execute_sql(SqlQuery::new("INSERT INTO notes (body) VALUES ('kept')"))
+        .await
+        .unwrap();
+    drop(a);
+    manager.get("b").await.unwrap();
+    manager.get("a").await.unwrap();
+    manager.get("c").await.unwrap();
+    assert_eq!(manager.open_tenants().await, vec!["a", "c"]);
+
+    manager.get("b").await.unwrap();
+    assert_eq!(manager.open_tenants().await, vec!["c", "b"]);
+
+    // Reopening an evicted tenant sees its data
+    let a = manager.get("a").await.unwrap();
+    let rows = a.execute_sql(note_count_query()).await.unwrap();
+    assert_eq!(rows[0]["count"], Value::Integer(1));
+}
+
+#[tokio::test]
+async fn test_tenant_ids_cannot_escape_the_base_directory() {
+    let dir = TempDir::new().unwrap();
+    let manager = TenantManager::new(dir.path(), template(), 2);
+    let err = manager
+        .get("../escape")
+        .await
+        .err()
+        .expect("path traversal must be rejected");
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::InvalidTenantId(_))
+    ));
+}
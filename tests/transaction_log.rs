@@ -0,0 +1,51 @@
+use rust_sqlite::sqlite::{
+    ColumnDefinition, CreateOperation, CrudOperation, DataType, Params, Schema, SqlQuery,
+    SqliteConfig, SqliteService, TableDefinition, Value,
+};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn test_transaction_log_records_recent_statements_and_evicts_old_ones() {
+    let dir = TempDir::new().unwrap();
+    let schema = Schema::new().add_table(
+        TableDefinition::new("users").with_column(ColumnDefinition::new("name", DataType::Text)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    let config = SqliteConfig::new(path, schema).with_transaction_log(3);
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+    assert!(service.transaction_log().is_empty());
+
+    for name in ["ann", "bob", "cy"] {
+        service
+            .execute_crud(CrudOperation::Create(
+                CreateOperation::new("users").with_value("name", name),
+            ))
+            .await
+            .unwrap();
+    }
+    let query = SqlQuery::new("SELECT name FROM users WHERE name = :name")
+        .with_params(Params::new().with_value("name", "bob"));
+    service.execute_sql(query).await.unwrap();
+    service
+        .execute_sql(SqlQuery::new("SELECT missing FROM users"))
+        .await
+        .unwrap_err();
+
+    // Only the last three statements are kept, oldest first
+    let log = service.transaction_log();
+    assert_eq!(log.len(), 3);
+    assert!(log[0].statement.starts_with("INSERT INTO"));
+    assert_eq!(log[0].params, vec![("?1".to_string(), Value::from("cy"))]);
+    assert_eq!(log[0].error, None);
+    assert_eq!(
+        log[1].statement,
+        "SELECT name FROM users WHERE name = :name"
+    );
+    assert_eq!(
+        log[1].params,
+        vec![("name".to_string(), Value::from("bob"))]
+    );
+    assert_eq!(log[2].statement, "SELECT missing FROM users");
+    assert!(log[2].error.as_deref().unwrap().contains("missing"));
+}
@@ -0,0 +1,80 @@
+use rusqlite::Connection;
+use rust_sqlite::sqlite::{
+    ColumnDefinition, DataType, Schema, SqlQuery, SqliteConfig, SqliteService, TableDefinition,
+    Value,
+};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use tempfile::TempDir;
+
+async fn counters_service(dir: &TempDir, max_attempts: u32) -> (SqliteService, String) {
+    let schema = Schema::new().add_table(
+        TableDefinition::new("counters").with_column(ColumnDefinition::new("n", DataType::Integer)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    // No busy timeout, so a held lock fails statements right away
+    let config = SqliteConfig::new(path.clone(), schema)
+        .with_busy_timeout(Duration::ZERO)
+        .with_transaction_retry(max_attempts, Duration::from_millis(20));
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+    (service, path)
+}
+
+#[tokio::test]
+async fn test_conflicting_transaction_succeeds_after_retry() {
+    let dir = TempDir::new().unwrap();
+    let (service, path) = counters_service(&dir, 20).await;
+
+    // Another connection holds the write lock for a while
+    let other = Connection::open(&path).unwrap();
+    other.execute_batch("BEGIN IMMEDIATE").unwrap();
+    let holder = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(200));
+        other.execute_batch("COMMIT").unwrap();
+    });
+
+    let attempts = AtomicU32::new(0);
+    let inserted = service
+        .transaction_retry(|tx| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            tx.execute("INSERT INTO counters (n) VALUES (1)", [])?;
+            Ok(tx.last_insert_rowid())
+        })
+        .await
+        .unwrap();
+    holder.join().unwrap();
+
+    assert!(attempts.load(Ordering::SeqCst) > 1);
+    assert_eq!(inserted, 1);
+    // Failed attempts left nothing behind
+    let rows = service
+        .execute_sql(SqlQuery::new("SELECT COUNT(*) AS count FROM counters"))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["count"], Value::Integer(1));
+}
+
+#[tokio::test]
+async fn test_other_errors_are_not_retried() {
+    let dir = TempDir::new().unwrap();
+    let (service, _path) = counters_service(&dir, 20).await;
+
+    let attempts = AtomicU32::new(0);
+    let result: anyhow::Result<()> = service
+        .transaction_retry(|tx| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            tx.execute("INSERT INTO counters (n) VALUES (1)", [])?;
+            tx.execute("INSERT INTO missing (n) VALUES (1)", [])?;
+            Ok(())
+        })
+        .await;
+
+    assert!(result.is_err());
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    let rows = service
+        .execute_sql(SqlQuery::new("SELECT COUNT(*) AS count FROM counters"))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["count"], Value::Integer(0));
+}
@@ -0,0 +1,59 @@
+#![cfg(feature = "testing")]
+
+use rust_sqlite::sqlite::{
+    ColumnDefinition, DataType, Params, Schema, SqlQuery, SqliteConfig, SqliteService,
+    TableDefinition, Value,
+};
+use tempfile::TempDir;
+
+const FIND_USER: &str = "SELECT name FROM users WHERE id = :id";
+const ADD_USER: &str = "INSERT INTO users (id, name) VALUES (:id, :name)";
+
+fn config(dir: &TempDir) -> SqliteConfig {
+    let schema = Schema::new().add_table(
+        TableDefinition::new("users")
+            .with_column(ColumnDefinition::new("id", DataType::Integer))
+            .with_column(ColumnDefinition::new("name", DataType::Text)),
+    );
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    SqliteConfig::new(path, schema)
+}
+
+#[tokio::test]
+async fn test_warmup_queries_are_cached_after_connect() {
+    let dir = TempDir::new().unwrap();
+    let config =
+        config(&dir).with_warmup_queries(vec![FIND_USER.to_string(), ADD_USER.to_string()]);
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+
+    let prepared = service.prepared_statements().await.unwrap();
+    assert!(
+        prepared.iter().any(|sql| sql == FIND_USER),
+        "{:?}",
+        prepared
+    );
+    assert!(prepared.iter().any(|sql| sql == ADD_USER), "{:?}", prepared);
+
+    // The cached statements serve requests, with no bindings left over
+    service
+        .execute_sql(
+            SqlQuery::new(ADD_USER)
+                .with_params(Params::new().with_value("id", 1).with_value("name", "ada")),
+        )
+        .await
+        .unwrap();
+    let rows = service
+        .execute_sql(SqlQuery::new(FIND_USER).with_params(Params::new().with_value("id", 1)))
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["name"], Value::Text("ada".to_string()));
+}
+
+#[tokio::test]
+async fn test_invalid_warmup_query_fails_connect() {
+    let dir = TempDir::new().unwrap();
+    let config = config(&dir).with_warmup_queries(vec!["SELECT * FROM missing".to_string()]);
+    let service = SqliteService::new(config);
+    assert!(service.connect().await.is_err());
+}
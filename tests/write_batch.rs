@@ -0,0 +1,154 @@
+use rust_sqlite::sqlite::{
+    Params, Schema, SqlQuery, SqliteConfig, SqliteError, SqliteService, Value,
+};
+use rust_sqlite::write_batch::WriteBatchStats;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn config(dir: &TempDir, interval: Duration, max_operations: usize) -> SqliteConfig {
+    let path = dir.path().join("test.db").to_str().unwrap().to_string();
+    SqliteConfig::new(path, Schema::new()).with_write_batching(interval, max_operations)
+}
+
+async fn connect(config: SqliteConfig) -> SqliteService {
+    let service = SqliteService::new(config);
+    service.connect().await.unwrap();
+    service
+        .execute_sql(SqlQuery::new(
+            "CREATE TABLE IF NOT EXISTS events (id INTEGER PRIMARY KEY, name TEXT NOT NULL)",
+        ))
+        .await
+        .unwrap();
+    service
+}
+
+fn insert(name: Option<&str>) -> SqlQuery {
+    let name = name.map_or(Value::Null, Value::from);
+    SqlQuery::new("INSERT INTO events (name) VALUES (:name)")
+        .with_params(Params::new().with_value("name", name))
+}
+
+async fn count(service: &SqliteService) -> Value {
+    let mut rows = service
+        .execute_sql(SqlQuery::new("SELECT COUNT(*) AS n FROM events"))
+        .await
+        .unwrap();
+    rows.remove(0).remove("n").unwrap()
+}
+
+#[tokio::test]
+async fn test_queued_writes_commit_in_few_transactions() {
+    let dir = TempDir::new().unwrap();
+    let service = connect(config(&dir, Duration::from_secs(60), 50)).await;
+
+    for i in 0..120 {
+        service
+            .queue_write(insert(Some(&format!("event {}", i))))
+            .await
+            .unwrap();
+    }
+    // Two full batches have committed; the rest waits for the interval
+    assert_eq!(count(&service).await, Value::Integer(100));
+    assert_eq!(
+        service.write_batch_stats(),
+        WriteBatchStats {
+            batches: 2,
+            writes: 100
+        }
+    );
+
+    service.flush().await.unwrap();
+    assert_eq!(service.write_batch_stats().batches, 3);
+    drop(service);
+
+    // Everything flushed is durable
+    let reopened = connect(config(&dir, Duration::from_secs(60), 50)).await;
+    assert_eq!(count(&reopened).await, Value::Integer(120));
+}
+
+#[tokio::test]
+async fn test_queued_writes_commit_after_the_interval() {
+    let dir = TempDir::new().unwrap();
+    let service = connect(config(&dir, Duration::from_millis(50), 1000)).await;
+
+    for i in 0..10 {
+        service
+            .queue_write(insert(Some(&format!("event {}", i))))
+            .await
+            .unwrap();
+    }
+    assert_eq!(count(&service).await, Value::Integer(0));
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(count(&service).await, Value::Integer(10));
+    assert_eq!(service.write_batch_stats().batches, 1);
+}
+
+#[tokio::test]
+async fn test_flush_reports_failed_writes_and_commits_the_rest() {
+    let dir = TempDir::new().unwrap();
+    let service = connect(config(&dir, Duration::from_secs(60), 1000)).await;
+
+    service.queue_write(insert(Some("first"))).await.unwrap();
+    service.queue_write(insert(None)).await.unwrap();
+    service.queue_write(insert(Some("last"))).await.unwrap();
+
+    let err = service.flush().await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::BatchedWritesFailed { failed: 1, first }) if first.contains("NOT NULL")
+    ));
+    assert_eq!(count(&service).await, Value::Integer(2));
+    // Failures are reported once
+    service.flush().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_queued_writes_are_observed_like_other_statements() {
+    let dir = TempDir::new().unwrap();
+    let service =
+        connect(config(&dir, Duration::from_millis(50), 1000).with_transaction_log(10)).await;
+
+    service.queue_write(insert(Some("queued"))).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let log = service.transaction_log();
+    let entry = log.last().unwrap();
+    assert_eq!(entry.statement, "INSERT INTO events (name) VALUES (:name)");
+    assert_eq!(
+        entry.params,
+        vec![("name".to_string(), Value::from("queued"))]
+    );
+}
+
+#[tokio::test]
+async fn test_queued_writes_after_close_fail() {
+    let dir = TempDir::new().unwrap();
+    let service = connect(config(&dir, Duration::from_secs(60), 1000)).await;
+    service.queue_write(insert(Some("before"))).await.unwrap();
+
+    // Closing commits what is queued, and later writes are refused
+    service.close().await.unwrap();
+    let err = service
+        .queue_write(insert(Some("after")))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SqliteError>(),
+        Some(SqliteError::Closed)
+    ));
+    service.flush().await.unwrap();
+
+    let reopened = connect(config(&dir, Duration::from_secs(60), 1000)).await;
+    assert_eq!(count(&reopened).await, Value::Integer(1));
+}
+
+#[tokio::test]
+async fn test_queued_writes_are_accepted_again_after_reopen() {
+    let dir = TempDir::new().unwrap();
+    let service = connect(config(&dir, Duration::from_secs(60), 1000)).await;
+    service.close().await.unwrap();
+
+    service.reopen(service.db_path()).await.unwrap();
+    service.queue_write(insert(Some("after"))).await.unwrap();
+    service.flush().await.unwrap();
+    assert_eq!(count(&service).await, Value::Integer(1));
+}